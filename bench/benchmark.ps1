@@ -82,6 +82,40 @@ foreach ($Threads in $ThreadCounts) {
     }
 }
 
+# Per-parent caps (spinning disks), at a fixed thread count
+foreach ($Limit in @(1, 2, 4)) {
+    $Methods += @{
+        Name = "rmbrr (16 threads, max ${Limit}/parent)"
+        Command = {
+            param($Path, $ThreadCount)
+            & $Rmbrr $Path --threads $ThreadCount --max-per-parent $Limit
+        }.GetNewClosure()
+        Threads = 16
+    }
+}
+
+# Handle closes moved off the workers, against closing inline above
+foreach ($Closers in @(2, 4)) {
+    $Methods += @{
+        Name = "rmbrr (16 threads, ${Closers} close threads)"
+        Command = {
+            param($Path, $ThreadCount)
+            & $Rmbrr $Path --threads $ThreadCount --close-threads $Closers
+        }.GetNewClosure()
+        Threads = 16
+    }
+}
+
+# Files opened relative to a directory handle via the NT API
+$Methods += @{
+    Name = "rmbrr (16 threads, nt backend)"
+    Command = {
+        param($Path, $ThreadCount)
+        & $Rmbrr $Path --threads $ThreadCount --backend nt
+    }.GetNewClosure()
+    Threads = 16
+}
+
 # Other tools (don't support thread configuration)
 $Methods += @(
     @{
@@ -193,7 +227,7 @@ Write-Host ""
 # Find best rmbrr time (for comparison baseline)
 $RmbrrTime = $null
 foreach ($Line in $Results) {
-    if ($Line -match "^rmbrr \(.+ threads\): (\d+)ms") {
+    if ($Line -match "^rmbrr \(.+\): (\d+)ms") {
         $CurrentTime = [int]$Matches[1]
         if ($RmbrrTime -eq $null -or $CurrentTime -lt $RmbrrTime) {
             $RmbrrTime = $CurrentTime
@@ -131,6 +131,37 @@ for THREADS in "${THREAD_COUNTS[@]}"; do
     sleep 0.5
 done
 
+# Per-parent caps (spinning disks), at a fixed thread count
+PARENT_LIMITS=(1 2 4)
+THREADS=16
+for LIMIT in "${PARENT_LIMITS[@]}"; do
+    NAME="rmbrr (${THREADS} threads, max ${LIMIT}/parent)"
+    TEST_DIR="${TEST_ROOT}/test_rmbrr_parent_${LIMIT}"
+
+    echo -e "${YELLOW}Testing: ${NAME}${NC}"
+    echo -e "${GRAY}  Copying test data...${NC}"
+    cp -r "$SOURCE_DIR" "$TEST_DIR"
+
+    echo -e "${GRAY}  Running deletion...${NC}"
+    START=$(date +%s%3N)
+    "$RMBRR" "$TEST_DIR" --threads "$THREADS" --max-per-parent "$LIMIT" 2>/dev/null || true
+    END=$(date +%s%3N)
+
+    if [ ! -d "$TEST_DIR" ]; then
+        TIME_MS=$((END - START))
+        TIME_SEC=$(echo "scale=3; $TIME_MS / 1000" | bc)
+        echo -e "${GREEN}  ✓ Completed in ${TIME_SEC}s (${TIME_MS}ms)${NC}"
+        RESULTS+=("${NAME}: ${TIME_MS}ms (${TIME_SEC}s)")
+    else
+        echo -e "${RED}  ✗ Failed (directory still exists)${NC}"
+        RESULTS+=("${NAME}: FAILED (incomplete deletion)")
+        rm -rf "$TEST_DIR"
+    fi
+
+    echo ""
+    sleep 0.5
+done
+
 # Test other methods
 declare -A METHODS=(
     ["rm -rf"]="rm -rf"
@@ -186,7 +217,7 @@ echo ""
 # Find best rmbrr time
 RMBRR_TIME=""
 for LINE in "${RESULTS[@]}"; do
-    if [[ "$LINE" =~ ^rmbrr\ \(.+\ threads\):\ ([0-9]+)ms ]]; then
+    if [[ "$LINE" =~ ^rmbrr\ \(.+\):\ ([0-9]+)ms ]]; then
         CURRENT_TIME="${BASH_REMATCH[1]}"
         if [ -z "$RMBRR_TIME" ] || [ "$CURRENT_TIME" -lt "$RMBRR_TIME" ]; then
             RMBRR_TIME="$CURRENT_TIME"
@@ -0,0 +1,47 @@
+#!/usr/bin/env pwsh
+
+# Compare directory enumeration backends on one huge flat directory
+# Usage: ./enum-backends.ps1 [-Entries 100000] [-Runs 5]
+
+param(
+    [int]$Entries = 100000,
+    [int]$Runs = 5
+)
+
+$ErrorActionPreference = "Stop"
+
+$BenchDir = $PSScriptRoot
+$TestDir = Join-Path $BenchDir "test_dirs\flat_$Entries"
+$Rmbrr = Join-Path (Split-Path $BenchDir) "target\release\rmbrr.exe"
+
+if (-not (Test-Path $Rmbrr)) {
+    Write-Host "Building rmbrr in release mode..." -ForegroundColor Yellow
+    Push-Location (Split-Path $BenchDir)
+    cargo build --release
+    Pop-Location
+}
+
+if (-not (Test-Path $TestDir)) {
+    Write-Host "Creating $Entries files in $TestDir..." -ForegroundColor Yellow
+    New-Item -ItemType Directory -Path $TestDir -Force | Out-Null
+    for ($i = 0; $i -lt $Entries; $i++) {
+        [System.IO.File]::WriteAllBytes((Join-Path $TestDir ("f{0:D7}.txt" -f $i)), @())
+    }
+}
+
+Write-Host "=== Enumeration backends: $Entries entries, $Runs runs each ===" -ForegroundColor Cyan
+
+foreach ($Backend in @("find-first-file", "nt-query")) {
+    $Times = @()
+    for ($r = 0; $r -lt $Runs; $r++) {
+        # Dry run only scans, so the same directory can be reused
+        $Elapsed = Measure-Command { & $Rmbrr -n --enum-backend $Backend $TestDir | Out-Null }
+        $Times += $Elapsed.TotalMilliseconds
+    }
+    $Sorted = $Times | Sort-Object
+    $Median = $Sorted[[math]::Floor($Sorted.Count / 2)]
+    Write-Host ("  {0,-16} median {1,8:N1} ms   (min {2:N1}, max {3:N1})" -f $Backend, $Median, $Sorted[0], $Sorted[-1])
+}
+
+Write-Host ""
+Write-Host "Delete $TestDir when done: rmbrr $TestDir" -ForegroundColor Yellow
@@ -0,0 +1,73 @@
+//! Time deleting a directory's files in listing order against `--delete-order locality`
+//!
+//! Usage: cargo run --release --example delete_order [files...]
+//!
+//! Fills a flat directory under the temp directory with files of 4K
+//! (default 20000), created in an order unrelated to their names so that
+//! listing order and allocation order differ, deletes it with
+//! [`rmbrr::Pipeline`] in each order, and prints the median of several
+//! passes. Alternates orders between passes so drift hits both alike.
+
+use rmbrr::worker::DeleteOrder;
+use rmbrr::Pipeline;
+use std::env;
+use std::fs;
+use std::path::Path;
+use std::time::{Duration, Instant};
+
+const PASSES: usize = 7;
+/// Prime, so stepping by it visits every index of any smaller count once
+const STRIDE: usize = 7919;
+
+fn main() {
+    let mut counts: Vec<usize> = env::args()
+        .skip(1)
+        .map(|arg| arg.parse().expect("file count"))
+        .collect();
+    if counts.is_empty() {
+        counts = vec![20_000];
+    }
+
+    for count in counts {
+        assert!(
+            count % STRIDE != 0,
+            "file count must not be a multiple of {}",
+            STRIDE
+        );
+        let dir = env::temp_dir().join(format!("rmbrr_delete_order_{}", count));
+        println!("=== {} files, median of {} passes ===", count, PASSES);
+        let mut times = [Vec::new(), Vec::new()];
+        for _ in 0..PASSES {
+            for (i, order) in [DeleteOrder::Listing, DeleteOrder::Locality]
+                .into_iter()
+                .enumerate()
+            {
+                fill(&dir, count);
+                times[i].push(delete(&dir, order));
+            }
+        }
+        for (name, mut times) in ["listing", "locality"].into_iter().zip(times) {
+            times.sort();
+            println!("  {:<8} {:>9.2?}", name, times[PASSES / 2]);
+        }
+    }
+}
+
+fn fill(dir: &Path, count: usize) {
+    fs::create_dir_all(dir).unwrap();
+    for i in 0..count {
+        let name = format!("file_{:07}.bin", (i * STRIDE) % count);
+        fs::write(dir.join(name), [0u8; 4096]).unwrap();
+    }
+}
+
+fn delete(dir: &Path, order: DeleteOrder) -> Duration {
+    let start = Instant::now();
+    let report = Pipeline::from_graph([dir.to_path_buf()], [])
+        .unwrap()
+        .order(order)
+        .run()
+        .unwrap();
+    assert!(report.is_complete() && !dir.exists());
+    start.elapsed()
+}
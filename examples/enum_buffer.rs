@@ -0,0 +1,69 @@
+//! Time directory listing with different enumeration buffer sizes
+//!
+//! Usage: cargo run --release --example enum_buffer [entries...] [--backend nt-query]
+//!
+//! Lists flat directories of 1000, 10000 and 100000 files (created once
+//! under the temp directory) in-process, so process start-up doesn't drown
+//! out the difference, and prints the median of several passes per size.
+
+use rmbrr::winapi::{self, EnumBackend};
+use std::env;
+use std::fs::{self, File};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+const SIZES: [usize; 6] = [4 << 10, 16 << 10, 64 << 10, 256 << 10, 1 << 20, 4 << 20];
+const PASSES: usize = 15;
+
+fn main() {
+    let mut counts = Vec::new();
+    let mut args = env::args().skip(1);
+    while let Some(arg) = args.next() {
+        if arg == "--backend" {
+            let backend: EnumBackend = args
+                .next()
+                .expect("--backend needs a value")
+                .parse()
+                .unwrap();
+            winapi::set_enum_backend(backend);
+        } else {
+            counts.push(arg.parse::<usize>().expect("entry count"));
+        }
+    }
+    if counts.is_empty() {
+        counts = vec![1000, 10_000, 100_000];
+    }
+
+    for count in counts {
+        let dir = flat_dir(count);
+        println!("=== {} entries, median of {} passes ===", count, PASSES);
+        for size in SIZES {
+            let mut times: Vec<Duration> = (0..PASSES).map(|_| list(&dir, size)).collect();
+            times.sort();
+            println!("  {:>5}K {:>9.2?}", size >> 10, times[PASSES / 2]);
+        }
+    }
+}
+
+fn flat_dir(count: usize) -> PathBuf {
+    let dir = env::temp_dir().join(format!("rmbrr_enum_buffer_{}", count));
+    if !dir.exists() {
+        fs::create_dir_all(&dir).unwrap();
+        for i in 0..count {
+            File::create(dir.join(format!("file_with_a_longish_name_{:07}.txt", i))).unwrap();
+        }
+    }
+    dir
+}
+
+fn list(dir: &Path, buffer_size: usize) -> Duration {
+    let start = Instant::now();
+    let mut entries = 0;
+    winapi::enumerate_entries_sized(dir, buffer_size, |_, _, _| {
+        entries += 1;
+        Ok(())
+    })
+    .unwrap();
+    assert!(entries > 0);
+    start.elapsed()
+}
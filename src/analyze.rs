@@ -0,0 +1,186 @@
+//! Read-only size breakdowns of a scanned tree
+
+use crate::tree::DirectoryTree;
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Totals for one subtree (the subtree's own directory included)
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SubtreeStats {
+    pub path: PathBuf,
+    pub dirs: usize,
+    pub files: usize,
+    pub bytes: u64,
+}
+
+impl SubtreeStats {
+    pub fn items(&self) -> usize {
+        self.dirs + self.files
+    }
+}
+
+/// Breakdown of a tree produced by [`analyze`]
+#[derive(Debug, Clone, Default)]
+pub struct Analysis {
+    /// Every directory down to the requested depth, largest first
+    pub subtrees: Vec<SubtreeStats>,
+    /// Files directly inside the root
+    pub root_files: SubtreeStats,
+    /// The whole tree, root directory included
+    pub total: SubtreeStats,
+}
+
+/// Total up `tree` per directory, down to `depth` levels below `root`
+///
+/// Like `du --max-depth`, each row is cumulative: a directory's totals
+/// include everything below it. File sizes are read on `threads` threads.
+pub fn analyze(tree: &DirectoryTree, root: &Path, depth: usize, threads: usize) -> Analysis {
+    let sizes = file_sizes(&tree.files, threads.max(1));
+
+    let mut rows: HashMap<PathBuf, SubtreeStats> = HashMap::new();
+    for dir in &tree.dirs {
+        for key in row_keys(dir, root, depth, true) {
+            row(&mut rows, key).dirs += 1;
+        }
+    }
+    let mut root_files = SubtreeStats {
+        path: root.to_path_buf(),
+        ..Default::default()
+    };
+    for (file, &bytes) in tree.files.iter().zip(&sizes) {
+        let keys = row_keys(file, root, depth, false);
+        if keys.is_empty() {
+            root_files.files += 1;
+            root_files.bytes += bytes;
+        }
+        for key in keys {
+            let stats = row(&mut rows, key);
+            stats.files += 1;
+            stats.bytes += bytes;
+        }
+    }
+
+    let total = SubtreeStats {
+        path: root.to_path_buf(),
+        dirs: tree.dirs.len(),
+        files: tree.files.len(),
+        bytes: sizes.iter().sum(),
+    };
+
+    let mut subtrees: Vec<SubtreeStats> = rows.into_values().collect();
+    subtrees.sort_by(|a, b| b.bytes.cmp(&a.bytes).then_with(|| a.path.cmp(&b.path)));
+
+    Analysis {
+        subtrees,
+        root_files,
+        total,
+    }
+}
+
+/// Row for `key`, created empty on first use
+fn row(rows: &mut HashMap<PathBuf, SubtreeStats>, key: PathBuf) -> &mut SubtreeStats {
+    rows.entry(key.clone()).or_insert_with(|| SubtreeStats {
+        path: key,
+        ..Default::default()
+    })
+}
+
+/// Rows `path` counts towards: the directories from one to `depth` levels
+/// below `root` on its way down (itself included, if it is a directory)
+fn row_keys(path: &Path, root: &Path, depth: usize, is_dir: bool) -> Vec<PathBuf> {
+    let Ok(relative) = path.strip_prefix(root) else {
+        return Vec::new();
+    };
+    let mut levels = relative.components().count();
+    if !is_dir {
+        levels = levels.saturating_sub(1);
+    }
+    let mut key = root.to_path_buf();
+    relative
+        .components()
+        .take(levels.min(depth))
+        .map(|component| {
+            key.push(component);
+            key.clone()
+        })
+        .collect()
+}
+
+/// Sizes of `files` in order, read in parallel (0 for unreadable entries)
+fn file_sizes(files: &[PathBuf], threads: usize) -> Vec<u64> {
+    let chunk = files.len().div_ceil(threads).max(1);
+    std::thread::scope(|s| {
+        let handles: Vec<_> = files
+            .chunks(chunk)
+            .map(|chunk| {
+                s.spawn(move || {
+                    chunk
+                        .iter()
+                        .map(|f| fs::symlink_metadata(f).map(|m| m.len()).unwrap_or(0))
+                        .collect::<Vec<u64>>()
+                })
+            })
+            .collect();
+        handles
+            .into_iter()
+            .flat_map(|h| h.join().unwrap_or_default())
+            .collect()
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tree;
+
+    #[test]
+    fn test_analyze_breakdown() {
+        let temp = std::env::temp_dir().join("rmbrr_analyze_test");
+        let _ = fs::remove_dir_all(&temp);
+        fs::create_dir_all(temp.join("big/nested")).unwrap();
+        fs::create_dir_all(temp.join("small")).unwrap();
+        fs::write(temp.join("big/a"), vec![0u8; 100]).unwrap();
+        fs::write(temp.join("big/nested/b"), vec![0u8; 50]).unwrap();
+        fs::write(temp.join("small/c"), vec![0u8; 10]).unwrap();
+        fs::write(temp.join("top"), vec![0u8; 5]).unwrap();
+
+        let tree = tree::discover_tree(&temp).unwrap();
+        let analysis = analyze(&tree, &temp, 1, 2);
+
+        let rows: Vec<(PathBuf, usize, usize, u64)> = analysis
+            .subtrees
+            .iter()
+            .map(|s| (s.path.clone(), s.dirs, s.files, s.bytes))
+            .collect();
+        assert_eq!(
+            rows,
+            vec![
+                (temp.join("big"), 2, 2, 150),
+                (temp.join("small"), 1, 1, 10)
+            ]
+        );
+        assert_eq!(
+            (analysis.root_files.files, analysis.root_files.bytes),
+            (1, 5)
+        );
+        assert_eq!(
+            (
+                analysis.total.dirs,
+                analysis.total.files,
+                analysis.total.bytes
+            ),
+            (4, 4, 165)
+        );
+
+        // One level deeper adds the nested directory as its own row
+        let deeper = analyze(&tree, &temp, 2, 1);
+        assert_eq!(deeper.subtrees.len(), 3);
+        assert!(deeper
+            .subtrees
+            .iter()
+            .any(|s| s.path == temp.join("big/nested") && s.bytes == 50));
+
+        fs::remove_dir_all(&temp).ok();
+    }
+}
@@ -1,10 +1,50 @@
 // Work broker: dependency tracking and work dispatch
 
-use crate::tree::DirectoryTree;
+use crate::journal::Journal;
+use crate::stall::Busy;
+use crate::tree::{DirectoryTree, ListedDir, SkippedEntry};
 use crossbeam_channel::{unbounded, Receiver, Sender};
-use std::collections::HashMap;
-use std::path::PathBuf;
-use std::sync::Mutex;
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering};
+use std::sync::{Arc, Condvar, Mutex, OnceLock, RwLock};
+use std::thread::ThreadId;
+use std::time::{Duration, Instant};
+
+/// Point-in-time view of deletion progress
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, serde::Serialize)]
+pub struct ProgressSnapshot {
+    /// Directories known so far (grows if subtrees are added mid-run)
+    pub dirs_total: usize,
+    /// Directories deleted
+    pub dirs_done: usize,
+    /// Directories intentionally kept because they contain skipped entries
+    pub dirs_kept: usize,
+    /// Files deleted
+    pub files_done: usize,
+    /// Bytes freed by deleted files, where sizes are known
+    pub bytes_done: u64,
+    /// Bytes in files to delete (0 unless the scan collected sizes)
+    pub bytes_total: u64,
+    /// Directories currently being processed by workers
+    pub in_flight: usize,
+}
+
+impl ProgressSnapshot {
+    /// Completion percentage (0-100)
+    ///
+    /// By bytes when file sizes were collected, since a handful of huge files
+    /// can dominate a tree; otherwise by directory count.
+    pub fn percent(&self) -> f64 {
+        if self.bytes_total > 0 {
+            self.bytes_done.min(self.bytes_total) as f64 / self.bytes_total as f64 * 100.0
+        } else if self.dirs_total == 0 {
+            100.0
+        } else {
+            (self.dirs_done + self.dirs_kept) as f64 / self.dirs_total as f64 * 100.0
+        }
+    }
+}
 
 pub struct Broker {
     /// Map: directory -> number of children still pending deletion
@@ -13,15 +53,87 @@ pub struct Broker {
     parent_map: Mutex<HashMap<PathBuf, PathBuf>>,
     /// Channel sender for pushing work to workers (Option so we can drop it)
     work_tx: Mutex<Option<Sender<PathBuf>>>,
-    /// Total directories to process
-    total_dirs: usize,
+    /// Total directories to process (grows when subtrees are added mid-run)
+    total_dirs: AtomicUsize,
     /// Directories completed (atomic counter)
-    completed: std::sync::atomic::AtomicUsize,
+    completed: AtomicUsize,
+    /// Directories that failed to delete
+    failed: AtomicUsize,
+    /// Directories kept on purpose because they contain excluded entries
+    kept: AtomicUsize,
+    /// Directories that are done one way or another (completed, failed, kept or blocked)
+    resolved: AtomicUsize,
+    /// Directories dispatched to workers but not yet resolved
+    in_flight: AtomicUsize,
+    /// Directories not attempted because something inside them failed to delete
+    blocked: Mutex<Vec<PathBuf>>,
+    /// Files deleted by workers
+    files_done: AtomicUsize,
+    /// Bytes freed by workers
+    bytes_done: AtomicU64,
+    /// Bytes in files to delete, if the scan collected sizes
+    bytes_total: AtomicU64,
+    /// Map: directory -> bytes in its files, until a worker has deleted them
+    dir_bytes: Mutex<HashMap<PathBuf, u64>>,
+    /// Map: directory -> entries in it, for directories the scan found large
+    large_dirs: Mutex<HashMap<PathBuf, usize>>,
+    /// Entries the scan decided not to delete
+    excluded: RwLock<HashSet<PathBuf>>,
+    /// Details of excluded entries, for reporting
+    excluded_entries: Mutex<Vec<SkippedEntry>>,
+    /// Directories to keep because they contain excluded entries
+    retained: RwLock<HashSet<PathBuf>>,
+    /// Stopped early by [`Broker::abort`]
+    aborted: AtomicBool,
+    /// Cap on siblings in flight at once, if any
+    throttle: Mutex<Option<Throttle>>,
+    /// Count of progress events so far, for [`Broker::wait_for_progress`]
+    events: AtomicU64,
+    /// Threads waiting on `progressed`; nobody is signalled while there are none
+    waiters: AtomicUsize,
+    /// Held to wait on or signal `progressed`
+    progress_lock: Mutex<()>,
+    /// Signalled on every progress event and when the run ends, while
+    /// anyone waits
+    progressed: Condvar,
+    /// Workers hold off taking on directories while set (see [`Broker::pause`])
+    paused: Mutex<bool>,
+    /// Signalled on resume and abort
+    unpaused: Condvar,
+    /// Where deleted directories are recorded, if anywhere (`--journal`)
+    journal: OnceLock<Arc<Journal>>,
+    /// Bumped on every file settled and directory resolved, for stall alerts
+    activity: AtomicU64,
+    /// Map: worker thread -> (its name, directory it is on, since when)
+    working: Mutex<HashMap<ThreadId, (String, PathBuf, Instant)>>,
+    /// Whether `working` is kept up to date (see [`Broker::track_workers`])
+    tracking: AtomicBool,
+}
+
+/// Per-parent limit on directories in flight (see [`Broker::with_parent_limit`])
+struct Throttle {
+    limit: usize,
+    /// Map: parent -> children dispatched and not yet resolved
+    active: HashMap<PathBuf, usize>,
+    /// Map: parent -> children held back until one of those resolves
+    waiting: HashMap<PathBuf, VecDeque<PathBuf>>,
 }
 
 impl Broker {
     /// Create broker from DirectoryTree, returns (Broker, Sender to drop, Receiver for workers)
     pub fn new(tree: DirectoryTree) -> (Self, Sender<PathBuf>, Receiver<PathBuf>) {
+        Self::with_parent_limit(tree, None)
+    }
+
+    /// Like [`Broker::new`], but with at most `max_per_parent` children of
+    /// any one directory in flight at once
+    ///
+    /// On spinning disks, many workers in sibling directories mostly seek;
+    /// holding extra siblings back spreads the workers across the tree.
+    pub fn with_parent_limit(
+        tree: DirectoryTree,
+        max_per_parent: Option<usize>,
+    ) -> (Self, Sender<PathBuf>, Receiver<PathBuf>) {
         let (tx, rx) = unbounded();
 
         let mut child_counts = HashMap::new();
@@ -40,15 +152,42 @@ impl Broker {
             child_counts: Mutex::new(child_counts),
             parent_map: Mutex::new(parent_map),
             work_tx: Mutex::new(Some(tx.clone())),
-            total_dirs,
-            completed: std::sync::atomic::AtomicUsize::new(0),
+            total_dirs: AtomicUsize::new(total_dirs),
+            completed: AtomicUsize::new(0),
+            failed: AtomicUsize::new(0),
+            kept: AtomicUsize::new(0),
+            resolved: AtomicUsize::new(0),
+            in_flight: AtomicUsize::new(0),
+            blocked: Mutex::new(Vec::new()),
+            files_done: AtomicUsize::new(0),
+            bytes_done: AtomicU64::new(0),
+            bytes_total: AtomicU64::new(tree.total_bytes()),
+            dir_bytes: Mutex::new(tree.dir_bytes),
+            large_dirs: Mutex::new(tree.large_dirs),
+            excluded: RwLock::new(tree.skipped.iter().map(|e| e.path.clone()).collect()),
+            excluded_entries: Mutex::new(tree.skipped),
+            retained: RwLock::new(tree.retained),
+            aborted: AtomicBool::new(false),
+            throttle: Mutex::new(max_per_parent.map(|limit| Throttle {
+                limit: limit.max(1),
+                active: HashMap::new(),
+                waiting: HashMap::new(),
+            })),
+            events: AtomicU64::new(0),
+            waiters: AtomicUsize::new(0),
+            progress_lock: Mutex::new(()),
+            progressed: Condvar::new(),
+            paused: Mutex::new(false),
+            unpaused: Condvar::new(),
+            journal: OnceLock::new(),
+            activity: AtomicU64::new(0),
+            working: Mutex::new(HashMap::new()),
+            tracking: AtomicBool::new(false),
         };
 
         // Push all initial leaves to work queue
         for leaf in tree.leaves {
-            if let Some(ref tx) = *broker.work_tx.lock().unwrap() {
-                tx.send(leaf).ok();
-            }
+            broker.dispatch(leaf);
         }
 
         (broker, tx, rx)
@@ -56,21 +195,84 @@ impl Broker {
 
     /// Mark directory as deleted, update dependency graph, push newly-available parents
     pub fn mark_complete(&self, dir: PathBuf) {
-        // Increment completed counter
-        let completed = self
-            .completed
-            .fetch_add(1, std::sync::atomic::Ordering::SeqCst)
-            + 1;
-
-        // Check if all work is done - if so, close the channel
-        if completed == self.total_dirs {
-            *self.work_tx.lock().unwrap() = None; // Drop sender to close channel
+        self.journal_done(std::slice::from_ref(&dir));
+        self.completed.fetch_add(1, Ordering::SeqCst);
+        self.release_slot(&dir);
+        self.release_parent(&dir);
+        self.finish(1);
+    }
+
+    /// Mark several deleted directories at once
+    ///
+    /// Equivalent to calling [`Broker::mark_complete`] for each, but takes the
+    /// graph locks once per batch instead of once per directory, which
+    /// matters on trees with millions of tiny directories.
+    pub fn mark_complete_batch(&self, dirs: Vec<PathBuf>) {
+        if dirs.is_empty() {
             return;
         }
+        self.journal_done(&dirs);
+        let count = dirs.len();
+        self.completed.fetch_add(count, Ordering::SeqCst);
+        for dir in &dirs {
+            self.release_slot(dir);
+        }
+
+        let mut ready = Vec::new();
+        {
+            let parent_map = self.parent_map.lock().unwrap();
+            let mut counts = self.child_counts.lock().unwrap();
+
+            for dir in &dirs {
+                let Some(parent) = parent_map.get(dir) else {
+                    continue;
+                };
+                if let Some(pending) = counts.get_mut(parent) {
+                    *pending -= 1;
+                    if *pending == 0 {
+                        counts.remove(parent);
+                        ready.push(parent.clone());
+                    }
+                }
+            }
+        }
+
+        for parent in ready {
+            self.dispatch(parent);
+        }
 
+        self.finish_items(count, count);
+    }
+
+    /// Record every directory deleted from now on in `journal`, so an
+    /// interrupted run can resume without them
+    pub fn record_to(&self, journal: Arc<Journal>) {
+        self.journal.set(journal).ok();
+    }
+
+    fn journal_done(&self, dirs: &[PathBuf]) {
+        if let Some(journal) = self.journal.get() {
+            // Missing records only cost a resume some already-deleted directories
+            journal.record_done(dirs).ok();
+        }
+    }
+
+    /// Mark directory as intentionally kept (see [`Broker::is_retained`])
+    ///
+    /// Its parent is released as if it had been deleted; the parent is
+    /// retained too, so it will be kept in turn.
+    pub fn mark_retained(&self, dir: PathBuf) {
+        self.kept.fetch_add(1, Ordering::SeqCst);
+        self.release_slot(&dir);
+        self.release_parent(&dir);
+        self.finish(1);
+    }
+
+    /// One child of `dir`'s parent is resolved; dispatch the parent if it was the last
+    fn release_parent(&self, dir: &Path) {
         let parent = {
             let parent_map = self.parent_map.lock().unwrap();
-            parent_map.get(&dir).cloned()
+            parent_map.get(dir).cloned()
         };
 
         if let Some(parent_path) = parent {
@@ -84,12 +286,472 @@ impl Broker {
                     counts.remove(&parent_path);
                     drop(counts); // Release lock before sending
 
-                    // Send work to channel
-                    if let Some(ref tx) = *self.work_tx.lock().unwrap() {
-                        tx.send(parent_path).ok();
+                    self.dispatch(parent_path);
+                }
+            }
+        }
+    }
+
+    /// Mark directory as failed; its whole ancestor chain is blocked
+    ///
+    /// An ancestor of a directory that still exists can never become empty,
+    /// so instead of attempting it (and recording a redundant "directory not
+    /// empty" failure) it is resolved as blocked.
+    pub fn mark_failed(&self, dir: PathBuf) {
+        self.failed.fetch_add(1, Ordering::SeqCst);
+        self.release_slot(&dir);
+        let blocked_count = self.block_ancestors(&dir);
+        self.finish(1 + blocked_count);
+    }
+
+    /// Mark directory as blocked without attempting it, because an entry
+    /// inside it failed to delete; its ancestors are blocked too
+    pub fn mark_blocked(&self, dir: PathBuf) {
+        self.blocked.lock().unwrap().push(dir.clone());
+        self.release_slot(&dir);
+        let blocked_count = self.block_ancestors(&dir);
+        self.finish(1 + blocked_count);
+    }
+
+    /// Resolve every pending ancestor of `dir` as blocked; returns how many
+    fn block_ancestors(&self, dir: &Path) -> usize {
+        let mut blocked = Vec::new();
+        {
+            let parent_map = self.parent_map.lock().unwrap();
+            let mut counts = self.child_counts.lock().unwrap();
+
+            let mut current = dir;
+            while let Some(parent) = parent_map.get(current) {
+                // Already removed: a sibling's failure blocked this chain
+                if counts.remove(parent).is_none() {
+                    break;
+                }
+                blocked.push(parent.clone());
+                current = parent;
+            }
+        }
+
+        let blocked_count = blocked.len();
+        self.blocked.lock().unwrap().extend(blocked);
+        blocked_count
+    }
+
+    /// Add directories discovered after construction
+    ///
+    /// `subtree` is attached below `parent`, which must be a directory this
+    /// broker hasn't resolved yet (pending, or in flight and about to be
+    /// handed back with [`Broker::defer`]); `None` adds an independent root.
+    /// Totals grow accordingly, so progress never exceeds 100%.
+    pub fn add_subtree(&self, parent: Option<&Path>, subtree: DirectoryTree) {
+        let Some(root) = subtree.dirs.iter().min_by_key(|d| d.components().count()) else {
+            return;
+        };
+        let root = root.clone();
+
+        if !subtree.skipped.is_empty() {
+            let mut excluded = self.excluded.write().unwrap();
+            excluded.extend(subtree.skipped.iter().map(|e| e.path.clone()));
+            self.excluded_entries
+                .lock()
+                .unwrap()
+                .extend(subtree.skipped.iter().cloned());
+
+            // The parent now holds a kept subtree, so it must be kept as well
+            let mut retained = self.retained.write().unwrap();
+            retained.extend(subtree.retained.iter().cloned());
+            if let Some(parent) = parent {
+                self.retain_with_ancestors(&mut retained, parent);
+            }
+        }
+
+        // Grow the total first so nothing below can look finished early
+        self.total_dirs
+            .fetch_add(subtree.dirs.len(), Ordering::SeqCst);
+
+        {
+            let mut parent_map = self.parent_map.lock().unwrap();
+            let mut counts = self.child_counts.lock().unwrap();
+
+            for (dir, children) in &subtree.children {
+                counts.insert(dir.clone(), children.len());
+                for child in children {
+                    parent_map.insert(child.clone(), dir.clone());
+                }
+            }
+
+            if let Some(parent) = parent {
+                parent_map.insert(root, parent.to_path_buf());
+                *counts.entry(parent.to_path_buf()).or_insert(0) += 1;
+            }
+        }
+
+        self.large_dirs.lock().unwrap().extend(subtree.large_dirs);
+        for leaf in subtree.leaves {
+            self.dispatch(leaf);
+        }
+    }
+
+    /// Keep `dir` and every directory above it that this broker tracks
+    fn retain_with_ancestors(&self, retained: &mut HashSet<PathBuf>, dir: &Path) {
+        for ancestor in dir.ancestors() {
+            if !retained.insert(ancestor.to_path_buf())
+                || !self.parent_map.lock().unwrap().contains_key(ancestor)
+            {
+                break;
+            }
+        }
+    }
+
+    /// Create a broker for a tree that is still being scanned
+    ///
+    /// The scan reports each directory with [`Broker::add_scanned_dir`],
+    /// parents before children (see [`crate::tree::stream_tree`]), then
+    /// calls [`Broker::finish_scan`]. Until then the scan counts as a work
+    /// item in flight, so idle workers are never taken for a stall.
+    pub fn streaming(max_per_parent: Option<usize>) -> (Self, Sender<PathBuf>, Receiver<PathBuf>) {
+        let (broker, tx, rx) = Self::with_parent_limit(DirectoryTree::new(), max_per_parent);
+        // The root, known before it is listed
+        broker.total_dirs.store(1, Ordering::SeqCst);
+        broker.in_flight.store(1, Ordering::SeqCst);
+        (broker, tx, rx)
+    }
+
+    /// Add a directory the streaming scan has just listed
+    ///
+    /// Its subdirectories count towards the total right away, so the run
+    /// can't look finished while some are still unlisted; a directory
+    /// without any is dispatched at once.
+    pub fn add_scanned_dir(&self, listed: ListedDir) {
+        let ListedDir {
+            dir,
+            children,
+            skipped,
+            kept,
+            entries,
+            ..
+        } = listed;
+        self.total_dirs.fetch_add(children.len(), Ordering::SeqCst);
+
+        if kept || !skipped.is_empty() {
+            let mut excluded = self.excluded.write().unwrap();
+            excluded.extend(skipped.iter().map(|e| e.path.clone()));
+            self.excluded_entries.lock().unwrap().extend(skipped);
+            self.retain_with_ancestors(&mut self.retained.write().unwrap(), &dir);
+        }
+        if entries >= crate::winapi::LARGE_DIR_ENTRIES {
+            self.large_dirs.lock().unwrap().insert(dir.clone(), entries);
+        }
+
+        if children.is_empty() {
+            self.dispatch(dir);
+            return;
+        }
+        let mut parent_map = self.parent_map.lock().unwrap();
+        let mut counts = self.child_counts.lock().unwrap();
+        for child in children.iter() {
+            parent_map.insert(child.clone(), dir.clone());
+        }
+        counts.insert(dir, children.len());
+    }
+
+    /// Leave `entry`, found in the in-flight directory `dir` during
+    /// deletion, in place, keeping `dir` and the directories above it
+    pub fn exclude_late(&self, dir: &Path, entry: SkippedEntry) {
+        let mut excluded = self.excluded.write().unwrap();
+        excluded.insert(entry.path.clone());
+        self.excluded_entries.lock().unwrap().push(entry);
+        self.retain_with_ancestors(&mut self.retained.write().unwrap(), dir);
+    }
+
+    /// The streaming scan is over: resolve as usual from here on
+    pub fn finish_scan(&self) {
+        self.finish_items(1, 0);
+    }
+
+    /// Hand the in-flight directory `dir` back without resolving it
+    ///
+    /// Used after [`Broker::add_subtree`] gave the directory new children:
+    /// it is dispatched again once they are all deleted.
+    pub fn defer(&self, dir: &Path) {
+        self.release_slot(dir);
+        self.finish(0);
+    }
+
+    /// Send a directory to the workers, or hold it back if its parent
+    /// already has its share of children in flight
+    fn dispatch(&self, dir: PathBuf) {
+        self.in_flight.fetch_add(1, Ordering::SeqCst);
+        if let Some(throttle) = self.throttle.lock().unwrap().as_mut() {
+            let parent = self.parent_map.lock().unwrap().get(&dir).cloned();
+            if let Some(parent) = parent {
+                let active = throttle.active.entry(parent.clone()).or_insert(0);
+                if *active >= throttle.limit {
+                    throttle.waiting.entry(parent).or_default().push_back(dir);
+                    return;
+                }
+                *active += 1;
+            }
+        }
+        self.send(dir);
+    }
+
+    /// `dir` is no longer in flight: pass its slot to a held-back sibling
+    fn release_slot(&self, dir: &Path) {
+        let next = {
+            let mut throttle = self.throttle.lock().unwrap();
+            let Some(throttle) = throttle.as_mut() else {
+                return;
+            };
+            let Some(parent) = self.parent_map.lock().unwrap().get(dir).cloned() else {
+                return;
+            };
+            match throttle
+                .waiting
+                .get_mut(&parent)
+                .and_then(VecDeque::pop_front)
+            {
+                Some(next) => Some(next),
+                None => {
+                    if let Some(active) = throttle.active.get_mut(&parent) {
+                        *active = active.saturating_sub(1);
+                        if *active == 0 {
+                            throttle.active.remove(&parent);
+                        }
                     }
+                    None
                 }
             }
+        };
+        if let Some(next) = next {
+            self.send(next);
+        }
+    }
+
+    fn send(&self, dir: PathBuf) {
+        if let Some(ref tx) = *self.work_tx.lock().unwrap() {
+            tx.send(dir).ok();
+        }
+    }
+
+    /// Account for one finished work item that resolved `count` directories
+    fn finish(&self, count: usize) {
+        self.finish_items(1, count);
+    }
+
+    /// Account for `items` finished work items that resolved `count` directories
+    fn finish_items(&self, items: usize, count: usize) {
+        let resolved = self.resolved.fetch_add(count, Ordering::SeqCst) + count;
+        let in_flight = self.in_flight.fetch_sub(items, Ordering::SeqCst) - items;
+        self.notify_progress();
+
+        if resolved >= self.total_dirs() {
+            self.close();
+        } else if in_flight == 0 {
+            self.handle_stall();
+        }
+    }
+
+    /// Nothing is in flight but directories remain: none of them can ever
+    /// become a leaf, so block them all rather than waiting forever
+    fn handle_stall(&self) {
+        let stranded: Vec<PathBuf> = self
+            .child_counts
+            .lock()
+            .unwrap()
+            .drain()
+            .map(|(dir, _)| dir)
+            .collect();
+
+        self.resolved.fetch_add(stranded.len(), Ordering::SeqCst);
+        self.blocked.lock().unwrap().extend(stranded);
+        self.close();
+    }
+
+    /// Stop dispatching work; workers exit once the queue drains
+    ///
+    /// Used when a worker dies mid-item: its directory (and everything
+    /// above it) would otherwise never resolve and the rest would wait forever.
+    pub fn abort(&self) {
+        self.aborted.store(true, Ordering::SeqCst);
+        self.close();
+        let _paused = self.paused.lock().unwrap();
+        self.unpaused.notify_all();
+    }
+
+    pub fn is_aborted(&self) -> bool {
+        self.aborted.load(Ordering::SeqCst)
+    }
+
+    /// Hold workers before their next directory until [`Broker::resume`]
+    ///
+    /// Directories already being worked on are finished first.
+    pub fn pause(&self) {
+        *self.paused.lock().unwrap() = true;
+    }
+
+    pub fn resume(&self) {
+        *self.paused.lock().unwrap() = false;
+        self.unpaused.notify_all();
+    }
+
+    /// Keep track of what each worker is on, for [`Broker::busy_workers`]
+    ///
+    /// Off unless asked for, as it takes a lock per directory; turn it on
+    /// before the workers start.
+    pub fn track_workers(&self) {
+        self.tracking.store(true, Ordering::Relaxed);
+    }
+
+    /// The calling worker is starting on `dir`
+    pub fn begin_work(&self, dir: &Path) {
+        if !self.tracking.load(Ordering::Relaxed) {
+            return;
+        }
+        let thread = std::thread::current();
+        let name = thread.name().unwrap_or("worker").to_string();
+        self.working
+            .lock()
+            .unwrap()
+            .insert(thread.id(), (name, dir.to_path_buf(), Instant::now()));
+    }
+
+    /// The calling worker has nothing in hand
+    pub fn end_work(&self) {
+        if !self.tracking.load(Ordering::Relaxed) {
+            return;
+        }
+        self.working
+            .lock()
+            .unwrap()
+            .remove(&std::thread::current().id());
+    }
+
+    /// A file was deleted or given up on; too frequent for a progress event
+    pub fn note_file_settled(&self) {
+        self.activity.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Count that moves whenever anything completes
+    pub fn activity(&self) -> u64 {
+        self.activity.load(Ordering::Relaxed)
+    }
+
+    /// What each busy worker is on, the longest busy first; none unless
+    /// [`Broker::track_workers`] was called
+    pub fn busy_workers(&self) -> Vec<Busy> {
+        let mut busy: Vec<Busy> = self
+            .working
+            .lock()
+            .unwrap()
+            .values()
+            .map(|(worker, path, since)| Busy {
+                worker: worker.clone(),
+                path: path.clone(),
+                busy_for: since.elapsed(),
+            })
+            .collect();
+        busy.sort_by_key(|busy| std::cmp::Reverse(busy.busy_for));
+        busy
+    }
+
+    pub fn is_paused(&self) -> bool {
+        *self.paused.lock().unwrap()
+    }
+
+    /// Block while paused; returns at once if the run was aborted
+    pub fn wait_while_paused(&self) {
+        let paused = self.paused.lock().unwrap();
+        drop(
+            self.unpaused
+                .wait_while(paused, |paused| *paused && !self.is_aborted())
+                .unwrap(),
+        );
+    }
+
+    /// Drop the sender so workers exit once the queue drains
+    fn close(&self) {
+        *self.work_tx.lock().unwrap() = None;
+        self.notify_progress();
+    }
+
+    /// Bytes in the files directly inside `dir`, handed out once
+    ///
+    /// 0 if sizes weren't collected or were already claimed on an earlier pass.
+    pub fn take_dir_bytes(&self, dir: &Path) -> u64 {
+        self.dir_bytes.lock().unwrap().remove(dir).unwrap_or(0)
+    }
+
+    /// Entries the scan found in `dir` if it was a large directory, handed
+    /// out once
+    ///
+    /// 0 for other directories or on a later pass.
+    pub fn take_entry_count(&self, dir: &Path) -> usize {
+        self.large_dirs.lock().unwrap().remove(dir).unwrap_or(0)
+    }
+
+    /// Record files deleted by a worker, for progress reporting
+    pub fn record_files_deleted(&self, count: usize, bytes: u64) {
+        self.files_done.fetch_add(count, Ordering::Relaxed);
+        self.bytes_done.fetch_add(bytes, Ordering::Relaxed);
+        self.notify_progress();
+    }
+
+    fn notify_progress(&self) {
+        self.activity.fetch_add(1, Ordering::Relaxed);
+        self.events.fetch_add(1, Ordering::SeqCst);
+        // Taking the lock, only when someone waits, keeps a waiter that has
+        // just seen no change from missing this
+        if self.waiters.load(Ordering::SeqCst) > 0 {
+            let _lock = self.progress_lock.lock().unwrap();
+            self.progressed.notify_all();
+        }
+    }
+
+    /// Block until there is progress past event `seen`, the run is over, or
+    /// `timeout` passes; returns the latest event count
+    ///
+    /// Lets a display redraw on change instead of polling, so short runs
+    /// don't finish unseen between polls.
+    pub fn wait_for_progress(&self, seen: u64, timeout: Duration) -> u64 {
+        self.wait_while(timeout, || {
+            self.events.load(Ordering::SeqCst) == seen && !self.is_over()
+        });
+        self.events.load(Ordering::SeqCst)
+    }
+
+    /// Block until the run is over or `timeout` passes; true if it is over
+    pub fn wait_for_end(&self, timeout: Duration) -> bool {
+        self.wait_while(timeout, || !self.is_over());
+        self.is_over()
+    }
+
+    /// Block on `progressed` while `waiting` holds, for up to `timeout`
+    fn wait_while(&self, timeout: Duration, waiting: impl Fn() -> bool) {
+        self.waiters.fetch_add(1, Ordering::SeqCst);
+        let lock = self.progress_lock.lock().unwrap();
+        drop(
+            self.progressed
+                .wait_timeout_while(lock, timeout, |_| waiting())
+                .unwrap(),
+        );
+        self.waiters.fetch_sub(1, Ordering::SeqCst);
+    }
+
+    /// Finished or aborted: no more progress is coming
+    fn is_over(&self) -> bool {
+        self.is_finished() || self.is_aborted()
+    }
+
+    /// Snapshot of current progress, safe to poll from any thread
+    pub fn progress(&self) -> ProgressSnapshot {
+        ProgressSnapshot {
+            dirs_total: self.total_dirs(),
+            dirs_done: self.completed_count(),
+            dirs_kept: self.kept_count(),
+            files_done: self.files_done.load(Ordering::Relaxed),
+            bytes_done: self.bytes_done.load(Ordering::Relaxed),
+            bytes_total: self.bytes_total.load(Ordering::Relaxed),
+            in_flight: self.in_flight.load(Ordering::Relaxed),
         }
     }
 
@@ -100,12 +762,52 @@ impl Broker {
 
     /// Get number of completed directories
     pub fn completed_count(&self) -> usize {
-        self.completed.load(std::sync::atomic::Ordering::Relaxed)
+        self.completed.load(Ordering::Relaxed)
+    }
+
+    /// Get number of directories that failed to delete
+    pub fn failed_count(&self) -> usize {
+        self.failed.load(Ordering::Relaxed)
+    }
+
+    /// Get number of directories kept because they contain excluded entries
+    pub fn kept_count(&self) -> usize {
+        self.kept.load(Ordering::Relaxed)
+    }
+
+    /// True if `path` was excluded by the scan and must be left alone
+    pub fn is_excluded(&self, path: &Path) -> bool {
+        self.excluded.read().unwrap().contains(path)
+    }
+
+    /// True if `dir` must be kept (not removed) because it contains excluded entries
+    pub fn is_retained(&self, dir: &Path) -> bool {
+        self.retained.read().unwrap().contains(dir)
+    }
+
+    /// Entries excluded by the scan (including subtrees added mid-run)
+    pub fn excluded_entries(&self) -> Vec<SkippedEntry> {
+        self.excluded_entries.lock().unwrap().clone()
+    }
+
+    /// Get number of directories that are completed, failed, kept or blocked
+    pub fn resolved_count(&self) -> usize {
+        self.resolved.load(Ordering::Relaxed)
+    }
+
+    /// True once every directory has been resolved
+    pub fn is_finished(&self) -> bool {
+        self.resolved_count() >= self.total_dirs()
+    }
+
+    /// Directories not attempted because a descendant could not be deleted
+    pub fn blocked_dirs(&self) -> Vec<PathBuf> {
+        self.blocked.lock().unwrap().clone()
     }
 
     /// Get total directories
     pub fn total_dirs(&self) -> usize {
-        self.total_dirs
+        self.total_dirs.load(Ordering::SeqCst)
     }
 }
 
@@ -217,4 +919,361 @@ mod tests {
         // Root now has 0 children, should be removed from counts
         assert_eq!(broker.pending_count(), 0);
     }
+
+    #[test]
+    fn test_broker_failure_blocks_ancestors() {
+        // Tree: root -> a -> {b, c}, root -> d
+        let root = PathBuf::from("/root");
+        let a = PathBuf::from("/root/a");
+        let b = PathBuf::from("/root/a/b");
+        let c = PathBuf::from("/root/a/c");
+        let d = PathBuf::from("/root/d");
+
+        let mut tree = DirectoryTree::new();
+        tree.dirs = vec![root.clone(), a.clone(), b.clone(), c.clone(), d.clone()];
+        tree.leaves = vec![b.clone(), c.clone(), d.clone()];
+
+        let mut children = HashMap::new();
+        children.insert(root.clone(), vec![a.clone(), d.clone()]);
+        children.insert(a.clone(), vec![b.clone(), c.clone()]);
+        tree.children = children;
+
+        let (broker, tx, rx) = Broker::new(tree);
+        drop(tx);
+
+        let mut received = Vec::new();
+        while let Ok(dir) = rx.try_recv() {
+            received.push(dir);
+        }
+        assert_eq!(received.len(), 3);
+
+        broker.mark_failed(b);
+        assert_eq!(broker.blocked_dirs(), vec![a.clone(), root.clone()]);
+        assert!(!broker.is_finished());
+
+        broker.mark_complete(c);
+        broker.mark_complete(d);
+
+        // Nothing else is dispatched, and the channel closes
+        assert!(rx.recv().is_err());
+        assert!(broker.is_finished());
+        assert_eq!(broker.completed_count(), 2);
+        assert_eq!(broker.failed_count(), 1);
+    }
+
+    #[test]
+    fn test_broker_mark_blocked() {
+        // Tree: root -> a (a file inside a failed to delete)
+        let root = PathBuf::from("/root");
+        let a = PathBuf::from("/root/a");
+
+        let mut tree = DirectoryTree::new();
+        tree.dirs = vec![root.clone(), a.clone()];
+        tree.leaves = vec![a.clone()];
+        tree.children.insert(root.clone(), vec![a.clone()]);
+
+        let (broker, tx, rx) = Broker::new(tree);
+        drop(tx);
+        assert_eq!(rx.recv().unwrap(), a);
+
+        broker.mark_blocked(a.clone());
+
+        assert!(rx.recv().is_err());
+        assert!(broker.is_finished());
+        assert_eq!(broker.failed_count(), 0);
+        assert_eq!(broker.blocked_dirs(), vec![a, root]);
+    }
+
+    #[test]
+    fn test_broker_closes_channel_when_done() {
+        let root = PathBuf::from("/root");
+        let a = PathBuf::from("/root/a");
+
+        let mut tree = DirectoryTree::new();
+        tree.dirs = vec![root.clone(), a.clone()];
+        tree.leaves = vec![a.clone()];
+        tree.children.insert(root.clone(), vec![a.clone()]);
+
+        let (broker, tx, rx) = Broker::new(tree);
+        drop(tx);
+
+        assert_eq!(rx.recv().unwrap(), a);
+        broker.mark_complete(a);
+        assert_eq!(rx.recv().unwrap(), root);
+        broker.mark_complete(root);
+
+        assert!(rx.recv().is_err());
+        assert!(broker.is_finished());
+        assert!(broker.blocked_dirs().is_empty());
+    }
+
+    #[test]
+    fn test_broker_wait_for_progress() {
+        let root = PathBuf::from("/root");
+        let a = PathBuf::from("/root/a");
+
+        let mut tree = DirectoryTree::new();
+        tree.dirs = vec![root.clone(), a.clone()];
+        tree.leaves = vec![a.clone()];
+        tree.children.insert(root.clone(), vec![a.clone()]);
+
+        let (broker, tx, rx) = Broker::new(tree);
+        drop(tx);
+
+        let timeout = Duration::from_secs(30);
+        let started = std::time::Instant::now();
+        let seen = std::thread::scope(|s| {
+            let waiter = s.spawn(|| broker.wait_for_progress(0, timeout));
+            assert_eq!(rx.recv().unwrap(), a);
+            broker.mark_complete(a);
+            waiter.join().unwrap()
+        });
+        assert!(seen > 0);
+        assert!(started.elapsed() < timeout);
+
+        assert_eq!(rx.recv().unwrap(), root);
+        broker.mark_complete(root);
+        // Over: neither call waits any more
+        assert!(broker.wait_for_end(timeout));
+        broker.wait_for_progress(u64::MAX, timeout);
+        assert!(started.elapsed() < timeout);
+    }
+
+    #[test]
+    fn test_broker_pause_resume() {
+        let mut tree = DirectoryTree::new();
+        tree.dirs = vec![PathBuf::from("/root")];
+        let (broker, _tx, _rx) = Broker::new(tree);
+
+        broker.pause();
+        assert!(broker.is_paused());
+        let resumed = std::sync::atomic::AtomicBool::new(false);
+        std::thread::scope(|s| {
+            let waiter = s.spawn(|| {
+                broker.wait_while_paused();
+                resumed.load(Ordering::SeqCst)
+            });
+            std::thread::sleep(Duration::from_millis(20));
+            resumed.store(true, Ordering::SeqCst);
+            broker.resume();
+            assert!(waiter.join().unwrap());
+        });
+
+        // An abort releases paused workers too
+        broker.pause();
+        broker.abort();
+        broker.wait_while_paused();
+    }
+
+    #[test]
+    fn test_broker_parent_limit() {
+        let root = PathBuf::from("/root");
+        let children: Vec<PathBuf> = ["a", "b", "c"].iter().map(|n| root.join(n)).collect();
+
+        let mut tree = DirectoryTree::new();
+        tree.dirs = vec![root.clone()];
+        tree.dirs.extend(children.iter().cloned());
+        tree.leaves = children.clone();
+        tree.children.insert(root.clone(), children.clone());
+
+        let (broker, tx, rx) = Broker::with_parent_limit(tree, Some(1));
+        drop(tx);
+
+        // One sibling at a time; each completion lets the next one through
+        for child in &children {
+            assert_eq!(&rx.recv().unwrap(), child);
+            assert!(rx.try_recv().is_err());
+            broker.mark_complete(child.clone());
+        }
+        assert_eq!(rx.recv().unwrap(), root);
+        broker.mark_complete(root);
+
+        assert!(rx.recv().is_err());
+        assert!(broker.is_finished());
+    }
+
+    #[test]
+    fn test_broker_add_subtree_under_in_flight_dir() {
+        // Start with a single directory, then discover root/new/deep mid-run
+        let root = PathBuf::from("/root");
+        let new = PathBuf::from("/root/new");
+        let deep = PathBuf::from("/root/new/deep");
+
+        let mut tree = DirectoryTree::new();
+        tree.dirs = vec![root.clone()];
+        tree.leaves = vec![root.clone()];
+
+        let (broker, tx, rx) = Broker::new(tree);
+        drop(tx);
+        assert_eq!(rx.recv().unwrap(), root);
+        assert_eq!(broker.total_dirs(), 1);
+
+        let mut subtree = DirectoryTree::new();
+        subtree.dirs = vec![new.clone(), deep.clone()];
+        subtree.leaves = vec![deep.clone()];
+        subtree.children.insert(new.clone(), vec![deep.clone()]);
+
+        broker.add_subtree(Some(&root), subtree);
+        broker.defer(&root);
+        assert_eq!(broker.total_dirs(), 3);
+
+        assert_eq!(rx.recv().unwrap(), deep);
+        broker.mark_complete(deep);
+        assert_eq!(rx.recv().unwrap(), new);
+        broker.mark_complete(new);
+
+        // Root comes back once its new children are gone
+        assert_eq!(rx.recv().unwrap(), root);
+        broker.mark_complete(root);
+
+        assert!(rx.recv().is_err());
+        assert_eq!(broker.completed_count(), 3);
+        assert!(broker.is_finished());
+    }
+
+    #[test]
+    fn test_broker_complete_batch() {
+        // Tree: root -> {a, b}, a -> c
+        let root = PathBuf::from("/root");
+        let a = PathBuf::from("/root/a");
+        let b = PathBuf::from("/root/b");
+        let c = PathBuf::from("/root/a/c");
+
+        let mut tree = DirectoryTree::new();
+        tree.dirs = vec![root.clone(), a.clone(), b.clone(), c.clone()];
+        tree.leaves = vec![b.clone(), c.clone()];
+        tree.children
+            .insert(root.clone(), vec![a.clone(), b.clone()]);
+        tree.children.insert(a.clone(), vec![c.clone()]);
+
+        let (broker, tx, rx) = Broker::new(tree);
+        drop(tx);
+        assert_eq!(rx.recv().unwrap(), b);
+        assert_eq!(rx.recv().unwrap(), c);
+
+        broker.mark_complete_batch(vec![b, c]);
+        assert_eq!(broker.completed_count(), 2);
+        assert_eq!(broker.progress().in_flight, 1);
+
+        // Root still waits for a, which the batch made ready
+        assert_eq!(rx.recv().unwrap(), a);
+        assert!(rx.try_recv().is_err());
+
+        broker.mark_complete_batch(vec![a]);
+        assert_eq!(rx.recv().unwrap(), root);
+        broker.mark_complete_batch(vec![root]);
+
+        assert!(rx.recv().is_err());
+        assert!(broker.is_finished());
+    }
+
+    #[test]
+    fn test_broker_progress_snapshot() {
+        let root = PathBuf::from("/root");
+        let a = PathBuf::from("/root/a");
+        let b = PathBuf::from("/root/b");
+
+        let mut tree = DirectoryTree::new();
+        tree.dirs = vec![root.clone(), a.clone(), b.clone()];
+        tree.leaves = vec![a.clone(), b.clone()];
+        tree.children
+            .insert(root.clone(), vec![a.clone(), b.clone()]);
+
+        let (broker, _tx, _rx) = Broker::new(tree);
+
+        let progress = broker.progress();
+        assert_eq!(progress.dirs_total, 3);
+        assert_eq!(progress.dirs_done, 0);
+        assert_eq!(progress.in_flight, 2);
+
+        broker.record_files_deleted(5, 1024);
+        broker.mark_complete(a);
+
+        let progress = broker.progress();
+        assert_eq!(progress.dirs_done, 1);
+        assert_eq!(progress.files_done, 5);
+        assert_eq!(progress.bytes_done, 1024);
+        assert_eq!(progress.in_flight, 1);
+        assert!((progress.percent() - 100.0 / 3.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_take_entry_count() {
+        let root = PathBuf::from("/root");
+        let mut tree = DirectoryTree::new();
+        tree.dirs = vec![root.clone()];
+        tree.leaves = vec![root.clone()];
+        tree.large_dirs.insert(root.clone(), 50_000);
+
+        let (broker, _tx, _rx) = Broker::new(tree);
+        assert_eq!(broker.take_entry_count(&root), 50_000);
+        assert_eq!(broker.take_entry_count(&root), 0);
+        assert_eq!(broker.take_entry_count(Path::new("/other")), 0);
+    }
+
+    #[test]
+    fn test_streaming_broker() {
+        let root = PathBuf::from("/root");
+        let a = PathBuf::from("/root/a");
+        let b = PathBuf::from("/root/b");
+        let listed = |dir: &PathBuf, children: Vec<PathBuf>| ListedDir {
+            dir: dir.clone(),
+            children,
+            files: 0,
+            skipped: Vec::new(),
+            kept: false,
+            entries: 0,
+        };
+
+        let (broker, tx, rx) = Broker::streaming(None);
+        drop(tx);
+        broker.add_scanned_dir(listed(&root, vec![a.clone(), b.clone()]));
+        assert_eq!(broker.total_dirs(), 3);
+        assert!(rx.try_recv().is_err());
+
+        // Leaves go out as soon as they are listed
+        broker.add_scanned_dir(listed(&a, vec![]));
+        assert_eq!(rx.recv().unwrap(), a);
+
+        // Nothing in flight while b is unlisted: not a stall, and not done
+        broker.mark_failed(a);
+        assert_eq!(broker.blocked_dirs(), vec![root.clone()]);
+        assert!(!broker.is_finished());
+
+        broker.add_scanned_dir(listed(&b, vec![]));
+        assert_eq!(rx.recv().unwrap(), b);
+        broker.mark_complete(b);
+        broker.finish_scan();
+        assert!(broker.is_finished());
+        assert!(rx.recv().is_err());
+    }
+
+    #[test]
+    fn test_progress_by_bytes() {
+        let root = PathBuf::from("/root");
+        let small = PathBuf::from("/root/small");
+        let huge = PathBuf::from("/root/huge");
+
+        let mut tree = DirectoryTree::new();
+        tree.dirs = vec![root.clone(), small.clone(), huge.clone()];
+        tree.leaves = vec![small.clone(), huge.clone()];
+        tree.children
+            .insert(root.clone(), vec![small.clone(), huge.clone()]);
+        tree.dir_bytes.insert(small.clone(), 100);
+        tree.dir_bytes.insert(huge.clone(), 9_900);
+
+        let (broker, _tx, _rx) = Broker::new(tree);
+        assert_eq!(broker.progress().bytes_total, 10_000);
+
+        // Sizes are handed out once per directory
+        let bytes = broker.take_dir_bytes(&small);
+        assert_eq!(bytes, 100);
+        assert_eq!(broker.take_dir_bytes(&small), 0);
+        broker.record_files_deleted(1, bytes);
+        broker.mark_complete(small);
+
+        // A third of the directories, but only 1% of the bytes
+        let progress = broker.progress();
+        assert!((progress.percent() - 1.0).abs() < 1e-9);
+    }
 }
@@ -0,0 +1,110 @@
+//! Failure injection for trying out error handling on healthy trees
+//!
+//! Only compiled in debug builds or with the `chaos` feature; otherwise
+//! [`inject`] is a no-op the optimizer removes.
+
+use std::time::Duration;
+
+/// What to inject into delete operations
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ChaosConfig {
+    /// Share of operations that fail, in percent (0-100)
+    pub fail_percent: u8,
+    /// Upper bound on the random delay added before each operation
+    pub max_delay: Duration,
+}
+
+#[cfg(any(debug_assertions, feature = "chaos"))]
+mod enabled {
+    use super::ChaosConfig;
+    use std::cell::Cell;
+    use std::collections::hash_map::RandomState;
+    use std::hash::BuildHasher;
+    use std::io;
+    use std::sync::OnceLock;
+    use std::time::Duration;
+
+    static CONFIG: OnceLock<ChaosConfig> = OnceLock::new();
+
+    thread_local! {
+        static RNG: Cell<u64> = Cell::new(RandomState::new().hash_one(0u8) | 1);
+    }
+
+    /// Turn on injection for the rest of the process (first call wins)
+    pub fn configure(config: ChaosConfig) {
+        let _ = CONFIG.set(config);
+    }
+
+    /// Maybe sleep, then maybe fail, as configured
+    pub fn inject() -> io::Result<()> {
+        let Some(config) = CONFIG.get() else {
+            return Ok(());
+        };
+        if !config.max_delay.is_zero() {
+            let nanos = config.max_delay.as_nanos() as u64;
+            std::thread::sleep(Duration::from_nanos(next() % (nanos + 1)));
+        }
+        if next() % 100 < u64::from(config.fail_percent) {
+            return Err(failure(next()));
+        }
+        Ok(())
+    }
+
+    /// xorshift64: plenty for picking victims, and no dependency
+    fn next() -> u64 {
+        RNG.with(|rng| {
+            let mut x = rng.get();
+            x ^= x << 13;
+            x ^= x >> 7;
+            x ^= x << 17;
+            rng.set(x);
+            x
+        })
+    }
+
+    /// An injected error, alternating between retried and permanent kinds
+    pub(super) fn failure(roll: u64) -> io::Error {
+        let kind = if roll.is_multiple_of(2) {
+            io::ErrorKind::ResourceBusy
+        } else {
+            io::ErrorKind::PermissionDenied
+        };
+        io::Error::new(kind, "injected by --chaos")
+    }
+}
+
+#[cfg(any(debug_assertions, feature = "chaos"))]
+pub use enabled::{configure, inject};
+
+/// Injection is compiled out of this build
+#[cfg(not(any(debug_assertions, feature = "chaos")))]
+pub fn configure(_config: ChaosConfig) {}
+
+#[cfg(not(any(debug_assertions, feature = "chaos")))]
+#[inline(always)]
+pub fn inject() -> std::io::Result<()> {
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_unconfigured_is_noop() {
+        // Tests never configure chaos, or other tests' deletes would fail
+        for _ in 0..1000 {
+            assert!(inject().is_ok());
+        }
+    }
+
+    #[cfg(any(debug_assertions, feature = "chaos"))]
+    #[test]
+    fn test_failure_kinds() {
+        use std::io;
+
+        let errors = [enabled::failure(0), enabled::failure(1)];
+        assert_eq!(errors[0].kind(), io::ErrorKind::ResourceBusy);
+        assert_eq!(errors[1].kind(), io::ErrorKind::PermissionDenied);
+    }
+}
@@ -0,0 +1,582 @@
+//! Defaults from config files, for the same policy without long command lines
+//!
+//! The user's `config.toml` (`RMBRR_CONFIG` if set, else in
+//! `%APPDATA%\rmbrr` on Windows, `$XDG_CONFIG_HOME/rmbrr` or
+//! `~/.config/rmbrr` elsewhere) is read first, then the `.rmbrr.toml`
+//! nearest the working directory. Each turns into command-line arguments
+//! placed before the real ones, so the command line wins for single values
+//! and adds to lists:
+//!
+//! ```toml
+//! threads = 8
+//! protect = ["/srv/releases", "/srv/shares/*"]  # refuse these, like system directories
+//! allow = ["/srv/build/*"]                # --allow
+//! exclude = ["*.keep"]                    # --exclude
+//! exclude_dir_names = [".git"]            # --exclude-dir-names
+//! flags = ["--stall-after", "5m"]         # any other arguments
+//! ```
+//!
+//! Only this much TOML is understood: `key = value` with integers, strings
+//! (`"..."` with escapes or literal `'...'`) and arrays of strings, and `#`
+//! comments. Relative paths, in `protect`, `allow` and the [`PATH_FLAGS`]
+//! in `flags`, are relative to the file.
+//!
+//! `flags` may only hold options: targets and subcommands belong on the
+//! command line. A `.rmbrr.toml` comes with whatever checkout rmbrr runs
+//! in, so it may not set `allow`, and its `flags` may only be
+//! [`LOCAL_FLAGS`]. `protect` lists stay in force under `--no-config`.
+
+use std::env;
+use std::ffi::OsString;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// Name of the repo-local config file
+pub const LOCAL_FILE: &str = ".rmbrr.toml";
+
+/// Options a repo-local file may set: ones that only change how targets
+/// are deleted and reported
+///
+/// Anything that weakens the safety checks or acts beyond the target
+/// (`--force`, `--resume`, `--manifest-out`, `--stage`, ...) is left to the
+/// command line and the user's own config file.
+pub const LOCAL_FLAGS: &[&str] = &[
+    "-t",
+    "--threads",
+    "--max-per-parent",
+    "-n",
+    "--dry-run",
+    "-v",
+    "--verbose",
+    "--ignore-errors",
+    "--strict",
+    "-c",
+    "--confirm",
+    "--stats",
+    "--json",
+    "--report-failures",
+    "--show-failures",
+    "--sort-failures",
+    "--missing-ok",
+    "--trash",
+    "--largest-first",
+    "--estimate",
+    "--keep-from",
+    "--keep-root",
+    "--rename-first",
+    "--stream",
+    "--exclude-dir-names",
+    "--include",
+    "--exclude",
+    "--older-than",
+    "--larger-than",
+    "--smaller-than",
+    "--min-depth",
+    "--max-depth",
+    "--root-must-match",
+    "--max-path-length",
+    "--list",
+    "--list-sizes",
+    "--protect",
+    "--no-follow",
+    "--heartbeat",
+    "--stall-after",
+    "--byte-progress",
+    "--free-at-least",
+    "--when-idle",
+    "--retries",
+    "--retry-delay",
+    "--retry-on",
+    "--backend",
+    "--delete-order",
+    "--enum-backend",
+    "--enum-buffer",
+    "--close-threads",
+    "--clear-attributes",
+];
+
+/// Options in `flags` taking a path, resolved against the file's directory
+pub const PATH_FLAGS: &[&str] = &[
+    "--protect",
+    "--allow",
+    "--keep-from",
+    "--stage",
+    "--manifest-out",
+    "--diff",
+    "--journal",
+    "--resume",
+];
+
+/// A config file that applies to this run
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Source {
+    pub file: PathBuf,
+    /// The repo-local file rather than the user's
+    pub local: bool,
+    pub config: Config,
+}
+
+/// Settings from one config file
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Config {
+    pub threads: Option<usize>,
+    /// Directories never deleted, nor any target holding them
+    pub protect: Vec<PathBuf>,
+    /// Targets deleted without `--force` or double-run warnings
+    pub allow: Vec<PathBuf>,
+    /// Globs of entries to keep
+    pub exclude: Vec<String>,
+    /// Names of directories to keep at any depth
+    pub exclude_dir_names: Vec<String>,
+    /// Further arguments, as given on the command line
+    pub flags: Vec<String>,
+    /// Directory the [`PATH_FLAGS`] in `flags` are relative to
+    pub dir: PathBuf,
+}
+
+/// A config value
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Value {
+    Integer(i64),
+    String(String),
+    Array(Vec<String>),
+}
+
+impl Config {
+    /// Parse a config file's text; errors name the line
+    pub fn parse(text: &str) -> Result<Self, String> {
+        let mut config = Config::default();
+        let mut parser = Parser { text, pos: 0 };
+        while let Some(line) = parser.next_key_line() {
+            let at = |e: String| format!("line {}: {}", line, e);
+            let key = parser.key().map_err(at)?;
+            parser.expect('=').map_err(at)?;
+            let value = parser.value().map_err(at)?;
+            parser.end_of_line().map_err(at)?;
+            config.set(&key, value).map_err(at)?;
+        }
+        Ok(config)
+    }
+
+    /// Read `file`, resolving relative `protect` and `allow` paths, and
+    /// later those in `flags`, against its directory (`None` if there is no
+    /// such file)
+    pub fn load(file: &Path) -> Result<Option<Self>, String> {
+        let text = match fs::read_to_string(file) {
+            Ok(text) => text,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(format!("{}: {}", file.display(), e)),
+        };
+        let mut config = Self::parse(&text).map_err(|e| format!("{}: {}", file.display(), e))?;
+        let dir = file.parent().unwrap_or(Path::new(""));
+        for path in config.protect.iter_mut().chain(&mut config.allow) {
+            if path.is_relative() {
+                *path = dir.join(&path);
+            }
+        }
+        config.dir = dir.to_path_buf();
+        Ok(Some(config))
+    }
+
+    /// Command-line arguments with the same effect
+    pub fn args(&self) -> Vec<OsString> {
+        let mut args = Vec::new();
+        if let Some(threads) = self.threads {
+            args.push("--threads".into());
+            args.push(threads.to_string().into());
+        }
+        for path in &self.protect {
+            args.push("--protect".into());
+            args.push(path.into());
+        }
+        for path in &self.allow {
+            args.push("--allow".into());
+            args.push(path.into());
+        }
+        for glob in &self.exclude {
+            args.push("--exclude".into());
+            args.push(glob.into());
+        }
+        for name in &self.exclude_dir_names {
+            args.push("--exclude-dir-names".into());
+            args.push(name.into());
+        }
+        let mut flags = self.flags.iter();
+        while let Some(flag) = flags.next() {
+            match flag.split_once('=') {
+                Some((name, path)) if PATH_FLAGS.contains(&name) => {
+                    let mut arg = OsString::from(format!("{}=", name));
+                    arg.push(self.dir.join(path));
+                    args.push(arg);
+                }
+                None if PATH_FLAGS.contains(&flag.as_str()) => {
+                    args.push(flag.into());
+                    args.extend(flags.next().map(|path| self.dir.join(path).into()));
+                }
+                _ => args.push(flag.into()),
+            }
+        }
+        args
+    }
+
+    /// Refuse `--` in `flags`, and from a repo-local file (`local`), `allow`
+    /// and any option not in [`LOCAL_FLAGS`]
+    pub fn check(&self, local: bool) -> Result<(), String> {
+        if self.flags.iter().any(|flag| flag == "--") {
+            return Err("'--' is not allowed in flags".to_string());
+        }
+        if local && !self.allow.is_empty() {
+            return Err("allow is only accepted in the user's config file".to_string());
+        }
+        // Whatever doesn't start with '-' is an option's value
+        let refused = self.flags.iter().find(|flag| {
+            let name = flag.split_once('=').map_or(flag.as_str(), |(name, _)| name);
+            name.starts_with('-') && !LOCAL_FLAGS.contains(&name)
+        });
+        match refused {
+            Some(flag) if local => Err(format!(
+                "{} is only accepted on the command line or in the user's config file",
+                flag
+            )),
+            _ => Ok(()),
+        }
+    }
+
+    fn set(&mut self, key: &str, value: Value) -> Result<(), String> {
+        match (key, value) {
+            ("threads", Value::Integer(n)) if n > 0 => self.threads = Some(n as usize),
+            ("threads", _) => return Err("threads must be a positive integer".to_string()),
+            ("protect", Value::Array(paths)) => {
+                self.protect = paths.into_iter().map(PathBuf::from).collect()
+            }
+            ("allow", Value::Array(paths)) => {
+                self.allow = paths.into_iter().map(PathBuf::from).collect()
+            }
+            ("exclude", Value::Array(globs)) => self.exclude = globs,
+            ("exclude_dir_names", Value::Array(names)) => self.exclude_dir_names = names,
+            ("flags", Value::Array(flags)) => self.flags = flags,
+            ("protect" | "allow" | "exclude" | "exclude_dir_names" | "flags", _) => {
+                return Err(format!("{} must be an array of strings", key))
+            }
+            _ => return Err(format!("unknown key '{}'", key)),
+        }
+        Ok(())
+    }
+}
+
+/// The user's and the repo-local config file, in that order
+///
+/// Files that don't exist are passed over.
+pub fn sources() -> Result<Vec<Source>, String> {
+    let cwd = env::current_dir().unwrap_or_default();
+    let files = [(user_file(), false), (local_file(&cwd), true)];
+    let mut sources = Vec::new();
+    for (file, local) in files {
+        let Some(file) = file else { continue };
+        if let Some(config) = Config::load(&file)? {
+            sources.push(Source {
+                file,
+                local,
+                config,
+            });
+        }
+    }
+    Ok(sources)
+}
+
+/// `RMBRR_CONFIG` if set, else `config.toml` in the user's config directory:
+/// `%APPDATA%\rmbrr` on Windows, `$XDG_CONFIG_HOME/rmbrr` or
+/// `~/.config/rmbrr` elsewhere
+pub fn user_file() -> Option<PathBuf> {
+    if let Some(file) = env::var_os("RMBRR_CONFIG") {
+        return Some(PathBuf::from(file));
+    }
+    let dir = if cfg!(windows) {
+        PathBuf::from(env::var_os("APPDATA")?)
+    } else {
+        match env::var_os("XDG_CONFIG_HOME") {
+            Some(config) if !config.is_empty() => PathBuf::from(config),
+            _ => PathBuf::from(env::var_os("HOME")?).join(".config"),
+        }
+    };
+    Some(dir.join("rmbrr").join("config.toml"))
+}
+
+/// The [`LOCAL_FILE`] in `dir` or the nearest of its parents
+pub fn local_file(dir: &Path) -> Option<PathBuf> {
+    dir.ancestors()
+        .map(|dir| dir.join(LOCAL_FILE))
+        .find(|file| file.is_file())
+}
+
+struct Parser<'a> {
+    text: &'a str,
+    pos: usize,
+}
+
+impl Parser<'_> {
+    fn rest(&self) -> &str {
+        &self.text[self.pos..]
+    }
+
+    fn peek(&self) -> Option<char> {
+        self.rest().chars().next()
+    }
+
+    fn line(&self) -> usize {
+        self.text[..self.pos].matches('\n').count() + 1
+    }
+
+    /// Skip spaces, tabs and a comment, and also line breaks if `newlines`
+    fn skip_blank(&mut self, newlines: bool) {
+        while let Some(c) = self.peek() {
+            match c {
+                ' ' | '\t' | '\r' => self.pos += 1,
+                '\n' if newlines => self.pos += 1,
+                '#' => self.pos += self.rest().find('\n').unwrap_or(self.rest().len()),
+                _ => break,
+            }
+        }
+    }
+
+    /// Move to the next `key = value` line, returning its number
+    fn next_key_line(&mut self) -> Option<usize> {
+        self.skip_blank(true);
+        (self.pos < self.text.len()).then(|| self.line())
+    }
+
+    fn key(&mut self) -> Result<String, String> {
+        let len = self
+            .rest()
+            .find(|c: char| !(c.is_ascii_alphanumeric() || c == '_' || c == '-'))
+            .unwrap_or(self.rest().len());
+        if len == 0 {
+            return Err("expected a key".to_string());
+        }
+        let key = self.rest()[..len].to_string();
+        self.pos += len;
+        Ok(key)
+    }
+
+    fn expect(&mut self, c: char) -> Result<(), String> {
+        self.skip_blank(false);
+        if self.peek() != Some(c) {
+            return Err(format!("expected '{}'", c));
+        }
+        self.pos += 1;
+        Ok(())
+    }
+
+    fn end_of_line(&mut self) -> Result<(), String> {
+        self.skip_blank(false);
+        match self.peek() {
+            None | Some('\n') => Ok(()),
+            Some(_) => Err("unexpected text after the value".to_string()),
+        }
+    }
+
+    fn value(&mut self) -> Result<Value, String> {
+        self.skip_blank(false);
+        match self.peek() {
+            Some('"' | '\'') => self.string().map(Value::String),
+            Some('[') => self.array().map(Value::Array),
+            Some(c) if c.is_ascii_digit() || c == '-' || c == '+' => {
+                let len = self
+                    .rest()
+                    .find(|c: char| c.is_whitespace() || c == '#')
+                    .unwrap_or(self.rest().len());
+                let number = self.rest()[..len].replace('_', "");
+                self.pos += len;
+                number
+                    .parse()
+                    .map(Value::Integer)
+                    .map_err(|_| "expected an integer".to_string())
+            }
+            _ => Err("expected a value (integer, string or array)".to_string()),
+        }
+    }
+
+    fn array(&mut self) -> Result<Vec<String>, String> {
+        self.pos += 1;
+        let mut items = Vec::new();
+        loop {
+            self.skip_blank(true);
+            match self.peek() {
+                Some(']') => {
+                    self.pos += 1;
+                    return Ok(items);
+                }
+                Some('"' | '\'') => items.push(self.string()?),
+                _ => return Err("expected a string or ']'".to_string()),
+            }
+            self.skip_blank(true);
+            match self.peek() {
+                Some(',') => self.pos += 1,
+                Some(']') => {}
+                _ => return Err("expected ',' or ']'".to_string()),
+            }
+        }
+    }
+
+    fn string(&mut self) -> Result<String, String> {
+        let quote = self.peek().unwrap_or('"');
+        self.pos += 1;
+        let mut out = String::new();
+        let mut chars = self.rest().char_indices();
+        while let Some((i, c)) = chars.next() {
+            match c {
+                '\n' => break,
+                c if c == quote => {
+                    self.pos += i + 1;
+                    return Ok(out);
+                }
+                '\\' if quote == '"' => {
+                    let escaped = match chars.next().map(|(_, c)| c) {
+                        Some('\\') => '\\',
+                        Some('"') => '"',
+                        Some('n') => '\n',
+                        Some('t') => '\t',
+                        Some('r') => '\r',
+                        Some('u') => {
+                            let hex: String = chars.by_ref().take(4).map(|(_, c)| c).collect();
+                            u32::from_str_radix(&hex, 16)
+                                .ok()
+                                .and_then(char::from_u32)
+                                .ok_or("invalid \\u escape")?
+                        }
+                        _ => return Err("invalid escape (use '...' for backslashes)".to_string()),
+                    };
+                    out.push(escaped);
+                }
+                c => out.push(c),
+            }
+        }
+        Err("unterminated string".to_string())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        let config = Config::parse(
+            "# team defaults\n\
+             threads = 8\n\
+             protect = ['C:\\releases', \"/srv/keep\"]  # never these\n\
+             exclude = [\n  \"*.keep\",\n  \"logs/**\",\n]\n\
+             exclude_dir_names = [\".git\"]\n\
+             flags = [\"--stall-after\", \"5m\"]\n",
+        )
+        .unwrap();
+        assert_eq!(config.threads, Some(8));
+        assert_eq!(
+            config.protect,
+            [PathBuf::from("C:\\releases"), PathBuf::from("/srv/keep")]
+        );
+        assert_eq!(config.exclude, ["*.keep", "logs/**"]);
+        assert_eq!(
+            config.args(),
+            [
+                "--threads",
+                "8",
+                "--protect",
+                "C:\\releases",
+                "--protect",
+                "/srv/keep",
+                "--exclude",
+                "*.keep",
+                "--exclude",
+                "logs/**",
+                "--exclude-dir-names",
+                ".git",
+                "--stall-after",
+                "5m"
+            ]
+        );
+        assert_eq!(Config::parse("").unwrap(), Config::default());
+    }
+
+    #[test]
+    fn test_parse_errors_name_the_line() {
+        let error = |text: &str| Config::parse(text).unwrap_err();
+        assert_eq!(
+            error("threads = 4\nthreds = 4"),
+            "line 2: unknown key 'threds'"
+        );
+        assert!(error("threads = \"4\"").contains("positive integer"));
+        assert!(error("exclude = \"*.log\"").contains("array of strings"));
+        assert!(error("flags = [\"-v\"").starts_with("line 1:"));
+        assert!(error("protect = [\"C:\\data\"]").contains("invalid escape"));
+        assert!(error("threads = 4 5").contains("after the value"));
+    }
+
+    #[test]
+    fn test_check() {
+        let config = |flags: &[&str]| Config {
+            flags: flags.iter().map(|flag| flag.to_string()).collect(),
+            ..Config::default()
+        };
+        assert!(config(&["--stall-after", "5m"]).check(true).is_ok());
+        assert!(config(&["-v", "--", "/elsewhere"]).check(false).is_err());
+        assert!(config(&["-v", "--exclude=*.log", "--retries", "3"])
+            .check(true)
+            .is_ok());
+        for flag in [
+            "--force",
+            "--allow=/srv",
+            "--unlock",
+            "--resume",
+            "--journal",
+            "--manifest-out=/tmp/x",
+            "--stage",
+            "--clean-leftovers",
+            "--diff",
+            "--no-history",
+            "-vf",
+        ] {
+            assert!(config(&[flag]).check(false).is_ok());
+            assert!(config(&[flag]).check(true).unwrap_err().contains(flag));
+        }
+        let allow = Config {
+            allow: vec![PathBuf::from("out")],
+            ..Config::default()
+        };
+        assert!(allow.check(false).is_ok());
+        assert!(allow.check(true).is_err());
+    }
+
+    #[test]
+    fn test_load_resolves_paths_against_the_file() {
+        let temp = env::temp_dir().join("rmbrr_config_test");
+        let _ = fs::remove_dir_all(&temp);
+        fs::create_dir_all(temp.join("repo/sub")).unwrap();
+        let file = temp.join("repo").join(LOCAL_FILE);
+        fs::write(
+            &file,
+            "protect = ['releases']\nallow = ['out/*']\n\
+             flags = ['--keep-from', 'keep.txt', '--manifest-out=/tmp/m.csv', '-v']\n",
+        )
+        .unwrap();
+
+        assert_eq!(local_file(&temp.join("repo/sub")), Some(file.clone()));
+        let config = Config::load(&file).unwrap().unwrap();
+        assert_eq!(config.protect, [temp.join("repo").join("releases")]);
+        assert_eq!(config.allow, [temp.join("repo").join("out/*")]);
+        let keep = temp.join("repo").join("keep.txt");
+        assert_eq!(
+            config.args()[4..],
+            [
+                OsString::from("--keep-from"),
+                keep.into(),
+                "--manifest-out=/tmp/m.csv".into(),
+                "-v".into()
+            ]
+        );
+        assert_eq!(Config::load(&temp.join("missing.toml")), Ok(None));
+
+        fs::remove_dir_all(&temp).ok();
+    }
+}
@@ -0,0 +1,356 @@
+//! High-level API: scan and delete a directory tree in one call
+//!
+//! ```no_run
+//! let report = rmbrr::Deleter::new("target").threads(8).run()?;
+//! println!("{} files deleted", report.files_deleted);
+//! # Ok::<(), rmbrr::error::Error>(())
+//! ```
+
+use crate::cancel::CancelToken;
+use crate::error::{EnumerationWarning, Error, FailedItem};
+use crate::filter::Filters;
+use crate::pipeline::Pipeline;
+use crate::safety;
+use crate::stall::StallHook;
+use crate::tree::{self, ScanOptions, ScanProgress, SkippedEntry};
+use crate::worker::{self, RetryPolicy};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+/// Deletes one directory tree with the same scan/broker/worker pipeline as
+/// the `rmbrr` binary
+///
+/// The same safety checks apply: system directories, drive roots, the home
+/// directory, directories added with [`crate::safety::protect`] and targets
+/// holding any of them are always refused, and the working directory (or
+/// one of its parents) needs [`Deleter::force`].
+#[derive(Debug, Clone)]
+pub struct Deleter {
+    path: PathBuf,
+    threads: Option<usize>,
+    dry_run: bool,
+    force: bool,
+    max_per_parent: Option<usize>,
+    filters: Filters,
+    follow_links: bool,
+    cancel: Option<CancelToken>,
+    retry: RetryPolicy,
+    stall: Option<StallHook>,
+}
+
+/// Outcome of [`Deleter::run`]
+#[derive(Debug, Clone, Default)]
+pub struct Report {
+    /// Directories deleted (for a dry run: that would be deleted)
+    pub dirs_deleted: usize,
+    /// Files deleted (for a dry run: that would be deleted)
+    pub files_deleted: usize,
+    /// Bytes in the files deleted (0 for a dry run)
+    pub bytes_freed: u64,
+    /// Directories kept because they hold skipped entries
+    pub dirs_kept: usize,
+    /// Entries the filters left in place
+    pub skipped: Vec<SkippedEntry>,
+    /// Files and directories that could not be deleted
+    pub failures: Vec<FailedItem>,
+    /// Directories not attempted because something inside them failed
+    pub blocked: Vec<PathBuf>,
+    /// Directories whose contents could not be listed
+    pub warnings: Vec<EnumerationWarning>,
+    pub scan_time: Duration,
+    pub delete_time: Duration,
+}
+
+impl Report {
+    /// Everything that was meant to go is gone
+    pub fn is_complete(&self) -> bool {
+        self.failures.is_empty() && self.blocked.is_empty()
+    }
+}
+
+impl Deleter {
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        Self {
+            path: path.into(),
+            threads: None,
+            dry_run: false,
+            force: false,
+            max_per_parent: None,
+            filters: Filters::new(),
+            follow_links: false,
+            cancel: None,
+            retry: RetryPolicy::default(),
+            stall: None,
+        }
+    }
+
+    /// Number of threads scanning, then deleting (default: logical CPU count)
+    pub fn threads(mut self, threads: usize) -> Self {
+        self.threads = Some(threads.max(1));
+        self
+    }
+
+    /// Scan and count only, deleting nothing
+    pub fn dry_run(mut self, dry_run: bool) -> Self {
+        self.dry_run = dry_run;
+        self
+    }
+
+    /// Allow deleting the working directory or one of its parents
+    pub fn force(mut self, force: bool) -> Self {
+        self.force = force;
+        self
+    }
+
+    /// At most `limit` subdirectories of any one directory in flight at once
+    pub fn max_per_parent(mut self, limit: usize) -> Self {
+        self.max_per_parent = Some(limit);
+        self
+    }
+
+    /// Entries to leave in place
+    pub fn filters(mut self, filters: Filters) -> Self {
+        self.filters = filters;
+        self
+    }
+
+    /// Also delete what symlinks and junctions to directories lead to,
+    /// instead of only unlinking them (see [`tree::FollowedLinks::follow`]);
+    /// without it, a path that is a link itself is refused
+    pub fn follow_links(mut self, follow: bool) -> Self {
+        self.follow_links = follow;
+        self
+    }
+
+    /// Abort the scan (before anything is deleted) once `cancel` is cancelled
+    pub fn cancel(mut self, cancel: CancelToken) -> Self {
+        self.cancel = Some(cancel);
+        self
+    }
+
+    /// How deletes failing because something else has the file open are retried
+    pub fn retry(mut self, retry: RetryPolicy) -> Self {
+        self.retry = retry;
+        self
+    }
+
+    /// Call `hook` when deletion stops making progress (see
+    /// [`Pipeline::on_stall`])
+    pub fn on_stall(mut self, hook: StallHook) -> Self {
+        self.stall = Some(hook);
+        self
+    }
+
+    /// Scan the tree, then delete it unless this is a dry run
+    ///
+    /// Failures to delete individual entries don't make this an error;
+    /// they are listed in the report (see [`Report::is_complete`]).
+    pub fn run(self) -> Result<Report, Error> {
+        let path = self.path;
+        if !path.is_dir() {
+            return Err(Error::InvalidPath {
+                reason: if path.exists() {
+                    "not a directory"
+                } else {
+                    "path does not exist"
+                }
+                .to_string(),
+                path,
+            });
+        }
+        if !self.follow_links && tree::is_link(&path) {
+            return Err(Error::InvalidPath {
+                reason: "is a symlink or junction".to_string(),
+                path,
+            });
+        }
+        check_target(&path, self.force)?;
+
+        let threads = self.threads.unwrap_or_else(|| {
+            std::thread::available_parallelism()
+                .map(|n| n.get())
+                .unwrap_or(4)
+        });
+        let options = ScanOptions {
+            cancel: self.cancel,
+            filters: self.filters,
+            collect_sizes: false,
+            threads,
+            follow_links: self.follow_links,
+        };
+        let scan_start = Instant::now();
+        let tree = tree::discover_tree_with_options(&path, &options, &ScanProgress::new());
+        if options
+            .cancel
+            .as_ref()
+            .is_some_and(CancelToken::is_cancelled)
+        {
+            return Err(Error::ScanCancelled { path });
+        }
+        let mut tree = tree.map_err(|e| Error::io_with_path(path.clone(), e))?;
+        let scan_time = scan_start.elapsed();
+
+        let dirs_kept = tree.retained.len();
+        if self.dry_run || tree.dirs.is_empty() {
+            return Ok(Report {
+                dirs_deleted: tree.dirs.len() - dirs_kept,
+                files_deleted: if self.dry_run { tree.file_count } else { 0 },
+                dirs_kept,
+                skipped: tree.skipped,
+                scan_time,
+                ..Default::default()
+            });
+        }
+
+        let delete_start = Instant::now();
+        let dirs_removed = if worker::is_empty_skeleton(&tree) {
+            worker::remove_empty_dirs(&mut tree)
+        } else {
+            0
+        };
+        if tree.dirs.is_empty() {
+            return Ok(Report {
+                dirs_deleted: dirs_removed,
+                scan_time,
+                delete_time: delete_start.elapsed(),
+                ..Default::default()
+            });
+        }
+
+        let mut pipeline = Pipeline::from_tree(tree)
+            .threads(threads)
+            .force(self.force)
+            .retry(self.retry)
+            .filters(options.filters);
+        if let Some(limit) = self.max_per_parent {
+            pipeline = pipeline.max_per_parent(limit);
+        }
+        if let Some(hook) = self.stall {
+            pipeline = pipeline.on_stall(hook);
+        }
+        let report = pipeline.run()?;
+        Ok(Report {
+            dirs_deleted: dirs_removed + report.dirs_deleted,
+            scan_time,
+            delete_time: delete_start.elapsed(),
+            ..report
+        })
+    }
+}
+
+/// Refuse `path` like the CLI does: if it is protected itself (including
+/// by [`safety::protect`]), or holds a protected directory; `force` only
+/// overrides the working directory check
+pub(crate) fn check_target(path: &Path, force: bool) -> Result<(), Error> {
+    let check = safety::check_path_safety(path);
+    if let Some(code) = check.code().filter(|_| !check.allows(force)) {
+        return Err(Error::Refused {
+            path: path.to_path_buf(),
+            code,
+            reason: check.reason().unwrap_or_default().to_string(),
+        });
+    }
+    if let Some(protected) = safety::protected_paths_within(path).first() {
+        return Err(Error::Refused {
+            path: path.to_path_buf(),
+            code: safety::SafetyCode::Depth,
+            reason: format!("contains protected directory {}", protected.display()),
+        });
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    #[test]
+    fn test_deleter_dry_run_then_delete() {
+        let temp = std::env::temp_dir().join("rmbrr_deleter_test");
+        let _ = fs::remove_dir_all(&temp);
+        fs::create_dir_all(temp.join("a/b")).unwrap();
+        fs::create_dir_all(temp.join("keep")).unwrap();
+        fs::write(temp.join("a/b/file.txt"), "x").unwrap();
+        fs::write(temp.join("keep/file.txt"), "x").unwrap();
+
+        let mut filters = Filters::new();
+        filters.keep(temp.join("keep"));
+
+        let report = Deleter::new(&temp)
+            .filters(filters.clone())
+            .dry_run(true)
+            .run()
+            .unwrap();
+        assert_eq!((report.dirs_deleted, report.files_deleted), (2, 1));
+        assert!(temp.join("a/b/file.txt").exists());
+
+        let report = Deleter::new(&temp)
+            .threads(2)
+            .filters(filters)
+            .run()
+            .unwrap();
+        assert!(report.is_complete());
+        assert_eq!((report.dirs_deleted, report.files_deleted), (2, 1));
+        assert_eq!(report.bytes_freed, 1);
+        assert_eq!(report.dirs_kept, 1);
+        assert_eq!(report.skipped.len(), 1);
+        assert!(!temp.join("a").exists());
+        assert!(temp.join("keep/file.txt").exists());
+
+        fs::remove_dir_all(&temp).ok();
+    }
+
+    #[test]
+    fn test_deleter_refuses_missing_and_system_paths() {
+        let missing = std::env::temp_dir().join("rmbrr_deleter_missing");
+        assert!(matches!(
+            Deleter::new(&missing).run(),
+            Err(Error::InvalidPath { .. })
+        ));
+        #[cfg(unix)]
+        assert!(matches!(
+            Deleter::new("/usr").dry_run(true).run(),
+            Err(Error::Refused {
+                code: crate::safety::SafetyCode::SystemDir,
+                ..
+            })
+        ));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_deleter_unlinks_symlinks_unless_following() {
+        let temp = std::env::temp_dir().join("rmbrr_deleter_links_test");
+        let _ = fs::remove_dir_all(&temp);
+        let (root, store) = (temp.join("node_modules"), temp.join("store"));
+        fs::create_dir_all(root.join("pkg")).unwrap();
+        fs::create_dir_all(&store).unwrap();
+        fs::write(store.join("index.js"), "x").unwrap();
+        std::os::unix::fs::symlink(&store, root.join("pkg/dep")).unwrap();
+
+        let report = Deleter::new(&root).run().unwrap();
+        assert!(report.is_complete());
+        assert_eq!((report.dirs_deleted, report.files_deleted), (2, 1));
+        assert!(!root.exists());
+        assert!(store.join("index.js").exists());
+
+        fs::create_dir_all(root.join("pkg")).unwrap();
+        std::os::unix::fs::symlink(&store, root.join("pkg/dep")).unwrap();
+        let link = temp.join("link");
+        std::os::unix::fs::symlink(&root, &link).unwrap();
+        assert!(matches!(
+            Deleter::new(&link).run(),
+            Err(Error::InvalidPath { .. })
+        ));
+        fs::remove_file(&link).unwrap();
+
+        let report = Deleter::new(&root).follow_links(true).run().unwrap();
+        assert!(report.is_complete());
+        assert_eq!((report.dirs_deleted, report.files_deleted), (3, 1));
+        assert!(!root.exists());
+        assert!(store.exists() && !store.join("index.js").exists());
+
+        fs::remove_dir_all(&temp).ok();
+    }
+}
@@ -1,12 +1,37 @@
 //! Error types for rmbrr
 
+use crate::handles::Holder;
+use crate::safety::SafetyCode;
+use serde::ser::{Serialize, SerializeStruct, Serializer};
 use std::fmt;
 use std::io;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::sync::Arc;
 
 /// Result type for rmbrr operations
 pub type Result<T> = std::result::Result<T, Error>;
 
+/// Exit codes, one per kind of outcome (see [`Error::exit_code`])
+pub const EXIT_SUCCESS: i32 = 0;
+/// Anything not covered below, like a journal that can't be written
+pub const EXIT_ERROR: i32 = 1;
+/// Something was left behind: items failed, or targets failed in
+/// different ways; also warnings with `--strict`
+pub const EXIT_PARTIAL: i32 = 2;
+/// A safety check refused the target
+pub const EXIT_REFUSED: i32 = 3;
+/// The target doesn't exist or can't be deleted as given
+pub const EXIT_INVALID_PATH: i32 = 4;
+/// Stopped by Ctrl+C
+pub const EXIT_INTERRUPTED: i32 = 5;
+/// `--free-at-least` wasn't reached
+pub const EXIT_INSUFFICIENT_SPACE: i32 = 6;
+/// A bug: a worker thread panicked
+pub const EXIT_INTERNAL: i32 = 7;
+/// The command line or a config file is invalid
+pub const EXIT_USAGE: i32 = 64;
+
 /// Main error type for rmbrr
 #[derive(Debug)]
 pub enum Error {
@@ -17,20 +42,475 @@ pub enum Error {
     },
     /// Path validation error
     InvalidPath { path: PathBuf, reason: String },
+    /// Refused by a safety check; `code` says which
+    Refused {
+        path: PathBuf,
+        code: SafetyCode,
+        reason: String,
+    },
+    /// Scan was cancelled before any deletion started
+    ScanCancelled { path: PathBuf },
+    /// Cancelled during a streamed scan (`--stream`), after deletion started
+    StreamCancelled { path: PathBuf, dirs_deleted: usize },
+    /// Cancelled while deleting: directories already being deleted were
+    /// finished, the rest left in place
+    DeleteCancelled {
+        path: PathBuf,
+        dirs_deleted: usize,
+        files_deleted: usize,
+        dirs_left: usize,
+    },
+    /// A worker thread panicked; deletion was stopped early
+    WorkerPanic { worker: usize, payload: String },
+    /// `--free-at-least` goal not reached after deleting every target
+    InsufficientSpace {
+        path: PathBuf,
+        available: u64,
+        wanted: u64,
+    },
     /// Partial deletion failure - some files/dirs failed
     PartialFailure {
         total: usize,
         failed: usize,
         errors: Vec<FailedItem>,
     },
+    /// Nothing failed, but something went wrong along the way (`--strict`)
+    Warnings { count: usize },
 }
 
 /// Represents a single failed file or directory deletion
 #[derive(Debug, Clone)]
 pub struct FailedItem {
     pub path: PathBuf,
-    pub error: String,
+    /// Underlying OS error (shared so failures stay cheap to clone)
+    pub error: Arc<io::Error>,
     pub is_dir: bool,
+    /// Retries attempted before giving up
+    pub retries: u32,
+    /// The entry was EFS-encrypted (Windows)
+    pub encrypted: bool,
+    /// Processes that had it open, for failures in use (see [`crate::handles`])
+    pub holders: Vec<Holder>,
+}
+
+impl FailedItem {
+    pub fn new(path: PathBuf, error: io::Error, is_dir: bool) -> Self {
+        Self {
+            path,
+            error: Arc::new(error),
+            is_dir,
+            retries: 0,
+            encrypted: false,
+            holders: Vec::new(),
+        }
+    }
+
+    pub fn with_retries(mut self, retries: u32) -> Self {
+        self.retries = retries;
+        self
+    }
+
+    pub fn with_encrypted(mut self, encrypted: bool) -> Self {
+        self.encrypted = encrypted;
+        self
+    }
+
+    pub fn with_holders(mut self, holders: Vec<Holder>) -> Self {
+        self.holders = holders;
+        self
+    }
+
+    /// Raw OS error code, if the failure came from the operating system
+    pub fn raw_os_error(&self) -> Option<i32> {
+        self.error.raw_os_error()
+    }
+
+    pub fn category(&self) -> ErrorCategory {
+        match ErrorCategory::of(&self.error) {
+            // Typically someone else's EFS file, which this user can't open
+            ErrorCategory::PermissionDenied if self.encrypted => ErrorCategory::Encrypted,
+            category => category,
+        }
+    }
+
+    /// Short "category, N retries, held by ..." note for failure listings
+    pub fn detail(&self) -> String {
+        let mut detail = match self.retries {
+            0 => self.category().description().to_string(),
+            1 => format!("{}, 1 retry", self.category().description()),
+            n => format!("{}, {} retries", self.category().description(), n),
+        };
+        for (i, holder) in self.holders.iter().enumerate() {
+            detail.push_str(if i == 0 { ", held by " } else { ", " });
+            detail.push_str(&holder.to_string());
+        }
+        detail
+    }
+}
+
+/// Broad kind of a deletion failure, so transient locks stand out from hard errors
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ErrorCategory {
+    PermissionDenied,
+    /// Another process has the entry open or locked
+    InUse,
+    NotFound,
+    NotEmpty,
+    ReadOnly,
+    PathTooLong,
+    /// EFS-encrypted and not accessible to this user
+    Encrypted,
+    Other,
+}
+
+impl ErrorCategory {
+    pub const ALL: [ErrorCategory; 8] = [
+        ErrorCategory::PermissionDenied,
+        ErrorCategory::InUse,
+        ErrorCategory::NotFound,
+        ErrorCategory::NotEmpty,
+        ErrorCategory::ReadOnly,
+        ErrorCategory::PathTooLong,
+        ErrorCategory::Encrypted,
+        ErrorCategory::Other,
+    ];
+
+    pub fn of(error: &io::Error) -> Self {
+        if let Some(category) = error.raw_os_error().and_then(Self::from_os_code) {
+            return category;
+        }
+        match error.kind() {
+            io::ErrorKind::PermissionDenied => ErrorCategory::PermissionDenied,
+            io::ErrorKind::ResourceBusy | io::ErrorKind::ExecutableFileBusy => ErrorCategory::InUse,
+            io::ErrorKind::NotFound => ErrorCategory::NotFound,
+            io::ErrorKind::DirectoryNotEmpty => ErrorCategory::NotEmpty,
+            io::ErrorKind::ReadOnlyFilesystem => ErrorCategory::ReadOnly,
+            io::ErrorKind::InvalidFilename => ErrorCategory::PathTooLong,
+            _ => ErrorCategory::Other,
+        }
+    }
+
+    /// Windows codes that `io::ErrorKind` doesn't distinguish
+    #[cfg(windows)]
+    fn from_os_code(code: i32) -> Option<Self> {
+        match code {
+            // ERROR_SHARING_VIOLATION, ERROR_LOCK_VIOLATION
+            32 | 33 => Some(ErrorCategory::InUse),
+            // ERROR_WRITE_PROTECT
+            19 => Some(ErrorCategory::ReadOnly),
+            // ERROR_FILENAME_EXCED_RANGE
+            206 => Some(ErrorCategory::PathTooLong),
+            // ERROR_ENCRYPTION_FAILED through ERROR_NO_USER_KEYS
+            6000..=6006 => Some(ErrorCategory::Encrypted),
+            _ => None,
+        }
+    }
+
+    #[cfg(not(windows))]
+    fn from_os_code(_code: i32) -> Option<Self> {
+        None
+    }
+
+    pub fn description(self) -> &'static str {
+        match self {
+            ErrorCategory::PermissionDenied => "permission denied",
+            ErrorCategory::InUse => "in use",
+            ErrorCategory::NotFound => "not found",
+            ErrorCategory::NotEmpty => "not empty",
+            ErrorCategory::ReadOnly => "read-only",
+            ErrorCategory::PathTooLong => "path too long",
+            ErrorCategory::Encrypted => "encrypted (EFS)",
+            ErrorCategory::Other => "other",
+        }
+    }
+
+    /// Name as in the JSON report (`permission_denied`, `in_use`, ...)
+    pub fn name(self) -> &'static str {
+        match self {
+            ErrorCategory::PermissionDenied => "permission_denied",
+            ErrorCategory::InUse => "in_use",
+            ErrorCategory::NotFound => "not_found",
+            ErrorCategory::NotEmpty => "not_empty",
+            ErrorCategory::ReadOnly => "read_only",
+            ErrorCategory::PathTooLong => "path_too_long",
+            ErrorCategory::Encrypted => "encrypted",
+            ErrorCategory::Other => "other",
+        }
+    }
+
+    /// The category called `name` (see [`ErrorCategory::name`]); `-` may
+    /// stand for `_`
+    pub fn from_name(name: &str) -> Option<Self> {
+        let name = name.trim().replace('-', "_");
+        Self::ALL
+            .into_iter()
+            .find(|category| category.name() == name)
+    }
+
+    /// Likely to succeed if simply tried again a moment later
+    pub fn is_transient(self) -> bool {
+        self == ErrorCategory::InUse
+    }
+}
+
+/// How much of what went wrong is listed in text output (`--report-failures`)
+///
+/// Only the listing is affected: everything is still counted, returned in
+/// [`Error::PartialFailure`] and included in the JSON report.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord)]
+pub enum ReportLevel {
+    /// Failures that need attention
+    Errors,
+    /// Also directories whose contents couldn't be listed
+    Warnings,
+    /// Also entries something else deleted first
+    #[default]
+    All,
+}
+
+impl ReportLevel {
+    pub const ALL: [ReportLevel; 3] =
+        [ReportLevel::Errors, ReportLevel::Warnings, ReportLevel::All];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            ReportLevel::Errors => "errors",
+            ReportLevel::Warnings => "warnings",
+            ReportLevel::All => "all",
+        }
+    }
+
+    /// Whether `failure` is listed at this level
+    pub fn shows(self, failure: &FailedItem) -> bool {
+        // Gone before we got to it: a race with another deleter, not a problem
+        self == ReportLevel::All || failure.category() != ErrorCategory::NotFound
+    }
+
+    /// Whether enumeration warnings are listed at this level
+    pub fn shows_warnings(self) -> bool {
+        self >= ReportLevel::Warnings
+    }
+}
+
+impl FromStr for ReportLevel {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        ReportLevel::ALL
+            .into_iter()
+            .find(|level| level.name().eq_ignore_ascii_case(s.trim()))
+            .ok_or_else(|| format!("unknown level '{}' (expected errors, warnings or all)", s))
+    }
+}
+
+/// Order failures are listed in text output (`--sort-failures`)
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum FailureOrder {
+    /// As they happened
+    #[default]
+    Found,
+    /// By [`ErrorCategory`]
+    Kind,
+    Path,
+    /// One from each top-level subtree of the target in turn, so the first
+    /// ones listed come from as many subtrees as possible
+    Subtree,
+}
+
+impl FailureOrder {
+    pub const ALL: [FailureOrder; 4] = [
+        FailureOrder::Found,
+        FailureOrder::Kind,
+        FailureOrder::Path,
+        FailureOrder::Subtree,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            FailureOrder::Found => "found",
+            FailureOrder::Kind => "kind",
+            FailureOrder::Path => "path",
+            FailureOrder::Subtree => "subtree",
+        }
+    }
+
+    /// Put `failures` under `root` in this order; ties keep the order found
+    pub fn sort<'a>(self, failures: &mut Vec<&'a FailedItem>, root: &Path) {
+        match self {
+            FailureOrder::Found => {}
+            FailureOrder::Kind => failures.sort_by_key(|failure| {
+                ErrorCategory::ALL
+                    .iter()
+                    .position(|&category| category == failure.category())
+            }),
+            FailureOrder::Path => failures.sort_by(|a, b| a.path.cmp(&b.path)),
+            FailureOrder::Subtree => {
+                // The nth failure of each subtree goes in round n
+                let mut seen: std::collections::HashMap<&Path, usize> = Default::default();
+                let mut rounds: Vec<(usize, &'a FailedItem)> = failures
+                    .drain(..)
+                    .map(|failure| {
+                        let subtree = failure
+                            .path
+                            .strip_prefix(root)
+                            .ok()
+                            .and_then(|relative| relative.components().next())
+                            .map_or(failure.path.as_path(), |first| Path::new(first.as_os_str()));
+                        let round = seen.entry(subtree).or_default();
+                        *round += 1;
+                        (*round, failure)
+                    })
+                    .collect();
+                rounds.sort_by_key(|&(round, _)| round);
+                failures.extend(rounds.into_iter().map(|(_, failure)| failure));
+            }
+        }
+    }
+}
+
+impl FromStr for FailureOrder {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        FailureOrder::ALL
+            .into_iter()
+            .find(|order| order.name().eq_ignore_ascii_case(s.trim()))
+            .ok_or_else(|| {
+                format!(
+                    "unknown order '{}' (expected found, kind, path or subtree)",
+                    s
+                )
+            })
+    }
+}
+
+impl fmt::Display for FailedItem {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let item_type = if self.is_dir { "directory" } else { "file" };
+        write!(
+            f,
+            "failed to delete {} '{}': {}",
+            item_type,
+            self.path.display(),
+            self.error
+        )
+    }
+}
+
+/// Serialized as `{ path, path_raw?, error, os_code, category, retries, is_dir }`
+///
+/// `path` is always a string (lossy if it isn't valid Unicode); `path_raw`
+/// carries the exact bytes (Unix) or UTF-16 units (Windows) only when the
+/// lossy form would not round-trip.
+impl Serialize for FailedItem {
+    fn serialize<S: Serializer>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error> {
+        let mut state = serializer.serialize_struct("FailedItem", 8)?;
+        serialize_path(&mut state, &self.path)?;
+        state.serialize_field("error", &self.error.to_string())?;
+        state.serialize_field("os_code", &self.raw_os_error())?;
+        state.serialize_field("category", &self.category())?;
+        state.serialize_field("retries", &self.retries)?;
+        state.serialize_field("is_dir", &self.is_dir)?;
+        state.serialize_field("held_by", &self.holders)?;
+        state.end()
+    }
+}
+
+/// Write `path` (lossy) and, when the lossy form wouldn't round-trip,
+/// `path_raw` (exact) into a struct being serialized
+pub fn serialize_path<S: SerializeStruct>(
+    state: &mut S,
+    path: &Path,
+) -> std::result::Result<(), S::Error> {
+    state.serialize_field("path", &path.to_string_lossy())?;
+    match raw_path(path) {
+        Some(raw) => state.serialize_field("path_raw", &raw),
+        None => state.skip_field("path_raw"),
+    }
+}
+
+/// Exact on-disk representation of a path that isn't valid Unicode
+#[cfg(unix)]
+pub fn raw_path(path: &Path) -> Option<Vec<u8>> {
+    use std::os::unix::ffi::OsStrExt;
+    path.to_str()
+        .is_none()
+        .then(|| path.as_os_str().as_bytes().to_vec())
+}
+
+/// Exact on-disk representation of a path that isn't valid Unicode
+#[cfg(windows)]
+pub fn raw_path(path: &Path) -> Option<Vec<u16>> {
+    use std::os::windows::ffi::OsStrExt;
+    path.to_str()
+        .is_none()
+        .then(|| path.as_os_str().encode_wide().collect())
+}
+
+#[cfg(not(any(unix, windows)))]
+pub fn raw_path(_path: &Path) -> Option<Vec<u8>> {
+    None
+}
+
+/// A directory whose contents could not be listed during deletion
+///
+/// Not a failure by itself (the directory's own removal is what fails),
+/// but it explains why everything inside was left behind.
+#[derive(Debug, Clone)]
+pub struct EnumerationWarning {
+    pub path: PathBuf,
+    pub error: Arc<io::Error>,
+}
+
+impl EnumerationWarning {
+    pub fn new(path: PathBuf, error: io::Error) -> Self {
+        Self {
+            path,
+            error: Arc::new(error),
+        }
+    }
+
+    pub fn category(&self) -> ErrorCategory {
+        ErrorCategory::of(&self.error)
+    }
+}
+
+impl fmt::Display for EnumerationWarning {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "cannot list directory '{}': {}",
+            self.path.display(),
+            self.error
+        )
+    }
+}
+
+/// Serialized as `{ path, path_raw?, error, os_code, category }`
+impl Serialize for EnumerationWarning {
+    fn serialize<S: Serializer>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error> {
+        let mut state = serializer.serialize_struct("EnumerationWarning", 5)?;
+        serialize_path(&mut state, &self.path)?;
+        state.serialize_field("error", &self.error.to_string())?;
+        state.serialize_field("os_code", &self.error.raw_os_error())?;
+        state.serialize_field("category", &self.category())?;
+        state.end()
+    }
+}
+
+/// Serializable summary of a run's failures
+#[derive(Debug, serde::Serialize)]
+pub struct FailureSummary<'a> {
+    pub total: usize,
+    pub failed: usize,
+    pub failures: &'a [FailedItem],
+}
+
+impl std::error::Error for FailedItem {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        Some(self.error.as_ref())
+    }
 }
 
 impl fmt::Display for Error {
@@ -46,6 +526,61 @@ impl fmt::Display for Error {
             Error::InvalidPath { path, reason } => {
                 write!(f, "Invalid path '{}': {}", path.display(), reason)
             }
+            Error::Refused { path, code, reason } => {
+                write!(
+                    f,
+                    "Refused to delete '{}': {} [{}]",
+                    path.display(),
+                    reason,
+                    code
+                )
+            }
+            Error::ScanCancelled { path } => {
+                write!(
+                    f,
+                    "Cancelled during scan of '{}', nothing deleted",
+                    path.display()
+                )
+            }
+            Error::StreamCancelled { path, dirs_deleted } => {
+                write!(
+                    f,
+                    "Cancelled while scanning and deleting '{}', {} directories already deleted",
+                    path.display(),
+                    dirs_deleted
+                )
+            }
+            Error::DeleteCancelled {
+                path,
+                dirs_deleted,
+                files_deleted,
+                dirs_left,
+            } => {
+                write!(
+                    f,
+                    "Cancelled while deleting '{}': {} directories and {} files deleted, {} directories left",
+                    path.display(),
+                    dirs_deleted,
+                    files_deleted,
+                    dirs_left
+                )
+            }
+            Error::WorkerPanic { worker, payload } => {
+                write!(f, "Worker thread {} panicked: {}", worker, payload)
+            }
+            Error::InsufficientSpace {
+                path,
+                available,
+                wanted,
+            } => {
+                write!(
+                    f,
+                    "Only {} free on the volume holding '{}' after deleting every target ({} wanted)",
+                    crate::units::format_bytes(*available),
+                    path.display(),
+                    crate::units::format_bytes(*wanted)
+                )
+            }
             Error::PartialFailure { total, failed, .. } => {
                 write!(
                     f,
@@ -53,6 +588,9 @@ impl fmt::Display for Error {
                     failed, total
                 )
             }
+            Error::Warnings { count } => {
+                write!(f, "Finished with {} warnings (--strict)", count)
+            }
         }
     }
 }
@@ -61,7 +599,18 @@ impl std::error::Error for Error {
     fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
         match self {
             Error::Io { source, .. } => Some(source),
-            _ => None,
+            Error::InvalidPath { .. }
+            | Error::Refused { .. }
+            | Error::ScanCancelled { .. }
+            | Error::StreamCancelled { .. }
+            | Error::DeleteCancelled { .. }
+            | Error::WorkerPanic { .. }
+            | Error::InsufficientSpace { .. }
+            | Error::Warnings { .. } => None,
+            // The first failure stands in for the rest; all of them are in `errors`
+            Error::PartialFailure { errors, .. } => errors
+                .first()
+                .map(|item| item as &(dyn std::error::Error + 'static)),
         }
     }
 }
@@ -84,12 +633,359 @@ impl Error {
         }
     }
 
+    /// Failure summary for partial failures, suitable for serialization
+    pub fn failure_summary(&self) -> Option<FailureSummary<'_>> {
+        match self {
+            Error::PartialFailure {
+                total,
+                failed,
+                errors,
+            } => Some(FailureSummary {
+                total: *total,
+                failed: *failed,
+                failures: errors,
+            }),
+            _ => None,
+        }
+    }
+
+    /// Which safety check refused the target, if one did
+    pub fn safety_code(&self) -> Option<SafetyCode> {
+        match self {
+            Error::Refused { code, .. } => Some(*code),
+            _ => None,
+        }
+    }
+
+    /// Raw OS error code of the underlying I/O error, if any
+    pub fn raw_os_error(&self) -> Option<i32> {
+        match self {
+            Error::Io { source, .. } => source.raw_os_error(),
+            Error::InvalidPath { .. }
+            | Error::Refused { .. }
+            | Error::ScanCancelled { .. }
+            | Error::StreamCancelled { .. }
+            | Error::DeleteCancelled { .. }
+            | Error::WorkerPanic { .. }
+            | Error::InsufficientSpace { .. }
+            | Error::Warnings { .. } => None,
+            Error::PartialFailure { errors, .. } => {
+                errors.first().and_then(FailedItem::raw_os_error)
+            }
+        }
+    }
+
     /// Get the exit code for this error
     pub fn exit_code(&self) -> i32 {
         match self {
-            Error::Io { .. } => 2,
-            Error::InvalidPath { .. } => 1,
-            Error::PartialFailure { .. } => 1,
+            Error::Io { .. } => EXIT_ERROR,
+            Error::PartialFailure { .. } | Error::Warnings { .. } => EXIT_PARTIAL,
+            Error::Refused { .. } => EXIT_REFUSED,
+            Error::InvalidPath { .. } => EXIT_INVALID_PATH,
+            Error::ScanCancelled { .. }
+            | Error::StreamCancelled { .. }
+            | Error::DeleteCancelled { .. } => EXIT_INTERRUPTED,
+            Error::InsufficientSpace { .. } => EXIT_INSUFFICIENT_SPACE,
+            Error::WorkerPanic { .. } => EXIT_INTERNAL,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::error::Error as _;
+
+    #[test]
+    fn test_io_error_source_chain() {
+        let err = Error::io_with_path(PathBuf::from("/some/dir"), io::Error::from_raw_os_error(2));
+
+        let source = err.source().expect("I/O errors must expose their source");
+        let io_err = source.downcast_ref::<io::Error>().unwrap();
+        assert_eq!(io_err.raw_os_error(), Some(2));
+        assert_eq!(err.raw_os_error(), Some(2));
+    }
+
+    #[test]
+    fn test_partial_failure_source_chain() {
+        let err = Error::PartialFailure {
+            total: 3,
+            failed: 1,
+            errors: vec![FailedItem::new(
+                PathBuf::from("/some/dir/file.txt"),
+                io::Error::from_raw_os_error(13),
+                false,
+            )],
+        };
+
+        let item = err.source().unwrap();
+        assert!(item.downcast_ref::<FailedItem>().is_some());
+
+        let io_err = item.source().unwrap().downcast_ref::<io::Error>().unwrap();
+        assert_eq!(io_err.raw_os_error(), Some(13));
+        assert_eq!(err.raw_os_error(), Some(13));
+    }
+
+    #[test]
+    fn test_failed_item_serialize() {
+        let item = FailedItem::new(
+            PathBuf::from("/some/dir"),
+            io::Error::from_raw_os_error(39),
+            true,
+        );
+
+        let json = serde_json::to_value(&item).unwrap();
+        assert_eq!(json["path"], "/some/dir");
+        assert_eq!(json["os_code"], 39);
+        assert_eq!(json["is_dir"], true);
+        assert!(json.get("path_raw").is_none());
+        assert_eq!(json["error"], item.error.to_string());
+    }
+
+    #[test]
+    fn test_enumeration_warning_serialize() {
+        let warning = EnumerationWarning::new(
+            PathBuf::from("/some/dir"),
+            io::Error::from(io::ErrorKind::PermissionDenied),
+        );
+
+        let json = serde_json::to_value(&warning).unwrap();
+        assert_eq!(json["path"], "/some/dir");
+        assert_eq!(json["category"], "permission_denied");
+        assert!(json["os_code"].is_null());
+        assert!(warning.to_string().starts_with("cannot list directory"));
+    }
+
+    #[test]
+    fn test_error_category() {
+        let category = |kind: io::ErrorKind| ErrorCategory::of(&io::Error::from(kind));
+        assert_eq!(
+            category(io::ErrorKind::PermissionDenied),
+            ErrorCategory::PermissionDenied
+        );
+        assert_eq!(category(io::ErrorKind::ResourceBusy), ErrorCategory::InUse);
+        assert_eq!(category(io::ErrorKind::NotFound), ErrorCategory::NotFound);
+        assert_eq!(category(io::ErrorKind::Other), ErrorCategory::Other);
+        assert!(ErrorCategory::InUse.is_transient());
+        assert!(!ErrorCategory::PermissionDenied.is_transient());
+
+        for category in ErrorCategory::ALL {
+            assert_eq!(serde_json::to_value(category).unwrap(), category.name());
+            assert_eq!(ErrorCategory::from_name(category.name()), Some(category));
         }
+        assert_eq!(
+            ErrorCategory::from_name("in-use"),
+            Some(ErrorCategory::InUse)
+        );
+        assert_eq!(ErrorCategory::from_name("busy"), None);
+
+        let item = FailedItem::new(
+            PathBuf::from("/locked"),
+            io::Error::from(io::ErrorKind::ResourceBusy),
+            false,
+        )
+        .with_retries(2);
+        assert_eq!(item.detail(), "in use, 2 retries");
+
+        let json = serde_json::to_value(&item).unwrap();
+        assert_eq!(json["category"], "in_use");
+        assert_eq!(json["held_by"], serde_json::json!([]));
+        let item = item.with_holders(vec![Holder {
+            pid: 42,
+            name: "WINWORD.EXE".to_string(),
+            started: 0,
+            critical: false,
+        }]);
+        assert_eq!(
+            item.detail(),
+            "in use, 2 retries, held by WINWORD.EXE (pid 42)"
+        );
+        assert_eq!(json["retries"], 2);
+    }
+
+    #[test]
+    fn test_encrypted_failure_category() {
+        let denied = || io::Error::from(io::ErrorKind::PermissionDenied);
+        let item = FailedItem::new(PathBuf::from("/efs"), denied(), false).with_encrypted(true);
+        assert_eq!(item.category(), ErrorCategory::Encrypted);
+        assert_eq!(item.detail(), "encrypted (EFS)");
+        assert_eq!(
+            serde_json::to_value(&item).unwrap()["category"],
+            "encrypted"
+        );
+
+        // Other failures of encrypted files keep their own category
+        let busy = FailedItem::new(
+            PathBuf::from("/efs"),
+            io::Error::from(io::ErrorKind::ResourceBusy),
+            false,
+        )
+        .with_encrypted(true);
+        assert_eq!(busy.category(), ErrorCategory::InUse);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_failed_item_serialize_non_utf8_path() {
+        use std::ffi::OsStr;
+        use std::os::unix::ffi::OsStrExt;
+
+        let path = PathBuf::from(OsStr::from_bytes(b"/tmp/bad\xffname"));
+        let item = FailedItem::new(path, io::Error::other("boom"), false);
+
+        let json = serde_json::to_value(&item).unwrap();
+        assert_eq!(json["path"], "/tmp/bad\u{fffd}name");
+        assert_eq!(
+            json["path_raw"],
+            serde_json::json!(b"/tmp/bad\xffname".to_vec())
+        );
+        assert!(json["os_code"].is_null());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_enumeration_warning_serialize_non_utf8_path() {
+        use std::ffi::OsStr;
+        use std::os::unix::ffi::OsStrExt;
+
+        let path = PathBuf::from(OsStr::from_bytes(b"/tmp/\x80dir"));
+        let warning = EnumerationWarning::new(path, io::Error::other("boom"));
+
+        let json = serde_json::to_value(&warning).unwrap();
+        assert_eq!(json["path"], "/tmp/\u{fffd}dir");
+        assert_eq!(
+            json["path_raw"],
+            serde_json::json!(b"/tmp/\x80dir".to_vec())
+        );
+
+        // Skipped entries used to fail to serialize at all
+        let entry = crate::tree::SkippedEntry {
+            path: PathBuf::from(OsStr::from_bytes(b"/tmp/\x80dir")),
+            is_dir: true,
+            reason: crate::tree::SkipReason::Kept,
+            bytes: 0,
+        };
+        let json = serde_json::to_value(&entry).unwrap();
+        assert_eq!(
+            json["path_raw"],
+            serde_json::json!(b"/tmp/\x80dir".to_vec())
+        );
+        assert_eq!(json["reason"], "kept");
+    }
+
+    #[test]
+    fn test_failure_summary() {
+        let err = Error::PartialFailure {
+            total: 10,
+            failed: 1,
+            errors: vec![FailedItem::new(
+                PathBuf::from("/x"),
+                io::Error::other("boom"),
+                false,
+            )],
+        };
+
+        let json = serde_json::to_value(err.failure_summary().unwrap()).unwrap();
+        assert_eq!(json["total"], 10);
+        assert_eq!(json["failed"], 1);
+        assert_eq!(json["failures"][0]["path"], "/x");
+    }
+
+    #[test]
+    fn test_invalid_path_has_no_source() {
+        let err = Error::InvalidPath {
+            path: PathBuf::from("/missing"),
+            reason: "path does not exist".to_string(),
+        };
+        assert!(err.source().is_none());
+        assert_eq!(err.raw_os_error(), None);
+    }
+
+    #[test]
+    fn test_exit_codes() {
+        let missing = Error::InvalidPath {
+            path: PathBuf::from("/missing"),
+            reason: "path does not exist".to_string(),
+        };
+        let refused = Error::Refused {
+            path: PathBuf::from("/"),
+            code: SafetyCode::VolumeRoot,
+            reason: "protected path".to_string(),
+        };
+        let partial = Error::PartialFailure {
+            total: 2,
+            failed: 1,
+            errors: Vec::new(),
+        };
+        let cancelled = Error::ScanCancelled {
+            path: PathBuf::from("/x"),
+        };
+        let codes: Vec<i32> = [missing, refused, partial, cancelled]
+            .iter()
+            .map(Error::exit_code)
+            .collect();
+        assert_eq!(
+            codes,
+            [
+                EXIT_INVALID_PATH,
+                EXIT_REFUSED,
+                EXIT_PARTIAL,
+                EXIT_INTERRUPTED
+            ]
+        );
+
+        // --strict fails runs that only had warnings, like a partial failure
+        let warnings = Error::Warnings { count: 3 };
+        assert_eq!(warnings.exit_code(), EXIT_PARTIAL);
+        assert_eq!(warnings.to_string(), "Finished with 3 warnings (--strict)");
+    }
+
+    #[test]
+    fn test_report_level() {
+        let gone = FailedItem::new(
+            PathBuf::from("/x"),
+            io::Error::from(io::ErrorKind::NotFound),
+            false,
+        );
+        let denied = FailedItem::new(
+            PathBuf::from("/y"),
+            io::Error::from(io::ErrorKind::PermissionDenied),
+            false,
+        );
+        assert_eq!("Warnings".parse(), Ok(ReportLevel::Warnings));
+        assert!("some".parse::<ReportLevel>().is_err());
+        assert!(ReportLevel::Errors.shows(&denied));
+        assert!(!ReportLevel::Errors.shows(&gone));
+        assert!(!ReportLevel::Warnings.shows(&gone));
+        assert!(ReportLevel::All.shows(&gone));
+        assert!(!ReportLevel::Errors.shows_warnings());
+        assert!(ReportLevel::Warnings.shows_warnings());
+    }
+
+    #[test]
+    fn test_failure_order() {
+        let failure =
+            |path: &str, kind| FailedItem::new(PathBuf::from(path), io::Error::from(kind), false);
+        let items = [
+            failure("/t/a/1", io::ErrorKind::ResourceBusy),
+            failure("/t/a/2", io::ErrorKind::PermissionDenied),
+            failure("/t/a/b/3", io::ErrorKind::ResourceBusy),
+            failure("/t/c/4", io::ErrorKind::ResourceBusy),
+            failure("/t/5", io::ErrorKind::PermissionDenied),
+        ];
+        let sorted = |order: FailureOrder| {
+            let mut failures: Vec<&FailedItem> = items.iter().collect();
+            order.sort(&mut failures, Path::new("/t"));
+            failures
+                .iter()
+                .map(|f| f.path.file_name().unwrap().to_str().unwrap())
+                .collect::<Vec<_>>()
+        };
+        assert_eq!("Subtree".parse(), Ok(FailureOrder::Subtree));
+        assert!("size".parse::<FailureOrder>().is_err());
+        assert_eq!(sorted(FailureOrder::Found), ["1", "2", "3", "4", "5"]);
+        assert_eq!(sorted(FailureOrder::Kind), ["2", "5", "1", "3", "4"]);
+        assert_eq!(sorted(FailureOrder::Path), ["5", "1", "2", "3", "4"]);
+        assert_eq!(sorted(FailureOrder::Subtree), ["1", "4", "5", "2", "3"]);
     }
 }
@@ -0,0 +1,257 @@
+//! Quick projections of a tree's size from a sample of its subdirectories
+
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+/// Directories to sample by default
+pub const DEFAULT_SAMPLE_SIZE: usize = 32;
+
+/// Levels to descend while looking for enough directories to sample from
+const MAX_FRAME_DEPTH: usize = 3;
+
+/// z-score for a 95% confidence interval
+const Z_95: f64 = 1.96;
+
+/// A projected quantity with 95% confidence bounds
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Projection {
+    pub low: f64,
+    pub expected: f64,
+    pub high: f64,
+}
+
+impl Projection {
+    fn exact(value: f64) -> Self {
+        Self {
+            low: value,
+            expected: value,
+            high: value,
+        }
+    }
+}
+
+/// Result of [`estimate`]
+#[derive(Debug, Clone)]
+pub struct Estimate {
+    /// Directories and files below the root
+    pub items: Projection,
+    /// Bytes in files below the root
+    pub bytes: Projection,
+    /// Directories walked in full
+    pub sampled: usize,
+    /// Directories the sample was drawn from
+    pub population: usize,
+    /// Time spent producing the estimate
+    pub elapsed: Duration,
+    /// Items visited per second while sampling
+    pub items_per_sec: f64,
+}
+
+impl Estimate {
+    /// Every directory was walked, so the counts are exact
+    pub fn is_exact(&self) -> bool {
+        self.sampled == self.population
+    }
+
+    /// Rough run time for `items`, from the rate seen while sampling
+    ///
+    /// Deletion touches each item more expensively than listing it but does
+    /// so on every core, which in practice lands near the single-threaded
+    /// listing rate. Treat it as an order of magnitude, not a promise.
+    pub fn duration_for(&self, items: f64) -> Duration {
+        if self.items_per_sec <= 0.0 {
+            return Duration::ZERO;
+        }
+        Duration::from_secs_f64(items / self.items_per_sec)
+    }
+}
+
+/// Items and bytes counted under one directory
+#[derive(Debug, Clone, Copy, Default)]
+struct Tally {
+    items: u64,
+    bytes: u64,
+}
+
+/// Project the size of the tree under `root` without walking all of it
+///
+/// The top of the tree is listed until a level with enough directories to
+/// sample from is found (at most a few levels down); everything above that
+/// level is counted exactly. Up to `sample_size` directories from it are
+/// then walked in full, evenly spaced in name order, and their mean is
+/// scaled up to the rest with a 95% confidence interval. Trees too small to
+/// be worth sampling are simply counted.
+pub fn estimate(root: &Path, sample_size: usize) -> Estimate {
+    let start = Instant::now();
+    let sample_size = sample_size.max(1);
+
+    let mut exact = Tally::default();
+    let mut level = vec![root.to_path_buf()];
+    let mut frame = Vec::new();
+    for depth in 0..MAX_FRAME_DEPTH {
+        let mut next = Vec::new();
+        for dir in &level {
+            list_dir(dir, &mut exact, &mut next);
+        }
+        frame = next;
+        if frame.len() >= sample_size * 4 || depth + 1 == MAX_FRAME_DEPTH {
+            break;
+        }
+        level = std::mem::take(&mut frame);
+        if level.is_empty() {
+            break;
+        }
+    }
+    frame.sort();
+
+    let population = frame.len();
+    let sample: Vec<&PathBuf> = if population <= sample_size {
+        frame.iter().collect()
+    } else {
+        (0..sample_size)
+            .map(|i| &frame[i * population / sample_size])
+            .collect()
+    };
+    let tallies: Vec<Tally> = sample.iter().map(|dir| walk(dir)).collect();
+
+    let mut visited = exact.items;
+    for tally in &tallies {
+        visited += tally.items;
+    }
+    let elapsed = start.elapsed();
+
+    Estimate {
+        items: project(
+            exact.items,
+            &tallies.iter().map(|t| t.items).collect::<Vec<_>>(),
+            population,
+        ),
+        bytes: project(
+            exact.bytes,
+            &tallies.iter().map(|t| t.bytes).collect::<Vec<_>>(),
+            population,
+        ),
+        sampled: tallies.len(),
+        population,
+        elapsed,
+        items_per_sec: visited as f64 / elapsed.as_secs_f64().max(1e-3),
+    }
+}
+
+/// Scale per-directory sample values up to `population` directories
+///
+/// Uses the sample mean with a finite population correction, and never
+/// reports a lower bound below what was actually counted.
+fn project(exact: u64, sample: &[u64], population: usize) -> Projection {
+    let n = sample.len();
+    let sampled_total: u64 = sample.iter().sum();
+    let counted = (exact + sampled_total) as f64;
+    if n == population || n == 0 {
+        return Projection::exact(counted);
+    }
+
+    let n_f = n as f64;
+    let pop = population as f64;
+    let mean = sampled_total as f64 / n_f;
+    let variance = if n > 1 {
+        sample
+            .iter()
+            .map(|&v| (v as f64 - mean).powi(2))
+            .sum::<f64>()
+            / (n_f - 1.0)
+    } else {
+        0.0
+    };
+    let correction = ((pop - n_f) / (pop - 1.0)).sqrt();
+    let margin = Z_95 * pop * (variance / n_f).sqrt() * correction;
+    let expected = exact as f64 + pop * mean;
+
+    Projection {
+        low: (expected - margin).max(counted),
+        expected,
+        high: expected + margin,
+    }
+}
+
+/// Count the entries of `dir` into `tally`, queueing its subdirectories
+fn list_dir(dir: &Path, tally: &mut Tally, subdirs: &mut Vec<PathBuf>) {
+    let Ok(entries) = fs::read_dir(dir) else {
+        return;
+    };
+    for entry in entries.flatten() {
+        tally.items += 1;
+        match entry.file_type() {
+            Ok(kind) if kind.is_dir() => subdirs.push(entry.path()),
+            _ => tally.bytes += entry.metadata().map(|m| m.len()).unwrap_or(0),
+        }
+    }
+}
+
+/// Count everything below `dir` (symlinks are not followed)
+fn walk(dir: &Path) -> Tally {
+    let mut tally = Tally::default();
+    let mut stack = vec![dir.to_path_buf()];
+    while let Some(dir) = stack.pop() {
+        list_dir(&dir, &mut tally, &mut stack);
+    }
+    tally
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn build_tree(base: &Path, dirs: usize, files_per_dir: usize) {
+        let _ = fs::remove_dir_all(base);
+        for d in 0..dirs {
+            let dir = base.join(format!("d{:03}", d)).join("inner");
+            fs::create_dir_all(&dir).unwrap();
+            for f in 0..files_per_dir {
+                fs::write(dir.join(format!("f{}", f)), b"0123456789").unwrap();
+            }
+        }
+        fs::write(base.join("top.txt"), b"abc").unwrap();
+    }
+
+    #[test]
+    fn test_small_tree_is_exact() {
+        let temp = std::env::temp_dir().join("rmbrr_estimate_exact_test");
+        build_tree(&temp, 5, 3);
+
+        let estimate = estimate(&temp, 8);
+        assert!(estimate.is_exact());
+        // 5 top dirs + 5 inner dirs + 15 files + top.txt
+        assert_eq!(estimate.items, Projection::exact(26.0));
+        assert_eq!(estimate.bytes, Projection::exact(153.0));
+
+        fs::remove_dir_all(&temp).ok();
+    }
+
+    #[test]
+    fn test_sampled_tree_bounds_contain_truth() {
+        let temp = std::env::temp_dir().join("rmbrr_estimate_sampled_test");
+        build_tree(&temp, 40, 2);
+
+        let estimate = estimate(&temp, 4);
+        assert!(!estimate.is_exact());
+        assert_eq!(estimate.population, 40);
+        assert_eq!(estimate.sampled, 4);
+        // Uniform tree: the sample mean is the true mean
+        let items = 40.0 * 4.0 + 1.0;
+        assert!(estimate.items.low <= items && items <= estimate.items.high);
+        assert_eq!(estimate.items.expected, items);
+
+        fs::remove_dir_all(&temp).ok();
+    }
+
+    #[test]
+    fn test_project_bounds() {
+        let projection = project(10, &[0, 10, 20, 30], 100);
+        assert_eq!(projection.expected, 10.0 + 100.0 * 15.0);
+        assert!(projection.low < projection.expected);
+        assert!(projection.high > projection.expected);
+        // Never below what was counted
+        assert!(project(10, &[0, 0, 0, 1000], 5).low >= 1010.0);
+    }
+}
@@ -0,0 +1,776 @@
+//! Filters deciding which entries a scan leaves in place
+
+use crate::tree::SkipReason;
+use std::collections::HashSet;
+use std::ffi::OsString;
+use std::fs;
+use std::io;
+use std::path::{Component, Path, PathBuf};
+use std::time::SystemTime;
+
+/// Entries to leave in place while everything around them is deleted
+#[derive(Debug, Clone, Default)]
+pub struct Filters {
+    /// Paths preserved together with everything below them
+    keep: HashSet<PathBuf>,
+    /// Directory base names (e.g. `.git`) preserved wherever they appear
+    exclude_dir_names: HashSet<OsString>,
+    /// Protected directories preserved together with everything below them
+    protected: HashSet<PathBuf>,
+    /// Files the run itself writes, left until it ends
+    own_files: HashSet<PathBuf>,
+    /// `--include` / `--exclude` patterns, if any were given
+    globs: Option<GlobFilters>,
+    /// Entries modified after this are preserved (`--older-than`)
+    keep_newer_than: Option<SystemTime>,
+    /// Only files larger than this are deleted (`--larger-than`)
+    larger_than: Option<u64>,
+    /// Only files smaller than this are deleted (`--smaller-than`)
+    smaller_than: Option<u64>,
+    /// `--min-depth` / `--max-depth`, if either was given
+    depths: Option<DepthLimits>,
+}
+
+/// Glob patterns matched against paths relative to the scan root
+#[derive(Debug, Clone)]
+struct GlobFilters {
+    root: PathBuf,
+    /// Only entries matching one of these (or inside a directory that
+    /// does) are deleted; empty means everything is
+    include: Vec<Glob>,
+    /// Entries matching any of these are preserved
+    exclude: Vec<Glob>,
+}
+
+/// Levels below the scan root entries are deleted at; the root is level 0
+#[derive(Debug, Clone)]
+struct DepthLimits {
+    root: PathBuf,
+    min: usize,
+    max: Option<usize>,
+}
+
+impl DepthLimits {
+    fn depth(&self, path: &Path) -> usize {
+        path.strip_prefix(&self.root)
+            .map_or(0, |rel| rel.components().count())
+    }
+}
+
+impl GlobFilters {
+    /// Components of `path` below the root, as matched by patterns
+    fn relative<'a>(&self, path: &'a Path) -> Option<Vec<std::borrow::Cow<'a, str>>> {
+        let rel = path.strip_prefix(&self.root).ok()?;
+        Some(rel.iter().map(|part| part.to_string_lossy()).collect())
+    }
+
+    fn excluded(&self, path: &Path, is_dir: bool) -> bool {
+        !self.exclude.is_empty()
+            && self
+                .relative(path)
+                .is_some_and(|rel| self.exclude.iter().any(|g| g.matches(&rel, is_dir)))
+    }
+
+    /// Matched by an include pattern, itself or through a directory above it
+    fn included(&self, path: &Path, is_dir: bool) -> bool {
+        if self.include.is_empty() {
+            return true;
+        }
+        let Some(rel) = self.relative(path) else {
+            return false;
+        };
+        (1..=rel.len()).any(|len| {
+            let prefix_is_dir = len < rel.len() || is_dir;
+            self.include
+                .iter()
+                .any(|g| g.matches(&rel[..len], prefix_is_dir))
+        })
+    }
+}
+
+impl Filters {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Preserve `path` (and, if it is a directory, its contents)
+    pub fn keep(&mut self, path: PathBuf) {
+        self.keep.insert(path);
+    }
+
+    /// Preserve `path`, a file the run itself writes, until it ends
+    pub fn keep_own_file(&mut self, path: PathBuf) {
+        self.own_files.insert(path);
+    }
+
+    /// Preserve every directory named `name`, at any depth
+    pub fn exclude_dir_name(&mut self, name: impl Into<OsString>) {
+        self.exclude_dir_names.insert(name.into());
+    }
+
+    /// Preserve the protected directory `path` and its contents
+    pub fn protect(&mut self, path: PathBuf) {
+        self.protected.insert(path);
+    }
+
+    /// Delete only entries under `root` matching one of `include` (all if
+    /// empty), and preserve entries matching any of `exclude`
+    ///
+    /// Directories that don't match `include` are kept, even once emptied.
+    pub fn globs(&mut self, root: PathBuf, include: Vec<Glob>, exclude: Vec<Glob>) {
+        self.globs = (!include.is_empty() || !exclude.is_empty()).then_some(GlobFilters {
+            root,
+            include,
+            exclude,
+        });
+    }
+
+    /// Preserve files modified after `cutoff`, and keep directories
+    /// modified after it once emptied
+    pub fn keep_newer_than(&mut self, cutoff: SystemTime) {
+        self.keep_newer_than = Some(cutoff);
+    }
+
+    /// Delete only files larger than `larger_than` and smaller than
+    /// `smaller_than` bytes; every directory is kept
+    pub fn sizes(&mut self, larger_than: Option<u64>, smaller_than: Option<u64>) {
+        self.larger_than = larger_than;
+        self.smaller_than = smaller_than;
+    }
+
+    /// Delete only entries at least `min` and at most `max` levels below
+    /// `root`, which is level 0
+    ///
+    /// Directories above `min` are kept once emptied; nothing below `max`
+    /// is scanned.
+    pub fn depths(&mut self, root: PathBuf, min: usize, max: Option<usize>) {
+        self.depths = (min > 0 || max.is_some()).then_some(DepthLimits { root, min, max });
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.keep.is_empty()
+            && self.own_files.is_empty()
+            && self.exclude_dir_names.is_empty()
+            && self.protected.is_empty()
+            && self.globs.is_none()
+            && self.keep_newer_than.is_none()
+            && !self.by_size()
+            && self.depths.is_none()
+    }
+
+    fn by_size(&self) -> bool {
+        self.larger_than.is_some() || self.smaller_than.is_some()
+    }
+
+    /// True if directory `dir` must stay even after everything in it is gone
+    /// (it isn't matched by `--include`, was modified too recently, is
+    /// above `--min-depth`, or files are picked by size)
+    pub fn retains_dir(&self, dir: &Path) -> bool {
+        self.by_size()
+            || self
+                .depths
+                .as_ref()
+                .is_some_and(|depths| depths.depth(dir) < depths.min)
+            || self
+                .globs
+                .as_ref()
+                .is_some_and(|globs| !globs.included(dir, true))
+            || self
+                .keep_newer_than
+                .is_some_and(|cutoff| newer_than(dir, cutoff))
+    }
+
+    /// Why `path` should be left in place, if it should
+    pub fn skip_reason(&self, path: &Path, is_dir: bool) -> Option<SkipReason> {
+        if !is_dir && self.own_files.contains(path) {
+            return Some(SkipReason::OwnFile);
+        }
+        if self.keep.contains(path) {
+            return Some(SkipReason::Kept);
+        }
+        if let Some(depths) = &self.depths {
+            let depth = depths.depth(path);
+            // A directory too deep is kept whole, without being searched
+            if depths.max.is_some_and(|max| depth > max) {
+                return Some(SkipReason::TooDeep);
+            }
+            // Shallower directories are still searched for deeper entries
+            if !is_dir && depth < depths.min {
+                return Some(SkipReason::TooShallow);
+            }
+        }
+        if is_dir && self.protected.contains(path) {
+            return Some(SkipReason::Protected);
+        }
+        if is_dir
+            && path
+                .file_name()
+                .is_some_and(|name| self.exclude_dir_names.contains(name))
+        {
+            return Some(SkipReason::ExcludedName);
+        }
+        if let Some(globs) = &self.globs {
+            if globs.excluded(path, is_dir) {
+                return Some(SkipReason::ExcludedGlob);
+            }
+            // Unmatched directories are still searched for matching entries
+            if !is_dir && !globs.included(path, false) {
+                return Some(SkipReason::NotIncluded);
+            }
+        }
+        // Last, as it costs a stat
+        if is_dir {
+            return None;
+        }
+        self.recheck_file(path, None)
+    }
+
+    /// Why the file `path` should be left in place going by its age and
+    /// size: the part of [`Filters::skip_reason`] that can change after the
+    /// scan. A `size` from the listing saves a stat for size filters.
+    pub fn recheck_file(&self, path: &Path, size: Option<u64>) -> Option<SkipReason> {
+        if self.keep_newer_than.is_none() && !self.by_size() {
+            return None;
+        }
+        let metadata = if self.keep_newer_than.is_some() || size.is_none() {
+            // Gone already: nothing to keep
+            Some(fs::symlink_metadata(path).ok()?)
+        } else {
+            None
+        };
+        if let Some(cutoff) = self.keep_newer_than {
+            let modified = metadata.as_ref().and_then(|m| m.modified().ok());
+            if modified.is_some_and(|modified| modified > cutoff) {
+                return Some(SkipReason::TooNew);
+            }
+        }
+        let size = size.or(metadata.as_ref().map(fs::Metadata::len))?;
+        if self.larger_than.is_some_and(|limit| size <= limit) {
+            return Some(SkipReason::TooSmall);
+        }
+        if self.smaller_than.is_some_and(|limit| size >= limit) {
+            return Some(SkipReason::TooLarge);
+        }
+        None
+    }
+}
+
+/// True if `path` (not following symlinks) was modified after `cutoff`
+fn newer_than(path: &Path, cutoff: SystemTime) -> bool {
+    fs::symlink_metadata(path)
+        .and_then(|metadata| metadata.modified())
+        .is_ok_and(|modified| modified > cutoff)
+}
+
+/// A shell-style pattern over `/`-separated paths
+///
+/// `*` and `?` match within one name, `[a-z]`/`[!0-9]` match one character
+/// from a set, and a `**` component matches any number of directories. A
+/// pattern without `/` matches names at any depth (`*.log`); one with a
+/// `/` is anchored at the target (`node_modules/**/*.map`). A trailing `/`
+/// matches directories only. Case-insensitive on Windows.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Glob {
+    segments: Vec<Segment>,
+    dir_only: bool,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Segment {
+    /// `**`: zero or more whole components
+    AnyDepth,
+    Name(Vec<Token>),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Token {
+    Literal(char),
+    /// `?`
+    AnyChar,
+    /// `*`
+    AnyChars,
+    /// `[...]`: inclusive ranges, negated by a leading `!` or `^`
+    Class {
+        negated: bool,
+        ranges: Vec<(char, char)>,
+    },
+}
+
+impl Glob {
+    pub fn parse(pattern: &str) -> Result<Self, String> {
+        Self::parse_with(pattern, false)
+    }
+
+    /// Like [`Glob::parse`], but anchored even without a `/` (`*` matches
+    /// the top level only)
+    pub fn anchored(pattern: &str) -> Result<Self, String> {
+        Self::parse_with(pattern, true)
+    }
+
+    fn parse_with(pattern: &str, anchor: bool) -> Result<Self, String> {
+        let trimmed = pattern.strip_prefix("./").unwrap_or(pattern);
+        let dir_only = trimmed.ends_with('/');
+        let anchored = anchor || trimmed.trim_end_matches('/').contains('/');
+        let body = trimmed.trim_matches('/');
+        if body.is_empty() {
+            return Err(format!("empty pattern: {:?}", pattern));
+        }
+
+        let mut segments = Vec::new();
+        if !anchored {
+            segments.push(Segment::AnyDepth);
+        }
+        for part in body.split('/').filter(|part| !part.is_empty()) {
+            segments.push(match part {
+                "**" => Segment::AnyDepth,
+                _ => Segment::Name(tokenize(part).map_err(|e| format!("{:?}: {}", pattern, e))?),
+            });
+        }
+        Ok(Self { segments, dir_only })
+    }
+
+    /// Whether the path with components `parts` (relative to the root) matches
+    pub fn matches<S: AsRef<str>>(&self, parts: &[S], is_dir: bool) -> bool {
+        if self.dir_only && !is_dir {
+            return false;
+        }
+        match_segments(&self.segments, parts)
+    }
+}
+
+fn tokenize(part: &str) -> Result<Vec<Token>, &'static str> {
+    let mut tokens = Vec::new();
+    let mut chars = part.chars().peekable();
+    while let Some(c) = chars.next() {
+        tokens.push(match c {
+            '?' => Token::AnyChar,
+            '*' => {
+                // Runs of `*` inside a name mean the same as one
+                while chars.next_if_eq(&'*').is_some() {}
+                Token::AnyChars
+            }
+            '[' => {
+                let negated = chars.next_if(|&c| c == '!' || c == '^').is_some();
+                let mut ranges = Vec::new();
+                loop {
+                    let start = match chars.next() {
+                        Some(']') if !ranges.is_empty() => break,
+                        Some(c) => c,
+                        None => return Err("unclosed '['"),
+                    };
+                    let end = if chars.next_if_eq(&'-').is_some() {
+                        match chars.next() {
+                            Some(']') | None => return Err("unfinished range in '[...]'"),
+                            Some(end) => end,
+                        }
+                    } else {
+                        start
+                    };
+                    ranges.push((start, end));
+                }
+                Token::Class { negated, ranges }
+            }
+            c => Token::Literal(c),
+        });
+    }
+    Ok(tokens)
+}
+
+fn match_segments<S: AsRef<str>>(segments: &[Segment], parts: &[S]) -> bool {
+    match segments.split_first() {
+        None => parts.is_empty(),
+        Some((Segment::AnyDepth, rest)) => {
+            (0..=parts.len()).any(|skip| match_segments(rest, &parts[skip..]))
+        }
+        Some((Segment::Name(tokens), rest)) => match parts.split_first() {
+            Some((part, parts)) => {
+                let name: Vec<char> = part.as_ref().chars().collect();
+                match_name(tokens, &name) && match_segments(rest, parts)
+            }
+            None => false,
+        },
+    }
+}
+
+/// Wildcard match of one name, backtracking only to the last `*`
+fn match_name(tokens: &[Token], name: &[char]) -> bool {
+    let (mut t, mut n) = (0, 0);
+    let mut star: Option<(usize, usize)> = None;
+    while n < name.len() {
+        match tokens.get(t) {
+            Some(Token::AnyChars) => {
+                star = Some((t, n));
+                t += 1;
+            }
+            Some(token) if token_matches(token, name[n]) => {
+                t += 1;
+                n += 1;
+            }
+            _ => match star {
+                Some((star_t, star_n)) => {
+                    t = star_t + 1;
+                    n = star_n + 1;
+                    star = Some((star_t, star_n + 1));
+                }
+                None => return false,
+            },
+        }
+    }
+    tokens[t..].iter().all(|token| *token == Token::AnyChars)
+}
+
+fn token_matches(token: &Token, c: char) -> bool {
+    match token {
+        Token::Literal(l) => chars_eq(*l, c),
+        Token::AnyChar => true,
+        Token::AnyChars => false,
+        Token::Class { negated, ranges } => {
+            let hit = ranges.iter().any(|&(lo, hi)| {
+                (lo..=hi).contains(&c) || (cfg!(windows) && (lo..=hi).contains(&fold(c)))
+            });
+            hit != *negated
+        }
+    }
+}
+
+fn chars_eq(a: char, b: char) -> bool {
+    a == b || (cfg!(windows) && fold(a) == fold(b))
+}
+
+/// Simple case fold, for Windows' case-insensitive names
+fn fold(c: char) -> char {
+    c.to_lowercase().next().unwrap_or(c)
+}
+
+/// Whether the base name of `dir` matches one of `patterns`
+///
+/// Names like `.` or `..` are resolved first, so `rmbrr .` inside
+/// `node_modules` checks "node_modules".
+pub fn root_name_matches(dir: &Path, patterns: &[Glob]) -> bool {
+    let name = match dir.file_name() {
+        Some(name) => name.to_os_string(),
+        None => match dir
+            .canonicalize()
+            .ok()
+            .and_then(|c| c.file_name().map(Into::into))
+        {
+            Some(name) => name,
+            None => return false,
+        },
+    };
+    let name = name.to_string_lossy();
+    patterns.iter().any(|glob| glob.matches(&[&*name], true))
+}
+
+/// Read a keep-list file and resolve its entries against `root`
+///
+/// One path per line; blank lines and lines starting with `#` are ignored.
+/// Relative entries are taken relative to `root`. Absolute entries (and
+/// relative ones that climb out with `..`) that don't fall under `root`
+/// are dropped, so one list can be shared by several targets.
+pub fn read_keep_list(file: &Path, root: &Path) -> io::Result<Vec<PathBuf>> {
+    let contents = fs::read_to_string(file)?;
+    let canonical_root = root.canonicalize().ok();
+
+    let mut paths = Vec::new();
+    for line in contents.lines() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+
+        let entry = Path::new(line);
+        let relative = if entry.is_absolute() {
+            let stripped = entry
+                .strip_prefix(root)
+                .ok()
+                .or_else(|| entry.strip_prefix(canonical_root.as_deref()?).ok());
+            match stripped {
+                Some(rel) => rel,
+                None => continue,
+            }
+        } else {
+            entry
+        };
+
+        if let Some(rel) = normalize(relative) {
+            paths.push(root.join(rel));
+        }
+    }
+    Ok(paths)
+}
+
+/// `file` spelled as a path under `root`, if it is inside it; compared as
+/// absolute paths, so `build/run.log` is inside `./build`
+pub fn inside(root: &Path, file: &Path) -> Option<PathBuf> {
+    let absolute_root = std::path::absolute(root).ok()?;
+    let absolute_file = std::path::absolute(file).ok()?;
+    let relative = absolute_file.strip_prefix(absolute_root).ok()?;
+    (!relative.as_os_str().is_empty()).then(|| root.join(relative))
+}
+
+/// Drop `.` components and trailing separators; `None` if the path uses `..`
+fn normalize(path: &Path) -> Option<PathBuf> {
+    let mut normalized = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::Normal(part) => normalized.push(part),
+            Component::CurDir => {}
+            _ => return None,
+        }
+    }
+    Some(normalized)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_read_keep_list() {
+        let temp = std::env::temp_dir().join("rmbrr_keep_list_test");
+        let _ = fs::remove_dir_all(&temp);
+        let root = temp.join("cache");
+        fs::create_dir_all(&root).unwrap();
+
+        let list = temp.join("keep.txt");
+        fs::write(
+            &list,
+            format!(
+                "# known-good entries\n\nv1/pkg\n./v2/\n../elsewhere\n{}\n/not/under/root\n",
+                root.join("abs").display()
+            ),
+        )
+        .unwrap();
+
+        let paths = read_keep_list(&list, &root).unwrap();
+        assert_eq!(
+            paths,
+            vec![root.join("v1/pkg"), root.join("v2"), root.join("abs")]
+        );
+
+        fs::remove_dir_all(&temp).ok();
+    }
+
+    #[test]
+    fn test_inside() {
+        let root = Path::new("build");
+        assert_eq!(
+            inside(root, Path::new("./build/logs/run.journal")),
+            Some(PathBuf::from("build/logs/run.journal"))
+        );
+        let absolute = std::path::absolute("build/manifest.csv").unwrap();
+        assert_eq!(
+            inside(root, &absolute),
+            Some(PathBuf::from("build/manifest.csv"))
+        );
+        assert_eq!(inside(root, Path::new("build")), None);
+        assert_eq!(inside(root, Path::new("build-logs/run.journal")), None);
+    }
+
+    #[test]
+    fn test_skip_reason() {
+        let mut filters = Filters::new();
+        assert!(filters.is_empty());
+
+        filters.keep(PathBuf::from("/cache/keep"));
+        assert_eq!(
+            filters.skip_reason(Path::new("/cache/keep"), false),
+            Some(SkipReason::Kept)
+        );
+        filters.keep_own_file(PathBuf::from("/cache/run.journal"));
+        assert_eq!(
+            filters.skip_reason(Path::new("/cache/run.journal"), false),
+            Some(SkipReason::OwnFile)
+        );
+        assert_eq!(filters.skip_reason(Path::new("/cache/other"), false), None);
+
+        // Name exclusions match directories only, at any depth
+        filters.exclude_dir_name(".git");
+        assert_eq!(
+            filters.skip_reason(Path::new("/ws/a/b/.git"), true),
+            Some(SkipReason::ExcludedName)
+        );
+        assert_eq!(filters.skip_reason(Path::new("/ws/a/.git"), false), None);
+        assert_eq!(filters.skip_reason(Path::new("/ws/a/.github"), true), None);
+
+        filters.protect(PathBuf::from("/home/me"));
+        assert_eq!(
+            filters.skip_reason(Path::new("/home/me"), true),
+            Some(SkipReason::Protected)
+        );
+        assert_eq!(filters.skip_reason(Path::new("/home/me/x"), true), None);
+    }
+
+    #[test]
+    fn test_sizes() {
+        let temp = std::env::temp_dir().join("rmbrr_filter_size_test");
+        let _ = fs::remove_dir_all(&temp);
+        fs::create_dir_all(&temp).unwrap();
+        let file = temp.join("file.bin");
+        fs::write(&file, [0u8; 100]).unwrap();
+
+        let mut filters = Filters::new();
+        filters.sizes(Some(100), None);
+        assert!(!filters.is_empty());
+        assert_eq!(
+            filters.skip_reason(&file, false),
+            Some(SkipReason::TooSmall)
+        );
+        assert_eq!(filters.recheck_file(&file, Some(101)), None);
+        // Directories have no size of their own: never skipped, always kept
+        assert_eq!(filters.skip_reason(&temp, true), None);
+        assert!(filters.retains_dir(&temp));
+
+        filters.sizes(Some(10), Some(100));
+        assert_eq!(
+            filters.skip_reason(&file, false),
+            Some(SkipReason::TooLarge)
+        );
+        assert_eq!(filters.recheck_file(&file, Some(99)), None);
+        // The listing's size is taken as it is, with no stat
+        assert_eq!(
+            filters.recheck_file(&temp.join("missing"), Some(5)),
+            Some(SkipReason::TooSmall)
+        );
+
+        fs::remove_dir_all(&temp).ok();
+    }
+
+    #[test]
+    fn test_depths() {
+        let mut filters = Filters::new();
+        filters.depths(PathBuf::from("/cache"), 0, None);
+        assert!(filters.is_empty());
+
+        filters.depths(PathBuf::from("/cache"), 2, Some(3));
+        assert!(!filters.is_empty());
+        // Shallow directories are searched and kept; shallow files stay
+        assert!(filters.retains_dir(Path::new("/cache")));
+        assert!(filters.retains_dir(Path::new("/cache/a")));
+        assert!(!filters.retains_dir(Path::new("/cache/a/b")));
+        assert_eq!(filters.skip_reason(Path::new("/cache/a"), true), None);
+        assert_eq!(
+            filters.skip_reason(Path::new("/cache/a"), false),
+            Some(SkipReason::TooShallow)
+        );
+        assert_eq!(filters.skip_reason(Path::new("/cache/a/b"), false), None);
+        assert_eq!(filters.skip_reason(Path::new("/cache/a/b/c"), true), None);
+        assert_eq!(
+            filters.skip_reason(Path::new("/cache/a/b/c/d"), true),
+            Some(SkipReason::TooDeep)
+        );
+    }
+
+    #[test]
+    fn test_keep_newer_than() {
+        let temp = std::env::temp_dir().join("rmbrr_filter_age_test");
+        let _ = fs::remove_dir_all(&temp);
+        fs::create_dir_all(&temp).unwrap();
+        let file = temp.join("file.txt");
+        fs::write(&file, "x").unwrap();
+
+        let mut filters = Filters::new();
+        let now = SystemTime::now();
+        filters.keep_newer_than(now + std::time::Duration::from_secs(3600));
+        assert!(!filters.is_empty());
+        assert_eq!(filters.skip_reason(&file, false), None);
+        assert!(!filters.retains_dir(&temp));
+
+        filters.keep_newer_than(now - std::time::Duration::from_secs(3600));
+        assert_eq!(filters.skip_reason(&file, false), Some(SkipReason::TooNew));
+        // Directories are only kept, not skipped with their contents
+        assert_eq!(filters.skip_reason(&temp, true), None);
+        assert!(filters.retains_dir(&temp));
+
+        fs::remove_dir_all(&temp).ok();
+    }
+
+    fn glob(pattern: &str, path: &str, is_dir: bool) -> bool {
+        let parts: Vec<&str> = path.split('/').collect();
+        Glob::parse(pattern).unwrap().matches(&parts, is_dir)
+    }
+
+    #[test]
+    fn test_glob_matching() {
+        // No slash: any depth
+        assert!(glob("*.log", "a.log", false));
+        assert!(glob("*.log", "x/y/a.log", false));
+        assert!(!glob("*.log", "a.log.gz", false));
+        // Slash: anchored at the root, with ** spanning directories
+        assert!(glob("node_modules/**/*.map", "node_modules/a.map", false));
+        assert!(glob(
+            "node_modules/**/*.map",
+            "node_modules/p/dist/a.map",
+            false
+        ));
+        assert!(!glob(
+            "node_modules/**/*.map",
+            "src/node_modules/a.map",
+            false
+        ));
+        // Trailing slash: directories only
+        assert!(glob("build/", "build", true));
+        assert!(!glob("build/", "build", false));
+        assert!(glob("file?.[a-c]", "file1.b", false));
+        assert!(!glob("file?.[!a-c]", "file1.b", false));
+        assert!(glob("a*b*c", "aXXbYYbZc", false));
+        assert!(!glob("a*b*c", "aXXbYY", false));
+
+        assert!(Glob::parse("[abc").is_err());
+        assert!(Glob::parse("/").is_err());
+    }
+
+    #[test]
+    fn test_root_name_matches() {
+        let patterns: Vec<Glob> = ["node_modules", "target", ".venv*"]
+            .iter()
+            .map(|p| Glob::parse(p).unwrap())
+            .collect();
+        assert!(root_name_matches(Path::new("app/node_modules"), &patterns));
+        assert!(root_name_matches(Path::new("app/target/"), &patterns));
+        assert!(root_name_matches(Path::new("/x/.venv-3.12"), &patterns));
+        assert!(!root_name_matches(Path::new("app/src"), &patterns));
+        assert!(!root_name_matches(Path::new("node_modules/src"), &patterns));
+        assert!(!root_name_matches(Path::new("/"), &patterns));
+
+        let temp = std::env::temp_dir().join("rmbrr_root_match_test/target");
+        fs::create_dir_all(&temp).unwrap();
+        assert!(root_name_matches(&temp.join("."), &patterns));
+        assert!(!root_name_matches(&temp.join(".."), &patterns));
+        fs::remove_dir_all(temp.parent().unwrap()).ok();
+    }
+
+    #[test]
+    fn test_glob_filters() {
+        let root = PathBuf::from("/proj");
+        let mut filters = Filters::new();
+        filters.globs(
+            root.clone(),
+            vec![
+                Glob::parse("node_modules/**/*.map").unwrap(),
+                Glob::parse("dist/").unwrap(),
+            ],
+            vec![Glob::parse("*.keep.map").unwrap()],
+        );
+        assert!(!filters.is_empty());
+
+        let reason = |path: &str, is_dir| filters.skip_reason(&root.join(path), is_dir);
+        assert_eq!(reason("node_modules/p/a.map", false), None);
+        assert_eq!(
+            reason("node_modules/p/a.js", false),
+            Some(SkipReason::NotIncluded)
+        );
+        assert_eq!(
+            reason("node_modules/p/a.keep.map", false),
+            Some(SkipReason::ExcludedGlob)
+        );
+        // Everything inside an included directory goes
+        assert_eq!(reason("dist/x/y.txt", false), None);
+        // Unmatched directories are searched, then kept
+        assert_eq!(reason("node_modules/p", true), None);
+        assert!(filters.retains_dir(&root.join("node_modules/p")));
+        assert!(filters.retains_dir(&root));
+        assert!(!filters.retains_dir(&root.join("dist/x")));
+    }
+}
@@ -0,0 +1,521 @@
+//! Which processes have a file open, for "in use" failures, and ending them
+//! (`--unlock`, Windows)
+//!
+//! Windows asks the Restart Manager, which knows the handles every process
+//! has open on a file. Linux looks through each process's open files in
+//! `/proc`, along with their working directories and mapped files, macOS
+//! through libproc's; both are best effort, only finding processes the
+//! user may inspect. A lookup takes a few milliseconds (more on Unix with
+//! many processes), so a run makes at most [`MAX_LOOKUPS`] of them; other
+//! platforms report nobody.
+
+use serde::Serialize;
+use std::fmt;
+use std::io::{self, BufRead, Write};
+use std::path::Path;
+use std::str::FromStr;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+
+#[cfg(windows)]
+use windows::core::{PCWSTR, PWSTR};
+#[cfg(windows)]
+use windows::Win32::Foundation::{CloseHandle, ERROR_MORE_DATA, FILETIME, WAIT_OBJECT_0};
+#[cfg(windows)]
+use windows::Win32::System::RestartManager::{
+    RmCritical, RmEndSession, RmExplorer, RmGetList, RmRegisterResources, RmService,
+    RmStartSession, CCH_RM_SESSION_KEY, RM_PROCESS_INFO,
+};
+#[cfg(windows)]
+use windows::Win32::System::Threading::{
+    GetCurrentProcessId, GetProcessTimes, OpenProcess, TerminateProcess, WaitForSingleObject,
+    PROCESS_QUERY_LIMITED_INFORMATION, PROCESS_SYNCHRONIZE, PROCESS_TERMINATE,
+};
+
+/// Lookups made per run at most
+pub const MAX_LOOKUPS: usize = 50;
+
+/// How long an ended process gets to let go of its handles
+#[cfg(windows)]
+const EXIT_WAIT_MS: u32 = 5000;
+
+static LOOKUPS: AtomicUsize = AtomicUsize::new(0);
+
+/// A process with a file open
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Holder {
+    pub pid: u32,
+    pub name: String,
+    /// When it started, telling it from a later process given the same pid
+    #[serde(skip)]
+    pub started: u64,
+    /// The system can't run without it, or it is a service or Explorer,
+    /// which ending would take down more than the file; never ended
+    #[serde(skip)]
+    pub critical: bool,
+}
+
+impl fmt::Display for Holder {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} (pid {})", self.name, self.pid)
+    }
+}
+
+/// What to do about processes holding files open (`--unlock`)
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Unlock {
+    /// Leave them; the files fail as in use
+    #[default]
+    Skip,
+    /// Ask before ending each
+    Prompt,
+    Kill,
+}
+
+impl FromStr for Unlock {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "skip" => Ok(Unlock::Skip),
+            "prompt" => Ok(Unlock::Prompt),
+            "kill" => Ok(Unlock::Kill),
+            other => Err(format!(
+                "unknown unlock mode '{}' (expected prompt, kill or skip)",
+                other
+            )),
+        }
+    }
+}
+
+/// Processes with `path` open; none if the lookup failed or the run's
+/// lookups are used up
+pub fn holders(path: &Path) -> Vec<Holder> {
+    if LOOKUPS.fetch_add(1, Ordering::Relaxed) >= MAX_LOOKUPS {
+        return Vec::new();
+    }
+    lookup(path).unwrap_or_default()
+}
+
+/// Answers to [`Unlock::Prompt`], so nobody is asked about a process twice
+static ANSWERS: Mutex<Vec<(u32, u64, bool)>> = Mutex::new(Vec::new());
+
+/// End the `holders` of `path` as `mode` says; true if they all ended, so
+/// deleting it is worth another try
+///
+/// Prompts are asked one at a time, however many workers get here.
+pub fn unlock(path: &Path, holders: &[Holder], mode: Unlock) -> bool {
+    if mode == Unlock::Skip || holders.is_empty() {
+        return false;
+    }
+    let mut answers = ANSWERS.lock().unwrap_or_else(|e| e.into_inner());
+    let mut all_ended = true;
+    for holder in holders {
+        if holder.critical {
+            crate::errln!("{}", crate::msg!(UnlockCritical, holder));
+            all_ended = false;
+            continue;
+        }
+        if mode == Unlock::Prompt {
+            let known = answers
+                .iter()
+                .find(|&&(pid, started, _)| (pid, started) == (holder.pid, holder.started))
+                .map(|&(_, _, yes)| yes);
+            let yes = known.unwrap_or_else(|| {
+                let yes = ask(path, holder);
+                answers.push((holder.pid, holder.started, yes));
+                yes
+            });
+            if !yes {
+                all_ended = false;
+                continue;
+            }
+        }
+        match terminate(holder) {
+            Ok(()) => crate::errln!("{}", crate::msg!(UnlockEnded, holder, path.display())),
+            Err(e) => {
+                crate::errln!("{}", crate::msg!(UnlockFailed, holder, e));
+                all_ended = false;
+            }
+        }
+    }
+    all_ended
+}
+
+fn ask(path: &Path, holder: &Holder) -> bool {
+    let response = crate::output::prompt(|| {
+        eprint!("{}", crate::msg!(UnlockPrompt, holder, path.display()));
+        io::stderr().flush().ok();
+        let mut response = String::new();
+        io::stdin().lock().read_line(&mut response).ok();
+        response
+    });
+    let response = response.trim().to_lowercase();
+    !response.is_empty()
+        && crate::msg!(ConfirmAnswers)
+            .split(',')
+            .any(|answer| answer.trim().to_lowercase() == response)
+}
+
+#[cfg(windows)]
+fn lookup(path: &Path) -> Option<Vec<Holder>> {
+    use std::os::windows::ffi::OsStrExt;
+
+    // A plain path: the Restart Manager doesn't take `\\?\` ones
+    let full = std::path::absolute(path).ok()?;
+    let wide: Vec<u16> = full.as_os_str().encode_wide().chain(Some(0)).collect();
+    unsafe {
+        let mut session = 0;
+        let mut key = [0u16; CCH_RM_SESSION_KEY as usize + 1];
+        if RmStartSession(&mut session, 0, PWSTR(key.as_mut_ptr())).is_err() {
+            return None;
+        }
+        let result = list(session, &wide);
+        let _ = RmEndSession(session);
+        result
+    }
+}
+
+#[cfg(target_os = "linux")]
+fn lookup(path: &Path) -> Option<Vec<Holder>> {
+    use std::fs;
+    use std::os::unix::fs::MetadataExt;
+
+    let target = fs::symlink_metadata(path).ok()?;
+    let target = (target.dev(), target.ino());
+    let mut holders = Vec::new();
+    for entry in fs::read_dir("/proc").ok()?.flatten() {
+        let Some(pid) = entry
+            .file_name()
+            .to_str()
+            .and_then(|name| name.parse().ok())
+        else {
+            continue;
+        };
+        let dir = entry.path();
+        // Other users' processes, without the privileges to look
+        let Ok(fds) = fs::read_dir(dir.join("fd")) else {
+            continue;
+        };
+        // Its open files, the program it runs (ETXTBSY), the directories it
+        // works in and is confined to, and the files it has mapped
+        let holds = fds
+            .flatten()
+            .map(|fd| fd.path())
+            .chain(["exe", "cwd", "root"].map(|link| dir.join(link)))
+            .any(|link| fs::metadata(link).is_ok_and(|m| (m.dev(), m.ino()) == target))
+            || maps(&dir.join("maps"), target);
+        if holds {
+            let name = fs::read_to_string(dir.join("comm")).unwrap_or_default();
+            // Field 22 of stat, counted after the name, which may hold spaces
+            let stat = fs::read_to_string(dir.join("stat")).unwrap_or_default();
+            let started = stat
+                .rsplit_once(')')
+                .and_then(|(_, fields)| fields.split_whitespace().nth(19)?.parse().ok());
+            holders.push(Holder {
+                pid,
+                name: name.trim_end().to_string(),
+                started: started.unwrap_or_default(),
+                critical: pid == 1 || pid == std::process::id(),
+            });
+        }
+    }
+    Some(holders)
+}
+
+/// Whether a `/proc/<pid>/maps` maps the file with `(dev, ino)`
+#[cfg(target_os = "linux")]
+fn maps(path: &Path, (dev, ino): (u64, u64)) -> bool {
+    let Ok(maps) = std::fs::read_to_string(path) else {
+        return false;
+    };
+    // address perms offset major:minor inode [path], numbers in hex but
+    // the inode
+    maps.lines().any(|line| {
+        let mut fields = line.split_whitespace().skip(3);
+        let (Some(device), Some(inode)) = (fields.next(), fields.next()) else {
+            return false;
+        };
+        let Some((major, minor)) = device.split_once(':') else {
+            return false;
+        };
+        let (Ok(major), Ok(minor)) = (
+            u32::from_str_radix(major, 16),
+            u32::from_str_radix(minor, 16),
+        ) else {
+            return false;
+        };
+        inode.parse() == Ok(ino) && libc::makedev(major, minor) == dev
+    })
+}
+
+#[cfg(target_os = "macos")]
+fn lookup(path: &Path) -> Option<Vec<Holder>> {
+    use std::mem::size_of;
+    use std::os::unix::fs::MetadataExt;
+
+    // libc lacks sys/proc_info.h's struct vnode_fdinfowithpath
+    const PROC_PIDFDVNODEPATHINFO: libc::c_int = 2;
+    #[repr(C)]
+    struct VnodeFdInfoWithPath {
+        /// struct proc_fileinfo
+        pfi: [u64; 3],
+        pvip: libc::vnode_info_path,
+    }
+
+    let target = std::fs::symlink_metadata(path).ok()?;
+    let target = (target.dev() as u32, target.ino());
+    unsafe {
+        let count = libc::proc_listallpids(std::ptr::null_mut(), 0);
+        // Room for processes started since
+        let mut pids: Vec<libc::c_int> = vec![0; usize::try_from(count).ok()? + 64];
+        let count = libc::proc_listallpids(
+            pids.as_mut_ptr().cast(),
+            (pids.len() * size_of::<libc::c_int>()) as libc::c_int,
+        );
+        pids.truncate(usize::try_from(count).ok()?);
+
+        let mut holders = Vec::new();
+        for pid in pids {
+            let size = libc::proc_pidinfo(pid, libc::PROC_PIDLISTFDS, 0, std::ptr::null_mut(), 0);
+            let Ok(size) = usize::try_from(size) else {
+                continue;
+            };
+            let mut fds: Vec<libc::proc_fdinfo> =
+                vec![std::mem::zeroed(); size / size_of::<libc::proc_fdinfo>()];
+            let size = libc::proc_pidinfo(
+                pid,
+                libc::PROC_PIDLISTFDS,
+                0,
+                fds.as_mut_ptr().cast(),
+                (fds.len() * size_of::<libc::proc_fdinfo>()) as libc::c_int,
+            );
+            // Other users' processes, without the privileges to look
+            let Ok(size) = usize::try_from(size) else {
+                continue;
+            };
+            fds.truncate(size / size_of::<libc::proc_fdinfo>());
+            let holds = fds
+                .iter()
+                .filter(|fd| fd.proc_fdtype == libc::PROX_FDTYPE_VNODE as u32)
+                .any(|fd| {
+                    let mut info: VnodeFdInfoWithPath = std::mem::zeroed();
+                    let size = size_of::<VnodeFdInfoWithPath>() as libc::c_int;
+                    let found = libc::proc_pidfdinfo(
+                        pid,
+                        fd.proc_fd,
+                        PROC_PIDFDVNODEPATHINFO,
+                        std::ptr::addr_of_mut!(info).cast(),
+                        size,
+                    );
+                    let stat = &info.pvip.vip_vi.vi_stat;
+                    found == size && (stat.vst_dev, stat.vst_ino) == target
+                });
+            if holds {
+                holders.push(bsd_holder(pid));
+            }
+        }
+        Some(holders)
+    }
+}
+
+#[cfg(target_os = "macos")]
+unsafe fn bsd_holder(pid: libc::c_int) -> Holder {
+    let mut name = [0u8; 256];
+    let len = libc::proc_name(pid, name.as_mut_ptr().cast(), name.len() as u32);
+    let mut info: libc::proc_bsdinfo = std::mem::zeroed();
+    let size = std::mem::size_of::<libc::proc_bsdinfo>() as libc::c_int;
+    let started = if libc::proc_pidinfo(
+        pid,
+        libc::PROC_PIDTBSDINFO,
+        0,
+        std::ptr::addr_of_mut!(info).cast(),
+        size,
+    ) == size
+    {
+        info.pbi_start_tvsec * 1_000_000 + info.pbi_start_tvusec
+    } else {
+        0
+    };
+    Holder {
+        pid: pid as u32,
+        name: String::from_utf8_lossy(&name[..usize::try_from(len).unwrap_or(0)]).into_owned(),
+        started,
+        critical: pid == 1 || pid as u32 == std::process::id(),
+    }
+}
+
+#[cfg(not(any(windows, target_os = "linux", target_os = "macos")))]
+fn lookup(_path: &Path) -> Option<Vec<Holder>> {
+    None
+}
+
+#[cfg(windows)]
+unsafe fn list(session: u32, wide_path: &[u16]) -> Option<Vec<Holder>> {
+    let files = [PCWSTR(wide_path.as_ptr())];
+    if RmRegisterResources(session, Some(&files), None, None).is_err() {
+        return None;
+    }
+    let mut infos: Vec<RM_PROCESS_INFO> = Vec::new();
+    // More processes can open it between asking how many and listing them
+    for _ in 0..3 {
+        let (mut needed, mut count, mut reasons) = (0, infos.len() as u32, 0);
+        let status = RmGetList(
+            session,
+            &mut needed,
+            &mut count,
+            Some(infos.as_mut_ptr()),
+            &mut reasons,
+        );
+        if status == ERROR_MORE_DATA {
+            infos.resize(needed as usize, RM_PROCESS_INFO::default());
+            continue;
+        }
+        if status.is_err() {
+            return None;
+        }
+        infos.truncate(count as usize);
+        return Some(
+            infos
+                .iter()
+                .map(|info| {
+                    let name = &info.strAppName;
+                    let len = name.iter().position(|&c| c == 0).unwrap_or(name.len());
+                    Holder {
+                        pid: info.Process.dwProcessId,
+                        name: String::from_utf16_lossy(&name[..len]),
+                        started: filetime(info.Process.ProcessStartTime),
+                        critical: [RmCritical, RmService, RmExplorer]
+                            .contains(&info.ApplicationType)
+                            || info.Process.dwProcessId == GetCurrentProcessId(),
+                    }
+                })
+                .collect(),
+        );
+    }
+    None
+}
+
+#[cfg(windows)]
+fn filetime(time: FILETIME) -> u64 {
+    u64::from(time.dwHighDateTime) << 32 | u64::from(time.dwLowDateTime)
+}
+
+/// End `holder` and wait for it to exit
+#[cfg(windows)]
+fn terminate(holder: &Holder) -> io::Result<()> {
+    let os_error = |e: windows::core::Error| io::Error::from_raw_os_error(e.code().0 & 0xFFFF);
+    unsafe {
+        let process = OpenProcess(
+            PROCESS_TERMINATE | PROCESS_QUERY_LIMITED_INFORMATION | PROCESS_SYNCHRONIZE,
+            false,
+            holder.pid,
+        )
+        .map_err(os_error)?;
+        let result = (|| {
+            let mut times = [FILETIME::default(); 4];
+            let [created, exited, kernel, user] = &mut times;
+            GetProcessTimes(process, created, exited, kernel, user).map_err(os_error)?;
+            // The pid was reused since the lookup: not the process holding it
+            if filetime(*created) != holder.started {
+                return Err(io::Error::new(io::ErrorKind::NotFound, "it already exited"));
+            }
+            TerminateProcess(process, 1).map_err(os_error)?;
+            if WaitForSingleObject(process, EXIT_WAIT_MS) != WAIT_OBJECT_0 {
+                return Err(io::Error::new(
+                    io::ErrorKind::TimedOut,
+                    "it didn't exit in time",
+                ));
+            }
+            Ok(())
+        })();
+        CloseHandle(process).ok();
+        result
+    }
+}
+
+#[cfg(not(windows))]
+fn terminate(_holder: &Holder) -> io::Result<()> {
+    Err(io::Error::new(
+        io::ErrorKind::Unsupported,
+        "only available on Windows",
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_unlock() {
+        assert_eq!("prompt".parse(), Ok(Unlock::Prompt));
+        assert!("force".parse::<Unlock>().is_err());
+
+        let holder = Holder {
+            pid: 42,
+            name: "node.exe".to_string(),
+            started: 1,
+            critical: false,
+        };
+        assert_eq!(holder.to_string(), "node.exe (pid 42)");
+        let json = serde_json::to_value(&holder).unwrap();
+        assert_eq!(json, serde_json::json!({"pid": 42, "name": "node.exe"}));
+
+        // Nothing to end, or told not to
+        assert!(!unlock(Path::new("/x"), &[], Unlock::Kill));
+        assert!(!unlock(Path::new("/x"), &[holder], Unlock::Skip));
+    }
+
+    #[test]
+    #[cfg(any(target_os = "linux", target_os = "macos"))]
+    fn test_lookup_finds_this_process() {
+        let temp = std::env::temp_dir().join("rmbrr_handles_test");
+        let _ = std::fs::remove_dir_all(&temp);
+        std::fs::create_dir_all(&temp).unwrap();
+        let file = temp.join("held.txt");
+        std::fs::write(&file, "x").unwrap();
+        assert!(lookup(&file).unwrap().is_empty());
+
+        let open = std::fs::File::open(&file).unwrap();
+        let holders = lookup(&file).unwrap();
+        let own = holders
+            .iter()
+            .find(|holder| holder.pid == std::process::id())
+            .expect("the test holds it open");
+        assert!(!own.name.is_empty());
+        assert!(own.started > 0);
+        // Never ends itself
+        assert!(own.critical);
+
+        drop(open);
+        std::fs::remove_dir_all(&temp).ok();
+    }
+
+    #[test]
+    #[cfg(target_os = "linux")]
+    fn test_lookup_finds_working_directories() {
+        use std::os::unix::fs::MetadataExt;
+
+        let temp = std::env::temp_dir().join("rmbrr_handles_cwd_test");
+        let _ = std::fs::remove_dir_all(&temp);
+        std::fs::create_dir_all(&temp).unwrap();
+
+        let mut child = std::process::Command::new("sleep")
+            .arg("30")
+            .current_dir(&temp)
+            .spawn()
+            .unwrap();
+        let holders = lookup(&temp).unwrap();
+        child.kill().ok();
+        child.wait().ok();
+        assert!(holders.iter().any(|holder| holder.pid == child.id()));
+
+        // The test binary has itself mapped
+        let exe = std::env::current_exe().unwrap();
+        let own = format!("/proc/{}/maps", std::process::id());
+        let exe = std::fs::metadata(exe).unwrap();
+        assert!(maps(Path::new(&own), (exe.dev(), exe.ino())));
+
+        std::fs::remove_dir_all(&temp).ok();
+    }
+}
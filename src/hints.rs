@@ -0,0 +1,130 @@
+//! Suggestions for what to do about failures, shown below a run's error
+//! summary
+//!
+//! [`RULES`] maps kinds of failure ([`ErrorCategory`]), optionally narrowed
+//! to a platform or to paths under some directories, to a message. Each
+//! failure is matched against the rules in order and takes the first that
+//! fits, so narrow rules go before the general one for their category.
+
+use crate::error::{ErrorCategory, FailedItem};
+use crate::messages::Msg;
+
+/// Platforms a [`Rule`] applies on
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Os {
+    Any,
+    Windows,
+    Unix,
+}
+
+impl Os {
+    fn is_current(self) -> bool {
+        match self {
+            Os::Any => true,
+            Os::Windows => cfg!(windows),
+            Os::Unix => cfg!(unix),
+        }
+    }
+}
+
+/// A hint for one kind of failure
+#[derive(Debug, Clone, Copy)]
+pub struct Rule {
+    pub category: ErrorCategory,
+    pub os: Os,
+    /// Only for failures under one of these directories (any if empty)
+    pub under: &'static [&'static str],
+    pub hint: Msg,
+}
+
+impl Rule {
+    const fn new(category: ErrorCategory, os: Os, hint: Msg) -> Self {
+        Self {
+            category,
+            os,
+            under: &[],
+            hint,
+        }
+    }
+
+    fn matches(&self, failure: &FailedItem) -> bool {
+        self.category == failure.category()
+            && self.os.is_current()
+            && (self.under.is_empty() || self.under.iter().any(|dir| failure.path.starts_with(dir)))
+    }
+}
+
+/// Directories only root can usually write to
+const SYSTEM_DIRS: &[&str] = &["/var", "/usr", "/opt", "/etc", "/srv", "/Library"];
+
+pub const RULES: &[Rule] = &[
+    Rule::new(ErrorCategory::InUse, Os::Windows, Msg::HintInUseWindows),
+    Rule::new(ErrorCategory::InUse, Os::Unix, Msg::HintInUse),
+    Rule::new(
+        ErrorCategory::PermissionDenied,
+        Os::Windows,
+        Msg::HintAccessDeniedWindows,
+    ),
+    Rule {
+        under: SYSTEM_DIRS,
+        ..Rule::new(ErrorCategory::PermissionDenied, Os::Unix, Msg::HintSudo)
+    },
+    Rule::new(
+        ErrorCategory::PermissionDenied,
+        Os::Unix,
+        Msg::HintPermissionDenied,
+    ),
+    Rule::new(ErrorCategory::ReadOnly, Os::Any, Msg::HintReadOnly),
+    Rule::new(ErrorCategory::PathTooLong, Os::Any, Msg::HintPathTooLong),
+    Rule::new(ErrorCategory::NotEmpty, Os::Any, Msg::HintNotEmpty),
+    Rule::new(ErrorCategory::Encrypted, Os::Any, Msg::HintEncrypted),
+];
+
+/// Hints for `failures`, each once, in the order of [`RULES`]
+pub fn hints<'a>(failures: impl IntoIterator<Item = &'a FailedItem>) -> Vec<Msg> {
+    let mut used = vec![false; RULES.len()];
+    for failure in failures {
+        if let Some(i) = RULES.iter().position(|rule| rule.matches(failure)) {
+            used[i] = true;
+        }
+    }
+    RULES
+        .iter()
+        .zip(used)
+        .filter(|(_, used)| *used)
+        .map(|(rule, _)| rule.hint)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io;
+    use std::path::PathBuf;
+
+    fn failure(path: &str, kind: io::ErrorKind) -> FailedItem {
+        FailedItem::new(PathBuf::from(path), io::Error::from(kind), false)
+    }
+
+    #[test]
+    fn test_hints() {
+        assert!(hints([]).is_empty());
+        // Nothing to suggest for something else deleting an entry first
+        assert!(hints([&failure("/tmp/a", io::ErrorKind::NotFound)]).is_empty());
+
+        let busy = failure("/tmp/a", io::ErrorKind::ResourceBusy);
+        let denied = failure("/tmp/b", io::ErrorKind::PermissionDenied);
+        let failures = [&denied, &busy, &busy];
+        if cfg!(windows) {
+            assert_eq!(
+                hints(failures),
+                [Msg::HintInUseWindows, Msg::HintAccessDeniedWindows]
+            );
+        } else {
+            assert_eq!(hints(failures), [Msg::HintInUse, Msg::HintPermissionDenied]);
+            // Under a system directory the narrower rule wins
+            let system = failure("/var/lib/x", io::ErrorKind::PermissionDenied);
+            assert_eq!(hints([&system]), [Msg::HintSudo]);
+        }
+    }
+}
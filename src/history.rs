@@ -0,0 +1,207 @@
+//! Local history of recent deletions, to catch a target deleted twice
+//!
+//! One line per deleted target: `<unix seconds>\t<operation id>\t<outcome>\t<path>`.
+//! Only the last [`MAX_ENTRIES`] are kept. Two runs finishing at the same
+//! moment can drop each other's lines; the history is a hint, not a log.
+
+use std::env;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// Lines kept in the history file
+pub const MAX_ENTRIES: usize = 500;
+
+/// The same target deleted again within this long is probably a script
+/// firing twice rather than something recreated on purpose
+pub const DOUBLE_RUN_WINDOW: Duration = Duration::from_secs(10 * 60);
+
+/// One deleted target
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Entry {
+    pub time: SystemTime,
+    pub operation: String,
+    /// `deleted`, `trashed`, `staged` or `partial`
+    pub outcome: String,
+    pub path: PathBuf,
+}
+
+/// The history file of this user
+#[derive(Debug, Clone)]
+pub struct History {
+    file: PathBuf,
+}
+
+impl History {
+    pub fn new(file: impl Into<PathBuf>) -> Self {
+        Self { file: file.into() }
+    }
+
+    /// `RMBRR_HISTORY` if set, else `history.tsv` in the [`state_dir`]
+    pub fn default_location() -> Option<Self> {
+        if let Some(file) = env::var_os("RMBRR_HISTORY") {
+            return Some(Self::new(file));
+        }
+        Some(Self::new(state_dir()?.join("history.tsv")))
+    }
+
+    pub fn file(&self) -> &Path {
+        &self.file
+    }
+
+    /// All entries, oldest first (none if there's no history yet)
+    pub fn entries(&self) -> io::Result<Vec<Entry>> {
+        match fs::read_to_string(&self.file) {
+            Ok(text) => Ok(text.lines().filter_map(parse_line).collect()),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(Vec::new()),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// The latest deletion of `path` no longer ago than `within`
+    pub fn recent_deletion(&self, path: &Path, within: Duration) -> Option<Entry> {
+        let now = SystemTime::now();
+        self.entries()
+            .ok()?
+            .into_iter()
+            .rev()
+            .find(|entry| entry.path == path)
+            .filter(|entry| now.duration_since(entry.time).unwrap_or_default() <= within)
+    }
+
+    /// Append `new`, dropping the oldest entries beyond [`MAX_ENTRIES`]
+    pub fn record(&self, new: &[Entry]) -> io::Result<()> {
+        if new.is_empty() {
+            return Ok(());
+        }
+        let mut entries = self.entries().unwrap_or_default();
+        entries.extend(new.iter().cloned());
+        let skip = entries.len().saturating_sub(MAX_ENTRIES);
+
+        let mut text = String::new();
+        for entry in &entries[skip..] {
+            if let Some(line) = format_line(entry) {
+                text.push_str(&line);
+                text.push('\n');
+            }
+        }
+        if let Some(dir) = self.file.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        // Replace in one step so a concurrent reader never sees half a file
+        let temp = self
+            .file
+            .with_extension(format!("tmp{}", std::process::id()));
+        fs::write(&temp, text)?;
+        fs::rename(&temp, &self.file).inspect_err(|_| {
+            fs::remove_file(&temp).ok();
+        })
+    }
+}
+
+/// Per-user state directory: `%LOCALAPPDATA%\rmbrr` on Windows,
+/// `$XDG_STATE_HOME/rmbrr` or `~/.local/state/rmbrr` elsewhere
+pub fn state_dir() -> Option<PathBuf> {
+    let dir = if cfg!(windows) {
+        PathBuf::from(env::var_os("LOCALAPPDATA")?)
+    } else {
+        match env::var_os("XDG_STATE_HOME") {
+            Some(state) if !state.is_empty() => PathBuf::from(state),
+            _ => PathBuf::from(env::var_os("HOME")?).join(".local/state"),
+        }
+    };
+    Some(dir.join("rmbrr"))
+}
+
+fn format_line(entry: &Entry) -> Option<String> {
+    let secs = entry.time.duration_since(UNIX_EPOCH).ok()?.as_secs();
+    let path = entry.path.to_str()?;
+    // A line per entry; such paths just aren't remembered
+    if path.contains('\n') {
+        return None;
+    }
+    Some(format!(
+        "{}\t{}\t{}\t{}",
+        secs, entry.operation, entry.outcome, path
+    ))
+}
+
+fn parse_line(line: &str) -> Option<Entry> {
+    let mut fields = line.splitn(4, '\t');
+    let secs: u64 = fields.next()?.parse().ok()?;
+    Some(Entry {
+        time: UNIX_EPOCH + Duration::from_secs(secs),
+        operation: fields.next()?.to_string(),
+        outcome: fields.next()?.to_string(),
+        path: PathBuf::from(fields.next()?),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(path: &str, ago: Duration) -> Entry {
+        Entry {
+            time: SystemTime::now() - ago,
+            operation: "op".to_string(),
+            outcome: "deleted".to_string(),
+            path: PathBuf::from(path),
+        }
+    }
+
+    #[test]
+    fn test_history_record_and_find() {
+        let temp = env::temp_dir().join("rmbrr_history_test");
+        let _ = fs::remove_dir_all(&temp);
+        let history = History::new(temp.join("state/history.tsv"));
+        assert!(history.entries().unwrap().is_empty());
+
+        history
+            .record(&[
+                entry("/data/build", Duration::from_secs(3600)),
+                entry("/data/cache\twith tab", Duration::from_secs(5)),
+            ])
+            .unwrap();
+        history
+            .record(&[entry("/data/build", Duration::from_secs(30))])
+            .unwrap();
+
+        let entries = history.entries().unwrap();
+        assert_eq!(entries.len(), 3);
+        assert_eq!(entries[1].path, PathBuf::from("/data/cache\twith tab"));
+
+        let within = Duration::from_secs(60);
+        let found = history
+            .recent_deletion(Path::new("/data/build"), within)
+            .unwrap();
+        assert!(SystemTime::now().duration_since(found.time).unwrap() < within);
+        assert!(history
+            .recent_deletion(Path::new("/data/other"), within)
+            .is_none());
+        assert!(history
+            .recent_deletion(Path::new("/data/build"), Duration::from_secs(10))
+            .is_none());
+
+        fs::remove_dir_all(&temp).ok();
+    }
+
+    #[test]
+    fn test_history_keeps_last_entries() {
+        let temp = env::temp_dir().join("rmbrr_history_trim_test");
+        let _ = fs::remove_dir_all(&temp);
+        let history = History::new(temp.join("history.tsv"));
+
+        let many: Vec<Entry> = (0..MAX_ENTRIES + 10)
+            .map(|i| entry(&format!("/t/{}", i), Duration::ZERO))
+            .collect();
+        history.record(&many).unwrap();
+
+        let entries = history.entries().unwrap();
+        assert_eq!(entries.len(), MAX_ENTRIES);
+        assert_eq!(entries[0].path, PathBuf::from("/t/10"));
+
+        fs::remove_dir_all(&temp).ok();
+    }
+}
@@ -0,0 +1,286 @@
+//! Telling whether the machine is idle (`--when-idle`)
+
+use std::time::{Duration, Instant};
+
+/// No keyboard or mouse input for this long counts as nobody at the machine
+pub const INPUT_IDLE_AFTER: Duration = Duration::from_secs(120);
+
+/// Other processes using more than this share of all CPUs counts as busy
+const MAX_OTHER_CPU: f64 = 0.25;
+
+/// Other processes reading more than this many bytes a second from disk
+/// counts as busy
+const MAX_OTHER_READS: f64 = 8.0 * 1024.0 * 1024.0;
+
+/// Watches user input, CPU load and disk reads to decide when deleting
+/// won't get in anyone's way
+///
+/// CPU time and disk reads of this process are left out, so the deletion
+/// itself doesn't count as activity. Disk writes aren't watched: the
+/// filesystem writes what a deletion frees on its own account, not this
+/// process's. User input is only seen on Windows, and disk reads only on
+/// Linux; the CPU load is watched on both, and elsewhere nothing is, so
+/// the machine always counts as idle.
+pub struct IdleMonitor {
+    last: Option<CpuSample>,
+    last_disk: Option<DiskSample>,
+}
+
+/// Cumulative bytes read from disk
+#[derive(Debug, Clone, Copy)]
+struct DiskSample {
+    /// By everything, over all disks
+    read: u64,
+    /// By this process
+    own: u64,
+    at: Instant,
+}
+
+/// Cumulative CPU time, all in the same unit
+#[derive(Debug, Clone, Copy)]
+struct CpuSample {
+    /// Busy time over all CPUs
+    busy: u64,
+    /// Busy plus idle time over all CPUs
+    total: u64,
+    /// Time used by this process
+    own: u64,
+}
+
+impl IdleMonitor {
+    pub fn new() -> Self {
+        Self {
+            last: cpu_sample(),
+            last_disk: disk_sample(),
+        }
+    }
+
+    /// Whether activity on this platform can be observed at all
+    pub fn is_supported() -> bool {
+        cpu_sample().is_some() || input_idle_time().is_some()
+    }
+
+    /// Idle since the previous call (or since the monitor was created)
+    pub fn is_idle(&mut self) -> bool {
+        let (cpu, disk) = (cpu_sample(), disk_sample());
+        let (last, last_disk) = (
+            std::mem::replace(&mut self.last, cpu),
+            std::mem::replace(&mut self.last_disk, disk),
+        );
+        if input_idle_time().is_some_and(|idle| idle < INPUT_IDLE_AFTER) {
+            return false;
+        }
+        let cpu_busy = match (last, cpu) {
+            (Some(before), Some(now)) => other_cpu_share(before, now) > MAX_OTHER_CPU,
+            _ => false,
+        };
+        let disk_busy = match (last_disk, disk) {
+            (Some(before), Some(now)) => other_read_rate(before, now) > MAX_OTHER_READS,
+            _ => false,
+        };
+        !cpu_busy && !disk_busy
+    }
+}
+
+impl Default for IdleMonitor {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Share of all CPU time between two samples used by other processes
+fn other_cpu_share(before: CpuSample, now: CpuSample) -> f64 {
+    let total = now.total.saturating_sub(before.total);
+    if total == 0 {
+        return 0.0;
+    }
+    let busy = now.busy.saturating_sub(before.busy);
+    let own = now.own.saturating_sub(before.own);
+    busy.saturating_sub(own) as f64 / total as f64
+}
+
+/// Bytes a second read from disk by other processes between two samples
+fn other_read_rate(before: DiskSample, now: DiskSample) -> f64 {
+    let seconds = now.at.duration_since(before.at).as_secs_f64();
+    if seconds == 0.0 {
+        return 0.0;
+    }
+    let read = now.read.saturating_sub(before.read);
+    let own = now.own.saturating_sub(before.own);
+    read.saturating_sub(own) as f64 / seconds
+}
+
+/// Bytes read from `/proc/diskstats` and `/proc/self/io`
+///
+/// Only disks backed by a device count: loop, device-mapper and RAM disks
+/// pass their reads on to one, which would count them twice.
+#[cfg(target_os = "linux")]
+fn disk_sample() -> Option<DiskSample> {
+    let stats = std::fs::read_to_string("/proc/diskstats").ok()?;
+    // major minor name reads merged sectors-read ...; sectors of 512 bytes
+    let read = stats
+        .lines()
+        .filter_map(|line| {
+            let mut fields = line.split_whitespace().skip(2);
+            let name = fields.next()?;
+            let sectors: u64 = fields.nth(2)?.parse().ok()?;
+            std::path::Path::new("/sys/block")
+                .join(name)
+                .join("device")
+                .exists()
+                .then_some(sectors * 512)
+        })
+        .sum();
+
+    let io = std::fs::read_to_string("/proc/self/io").ok()?;
+    let own = io
+        .lines()
+        .find_map(|line| line.strip_prefix("read_bytes:"))?
+        .trim()
+        .parse()
+        .ok()?;
+    Some(DiskSample {
+        read,
+        own,
+        at: Instant::now(),
+    })
+}
+
+#[cfg(not(target_os = "linux"))]
+fn disk_sample() -> Option<DiskSample> {
+    None
+}
+
+/// CPU times from `/proc/stat` and `/proc/self/stat`, in clock ticks
+#[cfg(target_os = "linux")]
+fn cpu_sample() -> Option<CpuSample> {
+    let stat = std::fs::read_to_string("/proc/stat").ok()?;
+    // cpu  user nice system idle iowait irq softirq steal ...
+    let times: Vec<u64> = stat
+        .lines()
+        .next()?
+        .split_whitespace()
+        .skip(1)
+        .filter_map(|t| t.parse().ok())
+        .collect();
+    let idle = times.get(3)? + times.get(4).unwrap_or(&0);
+    let total: u64 = times.iter().take(8).sum();
+
+    let own = std::fs::read_to_string("/proc/self/stat").ok()?;
+    // The command name may hold spaces; fields after it start with state (3)
+    let after_name = &own[own.rfind(')')? + 2..];
+    let mut fields = after_name.split_whitespace().skip(11);
+    let utime: u64 = fields.next()?.parse().ok()?;
+    let stime: u64 = fields.next()?.parse().ok()?;
+
+    Some(CpuSample {
+        busy: total - idle,
+        total,
+        own: utime + stime,
+    })
+}
+
+/// CPU times from `GetSystemTimes` and `GetProcessTimes`, in 100ns units
+#[cfg(windows)]
+fn cpu_sample() -> Option<CpuSample> {
+    use windows::Win32::Foundation::FILETIME;
+    use windows::Win32::System::Threading::{GetCurrentProcess, GetProcessTimes, GetSystemTimes};
+
+    let ticks = |t: FILETIME| (u64::from(t.dwHighDateTime) << 32) | u64::from(t.dwLowDateTime);
+    let (mut idle, mut kernel, mut user) = Default::default();
+    let (mut created, mut exited, mut own_kernel, mut own_user) = Default::default();
+    unsafe {
+        GetSystemTimes(Some(&mut idle), Some(&mut kernel), Some(&mut user)).ok()?;
+        GetProcessTimes(
+            GetCurrentProcess(),
+            &mut created,
+            &mut exited,
+            &mut own_kernel,
+            &mut own_user,
+        )
+        .ok()?;
+    }
+    // Kernel time includes idle time
+    let total = ticks(kernel) + ticks(user);
+    Some(CpuSample {
+        busy: total - ticks(idle),
+        total,
+        own: ticks(own_kernel) + ticks(own_user),
+    })
+}
+
+#[cfg(not(any(target_os = "linux", windows)))]
+fn cpu_sample() -> Option<CpuSample> {
+    None
+}
+
+/// Time since the last keyboard or mouse input in this session
+#[cfg(windows)]
+fn input_idle_time() -> Option<Duration> {
+    use windows::Win32::System::SystemInformation::GetTickCount;
+    use windows::Win32::UI::Input::KeyboardAndMouse::{GetLastInputInfo, LASTINPUTINFO};
+
+    let mut info = LASTINPUTINFO {
+        cbSize: std::mem::size_of::<LASTINPUTINFO>() as u32,
+        dwTime: 0,
+    };
+    if !unsafe { GetLastInputInfo(&mut info) }.as_bool() {
+        return None;
+    }
+    // Both wrap after 49.7 days; the wrapping difference stays right
+    let elapsed = unsafe { GetTickCount() }.wrapping_sub(info.dwTime);
+    Some(Duration::from_millis(u64::from(elapsed)))
+}
+
+#[cfg(not(windows))]
+fn input_idle_time() -> Option<Duration> {
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_other_cpu_share_leaves_out_own_time() {
+        let before = CpuSample {
+            busy: 100,
+            total: 1000,
+            own: 50,
+        };
+        let now = CpuSample {
+            busy: 700,
+            total: 2000,
+            own: 550,
+        };
+        // 600 busy of 1000, 500 of it ours
+        assert!((other_cpu_share(before, now) - 0.1).abs() < 1e-9);
+        assert_eq!(other_cpu_share(now, now), 0.0);
+    }
+
+    #[test]
+    fn test_other_read_rate_leaves_out_own_reads() {
+        let at = Instant::now();
+        let before = DiskSample {
+            read: 1000,
+            own: 100,
+            at,
+        };
+        let now = DiskSample {
+            read: 5000,
+            own: 3100,
+            at: at + Duration::from_secs(2),
+        };
+        // 4000 read in 2s, 3000 of it ours
+        assert_eq!(other_read_rate(before, now), 500.0);
+        assert_eq!(other_read_rate(before, before), 0.0);
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn test_cpu_sample_on_linux() {
+        let sample = cpu_sample().unwrap();
+        assert!(sample.total >= sample.busy);
+        assert!(IdleMonitor::is_supported());
+    }
+}
@@ -0,0 +1,299 @@
+//! Deleting as another account (`--as-user`, Windows)
+//!
+//! [`log_on`] logs the account on once per run. Threads that touch targets
+//! then impersonate it through [`enter`]: scan walkers and deletion workers
+//! throughout, the main thread only around its own calls on a target
+//! (checking, renaming or trashing it). Everything else, like the journal,
+//! manifest, keep list and history, is still done as the account running
+//! rmbrr, and the account's own ACLs decide what it may delete.
+
+use std::cell::Cell;
+use std::fmt;
+use std::io;
+use std::str::FromStr;
+
+#[cfg(windows)]
+use std::io::BufRead;
+#[cfg(windows)]
+use std::io::Write;
+#[cfg(windows)]
+use std::sync::OnceLock;
+#[cfg(windows)]
+use windows::core::PCWSTR;
+#[cfg(windows)]
+use windows::Win32::Foundation::{ERROR_LOGON_TYPE_NOT_GRANTED, HANDLE};
+#[cfg(windows)]
+use windows::Win32::Security::{
+    ImpersonateLoggedOnUser, LogonUserW, RevertToSelf, LOGON32_LOGON_BATCH,
+    LOGON32_LOGON_INTERACTIVE, LOGON32_LOGON_SERVICE, LOGON32_PROVIDER_DEFAULT,
+};
+#[cfg(windows)]
+use windows::Win32::System::Console::{
+    GetConsoleMode, GetStdHandle, SetConsoleMode, CONSOLE_MODE, ENABLE_ECHO_INPUT, STD_INPUT_HANDLE,
+};
+
+/// Environment variable holding the password, so scripts aren't prompted
+pub const PASSWORD_VAR: &str = "RMBRR_PASSWORD";
+
+/// The logged-on account's token, for the whole run
+#[cfg(windows)]
+static TOKEN: OnceLock<usize> = OnceLock::new();
+
+thread_local! {
+    /// Guards from [`enter`] alive on this thread
+    static DEPTH: Cell<usize> = const { Cell::new(0) };
+}
+
+/// An account to delete as: `DOMAIN\user`, `user@domain` or a local `user`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Account {
+    /// `None` for `user@domain`, which names its domain itself
+    pub domain: Option<String>,
+    pub user: String,
+}
+
+impl fmt::Display for Account {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.domain {
+            Some(domain) => write!(f, "{}\\{}", domain, self.user),
+            None => write!(f, "{}", self.user),
+        }
+    }
+}
+
+impl FromStr for Account {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (domain, user) = match s.split_once('\\') {
+            Some((domain, user)) => (Some(domain), user),
+            None if s.contains('@') => (None, s),
+            // The local machine's accounts
+            None => (Some("."), s),
+        };
+        if user.is_empty() || domain == Some("") || user.contains('\\') {
+            return Err(format!(
+                "invalid account '{}' (expected DOMAIN\\user, user@domain or user)",
+                s
+            ));
+        }
+        Ok(Account {
+            domain: domain.map(str::to_string),
+            user: user.to_string(),
+        })
+    }
+}
+
+/// Whether [`log_on`] succeeded, so [`enter`] impersonates
+#[cfg(windows)]
+pub fn is_active() -> bool {
+    TOKEN.get().is_some()
+}
+
+#[cfg(not(windows))]
+pub fn is_active() -> bool {
+    false
+}
+
+/// Impersonation of the logged-on account on this thread, until dropped;
+/// does nothing unless [`log_on`] succeeded
+///
+/// Nests: only the outermost guard reverts the thread to itself.
+pub fn enter() -> Impersonation {
+    if is_active() {
+        DEPTH.with(|depth| {
+            if depth.get() == 0 {
+                impersonate().expect("impersonating the --as-user account");
+            }
+            depth.set(depth.get() + 1);
+        });
+    }
+    Impersonation {
+        active: is_active(),
+    }
+}
+
+/// Returned by [`enter`]
+pub struct Impersonation {
+    active: bool,
+}
+
+impl Drop for Impersonation {
+    fn drop(&mut self) {
+        if !self.active {
+            return;
+        }
+        DEPTH.with(|depth| {
+            depth.set(depth.get() - 1);
+            if depth.get() == 0 {
+                revert();
+            }
+        });
+    }
+}
+
+/// A password, overwritten with zeros once dropped
+pub struct Password(String);
+
+impl Password {
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+}
+
+impl Drop for Password {
+    fn drop(&mut self) {
+        let mut bytes = std::mem::take(&mut self.0).into_bytes();
+        bytes.resize(bytes.capacity(), 0);
+        bytes.fill(0);
+        // Writes to memory about to be freed are otherwise dropped
+        std::hint::black_box(&bytes);
+    }
+}
+
+/// The password for `account`: [`PASSWORD_VAR`] if set, else asked for on
+/// the console without echoing it
+#[cfg(windows)]
+pub fn password(account: &Account) -> io::Result<Password> {
+    if let Ok(password) = std::env::var(PASSWORD_VAR) {
+        return Ok(Password(password));
+    }
+    eprint!("{}", crate::msg!(AsUserPassword, account));
+    io::stderr().flush().ok();
+    unsafe {
+        let input = GetStdHandle(STD_INPUT_HANDLE).map_err(os_error)?;
+        let mut mode = CONSOLE_MODE(0);
+        let console = GetConsoleMode(input, &mut mode).is_ok();
+        if console {
+            SetConsoleMode(input, mode & !ENABLE_ECHO_INPUT).map_err(os_error)?;
+        }
+        // Room enough that reading doesn't leave copies behind reallocating
+        let mut line = Password(String::with_capacity(1024));
+        let result = io::stdin().lock().read_line(&mut line.0);
+        if console {
+            SetConsoleMode(input, mode).ok();
+            // The newline typed wasn't echoed either
+            eprintln!();
+        }
+        result?;
+        let len = line.0.trim_end_matches(['\r', '\n']).len();
+        line.0.truncate(len);
+        Ok(line)
+    }
+}
+
+#[cfg(not(windows))]
+pub fn password(_account: &Account) -> io::Result<Password> {
+    Err(unsupported())
+}
+
+/// Log `account` on and make [`enter`] impersonate it
+///
+/// Service accounts often may not log on interactively, so batch and
+/// service logons are tried after an interactive one.
+#[cfg(windows)]
+pub fn log_on(account: &Account, password: &Password) -> io::Result<()> {
+    let user = wide(&account.user);
+    let domain = account.domain.as_deref().map(wide);
+    let mut password = wide(password.as_str());
+    let logon = |logon_type| {
+        let mut token = HANDLE::default();
+        unsafe {
+            LogonUserW(
+                PCWSTR(user.as_ptr()),
+                domain
+                    .as_ref()
+                    .map_or(PCWSTR::null(), |domain| PCWSTR(domain.as_ptr())),
+                PCWSTR(password.as_ptr()),
+                logon_type,
+                LOGON32_PROVIDER_DEFAULT,
+                &mut token,
+            )
+        }
+        .map(|()| token)
+    };
+    let mut result = logon(LOGON32_LOGON_INTERACTIVE);
+    for logon_type in [LOGON32_LOGON_BATCH, LOGON32_LOGON_SERVICE] {
+        match &result {
+            Err(e) if e.code() == ERROR_LOGON_TYPE_NOT_GRANTED.to_hresult() => {
+                result = logon(logon_type)
+            }
+            _ => break,
+        }
+    }
+    password.fill(0);
+    std::hint::black_box(&password);
+    let token = result.map_err(os_error)?;
+    TOKEN.get_or_init(|| token.0 as usize);
+    // Fail now rather than in the workers
+    impersonate()?;
+    revert();
+    Ok(())
+}
+
+#[cfg(not(windows))]
+pub fn log_on(_account: &Account, _password: &Password) -> io::Result<()> {
+    Err(unsupported())
+}
+
+#[cfg(windows)]
+fn impersonate() -> io::Result<()> {
+    let token = *TOKEN.get().expect("impersonating after logging on");
+    unsafe { ImpersonateLoggedOnUser(HANDLE(token as *mut _)) }.map_err(os_error)
+}
+
+#[cfg(not(windows))]
+fn impersonate() -> io::Result<()> {
+    Err(unsupported())
+}
+
+#[cfg(windows)]
+fn revert() {
+    unsafe { RevertToSelf() }.ok();
+}
+
+#[cfg(not(windows))]
+fn revert() {}
+
+#[cfg(windows)]
+fn wide(s: &str) -> Vec<u16> {
+    s.encode_utf16().chain(Some(0)).collect()
+}
+
+#[cfg(windows)]
+fn os_error(e: windows::core::Error) -> io::Error {
+    io::Error::from_raw_os_error(e.code().0 & 0xFFFF)
+}
+
+#[cfg(not(windows))]
+fn unsupported() -> io::Error {
+    io::Error::new(io::ErrorKind::Unsupported, "only available on Windows")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_account() {
+        let account: Account = r"CORP\svc-build".parse().unwrap();
+        assert_eq!(account.domain.as_deref(), Some("CORP"));
+        assert_eq!(account.user, "svc-build");
+        assert_eq!(account.to_string(), r"CORP\svc-build");
+
+        let account: Account = "svc-build@corp.example".parse().unwrap();
+        assert_eq!(account.domain, None);
+        assert_eq!(account.to_string(), "svc-build@corp.example");
+
+        let account: Account = "builder".parse().unwrap();
+        assert_eq!(account.domain.as_deref(), Some("."));
+
+        for invalid in ["", r"CORP\", r"\user", r"A\B\C"] {
+            assert!(invalid.parse::<Account>().is_err(), "{}", invalid);
+        }
+
+        // Nothing to impersonate without logging on
+        assert!(!is_active());
+        drop(enter());
+    }
+}
@@ -0,0 +1,452 @@
+//! Progress of a deletion, kept so an interrupted run can pick up where it
+//! stopped (`--journal`, `--resume`)
+//!
+//! An append-only file of tab-separated lines. Each target's plan comes
+//! first, written once its scan is done:
+//!
+//! ```text
+//! target  <path>
+//! dir     <files>  <path>                     one per directory found
+//! skip    <dir|file>  <reason>  <bytes>  <path>
+//! kept    <path>                              kept once emptied
+//! planned <path>                              the plan is complete
+//! ```
+//!
+//! then `done <path>` for every directory deleted, flushed a batch at a
+//! time. Paths that aren't Unicode, hold a tab or line break or start with
+//! `~` are written as `~` and the hex of their exact spelling. A line cut
+//! short by a crash is ignored, and a plan cut short means the target is
+//! scanned again.
+
+use crate::manifest::{decode_raw, encode_raw};
+use crate::tree::{DirectoryTree, SkipReason, SkippedEntry};
+use std::collections::{HashMap, HashSet};
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, BufWriter, Write};
+use std::path::{Component, Path, PathBuf};
+use std::sync::Mutex;
+
+/// A journal being written
+#[derive(Debug)]
+pub struct Journal {
+    file: PathBuf,
+    out: Mutex<BufWriter<File>>,
+}
+
+impl Journal {
+    /// Append to `file`, creating it if needed
+    pub fn open(file: &Path) -> io::Result<Self> {
+        let mut out = OpenOptions::new()
+            .create(true)
+            .read(true)
+            .append(true)
+            .open(file)?;
+        // Finish off a line torn by a crash, so it doesn't swallow the next
+        if ends_mid_line(&mut out)? {
+            out.write_all(b"\n")?;
+        }
+        Ok(Self {
+            file: file.to_path_buf(),
+            out: Mutex::new(BufWriter::new(out)),
+        })
+    }
+
+    pub fn file(&self) -> &Path {
+        &self.file
+    }
+
+    /// Record that `target` is about to be scanned, so that it is scanned
+    /// again on resume if its plan never follows
+    pub fn begin(&self, target: &Path) -> io::Result<()> {
+        let mut out = self.out.lock().unwrap();
+        writeln!(out, "target\t{}", encode(target))?;
+        out.flush()
+    }
+
+    /// Record what the scan of `target` found, superseding earlier plans
+    /// for it
+    pub fn write_plan(&self, target: &Path, tree: &DirectoryTree) -> io::Result<()> {
+        let mut files: HashMap<&Path, usize> = HashMap::new();
+        for file in &tree.files {
+            if let Some(dir) = file.parent() {
+                *files.entry(dir).or_default() += 1;
+            }
+        }
+
+        let mut out = self.out.lock().unwrap();
+        writeln!(out, "target\t{}", encode(target))?;
+        for dir in &tree.dirs {
+            let count = files.get(dir.as_path()).copied().unwrap_or(0);
+            writeln!(out, "dir\t{}\t{}", count, encode(dir))?;
+        }
+        for entry in &tree.skipped {
+            writeln!(
+                out,
+                "skip\t{}\t{}\t{}\t{}",
+                if entry.is_dir { "dir" } else { "file" },
+                entry.reason.name(),
+                entry.bytes,
+                encode(&entry.path)
+            )?;
+        }
+        for dir in &tree.retained {
+            writeln!(out, "kept\t{}", encode(dir))?;
+        }
+        writeln!(out, "planned\t{}", encode(target))?;
+        out.flush()
+    }
+
+    /// Record `dirs` as deleted
+    pub fn record_done(&self, dirs: &[PathBuf]) -> io::Result<()> {
+        let mut out = self.out.lock().unwrap();
+        for dir in dirs {
+            writeln!(out, "done\t{}", encode(dir))?;
+        }
+        out.flush()
+    }
+}
+
+fn ends_mid_line(file: &mut File) -> io::Result<bool> {
+    use std::io::{Read, Seek, SeekFrom};
+    if file.metadata()?.len() == 0 {
+        return Ok(false);
+    }
+    let mut last = [0u8];
+    file.seek(SeekFrom::End(-1))?;
+    file.read_exact(&mut last)?;
+    Ok(last[0] != b'\n')
+}
+
+/// What a journal says is left to delete
+#[derive(Debug, Default)]
+pub struct Resume {
+    /// The latest plan of each target, in the order they were first planned
+    plans: Vec<Plan>,
+}
+
+#[derive(Debug)]
+struct Plan {
+    target: PathBuf,
+    /// Directories with the number of files directly in each
+    dirs: Vec<(PathBuf, usize)>,
+    skipped: Vec<SkippedEntry>,
+    kept: HashSet<PathBuf>,
+    complete: bool,
+    done: HashSet<PathBuf>,
+}
+
+impl Plan {
+    fn new(target: PathBuf) -> Self {
+        Self {
+            target,
+            dirs: Vec::new(),
+            skipped: Vec::new(),
+            kept: HashSet::new(),
+            complete: false,
+            done: HashSet::new(),
+        }
+    }
+
+    /// Nothing left but directories kept on purpose
+    fn is_finished(&self) -> bool {
+        self.done.contains(&self.target)
+            || (self.complete
+                && self
+                    .dirs
+                    .iter()
+                    .all(|(dir, _)| self.done.contains(dir) || self.kept.contains(dir)))
+    }
+}
+
+/// Read the journal `file`
+pub fn read(file: &Path) -> io::Result<Resume> {
+    let text = String::from_utf8_lossy(&fs::read(file)?).into_owned();
+    // Whatever follows the last line break was being written when the run died
+    let complete = &text[..text.rfind('\n').map_or(0, |end| end + 1)];
+
+    let mut resume = Resume::default();
+    let mut current: Option<usize> = None;
+    for line in complete.lines() {
+        let Some((kind, rest)) = line.split_once('\t') else {
+            continue;
+        };
+        match kind {
+            "target" => {
+                let Some(target) = decode(rest) else {
+                    current = None;
+                    continue;
+                };
+                let index = match resume.plans.iter().position(|p| p.target == target) {
+                    Some(index) => {
+                        resume.plans[index] = Plan::new(target);
+                        index
+                    }
+                    None => {
+                        resume.plans.push(Plan::new(target));
+                        resume.plans.len() - 1
+                    }
+                };
+                current = Some(index);
+            }
+            "done" => {
+                let Some(dir) = decode(rest) else {
+                    continue;
+                };
+                // Targets never nest, so at most one holds it
+                if let Some(plan) = resume.plans.iter_mut().find(|p| dir.starts_with(&p.target)) {
+                    plan.done.insert(dir);
+                }
+            }
+            _ => {
+                if let Some(plan) = current.map(|i| &mut resume.plans[i]) {
+                    parse_plan_line(plan, kind, rest);
+                }
+            }
+        }
+    }
+    Ok(resume)
+}
+
+fn parse_plan_line(plan: &mut Plan, kind: &str, rest: &str) -> Option<()> {
+    // Only ever a path inside the target, whatever the file says
+    let inside = |path: PathBuf| {
+        (path.starts_with(&plan.target) && !path.components().any(|c| c == Component::ParentDir))
+            .then_some(path)
+    };
+    match kind {
+        "dir" => {
+            let (files, path) = rest.split_once('\t')?;
+            plan.dirs
+                .push((inside(decode(path)?)?, files.parse().ok()?));
+        }
+        "skip" => {
+            let mut fields = rest.splitn(4, '\t');
+            let is_dir = fields.next()? == "dir";
+            let reason = SkipReason::from_name(fields.next()?)?;
+            let bytes = fields.next()?.parse().ok()?;
+            plan.skipped.push(SkippedEntry {
+                path: inside(decode(fields.next()?)?)?,
+                is_dir,
+                reason,
+                bytes,
+            });
+        }
+        "kept" => {
+            plan.kept.insert(inside(decode(rest)?)?);
+        }
+        "planned" => plan.complete = decode(rest)? == plan.target,
+        _ => {}
+    }
+    Some(())
+}
+
+impl Resume {
+    /// Targets not yet deleted, in the order they were first planned
+    pub fn targets(&self) -> Vec<PathBuf> {
+        self.plans
+            .iter()
+            .filter(|plan| !plan.is_finished())
+            .map(|plan| plan.target.clone())
+            .collect()
+    }
+
+    /// The directories of `target` not yet deleted, arranged as if just
+    /// scanned; `None` if its plan was never completed, so it needs a scan
+    ///
+    /// Directories deleted after the last record that made it to disk are
+    /// left out too, as they no longer exist.
+    pub fn remaining(&self, target: &Path) -> Option<DirectoryTree> {
+        let plan = self
+            .plans
+            .iter()
+            .find(|plan| plan.target == target && plan.complete)?;
+
+        let left: HashSet<&Path> = plan
+            .dirs
+            .iter()
+            .map(|(dir, _)| dir.as_path())
+            .filter(|dir| !plan.done.contains(*dir) && dir.symlink_metadata().is_ok())
+            .collect();
+        let mut tree = DirectoryTree::new();
+        for (dir, files) in plan
+            .dirs
+            .iter()
+            .filter(|(dir, _)| left.contains(dir.as_path()))
+        {
+            tree.dirs.push(dir.clone());
+            tree.file_count += files;
+            if let Some(parent) = dir
+                .parent()
+                .filter(|p| *dir != plan.target && left.contains(p))
+            {
+                tree.children
+                    .entry(parent.to_path_buf())
+                    .or_default()
+                    .push(dir.clone());
+            }
+        }
+        tree.leaves = tree
+            .dirs
+            .iter()
+            .filter(|dir| !tree.children.contains_key(*dir))
+            .cloned()
+            .collect();
+        tree.skipped = plan.skipped.clone();
+        tree.retained = plan
+            .kept
+            .iter()
+            .filter(|dir| left.contains(dir.as_path()))
+            .cloned()
+            .collect();
+        Some(tree)
+    }
+}
+
+fn encode(path: &Path) -> String {
+    match path.to_str() {
+        Some(text) if !text.contains(['\t', '\n', '\r']) && !text.starts_with('~') => {
+            text.to_string()
+        }
+        _ => format!("~{}", encode_raw(path)),
+    }
+}
+
+fn decode(field: &str) -> Option<PathBuf> {
+    match field.strip_prefix('~') {
+        Some(hex) => decode_raw(hex),
+        None => Some(PathBuf::from(field)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tree_of(root: &Path) -> DirectoryTree {
+        let (a, b, c) = (root.join("a"), root.join("a/b"), root.join("c"));
+        let mut tree = DirectoryTree::new();
+        tree.dirs = vec![root.to_path_buf(), a.clone(), b.clone(), c.clone()];
+        tree.files = vec![b.join("1"), b.join("2"), c.join("3")];
+        tree.file_count = 3;
+        tree.children
+            .insert(root.to_path_buf(), vec![a.clone(), c.clone()]);
+        tree.children.insert(a.clone(), vec![b.clone()]);
+        tree.leaves = vec![b, c.clone()];
+        tree.skipped.push(SkippedEntry {
+            path: c.join("keep.log"),
+            is_dir: false,
+            reason: SkipReason::ExcludedGlob,
+            bytes: 7,
+        });
+        tree.retained = [root.to_path_buf(), c].into_iter().collect();
+        tree
+    }
+
+    #[test]
+    fn test_resume_skips_deleted_dirs() {
+        let temp = std::env::temp_dir().join("rmbrr_journal_test");
+        let _ = fs::remove_dir_all(&temp);
+        fs::create_dir_all(&temp).unwrap();
+        let file = temp.join("journal.tsv");
+        let root = temp.join("target\twith tab");
+        fs::create_dir_all(root.join("a")).unwrap();
+        fs::create_dir_all(root.join("c")).unwrap();
+
+        let journal = Journal::open(&file).unwrap();
+        journal.write_plan(&root, &tree_of(&root)).unwrap();
+        journal.record_done(&[root.join("a/b")]).unwrap();
+        drop(journal);
+        // Torn by a crash part way through a line
+        let mut out = OpenOptions::new().append(true).open(&file).unwrap();
+        write!(out, "done\t{}", root.join("a").display()).unwrap();
+        drop(out);
+
+        let resume = read(&file).unwrap();
+        assert_eq!(resume.targets(), vec![root.clone()]);
+        let tree = resume.remaining(&root).unwrap();
+        assert_eq!(
+            tree.dirs,
+            vec![root.clone(), root.join("a"), root.join("c")]
+        );
+        assert_eq!(tree.file_count, 1);
+        let mut leaves = tree.leaves.clone();
+        leaves.sort();
+        assert_eq!(leaves, vec![root.join("a"), root.join("c")]);
+        assert_eq!(tree.children[&root].len(), 2);
+        assert_eq!(tree.skipped.len(), 1);
+        assert_eq!(tree.skipped[0].reason, SkipReason::ExcludedGlob);
+        assert_eq!(tree.skipped[0].bytes, 7);
+        assert_eq!(tree.retained.len(), 2);
+
+        // Deleted, but the record never made it to disk
+        fs::remove_dir(root.join("a")).unwrap();
+        let tree = read(&file).unwrap().remaining(&root).unwrap();
+        assert_eq!(tree.dirs, vec![root.clone(), root.join("c")]);
+
+        // Only kept directories left: nothing to resume
+        let journal = Journal::open(&file).unwrap();
+        journal.record_done(&[root.join("a")]).unwrap();
+        assert!(read(&file).unwrap().targets().is_empty());
+
+        // A fresh scan supersedes the plan and what was done under it
+        fs::create_dir_all(root.join("a/b")).unwrap();
+        journal.write_plan(&root, &tree_of(&root)).unwrap();
+        let resume = read(&file).unwrap();
+        assert_eq!(resume.remaining(&root).unwrap().dirs.len(), 4);
+
+        fs::remove_dir_all(&temp).ok();
+    }
+
+    #[test]
+    fn test_unfinished_plan_needs_rescan() {
+        let temp = std::env::temp_dir().join("rmbrr_journal_unplanned_test");
+        let _ = fs::remove_dir_all(&temp);
+        fs::create_dir_all(&temp).unwrap();
+        let file = temp.join("journal.tsv");
+        let root = temp.join("target");
+        fs::write(&file, format!("target\t{0}\ndir\t0\t{0}\n", root.display())).unwrap();
+
+        let resume = read(&file).unwrap();
+        assert_eq!(resume.targets(), vec![root.clone()]);
+        assert!(resume.remaining(&root).is_none());
+
+        fs::remove_dir_all(&temp).ok();
+    }
+
+    #[test]
+    fn test_paths_outside_target_ignored() {
+        let temp = std::env::temp_dir().join("rmbrr_journal_outside_test");
+        let _ = fs::remove_dir_all(&temp);
+        let (root, outside) = (temp.join("target"), temp.join("outside"));
+        fs::create_dir_all(&root).unwrap();
+        fs::create_dir_all(&outside).unwrap();
+        let file = temp.join("journal.tsv");
+        fs::write(
+            &file,
+            format!(
+                "target\t{0}\ndir\t0\t{0}\ndir\t1\t{1}\ndir\t1\t{0}/../outside\n\
+                 kept\t{1}\nskip\tfile\texcluded_glob\t1\t{1}/x\nplanned\t{0}\n",
+                root.display(),
+                outside.display()
+            ),
+        )
+        .unwrap();
+
+        let tree = read(&file).unwrap().remaining(&root).unwrap();
+        assert_eq!(tree.dirs, vec![root.clone()]);
+        assert_eq!(tree.file_count, 0);
+        assert!(tree.retained.is_empty() && tree.skipped.is_empty());
+
+        fs::remove_dir_all(&temp).ok();
+    }
+
+    #[test]
+    fn test_encode_round_trips() {
+        for path in ["plain/path", "tab\there", "line\nbreak", "~tilde"] {
+            let field = encode(Path::new(path));
+            assert!(!field.contains(['\t', '\n']));
+            assert_eq!(decode(&field), Some(PathBuf::from(path)));
+        }
+        assert_eq!(encode(Path::new("plain/path")), "plain/path");
+    }
+}
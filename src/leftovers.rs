@@ -0,0 +1,190 @@
+//! Leftovers of rmbrr runs killed part way (`--clean-leftovers`)
+//!
+//! `--rename-first` moves a target to `.rmbrr-delete-<operation id>.<n>`
+//! next to it before deleting it. While that run is alive it holds a lock on
+//! `runs/<operation id>.lock` in the state directory, which the OS releases
+//! when the process dies; a renamed directory whose run holds no lock is
+//! left over and safe to delete.
+
+use crate::operation::{self, OperationId};
+use std::collections::BTreeSet;
+use std::fs::{self, File, TryLockError};
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+/// Name prefix of targets renamed aside by `--rename-first`
+pub const RENAMED_PREFIX: &str = ".rmbrr-delete-";
+
+/// A directory left behind by another run
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Leftover {
+    pub path: PathBuf,
+    /// Operation ID of the run that left it
+    pub operation: String,
+}
+
+/// Name of the `index`th target renamed aside by `operation`
+pub fn renamed_name(operation: OperationId, index: usize) -> String {
+    format!("{}{}.{}", RENAMED_PREFIX, operation, index)
+}
+
+/// Name for the next target this run renames aside
+pub fn next_renamed_name() -> String {
+    static RENAMED: AtomicUsize = AtomicUsize::new(0);
+    renamed_name(
+        operation::current(),
+        RENAMED.fetch_add(1, Ordering::Relaxed),
+    )
+}
+
+/// Whether `path` is a target some run renamed aside
+pub fn is_renamed(path: &Path) -> bool {
+    path.file_name()
+        .and_then(|name| name.to_str())
+        .is_some_and(|name| name.starts_with(RENAMED_PREFIX))
+}
+
+/// `runs` in the per-user state directory
+pub fn default_runs_dir() -> Option<PathBuf> {
+    crate::history::state_dir().map(|dir| dir.join("runs"))
+}
+
+/// Marks one run as alive while held
+#[derive(Debug)]
+pub struct RunLock {
+    file: Option<File>,
+    path: PathBuf,
+}
+
+impl RunLock {
+    /// Mark `operation` as running in `runs` until dropped
+    pub fn acquire(runs: &Path, operation: OperationId) -> io::Result<Self> {
+        fs::create_dir_all(runs)?;
+        let path = lock_file(runs, &operation.to_string());
+        let file = File::create(&path)?;
+        file.lock()?;
+        Ok(Self {
+            file: Some(file),
+            path,
+        })
+    }
+}
+
+impl Drop for RunLock {
+    fn drop(&mut self) {
+        // Closed first: Windows can't delete a file that is still open
+        drop(self.file.take());
+        fs::remove_file(&self.path).ok();
+    }
+}
+
+fn lock_file(runs: &Path, operation: &str) -> PathBuf {
+    runs.join(format!("{}.lock", operation))
+}
+
+/// Whether the run `operation` is still alive, removing its lock file if
+/// the run died
+pub fn is_running(runs: &Path, operation: &str) -> bool {
+    let path = lock_file(runs, operation);
+    let Ok(file) = File::open(&path) else {
+        return false;
+    };
+    match file.try_lock() {
+        Ok(()) => {
+            drop(file);
+            fs::remove_file(&path).ok();
+            false
+        }
+        Err(TryLockError::WouldBlock) => true,
+        // Can't tell, so leave it to its owner
+        Err(TryLockError::Error(_)) => true,
+    }
+}
+
+/// Renamed-aside directories next to `targets` whose run is no longer
+/// alive (the targets themselves excepted)
+pub fn find_leftovers(targets: &[PathBuf], runs: &Path) -> Vec<Leftover> {
+    // Empty for a target named relative to the working directory
+    let parents: BTreeSet<&Path> = targets.iter().filter_map(|t| t.parent()).collect();
+
+    let mut found = Vec::new();
+    for parent in parents {
+        let listed = if parent.as_os_str().is_empty() {
+            fs::read_dir(".")
+        } else {
+            fs::read_dir(parent)
+        };
+        let Ok(entries) = listed else {
+            continue;
+        };
+        for entry in entries.flatten() {
+            let name = entry.file_name();
+            let Some(operation) = name
+                .to_str()
+                .and_then(|n| n.strip_prefix(RENAMED_PREFIX))
+                .and_then(|n| n.split('.').next())
+            else {
+                continue;
+            };
+            // Named the way the targets are, so they compare equal
+            let path = parent.join(&name);
+            if operation.is_empty()
+                || !entry.file_type().is_ok_and(|t| t.is_dir())
+                || targets.contains(&path)
+                || is_running(runs, operation)
+            {
+                continue;
+            }
+            found.push(Leftover {
+                operation: operation.to_string(),
+                path,
+            });
+        }
+    }
+    found.sort_by(|a, b| a.path.cmp(&b.path));
+    found
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_find_leftovers() {
+        let temp = std::env::temp_dir().join("rmbrr_leftovers_test");
+        let _ = fs::remove_dir_all(&temp);
+        let runs = temp.join("state/runs");
+        let target = temp.join("build");
+        fs::create_dir_all(&target).unwrap();
+
+        let alive = OperationId::from_bytes([1; 16]);
+        let dead = OperationId::from_bytes([2; 16]);
+        let lock = RunLock::acquire(&runs, alive).unwrap();
+        fs::create_dir(temp.join(renamed_name(alive, 0))).unwrap();
+        fs::create_dir(temp.join(renamed_name(dead, 3))).unwrap();
+        // A run that died holding the lock file, but not the lock
+        File::create(lock_file(&runs, &dead.to_string())).unwrap();
+        fs::write(temp.join(".rmbrr-delete-file"), "x").unwrap();
+
+        let found = find_leftovers(std::slice::from_ref(&target), &runs);
+        assert_eq!(
+            found,
+            vec![Leftover {
+                path: temp.join(renamed_name(dead, 3)),
+                operation: dead.to_string(),
+            }]
+        );
+        assert!(!lock_file(&runs, &dead.to_string()).exists());
+
+        assert!(is_renamed(&found[0].path) && !is_renamed(&target));
+        // Named as a target itself, it isn't a leftover as well
+        assert!(find_leftovers(&[temp.join(renamed_name(dead, 3))], &runs).is_empty());
+
+        drop(lock);
+        assert!(!lock_file(&runs, &alive.to_string()).exists());
+        assert_eq!(find_leftovers(&[target], &runs).len(), 2);
+
+        fs::remove_dir_all(&temp).ok();
+    }
+}
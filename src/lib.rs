@@ -1,6 +1,46 @@
+pub mod analyze;
 pub mod broker;
+pub mod cancel;
+pub mod chaos;
+pub mod config;
+pub mod deleter;
 pub mod error;
+pub mod estimate;
+pub mod filter;
+pub mod handles;
+pub mod hints;
+pub mod history;
+pub mod idle;
+pub mod impersonate;
+pub mod journal;
+pub mod leftovers;
+pub mod manifest;
+pub mod messages;
+pub mod notify;
+pub mod operation;
+pub mod output;
+pub mod ownership;
+pub mod pathlen;
+pub mod pipeline;
+pub mod preserve;
+#[cfg(feature = "progress")]
+pub mod progress;
+pub mod report;
 pub mod safety;
+pub mod snapshot;
+pub mod staging;
+pub mod stall;
+pub mod sweep;
+pub mod timing;
+#[cfg(feature = "trash")]
+pub mod trash;
 pub mod tree;
+pub mod units;
+#[cfg(target_os = "linux")]
+pub mod uring;
+pub mod volume;
 pub mod winapi;
 pub mod worker;
+
+pub use deleter::{Deleter, Report};
+pub use pipeline::Pipeline;
@@ -1,9 +1,32 @@
-use clap::Parser;
-use rmbrr::{broker::Broker, error::Error, safety, tree, worker};
+use clap::{CommandFactory, Parser, Subcommand};
+#[cfg(feature = "progress")]
+use rmbrr::output;
+#[cfg(feature = "progress")]
+use rmbrr::progress;
+use rmbrr::sweep::{self, ProjectKind};
+use rmbrr::timing::{Stage, Timings};
+#[cfg(feature = "trash")]
+use rmbrr::trash;
+use rmbrr::{
+    analyze,
+    broker::{Broker, ProgressSnapshot},
+    cancel::CancelToken,
+    config,
+    error::{
+        Error, FailureOrder, ReportLevel, EXIT_ERROR, EXIT_INTERRUPTED, EXIT_SUCCESS, EXIT_USAGE,
+    },
+    estimate, filter, handles, hints, history, idle, impersonate, journal, leftovers, manifest,
+    messages, notify, operation, pathlen, preserve, report, safety, snapshot, staging, stall, tree,
+    units, volume, winapi, worker,
+};
+use rmbrr::{errln, msg, outln};
+use std::collections::hash_map::{Entry, HashMap};
+use std::ffi::OsString;
 use std::path::{Path, PathBuf};
 use std::process;
-use std::sync::Arc;
-use std::time::Instant;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, LazyLock, Mutex};
+use std::time::{Duration, Instant, SystemTime};
 
 /// Windows efficient rmdir with cross-platform compatibility
 #[derive(Parser, Debug)]
@@ -20,147 +43,2269 @@ for immediate namespace removal. Benchmarks show 2-6x faster than alternatives l
   rmbrr -n ./build                  Dry run (preview what would be deleted)\n  \
   rmbrr -v ./dist                   Verbose mode (show all errors)\n  \
   rmbrr --stats ./target            Show detailed statistics\n  \
+  rmbrr --estimate ./huge           Quickly project size and run time\n  \
   rmbrr --confirm ./data            Ask for confirmation before deleting\n  \
-  rmbrr ./dir1 ./dir2 ./dir3        Delete multiple directories\n\n\
+  rmbrr ./dir1 ./dir2 ./dir3        Delete multiple directories\n  \
+  rmbrr --largest-first ./a ./b     Delete the biggest target first\n  \
+  rmbrr --keep-from keep.txt ./cache  Delete everything except listed paths\n  \
+  rmbrr --exclude-dir-names .git ./ws  Delete everything but .git directories\n  \
+  rmbrr sweep-projects ~/code -n    List stale build dirs across projects\n  \
+  rmbrr analyze ./node_modules      Show what takes up space, delete nothing\n\n\
 For more information, visit: https://github.com/mtopolski/rmbrr")]
+#[command(subcommand_negates_reqs = true)]
+#[command(args_override_self = true)]
 struct Args {
+    #[command(subcommand)]
+    command: Option<Command>,
+
     /// Target directory(s) to delete
-    #[arg(required = true)]
+    #[arg(required_unless_present = "resume")]
     paths: Vec<PathBuf>,
 
-    /// Number of worker threads (default: logical CPU count)
-    #[arg(short = 't', long)]
+    /// Number of threads scanning, then deleting (default: logical CPU count)
+    #[arg(short = 't', long, global = true)]
     threads: Option<usize>,
 
+    /// Most subdirectories of any one directory deleted at once (default:
+    /// no limit); 1-2 helps spinning disks, where workers spread over many
+    /// siblings mostly wait on seeks
+    #[arg(long, global = true, value_name = "N")]
+    max_per_parent: Option<usize>,
+
     /// Dry run - scan and plan but don't delete anything
-    #[arg(short = 'n', long)]
+    #[arg(short = 'n', long, global = true)]
     dry_run: bool,
 
     /// Show progress and completion messages
-    #[arg(short = 'v', long)]
+    #[arg(short = 'v', long, global = true)]
     verbose: bool,
 
     /// Ignore errors and continue deletion (default behavior)
     #[arg(long, default_value_t = true)]
     ignore_errors: bool,
 
+    /// Exit with a failure code when anything at all went wrong, even what
+    /// doesn't stop targets from being deleted: directories that couldn't
+    /// be listed, a history or journal that couldn't be updated or deleted
+    #[arg(long, global = true)]
+    strict: bool,
+
     /// Ask for confirmation before deleting
-    #[arg(short = 'c', long)]
+    #[arg(short = 'c', long, global = true)]
     confirm: bool,
 
     /// Show detailed statistics at the end
-    #[arg(long)]
+    #[arg(long, global = true)]
     stats: bool,
 
+    /// Print one JSON report (per-target results, failures, timings) on
+    /// stdout instead of the usual text
+    #[arg(long, global = true, conflicts_with_all = ["verbose", "stats", "confirm"])]
+    json: bool,
+
+    /// Which failures to list: errors (just what needs attention), warnings
+    /// (also directories that couldn't be listed) or all (also entries
+    /// something else deleted first); all are still counted
+    #[arg(long, global = true, value_name = "LEVEL", default_value = "all")]
+    report_failures: ReportLevel,
+
+    /// How many of a target's failures to list (a number, or all)
+    #[arg(long, global = true, value_name = "N|all", value_parser = units::parse_limit, default_value = "10")]
+    show_failures: usize,
+
+    /// Order failures are listed in: found (as they happened), kind, path
+    /// or subtree (one from each top-level directory of the target in turn)
+    #[arg(long, global = true, value_name = "ORDER", default_value = "found")]
+    sort_failures: FailureOrder,
+
     /// Force deletion of dangerous paths (use with extreme caution)
-    #[arg(long)]
+    #[arg(long, global = true)]
     force: bool,
+
+    /// Treat targets that don't exist as already deleted rather than as
+    /// errors, like `rm -rf`
+    #[arg(long, global = true)]
+    missing_ok: bool,
+
+    /// Move targets to the trash (Recycle Bin on Windows) instead of
+    /// deleting them permanently
+    #[arg(long, global = true)]
+    trash: bool,
+
+    /// Move targets into this staging area (on the same volume) instead of
+    /// deleting them; `rmbrr purge-staged DIR` deletes them once expired
+    #[arg(long, global = true, value_name = "DIR", conflicts_with_all = ["trash", "rename_first"])]
+    stage: Option<PathBuf>,
+
+    /// How long staged targets are kept before they may be purged
+    #[arg(long, global = true, value_name = "DURATION", value_parser = units::parse_duration, default_value = "7d")]
+    stage_for: Duration,
+
+    /// With multiple paths, estimate sizes and delete the largest first
+    #[arg(long, global = true)]
+    largest_first: bool,
+
+    /// Project item count, size and run time from a sample of subdirectories
+    /// instead of deleting (fast, approximate)
+    #[arg(long, conflicts_with = "json")]
+    estimate: bool,
+
+    /// Keep the paths listed in FILE (one per line, relative to the target)
+    /// and delete everything else
+    #[arg(long, value_name = "FILE")]
+    keep_from: Option<PathBuf>,
+
+    /// Delete everything inside the target but keep the directory itself,
+    /// with its timestamps and permissions as they were
+    #[arg(long, conflicts_with_all = ["rename_first", "stage"])]
+    keep_root: bool,
+
+    /// Rename each target to a hidden sibling (.rmbrr-delete-<id>.<n>) before
+    /// deleting it, so its path can be recreated right away
+    #[arg(long, global = true, conflicts_with = "trash")]
+    rename_first: bool,
+
+    /// Also delete directories that interrupted --rename-first runs left
+    /// next to the targets (otherwise they are only reported)
+    #[arg(long, global = true)]
+    clean_leftovers: bool,
+
+    /// Start deleting while the scan is still running instead of scanning
+    /// the whole tree first (no totals up front, so no progress bar)
+    #[arg(
+        long,
+        conflicts_with_all = ["dry_run", "confirm", "estimate", "trash", "stage", "rename_first", "manifest_out", "byte_progress", "snapshot_above"]
+    )]
+    stream: bool,
+
+    /// Keep every directory with one of these names, at any depth
+    /// (comma-separated, e.g. .git,.svn)
+    #[arg(long, value_delimiter = ',', value_name = "NAMES")]
+    exclude_dir_names: Vec<String>,
+
+    /// Delete only entries matching this glob (repeatable), relative to the
+    /// target: `*.map` matches at any depth, `node_modules/**/*.map` from
+    /// the top; other files and directories are kept
+    #[arg(long, value_name = "GLOB", value_parser = filter::Glob::parse)]
+    include: Vec<filter::Glob>,
+
+    /// Keep entries matching this glob (repeatable), e.g. '*.log'
+    #[arg(long, value_name = "GLOB", value_parser = filter::Glob::parse)]
+    exclude: Vec<filter::Glob>,
+
+    /// Delete only entries last modified longer ago than this (e.g. 7d);
+    /// newer ones stay, and so do the directories holding them
+    #[arg(long, value_name = "DURATION", value_parser = parse_age_cutoff)]
+    older_than: Option<SystemTime>,
+
+    /// Delete only files larger than this (e.g. 100M); smaller files and
+    /// all directories stay
+    #[arg(long, value_name = "SIZE", value_parser = units::parse_bytes)]
+    larger_than: Option<u64>,
+
+    /// Delete only files smaller than this; larger files and all
+    /// directories stay
+    #[arg(long, value_name = "SIZE", value_parser = units::parse_bytes)]
+    smaller_than: Option<u64>,
+
+    /// Delete only entries at least this many levels below the target
+    /// (1: its contents, keeping the target itself); files above stay
+    #[arg(long, value_name = "N", default_value_t = 0)]
+    min_depth: usize,
+
+    /// Delete only entries at most this many levels below the target;
+    /// deeper ones stay, and so do the directories holding them
+    #[arg(long, value_name = "N")]
+    max_depth: Option<usize>,
+
+    /// Refuse a target unless its own name matches one of these globs
+    /// ('|'-separated, e.g. 'node_modules|target|.venv')
+    #[arg(long, global = true, value_name = "NAMES", value_delimiter = '|', value_parser = filter::Glob::parse)]
+    root_must_match: Vec<filter::Glob>,
+
+    /// Write a CSV of every item's path, size and modification time at scan
+    /// time, with what became of it (deleted, failed, skipped, remaining)
+    #[arg(long, value_name = "FILE")]
+    manifest_out: Option<PathBuf>,
+
+    /// Before deleting, report how many paths are longer than N characters
+    /// (`--max-path-length=N`; default 259, all the classic Windows MAX_PATH
+    /// allows), which many other tools can't handle; with --dry-run, also
+    /// list the longest
+    #[arg(
+        long,
+        value_name = "N",
+        num_args = 0..=1,
+        require_equals = true,
+        default_missing_value = "259",
+        conflicts_with_all = ["stream", "json"]
+    )]
+    max_path_length: Option<usize>,
+
+    /// With --dry-run: list what's new, gone or changed since a manifest
+    /// written earlier with --manifest-out
+    #[arg(
+        long,
+        value_name = "FILE",
+        requires = "dry_run",
+        conflicts_with = "json"
+    )]
+    diff: Option<PathBuf>,
+
+    /// With --dry-run: list every directory (d) and file (f) that would be
+    /// deleted, one per line, not just how many
+    #[arg(long, requires = "dry_run", conflicts_with = "json")]
+    list: bool,
+
+    /// With --list: show each file's size
+    #[arg(long, requires = "list")]
+    list_sizes: bool,
+
+    /// Record each target's scan and every directory deleted in FILE, so an
+    /// interrupted run can be finished with --resume
+    #[arg(
+        long,
+        value_name = "FILE",
+        conflicts_with_all = ["dry_run", "estimate", "stream", "trash", "stage", "rename_first", "resume"]
+    )]
+    journal: Option<PathBuf>,
+
+    /// Finish the targets of an interrupted --journal run (or just the ones
+    /// given), skipping what it deleted instead of scanning again; keeps
+    /// journaling to FILE
+    #[arg(
+        long,
+        value_name = "FILE",
+        conflicts_with_all = ["dry_run", "estimate", "stream", "trash", "stage", "rename_first"]
+    )]
+    resume: Option<PathBuf>,
+
+    /// Don't keep or check the local history of recent deletions (used to
+    /// warn when another run deleted the same target moments ago)
+    #[arg(long, global = true)]
+    no_history: bool,
+
+    /// Ignore config.toml and .rmbrr.toml (see README) for this run, apart
+    /// from the directories they protect
+    #[arg(long, global = true)]
+    no_config: bool,
+
+    /// Never delete PATH, or any target holding it (repeatable); like a
+    /// system directory, --force doesn't override this. Wildcards protect
+    /// every match, e.g. 'D:\Shares\*'
+    #[arg(long, global = true, value_name = "PATH")]
+    protect: Vec<PathBuf>,
+
+    /// Delete PATH without --force even though it is (or holds) the working
+    /// directory, and without warning that it was deleted moments ago
+    /// (repeatable, wildcards as for --protect); protected paths stay protected
+    #[arg(long, global = true, value_name = "PATH")]
+    allow: Vec<PathBuf>,
+
+    /// Leave protected directories found inside a target (system or home
+    /// directories) in place and delete around them, instead of refusing
+    #[arg(long)]
+    skip_protected: bool,
+
+    /// Descend into symlinks and junctions to directories and delete what
+    /// they lead to as well (those directories stay, emptied); links into
+    /// the target, to protected directories or to other filesystems are
+    /// still only unlinked. Also needed for a target that is a link itself
+    #[arg(long, overrides_with = "no_follow")]
+    follow_symlinks: bool,
+
+    /// Unlink symlinks and junctions without descending into them (the
+    /// default); mount points are never descended into either way
+    #[arg(long, overrides_with = "follow_symlinks")]
+    no_follow: bool,
+
+    /// When output isn't a terminal (e.g. CI logs), print a progress line this
+    /// often so long runs aren't mistaken for hangs (0 disables)
+    #[arg(
+        long,
+        global = true,
+        value_parser = units::parse_duration,
+        default_value = "30s",
+        value_name = "INTERVAL"
+    )]
+    heartbeat: Duration,
+
+    /// Warn, naming what each worker is on, when nothing has completed for
+    /// this long while deleting (e.g. a hung network share); 0 turns it off
+    #[arg(
+        long,
+        global = true,
+        value_parser = units::parse_duration,
+        default_value = "60s",
+        value_name = "DURATION"
+    )]
+    stall_after: Duration,
+
+    /// Collect file sizes during the scan and drive progress by bytes deleted
+    /// rather than item count (costs a stat per file off Windows)
+    #[arg(long, global = true)]
+    byte_progress: bool,
+
+    /// Snapshot the filesystem (btrfs, ZFS or VSS) before deleting a target
+    /// larger than SIZE (e.g. 10G); without --force, refuse if that fails
+    #[arg(long, global = true, value_name = "SIZE", value_parser = units::parse_bytes)]
+    snapshot_above: Option<u64>,
+
+    /// Delete targets in order only until at least SIZE (e.g. 50G) is free
+    /// on their volume, then stop; the rest are left in place
+    #[arg(long, global = true, value_name = "SIZE", value_parser = units::parse_bytes, conflicts_with = "trash")]
+    free_at_least: Option<u64>,
+
+    /// Delete only while the machine is idle (no recent input on Windows,
+    /// little CPU use and, on Linux, disk reading by other programs),
+    /// pausing whenever it's in use
+    #[arg(long, global = true)]
+    when_idle: bool,
+
+    /// Retry a delete this many times while it fails because something
+    /// else has the file open (antivirus, indexers)
+    #[arg(long, global = true, value_name = "N", default_value_t = 2)]
+    retries: u32,
+
+    /// Wait before the first retry, doubled before each further one
+    #[arg(long, global = true, value_name = "DURATION", value_parser = units::parse_duration, default_value = "10ms")]
+    retry_delay: Duration,
+
+    /// Retry one kind of failure differently (repeatable): KIND=N[:DELAY],
+    /// e.g. in_use=5:50ms, permission_denied=0 (never), not_empty=2 (empty
+    /// the directory again first); kinds as in the JSON report
+    #[arg(long, global = true, value_name = "RULE", value_parser = worker::RetryRule::parse)]
+    retry_on: Vec<worker::RetryRule>,
+
+    /// Fail this percentage of deletes at random, to see how errors are
+    /// handled (debug builds or the `chaos` feature only)
+    #[cfg(any(debug_assertions, feature = "chaos"))]
+    #[arg(long, global = true, value_name = "PERCENT", value_parser = clap::value_parser!(u8).range(0..=100))]
+    chaos: Option<u8>,
+
+    /// With --chaos, wait up to this many milliseconds before each delete
+    #[cfg(any(debug_assertions, feature = "chaos"))]
+    #[arg(long, global = true, value_name = "MS", default_value_t = 0)]
+    chaos_delay_ms: u64,
+
+    /// File deletion backend: sync, nt (Windows: open files relative to a
+    /// handle on their directory) or uring (Linux 5.11+: delete each
+    /// directory's files as one io_uring batch); falls back to sync where
+    /// unsupported
+    #[arg(long, global = true, value_name = "BACKEND", default_value = "sync")]
+    backend: winapi::DeleteBackend,
+
+    /// Experimental: order each directory's files are deleted in; listing
+    /// (as enumerated) or locality (by creation time on Windows, inode
+    /// number elsewhere, aiming at larger contiguous free extents for SSD
+    /// TRIM; see README)
+    #[arg(long, global = true, value_name = "ORDER", default_value = "listing")]
+    delete_order: worker::DeleteOrder,
+
+    /// Directory enumeration backend on Windows: find-first-file (the
+    /// default until nt-query is measured faster) or nt-query
+    #[arg(long, global = true, hide = true, default_value = "find-first-file")]
+    enum_backend: winapi::EnumBackend,
+
+    /// Directory listing buffer size (e.g. 1M) for every directory, instead
+    /// of 64K, or 256K for ones the scan found holding 4096+ entries
+    /// (advanced; no effect on macOS)
+    #[arg(long, global = true, value_name = "SIZE", value_parser = units::parse_bytes)]
+    enum_buffer: Option<u64>,
+
+    /// Close handles of deleted files on this many background threads
+    /// instead of in the workers (Windows; 0 closes inline)
+    #[arg(
+        long,
+        global = true,
+        hide = true,
+        value_name = "N",
+        default_value_t = 0
+    )]
+    close_threads: usize,
+
+    /// When access to an entry is denied because it is read-only, hidden or
+    /// system, clear those attributes and try again (Windows; on by
+    /// default, `--clear-attributes=false` turns it off)
+    #[arg(
+        long,
+        global = true,
+        value_name = "BOOL",
+        num_args = 0..=1,
+        require_equals = true,
+        default_value_t = true,
+        default_missing_value = "true",
+        action = clap::ArgAction::Set
+    )]
+    clear_attributes: bool,
+
+    /// When access to an entry is denied, make yourself its owner with full
+    /// access to it, and try again (Windows, elevated)
+    #[arg(long, global = true)]
+    take_ownership: bool,
+
+    /// What to do about processes holding files in the target open
+    /// (Windows): skip (fail those files), prompt before ending each, or
+    /// kill them
+    #[arg(long, global = true, value_name = "MODE", default_value = "skip")]
+    unlock: handles::Unlock,
+
+    /// Delete as this account (Windows): DOMAIN\user, user@domain or a
+    /// local user, logged on with the password in RMBRR_PASSWORD or asked for
+    #[arg(long, global = true, value_name = "ACCOUNT")]
+    as_user: Option<impersonate::Account>,
+
+    /// Time taken to parse the command line and config files
+    #[arg(skip)]
+    parse_time: Duration,
+}
+
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Delete build-artifact directories of projects that haven't been touched recently
+    SweepProjects {
+        /// Directory to search for projects
+        root: PathBuf,
+
+        /// Project kinds to sweep (comma-separated: rust, node)
+        #[arg(long, value_delimiter = ',', default_value = "rust,node")]
+        kinds: Vec<ProjectKind>,
+
+        /// Only sweep projects idle for at least this long (e.g. 60d, 12h)
+        #[arg(long, value_parser = units::parse_duration, default_value = "30d")]
+        unused_for: Duration,
+    },
+    /// Scan a directory and show size and item counts per subdirectory, deleting nothing
+    Analyze {
+        /// Directory to analyze
+        path: PathBuf,
+
+        /// Break totals down this many levels below the path
+        #[arg(long, default_value_t = 1)]
+        depth: usize,
+
+        /// Show only the largest N subdirectories (0 shows all)
+        #[arg(long, default_value_t = 20)]
+        top: usize,
+    },
+    /// Permanently delete what `--stage` moved into a staging area and whose
+    /// time is up
+    PurgeStaged {
+        /// The staging area
+        dir: PathBuf,
+
+        /// Purge everything staged, expired or not
+        #[arg(long)]
+        all: bool,
+    },
 }
 
+/// Cancelled by Ctrl+C while a scan is in progress
+static SCAN_CANCEL: LazyLock<CancelToken> = LazyLock::new(CancelToken::new);
+/// True while a scan is running
+static SCANNING: AtomicBool = AtomicBool::new(false);
+/// Cancelled by Ctrl+C while deleting: workers finish their directories and stop
+static DELETE_CANCEL: LazyLock<CancelToken> = LazyLock::new(CancelToken::new);
+/// True while workers are deleting; Ctrl+C outside a scan or deletion (or
+/// pressed again) exits immediately
+static DELETING: AtomicBool = AtomicBool::new(false);
+
+/// Shortest gap between progress redraws; faster events are coalesced
+#[cfg(feature = "progress")]
+const PROGRESS_MIN_REDRAW: Duration = Duration::from_millis(100);
+/// Redraw at least this often even without events (keeps the line fresh)
+#[cfg(feature = "progress")]
+const PROGRESS_IDLE_REDRAW: Duration = Duration::from_secs(1);
+/// How often `--when-idle` samples activity
+const IDLE_CHECK: Duration = Duration::from_secs(5);
+/// Longest paths listed by `--max-path-length` in a dry run
+const LONG_PATHS_SHOWN: usize = 10;
+
 fn main() {
-    let args = Args::parse();
+    let start = Instant::now();
+    let mut args = Args::try_parse_from(with_config(std::env::args_os().collect()))
+        .unwrap_or_else(|e| usage_error(e));
+    messages::init();
+    safety::protect(args.protect.iter().cloned());
+    safety::allow(args.allow.iter().cloned());
+    args.parse_time = start.elapsed();
+    winapi::set_enum_backend(args.enum_backend);
+    winapi::set_enum_buffer(
+        args.enum_buffer
+            .map(|size| usize::try_from(size).unwrap_or(usize::MAX)),
+    );
+    winapi::set_delete_backend(args.backend);
+    #[cfg(target_os = "linux")]
+    if args.backend == winapi::DeleteBackend::Uring && !rmbrr::uring::is_supported() {
+        eprintln!("{}", msg!(UringUnsupported));
+    }
+    winapi::set_close_threads(args.close_threads);
+    winapi::set_clear_attributes(args.clear_attributes);
+    if args.take_ownership {
+        if let Err(e) = rmbrr::ownership::enable() {
+            eprintln!("{}", msg!(TakeOwnershipUnavailable, e));
+        }
+    }
+    if let Some(account) = &args.as_user {
+        // Deleting as yourself instead could delete more than asked
+        if let Err(e) = impersonate::password(account)
+            .and_then(|password| impersonate::log_on(account, &password))
+        {
+            eprintln!("{}", msg!(AsUserFailed, account, e));
+            process::exit(EXIT_ERROR);
+        }
+    }
+    #[cfg(any(debug_assertions, feature = "chaos"))]
+    if let Some(fail_percent) = args.chaos {
+        rmbrr::chaos::configure(rmbrr::chaos::ChaosConfig {
+            fail_percent,
+            max_delay: Duration::from_millis(args.chaos_delay_ms),
+        });
+    }
+
+    ctrlc::set_handler(|| {
+        if SCANNING.load(Ordering::SeqCst) && !SCAN_CANCEL.is_cancelled() {
+            SCAN_CANCEL.cancel();
+        } else if DELETING.load(Ordering::SeqCst) && !DELETE_CANCEL.is_cancelled() {
+            DELETE_CANCEL.cancel();
+        } else {
+            process::exit(EXIT_INTERRUPTED);
+        }
+    })
+    .ok();
+
+    reject_unavailable_features(&args);
+    if args.json && matches!(args.command, Some(Command::Analyze { .. })) {
+        usage_error(Args::command().error(
+            clap::error::ErrorKind::ArgumentConflict,
+            "--json is not supported by analyze",
+        ));
+    }
+
+    let mut report = args.json.then(|| report::RunReport::new(args.dry_run));
+    if let Some(report) = &mut report {
+        report.totals.stages_ms.add(Stage::Args, args.parse_time);
+    }
+    let result = match &args.command {
+        Some(Command::SweepProjects {
+            root,
+            kinds,
+            unused_for,
+        }) => run_sweep(root, kinds, *unused_for, &args, report.as_mut()),
+        Some(Command::Analyze { path, depth, top }) => run_analyze(path, *depth, *top, &args),
+        Some(Command::PurgeStaged { dir, all }) => run_purge(dir, *all, &args, report.as_mut()),
+        None => delete_paths(&args.paths, &args, report.as_mut()),
+    };
 
-    if let Err(e) = run(args) {
-        eprintln!("Error: {}", e);
+    if let Some(mut report) = report {
+        report.finish(&result);
+        print_report(&report);
+    }
+    if let Err(e) = result {
+        eprintln!("{}", msg!(ErrorPrefix, e));
         process::exit(e.exit_code());
     }
 }
 
-fn run(args: Args) -> Result<(), Error> {
+/// Print a command-line error and exit with [`EXIT_USAGE`] instead of
+/// clap's 2, which means a partial failure here (`--help` and `--version`
+/// still succeed)
+fn usage_error(e: clap::Error) -> ! {
+    let code = if e.use_stderr() {
+        EXIT_USAGE
+    } else {
+        EXIT_SUCCESS
+    };
+    e.print().ok();
+    process::exit(code)
+}
+
+/// The command line with the config files' arguments put before the
+/// user's; with `--no-config`, only their `protect` lists
+fn with_config(mut argv: Vec<OsString>) -> Vec<OsString> {
+    let mut own = argv.iter().skip(1).take_while(|arg| *arg != "--");
+    if own.any(|arg| arg == "--no-config") {
+        // A policy, not a default: still in force, unless the file is broken
+        let protect = config::sources()
+            .unwrap_or_default()
+            .into_iter()
+            .map(|source| {
+                config::Config {
+                    protect: source.config.protect,
+                    ..Default::default()
+                }
+                .args()
+            });
+        argv.splice(1..1, protect.flatten().collect::<Vec<_>>());
+        return argv;
+    }
+    let defaults = config::sources().and_then(|sources| {
+        let mut defaults = Vec::new();
+        for source in sources {
+            let args = source.config.args();
+            source
+                .config
+                .check(source.local)
+                .and_then(|()| options_only(&args))
+                .map_err(|e| format!("{}: {}", source.file.display(), e))?;
+            defaults.extend(args);
+        }
+        Ok(defaults)
+    });
+    match defaults {
+        Ok(defaults) => {
+            argv.splice(1..1, defaults);
+            argv
+        }
+        Err(e) => usage_error(Args::command().error(
+            clap::error::ErrorKind::InvalidValue,
+            format!("invalid config file {}", e),
+        )),
+    }
+}
+
+/// Refuse config arguments that name targets or a subcommand, or that only
+/// make sense with the rest of the command line (a trailing option would
+/// take its first argument as its value)
+fn options_only(args: &[OsString]) -> Result<(), String> {
+    const TARGET: &str = "<target>";
+    let argv = ["rmbrr".into()]
+        .into_iter()
+        .chain(args.iter().cloned())
+        .chain(["--".into(), TARGET.into()]);
+    let subcommand = "flags can't run a subcommand".to_string();
+    let parsed = Args::try_parse_from(argv).map_err(|e| {
+        // Caught here: the target is taken as the subcommand's argument
+        let command = Args::command();
+        let mut names = command.get_subcommands().map(|command| command.get_name());
+        if names.any(|name| args.iter().any(|arg| arg == name)) {
+            return subcommand.clone();
+        }
+        let text = e.render().to_string();
+        let first = text.lines().next().unwrap_or_default();
+        format!("flags: {}", first.trim_start_matches("error: "))
+    })?;
+    if parsed.command.is_some() {
+        return Err(subcommand);
+    }
+    match parsed.paths.iter().find(|path| *path != Path::new(TARGET)) {
+        Some(path) => Err(format!("flags can't name targets ('{}')", path.display())),
+        None => Ok(()),
+    }
+}
+
+fn run_sweep(
+    root: &Path,
+    kinds: &[ProjectKind],
+    unused_for: Duration,
+    args: &Args,
+    report: Option<&mut report::RunReport>,
+) -> Result<(), Error> {
+    if !root.is_dir() {
+        return Err(Error::InvalidPath {
+            path: root.to_path_buf(),
+            reason: "not a directory".to_string(),
+        });
+    }
+
+    if args.verbose {
+        println!("{}", msg!(SearchingProjects, root.display()));
+    }
+    let candidates = sweep::find_candidates(root, kinds, unused_for)
+        .map_err(|e| Error::io_with_path(root.to_path_buf(), e))?;
+
+    if (args.dry_run || args.verbose) && !args.json {
+        print_sweep_table(&candidates);
+    }
+
+    // A JSON dry run reports each candidate the way a dry-run delete would
+    if (args.dry_run && !args.json) || candidates.is_empty() {
+        return Ok(());
+    }
+
+    let paths: Vec<PathBuf> = candidates.into_iter().map(|c| c.artifact).collect();
+    delete_paths(&paths, args, report)
+}
+
+fn run_purge(
+    dir: &Path,
+    all: bool,
+    args: &Args,
+    report: Option<&mut report::RunReport>,
+) -> Result<(), Error> {
+    let area = staging::StagingArea::new(dir);
+    let expired = if all {
+        area.entries().map(|entries| {
+            entries
+                .into_iter()
+                .filter(|staged| staged.path.exists())
+                .collect::<Vec<_>>()
+        })
+    } else {
+        area.expired(SystemTime::now())
+    }
+    .map_err(|e| Error::io_with_path(dir.to_path_buf(), e))?;
+    if expired.is_empty() {
+        if !args.json {
+            println!("{}", msg!(NothingExpired, dir.display()));
+        }
+        return Ok(());
+    }
+
+    let paths: Vec<PathBuf> = expired.into_iter().map(|staged| staged.path).collect();
+    let result = delete_paths(&paths, args, report);
+    if !args.dry_run {
+        if let Err(e) = area.forget_missing() {
+            eprintln!("{}", msg!(FailedToProcess, dir.display(), e));
+        }
+    }
+    result
+}
+
+fn print_sweep_table(candidates: &[sweep::Candidate]) {
+    if candidates.is_empty() {
+        println!("{}", msg!(NoStaleProjects));
+        return;
+    }
+
+    println!(
+        "{:<6} {:>9} {:>10}  {}",
+        msg!(HeaderKind),
+        msg!(HeaderIdle),
+        msg!(HeaderSize),
+        msg!(HeaderPath)
+    );
+    for candidate in candidates {
+        println!(
+            "{:<6} {:>9} {:>10}  {}",
+            candidate.kind.name(),
+            units::format_age(candidate.age()),
+            units::format_bytes(candidate.size),
+            candidate.artifact.display()
+        );
+    }
+
+    let total: u64 = candidates.iter().map(|c| c.size).sum();
+    println!(
+        "\n{}",
+        msg!(SweepTotal, candidates.len(), units::format_bytes(total))
+    );
+}
+
+/// `--threads`, or one per logical CPU; used for both scanning and deleting
+fn thread_count(args: &Args) -> usize {
+    args.threads.unwrap_or_else(|| {
+        std::thread::available_parallelism()
+            .map(|n| n.get())
+            .unwrap_or(4)
+    })
+}
+
+fn run_analyze(path: &Path, depth: usize, top: usize, args: &Args) -> Result<(), Error> {
+    if !path.is_dir() {
+        return Err(Error::InvalidPath {
+            path: path.to_path_buf(),
+            reason: "not a directory".to_string(),
+        });
+    }
+
+    let start = Instant::now();
+    let scan_progress = tree::ScanProgress::new();
+    let scan_done = AtomicBool::new(false);
+    let threads = thread_count(args);
+    let scan_options = tree::ScanOptions {
+        cancel: Some(SCAN_CANCEL.clone()),
+        threads,
+        ..Default::default()
+    };
+    SCANNING.store(true, Ordering::SeqCst);
+    let tree = std::thread::scope(|s| {
+        if args.verbose {
+            spawn_scan_spinner(s, &scan_progress, &scan_done);
+        }
+        let result = tree::discover_tree_with_options(path, &scan_options, &scan_progress);
+        scan_done.store(true, Ordering::Relaxed);
+        result
+    });
+    SCANNING.store(false, Ordering::SeqCst);
+
+    if SCAN_CANCEL.is_cancelled() {
+        return Err(Error::ScanCancelled {
+            path: path.to_path_buf(),
+        });
+    }
+    let tree = tree.map_err(|e| Error::io_with_path(path.to_path_buf(), e))?;
+
+    let analysis = analyze::analyze(&tree, path, depth, threads);
+    print_analysis(&analysis, top);
+    print_skipped(&tree.skipped, args.verbose);
+    if args.verbose {
+        println!("\n{}", msg!(AnalyzedIn, format!("{:.2?}", start.elapsed())));
+    }
+    Ok(())
+}
+
+fn print_analysis(analysis: &analyze::Analysis, top: usize) {
+    let row = |stats: &analyze::SubtreeStats, label: &dyn std::fmt::Display| {
+        println!(
+            "{:>10} {:>8} {:>8} {:>8}  {}",
+            units::format_bytes(stats.bytes),
+            units::format_count(stats.items()),
+            stats.dirs,
+            stats.files,
+            label
+        );
+    };
+
+    println!(
+        "{:>10} {:>8} {:>8} {:>8}  {}",
+        msg!(HeaderSize),
+        msg!(HeaderItems),
+        msg!(HeaderDirs),
+        msg!(HeaderFiles),
+        msg!(HeaderPath)
+    );
+    let shown = if top == 0 {
+        analysis.subtrees.len()
+    } else {
+        top.min(analysis.subtrees.len())
+    };
+    for stats in &analysis.subtrees[..shown] {
+        row(stats, &stats.path.display());
+    }
+    if shown < analysis.subtrees.len() {
+        println!(
+            "{:>10} {:>8} {:>8} {:>8}  {}",
+            "...",
+            "",
+            "",
+            "",
+            msg!(AnalyzeMore, analysis.subtrees.len() - shown)
+        );
+    }
+    if analysis.root_files.files > 0 {
+        row(&analysis.root_files, &msg!(AnalyzeRootFiles));
+    }
+    row(
+        &analysis.total,
+        &msg!(AnalyzeTotal, analysis.total.path.display()),
+    );
+}
+
+fn delete_paths(
+    paths: &[PathBuf],
+    args: &Args,
+    mut report: Option<&mut report::RunReport>,
+) -> Result<(), Error> {
+    if args.estimate {
+        return estimate_paths(paths, args);
+    }
+    if args.verbose {
+        println!("{}", msg!(OperationStart, operation::current()));
+    }
+
+    let mut manifest = match &args.manifest_out {
+        Some(file) => Some(
+            manifest::Manifest::create(file).map_err(|e| Error::io_with_path(file.clone(), e))?,
+        ),
+        None => None,
+    };
+    let plan = match &args.diff {
+        Some(file) => Some(manifest::read(file).map_err(|e| Error::io_with_path(file.clone(), e))?),
+        None => None,
+    };
+
+    let resume = match &args.resume {
+        Some(file) => Some(journal::read(file).map_err(|e| Error::io_with_path(file.clone(), e))?),
+        None => None,
+    };
+    let journal = match args.journal.as_ref().or(args.resume.as_ref()) {
+        Some(file) => Some(Arc::new(
+            journal::Journal::open(file).map_err(|e| Error::io_with_path(file.clone(), e))?,
+        )),
+        None => None,
+    };
+    // Journaled by absolute path, so a resume can start in any directory
+    let journaled: Vec<PathBuf>;
+    let paths = match (&journal, &resume) {
+        (None, _) => paths,
+        (Some(journal), Some(resume)) if paths.is_empty() => {
+            journaled = resume.targets();
+            if journaled.is_empty() {
+                println!("{}", msg!(NothingToResume, journal.file().display()));
+                return Ok(());
+            }
+            &journaled[..]
+        }
+        (Some(_), _) => {
+            journaled = paths
+                .iter()
+                .map(|p| std::path::absolute(p).unwrap_or_else(|_| p.clone()))
+                .collect();
+            &journaled[..]
+        }
+    };
+
+    if args.when_idle && !args.dry_run {
+        wait_for_idle();
+    }
+
+    let mut unique = dedupe_targets(paths, args.verbose);
+    // Held while renamed targets are deleted, so other runs leave them alone
+    let _run_lock = match leftovers::default_runs_dir() {
+        Some(runs) if args.rename_first && !args.dry_run => {
+            leftovers::RunLock::acquire(&runs, operation::current()).ok()
+        }
+        _ => None,
+    };
+    if let Some(runs) = leftovers::default_runs_dir() {
+        unique.extend(check_leftovers(&unique, &runs, args));
+    }
+    let paths = &unique[..];
+
+    let ordered;
+    let paths = if args.largest_first && paths.len() > 1 {
+        ordered = order_by_size(paths, args.verbose);
+        &ordered[..]
+    } else {
+        paths
+    };
+
+    // Nothing deleted, nothing worth remembering
+    let history = (!args.no_history && !args.dry_run)
+        .then(history::History::default_location)
+        .flatten();
+    let history_keys: Vec<PathBuf> = match &history {
+        Some(history) => {
+            let keys: Vec<PathBuf> = paths.iter().map(|p| history_key(p)).collect();
+            warn_recent_deletions(history, paths, &keys);
+            keys
+        }
+        None => Vec::new(),
+    };
+    let mut remembered = Vec::new();
+
     let mut total_stats = DeletionStats::default();
     let mut all_failures = Vec::new();
     let mut failed_paths = Vec::new();
+    // Items in paths that only partially failed, and paths that failed outright
+    let mut partial_items = 0;
+    let mut outright = Vec::new();
+    // What went wrong without failing anything, for --strict
+    let mut warnings = 0;
+
+    // Scan every target up front so progress can be weighted across all of them
+    let mut scanned = Vec::with_capacity(paths.len());
+    // Targets left alone because --free-at-least was already satisfied
+    let mut targets_spared = 0;
+    let mut last_free = 0;
+    // Targets deleted without failures
+    let mut cleared = Vec::new();
+    for (i, path) in paths.iter().enumerate() {
+        if paths.len() > 1 && args.verbose {
+            println!(
+                "\n{}",
+                msg!(ScanningTarget, i + 1, paths.len(), path.display())
+            );
+        }
+
+        if args.missing_ok && is_missing(path) {
+            if args.verbose {
+                println!("{}", msg!(TargetMissing, path.display()));
+            }
+            if let Some(report) = report.as_deref_mut() {
+                report.push(report::TargetReport::missing(path.clone()));
+            }
+            continue;
+        }
+
+        let resumed = resume.as_ref().and_then(|resume| resume.remaining(path));
+        let fresh = resumed.is_none();
+        if let Some(journal) = journal.as_ref().filter(|_| fresh) {
+            journal
+                .begin(path)
+                .map_err(|e| Error::io_with_path(journal.file().to_path_buf(), e))?;
+        }
+        match scan_path(path, args, resumed) {
+            Ok(mut scan) => {
+                if let Some(journal) = journal.as_ref().filter(|_| fresh) {
+                    // Without the plan, a resume would have nothing to go on
+                    journal
+                        .write_plan(path, &scan.tree)
+                        .map_err(|e| Error::io_with_path(journal.file().to_path_buf(), e))?;
+                }
+                if manifest.is_some() || plan.is_some() {
+                    scan.manifest = manifest::record(&scan.tree);
+                }
+                scanned.push((i, path, scan))
+            }
+            Err(e @ Error::ScanCancelled { .. }) => return Err(e),
+            Err(e) => {
+                eprintln!("{}", msg!(FailedToProcess, path.display(), e));
+                if let Some(report) = report.as_deref_mut() {
+                    report.push(report::TargetReport::failed(path.clone(), &e));
+                }
+                failed_paths.push(path.to_path_buf());
+                outright.push(e);
+            }
+        }
+    }
 
-    for (i, path) in args.paths.iter().enumerate() {
-        if args.paths.len() > 1 && args.verbose {
+    let mut run = RunProgress {
+        items_before: 0,
+        items_total: scanned.iter().map(|(_, _, scan)| scan.item_count()).sum(),
+        bytes_before: 0,
+        bytes_total: scanned
+            .iter()
+            .map(|(_, _, scan)| scan.tree.total_bytes())
+            .sum(),
+        multi: paths.len() > 1,
+        started: Instant::now(),
+    };
+
+    for (i, path, mut scan) in scanned {
+        if paths.len() > 1 && args.verbose {
             println!(
-                "\n[{}/{}] Processing: {}",
-                i + 1,
-                args.paths.len(),
-                path.display()
+                "\n{}",
+                msg!(ProcessingTarget, i + 1, paths.len(), path.display())
             );
         }
 
-        match process_single_path(path, &args) {
+        let items = scan.item_count();
+        let bytes = scan.tree.total_bytes();
+        let entries = std::mem::take(&mut scan.manifest);
+        if let (Some(plan), Some(file)) = (&plan, &args.diff) {
+            print_diff(&manifest::diff(plan, path, &entries), path, file);
+        }
+        let spared = match free_goal_met(path, args)? {
+            Some(free) => {
+                targets_spared += 1;
+                last_free = free;
+                true
+            }
+            None => false,
+        };
+        let mut result = if spared {
+            Ok(DeletionStats {
+                timings: scan.timings,
+                ..Default::default()
+            })
+        } else {
+            delete_scanned(path, scan, args, run, journal.clone())
+        };
+        // The trash tells the shell itself
+        if !spared && !args.dry_run && !args.trash {
+            let notify_start = Instant::now();
+            notify::deleted(path);
+            if let Ok(stats) = &mut result {
+                stats.timings.since(Stage::Finish, notify_start);
+            }
+        }
+        run.items_before += items;
+        run.bytes_before += bytes;
+
+        if let Some(report) = report.as_deref_mut() {
+            report.push(target_report(path, &result, spared, args));
+        }
+        let result = result.and_then(DeletionStats::into_result);
+
+        let outcome = match &result {
+            Ok(stats) if stats.total_items() > 0 => Some(removed_as(args)),
+            Err(Error::PartialFailure { .. }) => Some("partial"),
+            _ => None,
+        };
+        if let (Some(outcome), Some(key)) = (outcome, history_keys.get(i)) {
+            remembered.push(history::Entry {
+                time: SystemTime::now(),
+                operation: operation::current().to_string(),
+                outcome: outcome.to_string(),
+                path: key.clone(),
+            });
+        }
+
+        if let (Some(manifest), Some(file)) = (&mut manifest, &args.manifest_out) {
+            let failures = match &result {
+                Err(Error::PartialFailure { errors, .. }) => &errors[..],
+                _ => &[],
+            };
+            // An incomplete audit record is worse than stopping early
+            manifest
+                .write_target(&entries, failures, removed_as(args))
+                .map_err(|e| Error::io_with_path(file.clone(), e))?;
+        }
+
+        match result {
             Ok(stats) => {
                 total_stats.merge(&stats);
+                warnings += stats.warnings.len();
+                if !spared {
+                    cleared.push(path.to_path_buf());
+                }
+            }
+            // A bug, not a property of this path: don't carry on with the rest
+            Err(e @ Error::WorkerPanic { .. }) => return Err(e),
+            // Ctrl+C during a streamed scan or deletion
+            Err(e @ (Error::StreamCancelled { .. } | Error::DeleteCancelled { .. })) => {
+                return Err(e)
             }
             Err(e) => {
-                eprintln!("Failed to process {}: {}", path.display(), e);
+                eprintln!("{}", msg!(FailedToProcess, path.display(), e));
                 failed_paths.push(path.to_path_buf());
-                if let Error::PartialFailure { errors, .. } = e {
+                if let Error::PartialFailure { total, errors, .. } = e {
+                    partial_items += total;
                     all_failures.extend(errors);
+                } else {
+                    outright.push(e);
                 }
             }
         }
     }
 
-    if args.paths.len() > 1 && args.verbose {
-        print_summary(&total_stats, &all_failures, &failed_paths, &args);
+    if let Some(history) = &history {
+        if let Err(e) = history.record(&remembered) {
+            warnings += 1;
+            if args.verbose || args.strict {
+                eprintln!("{}", msg!(HistoryUnwritable, history.file().display(), e));
+            }
+        }
     }
 
-    if !failed_paths.is_empty() || !all_failures.is_empty() {
-        Err(Error::PartialFailure {
-            total: total_stats.total_items(),
-            failed: all_failures.len() + failed_paths.len(),
-            errors: all_failures,
-        })
-    } else {
-        Ok(())
+    if paths.len() > 1 && args.verbose {
+        print_summary(
+            paths.len(),
+            &total_stats,
+            &all_failures,
+            &failed_paths,
+            args,
+        );
+    }
+
+    if let Some(goal) = args.free_at_least {
+        if targets_spared > 0 && !args.json {
+            println!(
+                "{}",
+                msg!(
+                    FreeGoalReached,
+                    units::format_bytes(last_free),
+                    units::format_bytes(goal),
+                    targets_spared
+                )
+            );
+        }
+    }
+
+    // With nothing left to resume or audit, rmbrr's own files inside the
+    // targets go too; closed first, as Windows won't delete open files
+    if failed_paths.is_empty() && all_failures.is_empty() && !args.dry_run {
+        drop(journal);
+        drop(manifest);
+        warnings += remove_own_files(&cleared, args);
+    }
+
+    // Targets that all failed the same way, before anything in them was
+    // deleted, say how (one that didn't exist, say) rather than "partial"
+    let same_way = outright
+        .first()
+        .is_some_and(|first| outright.iter().all(|e| e.exit_code() == first.exit_code()));
+    if same_way && all_failures.is_empty() && partial_items == 0 {
+        Err(outright.swap_remove(0))
+    } else if !failed_paths.is_empty() || !all_failures.is_empty() {
+        Err(Error::PartialFailure {
+            total: total_stats.total_items() + partial_items + outright.len(),
+            failed: all_failures.len() + outright.len(),
+            errors: all_failures,
+        })
+    } else if let Some(shortfall) = free_goal_shortfall(paths, args)? {
+        Err(shortfall)
+    } else if args.strict && warnings > 0 {
+        Err(Error::Warnings { count: warnings })
+    } else {
+        Ok(())
+    }
+}
+
+/// Files the run writes as it goes, which may be inside a target
+fn own_files(args: &Args) -> impl Iterator<Item = &PathBuf> {
+    args.journal
+        .iter()
+        .chain(&args.resume)
+        .chain(&args.manifest_out)
+}
+
+/// Delete rmbrr's own files left inside `cleared` targets until the end of
+/// the run, then the directories that leaves empty, up to the target;
+/// returns how many couldn't be deleted
+fn remove_own_files(cleared: &[PathBuf], args: &Args) -> usize {
+    let mut left = 0;
+    for target in cleared {
+        for file in own_files(args) {
+            let Some(file) = filter::inside(target, file) else {
+                continue;
+            };
+            match std::fs::remove_file(&file) {
+                Ok(()) if !args.json => println!("{}", msg!(OwnFileDeleted, file.display())),
+                Ok(()) => {}
+                Err(e) if e.kind() == std::io::ErrorKind::NotFound => continue,
+                Err(e) => {
+                    eprintln!("{}", msg!(OwnFileLeft, file.display(), e));
+                    left += 1;
+                    continue;
+                }
+            }
+            for dir in file.ancestors().skip(1) {
+                if args.keep_root && dir == target.as_path() {
+                    break;
+                }
+                // Not empty yet, while another of the files is left
+                if std::fs::remove_dir(dir).is_err() || dir == target.as_path() {
+                    break;
+                }
+            }
+        }
+    }
+    left
+}
+
+/// The staging area targets are moved into instead of being deleted
+/// (`--stage`), except for the ones `purge-staged` deletes from it
+fn staging_area(args: &Args) -> Option<staging::StagingArea> {
+    match args.command {
+        Some(Command::PurgeStaged { .. }) => None,
+        _ => args.stage.as_ref().map(staging::StagingArea::new),
+    }
+}
+
+/// What became of targets that are gone, in the history and manifests
+fn removed_as(args: &Args) -> &'static str {
+    if args.trash {
+        "trashed"
+    } else if staging_area(args).is_some() {
+        "staged"
+    } else {
+        "deleted"
+    }
+}
+
+/// `--older-than`: the moment entries must be older than, fixed at startup
+fn parse_age_cutoff(input: &str) -> Result<SystemTime, String> {
+    let age = units::parse_duration(input)?;
+    // Further back than the clock goes: nothing is that old
+    Ok(SystemTime::now()
+        .checked_sub(age)
+        .unwrap_or(std::time::UNIX_EPOCH))
+}
+
+/// Rename `path` to a unique hidden sibling, freeing its name at once
+fn rename_aside(path: &Path) -> std::io::Result<PathBuf> {
+    let _as_user = impersonate::enter();
+    let aside = path.with_file_name(leftovers::next_renamed_name());
+    std::fs::rename(path, &aside)?;
+    Ok(aside)
+}
+
+/// How a target is named in the history: absolute, symlinks resolved
+fn history_key(path: &Path) -> PathBuf {
+    let identity = safety::target_identity(path);
+    std::path::absolute(&identity).unwrap_or(identity)
+}
+
+/// Warn about targets another run deleted moments ago (unless `--allow`ed)
+fn warn_recent_deletions(history: &history::History, paths: &[PathBuf], keys: &[PathBuf]) {
+    for (path, key) in paths.iter().zip(keys) {
+        if safety::is_allowed(path) {
+            continue;
+        }
+        if let Some(entry) = history.recent_deletion(key, history::DOUBLE_RUN_WINDOW) {
+            let ago = SystemTime::now()
+                .duration_since(entry.time)
+                .unwrap_or_default();
+            eprintln!(
+                "{}",
+                msg!(
+                    RecentlyDeleted,
+                    path.display(),
+                    units::format_age(ago),
+                    entry.operation
+                )
+            );
+        }
+    }
+}
+
+/// JSON report entry for a target that was scanned
+fn target_report(
+    path: &Path,
+    result: &Result<DeletionStats, Error>,
+    spared: bool,
+    args: &Args,
+) -> report::TargetReport {
+    let stats = match result {
+        Ok(stats) => stats,
+        Err(e) => return report::TargetReport::failed(path.to_path_buf(), e),
+    };
+    let outcome = if spared {
+        report::Outcome::Spared
+    } else if args.dry_run {
+        report::Outcome::WouldDelete
+    } else if !stats.failures.is_empty() || stats.dirs_blocked > 0 {
+        report::Outcome::Partial
+    } else if args.trash {
+        report::Outcome::Trashed
+    } else if staging_area(args).is_some() {
+        report::Outcome::Staged
+    } else {
+        report::Outcome::Deleted
+    };
+    report::TargetReport {
+        path: path.to_path_buf(),
+        outcome,
+        error: None,
+        error_code: None,
+        dirs_deleted: stats.dirs_deleted,
+        files_deleted: stats.files_deleted,
+        bytes_freed: stats.bytes_freed,
+        items_skipped: stats.items_skipped,
+        bytes_skipped: stats.bytes_skipped,
+        dirs_blocked: stats.dirs_blocked,
+        scan_time: stats.timings.get(Stage::Scan),
+        delete_time: stats.timings.get(Stage::Delete),
+        stages: stats.timings,
+        snapshots: stats.snapshots.iter().map(|s| s.to_string()).collect(),
+        failures: stats.failures.clone(),
+        warnings: stats.warnings.clone(),
+        stalls: stats.stalls.clone(),
+    }
+}
+
+/// Free space on `path`'s volume, if `--free-at-least` is set and already met
+fn free_goal_met(path: &Path, args: &Args) -> Result<Option<u64>, Error> {
+    let Some(goal) = args.free_at_least else {
+        return Ok(None);
+    };
+    // Deleting blind would defeat the point of the goal
+    let free = volume::available(path).map_err(|e| Error::io_with_path(path.to_path_buf(), e))?;
+    if free < goal {
+        return Ok(None);
+    }
+    if args.verbose {
+        println!(
+            "{}",
+            msg!(
+                FreeGoalMet,
+                path.display(),
+                units::format_bytes(free),
+                units::format_bytes(goal)
+            )
+        );
+    }
+    Ok(Some(free))
+}
+
+/// `--free-at-least` goal still unmet on some target's volume after the run
+fn free_goal_shortfall(paths: &[PathBuf], args: &Args) -> Result<Option<Error>, Error> {
+    let Some(goal) = args.free_at_least else {
+        return Ok(None);
+    };
+    if args.dry_run {
+        return Ok(None);
+    }
+    for path in paths {
+        let free =
+            volume::available(path).map_err(|e| Error::io_with_path(path.to_path_buf(), e))?;
+        if free < goal {
+            return Ok(Some(Error::InsufficientSpace {
+                path: path.to_path_buf(),
+                available: free,
+                wanted: goal,
+            }));
+        }
+    }
+    Ok(None)
+}
+
+/// Whether nothing at all is at `path`, for `--missing-ok`; not so for a
+/// broken symlink or a parent that is a file, which still fail
+fn is_missing(path: &Path) -> bool {
+    matches!(path.symlink_metadata(), Err(e) if e.kind() == std::io::ErrorKind::NotFound)
+}
+
+/// Print a sampled size estimate for each path, deleting nothing
+fn estimate_paths(paths: &[PathBuf], args: &Args) -> Result<(), Error> {
+    for path in paths {
+        if args.missing_ok && is_missing(path) {
+            println!("{}", msg!(TargetMissing, path.display()));
+            continue;
+        }
+        if !path.is_dir() {
+            return Err(Error::InvalidPath {
+                path: path.to_path_buf(),
+                reason: "not a directory".to_string(),
+            });
+        }
+
+        let estimate = estimate::estimate(path, estimate::DEFAULT_SAMPLE_SIZE);
+        let count = |v: f64| units::format_count(v as usize);
+        let bytes = |v: f64| units::format_bytes(v as u64);
+        let eta = |v: f64| units::format_eta(estimate.duration_for(v));
+
+        if estimate.is_exact() {
+            println!(
+                "{}",
+                msg!(
+                    EstimateExact,
+                    path.display(),
+                    format!("{:.2?}", estimate.elapsed)
+                )
+            );
+            println!("{}", msg!(EstimateItems, count(estimate.items.expected)));
+            println!("{}", msg!(EstimateSize, bytes(estimate.bytes.expected)));
+            println!("{}", msg!(EstimateDuration, eta(estimate.items.expected)));
+        } else {
+            println!(
+                "{}",
+                msg!(
+                    EstimateSampled,
+                    path.display(),
+                    estimate.sampled,
+                    estimate.population,
+                    format!("{:.2?}", estimate.elapsed)
+                )
+            );
+            let (items, size) = (estimate.items, estimate.bytes);
+            println!(
+                "{}",
+                msg!(
+                    EstimateItemsRange,
+                    count(items.expected),
+                    count(items.low),
+                    count(items.high)
+                )
+            );
+            println!(
+                "{}",
+                msg!(
+                    EstimateSizeRange,
+                    bytes(size.expected),
+                    bytes(size.low),
+                    bytes(size.high)
+                )
+            );
+            println!(
+                "{}",
+                msg!(
+                    EstimateDurationRange,
+                    eta(items.expected),
+                    eta(items.low),
+                    eta(items.high)
+                )
+            );
+        }
+    }
+    Ok(())
+}
+
+/// Exit with a usage error for flags whose feature this build left out
+fn reject_unavailable_features(args: &Args) {
+    let missing = [
+        (args.json && !cfg!(feature = "json"), "--json", "json"),
+        (args.trash && !cfg!(feature = "trash"), "--trash", "trash"),
+    ];
+    if let Some((_, flag, feature)) = missing.into_iter().find(|(used, ..)| *used) {
+        usage_error(Args::command().error(
+            clap::error::ErrorKind::InvalidValue,
+            format!(
+                "{} is not available: rmbrr was built without the `{}` feature",
+                flag, feature
+            ),
+        ));
+    }
+    // Processes are named on Unix too, but only Windows can end them
+    if !cfg!(windows) && args.unlock != handles::Unlock::Skip {
+        usage_error(Args::command().error(
+            clap::error::ErrorKind::InvalidValue,
+            "--unlock prompt and --unlock kill are only available on Windows",
+        ));
     }
 }
 
+#[cfg(feature = "json")]
+fn print_report(report: &report::RunReport) {
+    println!(
+        "{}",
+        serde_json::to_string_pretty(report).expect("report is serializable")
+    );
+}
+
+#[cfg(not(feature = "json"))]
+fn print_report(_report: &report::RunReport) {
+    unreachable!("--json is rejected without the json feature")
+}
+
+/// Show a spinner with live discovery counts until `done` is set
+#[cfg(feature = "progress")]
+fn spawn_scan_spinner<'scope>(
+    scope: &'scope std::thread::Scope<'scope, '_>,
+    progress: &'scope tree::ScanProgress,
+    done: &'scope AtomicBool,
+) {
+    scope.spawn(|| show_scan_spinner(progress, done));
+}
+
+#[cfg(not(feature = "progress"))]
+fn spawn_scan_spinner<'scope>(
+    _scope: &'scope std::thread::Scope<'scope, '_>,
+    _progress: &'scope tree::ScanProgress,
+    _done: &'scope AtomicBool,
+) {
+}
+
+#[cfg(feature = "progress")]
+fn show_scan_spinner(progress: &tree::ScanProgress, done: &AtomicBool) {
+    const FRAMES: [char; 4] = ['|', '/', '-', '\\'];
+    let mut frame = 0;
+    let mut ticks = 0;
+    while !done.load(Ordering::Relaxed) {
+        // Redraw every 100ms (starting after the first, so quick scans stay
+        // quiet) but poll often so short scans aren't held up
+        if ticks % 10 == 9 {
+            output::status(&msg!(
+                ScanSpinner,
+                FRAMES[frame % FRAMES.len()],
+                progress.dirs(),
+                progress.files()
+            ));
+            frame += 1;
+        }
+        ticks += 1;
+        std::thread::sleep(Duration::from_millis(10));
+    }
+    if frame > 0 {
+        output::clear_status();
+    }
+}
+
+/// Heartbeat interval, if heartbeats are enabled and stderr isn't a terminal
+fn heartbeat_interval(args: &Args) -> Option<Duration> {
+    use std::io::IsTerminal;
+    (!args.heartbeat.is_zero() && !std::io::stderr().is_terminal()).then_some(args.heartbeat)
+}
+
+/// Print `line()` to stderr every `interval` until `done` is set
+///
+/// One self-contained line per beat, so CI logs keep a progress history and
+/// runners with inactivity timeouts see output during long deletions.
+fn heartbeat(interval: Duration, done: &AtomicBool, line: impl Fn() -> String) {
+    let mut last = Instant::now();
+    while !done.load(Ordering::Relaxed) {
+        std::thread::sleep(Duration::from_millis(50));
+        if last.elapsed() >= interval {
+            eprintln!("rmbrr: {}", line());
+            last = Instant::now();
+        }
+    }
+}
+
+/// Block until the machine is idle (`--when-idle`)
+fn wait_for_idle() {
+    if !idle::IdleMonitor::is_supported() {
+        eprintln!("{}", msg!(IdleUnsupported));
+        return;
+    }
+    let mut monitor = idle::IdleMonitor::new();
+    let mut announced = false;
+    loop {
+        std::thread::sleep(IDLE_CHECK);
+        if monitor.is_idle() {
+            return;
+        }
+        if !announced {
+            eprintln!("{}", msg!(IdleWaiting));
+            announced = true;
+        }
+    }
+}
+
+/// Pause the workers while the machine is in use, until `done` is set
+fn watch_idle(broker: &Broker, done: &AtomicBool) {
+    let mut monitor = idle::IdleMonitor::new();
+    let mut last = Instant::now();
+    while !done.load(Ordering::Relaxed) {
+        std::thread::sleep(Duration::from_millis(50));
+        if last.elapsed() < IDLE_CHECK {
+            continue;
+        }
+        last = Instant::now();
+        match (monitor.is_idle(), broker.is_paused()) {
+            (true, true) => {
+                broker.resume();
+                errln!("{}", msg!(IdleResumed));
+            }
+            (false, false) => {
+                broker.pause();
+                errln!("{}", msg!(IdlePaused));
+            }
+            _ => {}
+        }
+    }
+}
+
+/// On Ctrl+C, stop handing out directories; workers finish the ones they
+/// are on
+fn watch_cancel(broker: &Broker, done: &AtomicBool) {
+    while !done.load(Ordering::Relaxed) {
+        std::thread::sleep(Duration::from_millis(50));
+        if DELETE_CANCEL.is_cancelled() {
+            errln!("\n{}", msg!(CancelStopping));
+            broker.abort();
+            return;
+        }
+    }
+}
+
+/// What a deletion stopped by Ctrl+C did and didn't get to
+fn print_cancelled(cancelled: &Error, failures: usize, args: &Args) {
+    let Error::DeleteCancelled {
+        path,
+        dirs_deleted,
+        files_deleted,
+        dirs_left,
+    } = cancelled
+    else {
+        return;
+    };
+    eprintln!("\n{}", msg!(CancelSummary, path.display()));
+    eprintln!("{}", msg!(CancelDeleted, dirs_deleted, files_deleted));
+    eprintln!("{}", msg!(CancelLeft, dirs_left));
+    if failures > 0 {
+        eprintln!("{}", msg!(CancelFailed, failures));
+    }
+    if let Some(journal) = args.journal.as_ref().or(args.resume.as_ref()) {
+        eprintln!("{}", msg!(CancelResume, journal.display()));
+    }
+}
+
+/// Warn that nothing has completed for a while (`--stall-after`)
+fn print_stall(stall: &stall::Stall) {
+    let mut text = msg!(StallDetected, units::format_eta(stall.idle)).to_string();
+    for busy in &stall.workers {
+        text.push('\n');
+        text.push_str(&msg!(
+            StallWorker,
+            busy.worker,
+            busy.path.display(),
+            units::format_eta(busy.busy_for)
+        ));
+    }
+    // One block, so other output can't land between its lines
+    errln!("{}", text);
+}
+
+/// Heartbeat line for the deletion phase
+fn delete_heartbeat(broker: &Broker, failures: usize, run: &RunProgress) -> String {
+    let progress = broker.progress();
+    let percent = run.percent(&progress);
+    let eta = if percent > 0.0 && percent < 100.0 {
+        let remaining = run.started.elapsed().mul_f64((100.0 - percent) / percent);
+        units::format_eta(remaining)
+    } else {
+        msg!(EtaUnknown).to_string()
+    };
+    msg!(
+        HeartbeatDelete,
+        percent as u32,
+        bytes_progress(&progress),
+        units::format_count(progress.dirs_done + progress.files_done),
+        failures,
+        eta
+    )
+}
+
+/// Heartbeat line for `--stream`, where there's no total to measure against
+fn stream_heartbeat(broker: &Broker, scan: &tree::ScanProgress, failures: usize) -> String {
+    let progress = broker.progress();
+    msg!(
+        HeartbeatStream,
+        units::format_count(scan.dirs() + scan.files()),
+        units::format_count(progress.dirs_done + progress.files_done),
+        failures
+    )
+}
+
+/// Live progress bar on stdout, redrawn in place
+#[cfg(feature = "progress")]
+struct ProgressLine {
+    rate: progress::RateTracker,
+    drawn: bool,
+}
+
+#[cfg(feature = "progress")]
+impl ProgressLine {
+    fn new() -> Self {
+        Self {
+            rate: progress::RateTracker::new(),
+            drawn: false,
+        }
+    }
+
+    fn draw(&mut self, broker: &Broker, run: &RunProgress) {
+        let progress = broker.progress();
+        let percent = run.percent(&progress);
+        let items = progress.dirs_done + progress.files_done;
+        self.rate.update(items, percent / 100.0);
+        let bar = progress::bar(percent / 100.0, progress::BAR_WIDTH);
+        let rate = units::format_count(self.rate.items_per_sec() as usize);
+        let eta = match self.rate.eta() {
+            Some(eta) => units::format_eta(eta),
+            None => msg!(EtaUnknown).to_string(),
+        };
+        let line = if run.multi {
+            msg!(
+                ProgressBarMulti,
+                bar,
+                percent as u32,
+                bytes_progress(&progress),
+                units::format_count(items),
+                rate,
+                eta
+            )
+        } else {
+            msg!(
+                ProgressBar,
+                bar,
+                percent as u32,
+                bytes_progress(&progress),
+                units::format_count(items),
+                rate,
+                eta
+            )
+        };
+        output::status(&line);
+        self.drawn = true;
+    }
+
+    /// Blank the line so the final summary starts clean
+    fn clear(&self) {
+        if self.drawn {
+            output::clear_status();
+        }
+    }
+}
+
+/// Start redrawing the progress bar until the broker finishes
+///
+/// Only for `--verbose` on a terminal: redirected output gets just the
+/// final line, not a stream of redraws.
+#[cfg(feature = "progress")]
+fn spawn_progress_line(
+    broker: &Arc<Broker>,
+    run: RunProgress,
+    args: &Args,
+) -> Option<std::thread::JoinHandle<()>> {
+    use std::io::IsTerminal;
+
+    if !args.verbose || !std::io::stdout().is_terminal() {
+        return None;
+    }
+    let broker = broker.clone();
+    Some(std::thread::spawn(move || {
+        let mut line = ProgressLine::new();
+        let mut seen = 0;
+        loop {
+            seen = broker.wait_for_progress(seen, PROGRESS_IDLE_REDRAW);
+            if broker.is_finished() || broker.is_aborted() {
+                break;
+            }
+            line.draw(&broker, &run);
+            // Bursts of events collapse into one redraw of the latest state
+            if broker.wait_for_end(PROGRESS_MIN_REDRAW) {
+                break;
+            }
+        }
+        line.clear();
+    }))
+}
+
+#[cfg(not(feature = "progress"))]
+fn spawn_progress_line(
+    _broker: &Arc<Broker>,
+    _run: RunProgress,
+    _args: &Args,
+) -> Option<std::thread::JoinHandle<()>> {
+    None
+}
+
+/// "X of Y, " for progress lines when sizes were collected, else empty
+fn bytes_progress(progress: &ProgressSnapshot) -> String {
+    if progress.bytes_total == 0 {
+        return String::new();
+    }
+    msg!(
+        BytesProgress,
+        units::format_bytes(progress.bytes_done),
+        units::format_bytes(progress.bytes_total)
+    )
+}
+
+/// Report directories renamed aside by interrupted runs next to `targets`,
+/// returning them as extra targets with `--clean-leftovers`
+fn check_leftovers(targets: &[PathBuf], runs: &Path, args: &Args) -> Vec<PathBuf> {
+    let found = leftovers::find_leftovers(targets, runs);
+    if found.is_empty() {
+        return Vec::new();
+    }
+    if !args.clean_leftovers {
+        eprintln!("{}", msg!(LeftoversFound, found.len()));
+        for leftover in &found {
+            eprintln!(
+                "{}",
+                msg!(LeftoverLine, leftover.path.display(), leftover.operation)
+            );
+        }
+        eprintln!("{}", msg!(LeftoversHint));
+        return Vec::new();
+    }
+    if args.verbose {
+        println!("{}", msg!(LeftoversCleaning, found.len()));
+    }
+    found.into_iter().map(|leftover| leftover.path).collect()
+}
+
+/// Drop targets naming a directory that an earlier target already names
+fn dedupe_targets(paths: &[PathBuf], verbose: bool) -> Vec<PathBuf> {
+    let mut seen: HashMap<PathBuf, &Path> = HashMap::new();
+    let mut unique = Vec::with_capacity(paths.len());
+    for path in paths {
+        match seen.entry(safety::target_identity(path)) {
+            Entry::Occupied(first) => {
+                if verbose {
+                    println!(
+                        "{}",
+                        msg!(DuplicateTarget, path.display(), first.get().display())
+                    );
+                }
+            }
+            Entry::Vacant(slot) => {
+                slot.insert(path);
+                unique.push(path.clone());
+            }
+        }
+    }
+    unique
+}
+
+/// Estimate each target's size and return the paths largest first
+fn order_by_size(paths: &[PathBuf], verbose: bool) -> Vec<PathBuf> {
+    let mut sized: Vec<(PathBuf, u64)> = paths
+        .iter()
+        .map(|p| (p.clone(), tree::dir_size(p)))
+        .collect();
+    sized.sort_by_key(|(_, size)| std::cmp::Reverse(*size));
+
+    if verbose {
+        println!("{}", msg!(TargetsLargestFirst));
+        for (path, size) in &sized {
+            println!("  {:>10}  {}", units::format_bytes(*size), path.display());
+        }
+    }
+
+    sized.into_iter().map(|(path, _)| path).collect()
+}
+
 #[derive(Default)]
 struct DeletionStats {
     dirs_deleted: usize,
     files_deleted: usize,
-    total_scan_time: std::time::Duration,
-    total_delete_time: std::time::Duration,
+    /// Bytes in the files deleted
+    bytes_freed: u64,
+    /// Entries left in place on purpose (mount points, ...)
+    items_skipped: usize,
+    bytes_skipped: u64,
+    /// Time per stage
+    timings: Timings,
+    /// Snapshots taken before deleting, for recovery
+    snapshots: Vec<snapshot::Snapshot>,
+    /// Items found by the scan (the total a partial failure is out of)
+    items_scanned: usize,
+    /// Files and directories that could not be deleted
+    failures: Vec<rmbrr::error::FailedItem>,
+    /// Directories not attempted because something inside them failed
+    dirs_blocked: usize,
+    /// Directories whose contents couldn't be listed
+    warnings: Vec<rmbrr::error::EnumerationWarning>,
+    /// Times nothing completed for `--stall-after`
+    stalls: Vec<stall::Stall>,
 }
 
 impl DeletionStats {
     fn merge(&mut self, other: &DeletionStats) {
         self.dirs_deleted += other.dirs_deleted;
         self.files_deleted += other.files_deleted;
-        self.total_scan_time += other.total_scan_time;
-        self.total_delete_time += other.total_delete_time;
+        self.bytes_freed += other.bytes_freed;
+        self.items_skipped += other.items_skipped;
+        self.bytes_skipped += other.bytes_skipped;
+        self.timings.merge(&other.timings);
+        self.snapshots.extend(other.snapshots.iter().cloned());
     }
 
     fn total_items(&self) -> usize {
         self.dirs_deleted + self.files_deleted
     }
+
+    /// A target that left anything behind, as a partial failure
+    fn into_result(mut self) -> Result<Self, Error> {
+        if self.failures.is_empty() && self.dirs_blocked == 0 {
+            return Ok(self);
+        }
+        Err(Error::PartialFailure {
+            total: self.items_scanned,
+            failed: self.failures.len(),
+            errors: std::mem::take(&mut self.failures),
+        })
+    }
+}
+
+fn print_summary(
+    path_count: usize,
+    stats: &DeletionStats,
+    failures: &[rmbrr::error::FailedItem],
+    failed_paths: &[PathBuf],
+    args: &Args,
+) {
+    println!("\n{}", "=".repeat(60));
+    println!("{}", msg!(SummaryTitle));
+    println!("{}", "=".repeat(60));
+    println!("{}", msg!(SummaryOperation, operation::current()));
+    println!("{}", msg!(SummaryPaths, path_count));
+    println!("{}", msg!(SummaryDirsDeleted, stats.dirs_deleted));
+    println!("{}", msg!(SummaryFilesDeleted, stats.files_deleted));
+    if stats.bytes_freed > 0 {
+        println!(
+            "{}",
+            msg!(SummaryBytesFreed, units::format_bytes(stats.bytes_freed))
+        );
+    }
+    if stats.items_skipped > 0 {
+        println!(
+            "{}",
+            msg!(
+                SummarySkipped,
+                stats.items_skipped,
+                units::format_bytes(stats.bytes_skipped)
+            )
+        );
+    }
+    let listed = failures
+        .iter()
+        .filter(|f| args.report_failures.shows(f))
+        .count();
+    if listed > 0 {
+        println!("{}", msg!(SummaryFailedItems, listed));
+    }
+    if !failed_paths.is_empty() {
+        println!("{}", msg!(SummaryFailedPaths, failed_paths.len()));
+    }
+    for snapshot in &stats.snapshots {
+        println!("{}", msg!(SummarySnapshot, snapshot));
+    }
+    if args.stats {
+        let (scan_time, delete_time) = (
+            stats.timings.get(Stage::Scan),
+            stats.timings.get(Stage::Delete),
+        );
+        println!("\n{}", msg!(TimingHeader));
+        println!("{}", msg!(TotalScanTime, format!("{:.2?}", scan_time)));
+        println!("{}", msg!(TotalDeleteTime, format!("{:.2?}", delete_time)));
+        println!(
+            "{}",
+            msg!(TotalTime, format!("{:.2?}", scan_time + delete_time))
+        );
+        print_stages(&stats.timings, args);
+    }
+}
+
+/// `--stats` output for a target deleted without failures
+fn print_stats(
+    dir_count: usize,
+    file_count: usize,
+    bytes_freed: u64,
+    timings: &Timings,
+    error_tracker: &worker::ErrorTracker,
+    args: &Args,
+) {
+    let (scan_time, delete_time) = (timings.get(Stage::Scan), timings.get(Stage::Delete));
+    let total_time = scan_time + delete_time;
+    println!("\n{}", msg!(StatisticsHeader));
+    println!("{}", msg!(StatDirs, dir_count));
+    println!("{}", msg!(StatFiles, file_count));
+    println!("{}", msg!(StatTotal, dir_count + file_count));
+    if bytes_freed > 0 {
+        println!("{}", msg!(StatBytesFreed, units::format_bytes(bytes_freed)));
+    }
+    let type_changes = error_tracker.type_change_count();
+    if type_changes > 0 {
+        println!("{}", msg!(StatTypeChanged, type_changes));
+    }
+    let compressed = error_tracker.compressed_count();
+    if compressed > 0 {
+        println!("{}", msg!(StatCompressed, compressed));
+    }
+    let encrypted = error_tracker.encrypted_count();
+    if encrypted > 0 {
+        println!("{}", msg!(StatEncrypted, encrypted));
+    }
+    println!("\n{}", msg!(TimingHeader));
+    let scan_rate = (dir_count + file_count) as f64 / scan_time.as_secs_f64();
+    println!(
+        "{}",
+        msg!(
+            ScanTimeRate,
+            format!("{:.2?}", scan_time),
+            format!("{:.0}", scan_rate)
+        )
+    );
+    println!("{}", msg!(DeleteTime, format!("{:.2?}", delete_time)));
+    println!("{}", msg!(TimeTotal, format!("{:.2?}", total_time)));
+    print_stages(timings, args);
+    println!("\n{}", msg!(PerformanceHeader));
+    let items_per_sec = (dir_count + file_count) as f64 / total_time.as_secs_f64();
+    println!("{}", msg!(Throughput, format!("{:.0}", items_per_sec)));
+    if bytes_freed > 0 {
+        let bytes_per_sec = (bytes_freed as f64 / total_time.as_secs_f64()) as u64;
+        println!(
+            "{}",
+            msg!(ByteThroughput, units::format_bytes(bytes_per_sec))
+        );
+    }
+}
+
+/// `--stats` breakdown of where the time went, parsing the arguments included
+fn print_stages(timings: &Timings, args: &Args) {
+    let mut timings = *timings;
+    timings.add(Stage::Args, args.parse_time);
+    println!("\n{}", msg!(StagesHeader));
+    for (stage, time) in timings.iter() {
+        println!("{}", msg!(StageTime, stage.name(), format!("{:.2?}", time)));
+    }
+}
+
+/// Space freed by a target, if any files with a known size were deleted
+fn print_bytes_freed(bytes: u64) {
+    if bytes > 0 {
+        println!("{}", msg!(BytesFreed, units::format_bytes(bytes)));
+    }
+}
+
+/// Scan, delete and total time of one target
+fn print_times(scan_time: Duration, delete_time: Duration, total_time: Duration) {
+    println!("{}", msg!(ScanTime, format!("{:.2?}", scan_time)));
+    println!("{}", msg!(DeleteTime, format!("{:.2?}", delete_time)));
+    println!("{}", msg!(TimeTotal, format!("{:.2?}", total_time)));
+}
+
+/// Report entries that were left in place on purpose, grouped by reason
+///
+/// rmbrr's own files aren't among them: they go at the end of the run,
+/// which says so then.
+fn print_skipped(skipped: &[tree::SkippedEntry], verbose: bool) {
+    let skipped: Vec<_> = skipped
+        .iter()
+        .filter(|entry| entry.reason != tree::SkipReason::OwnFile)
+        .cloned()
+        .collect();
+    if skipped.is_empty() {
+        return;
+    }
+    println!("\n{}", msg!(SkippedHeader));
+    for (reason, count, bytes) in tree::summarize_skipped(&skipped) {
+        println!(
+            "{}",
+            msg!(
+                SkippedReason,
+                reason.description(),
+                count,
+                units::format_bytes(bytes)
+            )
+        );
+    }
+    if verbose {
+        for entry in &skipped {
+            println!("    {}", entry.path.display());
+        }
+    }
+}
+
+/// What a dry run would delete, for `--list`: `d <dir>` and `f <file>`
+/// lines, with a size column before the path if `sizes`
+fn print_listing(tree: &tree::DirectoryTree, sizes: bool) {
+    use std::io::Write;
+    let mut out = std::io::stdout().lock();
+    for (entry, is_dir) in tree.to_delete() {
+        let marker = if is_dir { 'd' } else { 'f' };
+        let line = if !sizes {
+            format!("{} {}", marker, entry.display())
+        } else if is_dir {
+            format!("{} {:>10} {}", marker, "", entry.display())
+        } else {
+            let size = std::fs::symlink_metadata(entry)
+                .map_or_else(|_| "?".to_string(), |m| units::format_bytes(m.len()));
+            format!("{} {:>10} {}", marker, size, entry.display())
+        };
+        // A closed pipe (`| head`) ends the listing
+        if writeln!(out, "{}", line).is_err() {
+            break;
+        }
+    }
+}
+
+/// Paths over the `--max-path-length` limit, and whether rmbrr works around it
+fn print_long_paths(long: &pathlen::LongPaths) {
+    if long.count == 0 {
+        println!("{}", msg!(LongPathsNone, long.checked, long.limit));
+        return;
+    }
+    let longest = long.worst.first().map_or(0, |(_, chars)| *chars);
+    println!(
+        "{}",
+        msg!(
+            LongPathsFound,
+            long.count,
+            long.checked,
+            long.limit,
+            longest
+        )
+    );
+    if pathlen::EXTENDED_LENGTH {
+        println!("{}", msg!(LongPathsExtended));
+    } else {
+        println!("{}", msg!(LongPathsNoLimit));
+    }
+    if !long.worst.is_empty() {
+        println!("\n{}", msg!(LongPathsWorst, long.worst.len()));
+        for (path, chars) in &long.worst {
+            println!(
+                "{}",
+                msg!(LongPathLine, format!("{:>5}", chars), path.display())
+            );
+        }
+    }
+}
+
+/// Report directories whose contents couldn't be listed during deletion
+fn print_enumeration_warnings(warnings: &[rmbrr::error::EnumerationWarning], verbose: bool) {
+    if warnings.is_empty() {
+        return;
+    }
+    println!("\n{}", msg!(ListWarningsHeader, warnings.len()));
+    let shown = if verbose { warnings.len() } else { 10 };
+    for warning in warnings.iter().take(shown) {
+        println!(
+            "  {}: {} ({})",
+            warning.path.display(),
+            warning.error,
+            warning.category().description()
+        );
+    }
+    if warnings.len() > shown {
+        println!("{}", msg!(AndMore, warnings.len() - shown));
+    }
+}
+
+/// Where the current target sits within the whole run, for weighted progress
+#[derive(Debug, Clone, Copy)]
+struct RunProgress {
+    /// Scanned items (dirs + files) in targets already processed
+    items_before: usize,
+    /// Scanned items across all targets
+    items_total: usize,
+    /// Bytes to delete in targets already processed (0 unless sizes were collected)
+    bytes_before: u64,
+    /// Bytes to delete across all targets
+    bytes_total: u64,
+    /// More than one target in this run
+    multi: bool,
+    /// When deletion of the first target started
+    started: Instant,
+}
+
+impl RunProgress {
+    /// Overall completion (0-100), given progress within the current target
+    ///
+    /// Weighted by bytes when sizes were collected, by item count otherwise.
+    fn percent(&self, current: &ProgressSnapshot) -> f64 {
+        if self.bytes_total > 0 {
+            let done = self.bytes_before + current.bytes_done;
+            return done.min(self.bytes_total) as f64 / self.bytes_total as f64 * 100.0;
+        }
+        if self.items_total == 0 {
+            return 100.0;
+        }
+        let done = self.items_before + current.dirs_done + current.dirs_kept + current.files_done;
+        done.min(self.items_total) as f64 / self.items_total as f64 * 100.0
+    }
 }
 
-fn print_summary(
-    stats: &DeletionStats,
-    failures: &[rmbrr::error::FailedItem],
-    failed_paths: &[PathBuf],
-    args: &Args,
-) {
-    println!("\n{}", "=".repeat(60));
-    println!("SUMMARY");
-    println!("{}", "=".repeat(60));
-    println!("Paths processed: {}", args.paths.len());
-    println!("Directories deleted: {}", stats.dirs_deleted);
-    println!("Files deleted: {}", stats.files_deleted);
-    if !failures.is_empty() {
-        println!("Failed items: {}", failures.len());
+fn print_diff(diff: &manifest::Diff, path: &Path, plan: &Path) {
+    if diff.is_empty() {
+        println!("{}", msg!(DiffNone, path.display(), plan.display()));
+        return;
     }
-    if !failed_paths.is_empty() {
-        println!("Failed paths: {}", failed_paths.len());
+    println!("{}", msg!(DiffHeader, path.display(), plan.display()));
+    for new in &diff.new {
+        println!("  + {}", new.display());
     }
-    if args.stats {
-        println!("\nTiming:");
-        println!("  Total scan time:   {:.2?}", stats.total_scan_time);
-        println!("  Total delete time: {:.2?}", stats.total_delete_time);
-        println!(
-            "  Total time:        {:.2?}",
-            stats.total_scan_time + stats.total_delete_time
-        );
+    for gone in &diff.gone {
+        println!("  - {}", gone.display());
+    }
+    for (changed, what) in &diff.changed {
+        println!("  ~ {} ({})", changed.display(), what);
+    }
+    println!(
+        "{}",
+        msg!(
+            DiffSummary,
+            diff.new.len(),
+            diff.gone.len(),
+            diff.changed.len()
+        )
+    );
+}
+
+/// A target that passed the safety checks and has been scanned
+struct ScannedPath {
+    tree: tree::DirectoryTree,
+    /// Time spent on the safety checks and the scan
+    timings: Timings,
+    /// Items as scanned, for `--manifest-out` and `--diff` (empty otherwise)
+    manifest: Vec<manifest::Entry>,
+    /// With `--stream`: how to scan the target while it's deleted (the
+    /// tree is left empty)
+    stream: Option<tree::ScanOptions>,
+    /// Filters the scan applied, for the workers to check files against again
+    filters: Arc<filter::Filters>,
+    /// The target's identity when it was checked, to make sure it's still
+    /// the same directory when deletion starts
+    identity: Option<winapi::FileIdentity>,
+}
+
+impl ScannedPath {
+    /// Directories and files found, used to weight overall progress
+    fn item_count(&self) -> usize {
+        self.tree.dirs.len() + self.tree.file_count
     }
 }
 
-fn process_single_path(path: &Path, args: &Args) -> Result<DeletionStats, Error> {
+/// Check `path` and scan it, or with `resumed` (what a journal says is left
+/// of it) take that instead of scanning
+fn scan_path(
+    path: &Path,
+    args: &Args,
+    resumed: Option<tree::DirectoryTree>,
+) -> Result<ScannedPath, Error> {
+    let as_user = impersonate::enter();
+    let mut timings = Timings::new();
+    let checks_start = Instant::now();
+    // Before the checks, so what they pass is what gets deleted
+    let identity = winapi::file_identity(path).ok();
     if !path.exists() {
         return Err(Error::InvalidPath {
             path: path.to_path_buf(),
@@ -175,85 +2320,393 @@ fn process_single_path(path: &Path, args: &Args) -> Result<DeletionStats, Error>
         });
     }
 
-    match safety::check_path_safety(path) {
-        safety::SafetyCheck::Safe => {}
-        safety::SafetyCheck::Dangerous {
-            reason,
-            can_override,
-        } => {
-            if !args.force {
-                eprintln!("\n⚠️  WARNING: Dangerous operation detected!");
-                eprintln!("   {}", reason);
-                eprintln!();
+    // Scanning it would delete what it leads to, wherever that is
+    if !args.follow_symlinks && tree::is_link(path) {
+        return Err(Error::InvalidPath {
+            path: path.to_path_buf(),
+            reason: "is a symlink or junction (--follow-symlinks empties what it leads to)"
+                .to_string(),
+        });
+    }
 
-                if can_override {
-                    eprintln!("   To proceed anyway, use the --force flag");
-                    eprintln!("   Example: rmbrr --force {}", path.display());
-                } else {
-                    eprintln!("   This path cannot be deleted for safety reasons.");
-                    eprintln!("   Deletion of system directories is not allowed.");
-                }
-                eprintln!();
+    if !args.root_must_match.is_empty() && !filter::root_name_matches(path, &args.root_must_match) {
+        eprintln!("\n{}", msg!(RootMismatch, path.display()));
+        eprintln!();
+        return Err(Error::InvalidPath {
+            path: path.to_path_buf(),
+            reason: "name does not match --root-must-match".to_string(),
+        });
+    }
 
-                return Err(Error::InvalidPath {
-                    path: path.to_path_buf(),
-                    reason: "dangerous path - requires --force (if allowed)".to_string(),
-                });
-            } else if !can_override {
-                eprintln!("\n⛔ ERROR: Cannot delete system directory");
+    let check = if args.keep_root {
+        safety::check_emptying_safety(path)
+    } else {
+        safety::check_path_safety(path)
+    };
+    match check {
+        safety::SafetyCheck::Safe => {}
+        safety::SafetyCheck::Dangerous { reason, .. } if args.force => {
+            if args.verbose {
+                eprintln!("\n{}", msg!(DangerousWithForce));
                 eprintln!("   {}", reason);
-                eprintln!("   System directories cannot be deleted even with --force");
                 eprintln!();
+            }
+        }
+        safety::SafetyCheck::Forbidden { code, reason } if args.force => {
+            eprintln!("\n{}", msg!(SystemDirForbidden));
+            eprintln!("   {}", reason);
+            eprintln!("{}", msg!(SystemDirNoForce));
+            eprintln!();
 
-                return Err(Error::InvalidPath {
-                    path: path.to_path_buf(),
-                    reason: "system directory cannot be deleted".to_string(),
-                });
-            } else if args.verbose {
-                eprintln!("\n⚠️  WARNING: Deleting dangerous path with --force");
-                eprintln!("   {}", reason);
-                eprintln!();
+            return Err(Error::Refused {
+                path: path.to_path_buf(),
+                code,
+                reason: "cannot be deleted, not even with --force".to_string(),
+            });
+        }
+        check @ (safety::SafetyCheck::Dangerous { code, .. }
+        | safety::SafetyCheck::Forbidden { code, .. }) => {
+            eprintln!("\n{}", msg!(DangerousDetected));
+            eprintln!("   {}", check.reason().unwrap_or_default());
+            eprintln!();
+
+            if check.can_override() {
+                eprintln!("{}", msg!(UseForce));
+                eprintln!("{}", msg!(ForceExample, path.display()));
+            } else {
+                eprintln!("{}", msg!(CannotDelete));
+                eprintln!("{}", msg!(NoSystemDirs));
             }
+            eprintln!();
+
+            let reason = if check.can_override() {
+                "dangerous path - requires --force"
+            } else {
+                "protected path - cannot be deleted"
+            };
+            return Err(Error::Refused {
+                path: path.to_path_buf(),
+                code,
+                reason: reason.to_string(),
+            });
         }
     }
 
     if args.dry_run && args.verbose {
-        println!("DRY RUN MODE - no files will be deleted");
+        println!("{}", msg!(DryRunMode));
     }
 
-    let worker_count = args.threads.unwrap_or_else(|| {
-        std::thread::available_parallelism()
-            .map(|n| n.get())
-            .unwrap_or(4)
-    });
+    if args.verbose && args.stream {
+        println!("{}", msg!(StreamingTree, path.display()));
+    } else if args.verbose && resumed.is_none() {
+        println!("{}", msg!(ScanningTree, path.display()));
+    }
+
+    let scan_progress = tree::ScanProgress::new();
+    let scan_done = AtomicBool::new(false);
+    let mut scan_options = tree::ScanOptions {
+        cancel: Some(SCAN_CANCEL.clone()),
+        collect_sizes: args.byte_progress,
+        threads: thread_count(args),
+        follow_links: args.follow_symlinks,
+        ..Default::default()
+    };
+    // The keep list is the user's own file; the rest is on the target
+    drop(as_user);
+    let keep = match &args.keep_from {
+        Some(keep_file) => Some(
+            filter::read_keep_list(keep_file, path)
+                .map_err(|e| Error::io_with_path(keep_file.clone(), e))?,
+        ),
+        None => None,
+    };
+    let _as_user = impersonate::enter();
+    if let Some(keep) = keep {
+        if keep.iter().any(|p| p == path) {
+            // The whole target is kept: nothing to scan or delete
+            let mut tree = tree::DirectoryTree::new();
+            tree.skipped.push(tree::SkippedEntry {
+                path: path.to_path_buf(),
+                is_dir: true,
+                reason: tree::SkipReason::Kept,
+                bytes: tree::dir_size(path),
+            });
+            timings.since(Stage::Safety, checks_start);
+            return Ok(ScannedPath {
+                tree,
+                timings,
+                manifest: Vec::new(),
+                stream: None,
+                filters: Arc::default(),
+                identity,
+            });
+        }
+        for entry in keep {
+            if args.verbose && entry.symlink_metadata().is_err() {
+                eprintln!("{}", msg!(KeepEntryMissing, entry.display()));
+            }
+            scan_options.filters.keep(entry);
+        }
+    }
+    // Deleted with the rest at the end of the run, once done writing them;
+    // moving the whole target takes them along instead
+    if !args.trash && staging_area(args).is_none() {
+        for file in own_files(args) {
+            if let Some(inside) = filter::inside(path, file) {
+                if args.verbose {
+                    println!("{}", msg!(OwnFileKept, inside.display()));
+                }
+                scan_options.filters.keep_own_file(inside);
+            }
+        }
+    }
+    for name in &args.exclude_dir_names {
+        scan_options.filters.exclude_dir_name(name);
+    }
+    if args.keep_root && volume::is_root(path) {
+        for folder in volume::system_folders(path) {
+            scan_options.filters.keep(folder);
+        }
+    }
+    scan_options.filters.globs(
+        path.to_path_buf(),
+        args.include.clone(),
+        args.exclude.clone(),
+    );
+    if let Some(cutoff) = args.older_than {
+        scan_options.filters.keep_newer_than(cutoff);
+    }
+    scan_options
+        .filters
+        .sizes(args.larger_than, args.smaller_than);
+    scan_options
+        .filters
+        .depths(path.to_path_buf(), args.min_depth, args.max_depth);
+    let protected = safety::protected_paths_within(path);
+    for protected in &protected {
+        if !args.skip_protected {
+            eprintln!("\n{}", msg!(ProtectedInside, protected.display()));
+            eprintln!("{}", msg!(UseSkipProtected));
+            return Err(Error::Refused {
+                path: path.to_path_buf(),
+                code: safety::SafetyCode::Depth,
+                reason: format!("contains protected directory {}", protected.display()),
+            });
+        }
+        scan_options.filters.protect(protected.clone());
+    }
+    timings.since(Stage::Safety, checks_start);
+    if args.stream {
+        return Ok(ScannedPath {
+            tree: tree::DirectoryTree::new(),
+            timings,
+            manifest: Vec::new(),
+            filters: Arc::new(scan_options.filters.clone()),
+            stream: Some(scan_options),
+            identity,
+        });
+    }
+    let start = Instant::now();
+    let (tree, scan_time) = match resumed {
+        Some(mut tree) => {
+            // Nothing scanned to leave protected directories out
+            for dir in &protected {
+                tree.skip_dir(dir, tree::SkipReason::Protected);
+            }
+            if args.verbose {
+                println!("{}", msg!(ResumingTree, path.display(), tree.dirs.len()));
+            }
+            (Ok(tree), Duration::ZERO)
+        }
+        None => {
+            SCANNING.store(true, Ordering::SeqCst);
+            let scanned = std::thread::scope(|s| {
+                if args.verbose {
+                    spawn_scan_spinner(s, &scan_progress, &scan_done);
+                }
+                if let Some(interval) = heartbeat_interval(args) {
+                    let (scan_progress, scan_done) = (&scan_progress, &scan_done);
+                    s.spawn(move || {
+                        heartbeat(interval, scan_done, || {
+                            msg!(
+                                HeartbeatScan,
+                                path.display(),
+                                units::format_count(scan_progress.dirs() + scan_progress.files())
+                            )
+                        })
+                    });
+                }
+                let result = tree::discover_tree_with_options(path, &scan_options, &scan_progress);
+                scan_done.store(true, Ordering::Relaxed);
+                (result, start.elapsed())
+            });
+            SCANNING.store(false, Ordering::SeqCst);
+            scanned
+        }
+    };
+
+    if SCAN_CANCEL.is_cancelled() {
+        return Err(Error::ScanCancelled {
+            path: path.to_path_buf(),
+        });
+    }
+    let mut tree = tree.map_err(|e| Error::io_with_path(path.to_path_buf(), e))?;
+    if args.keep_root {
+        tree.retained.insert(path.to_path_buf());
+    }
 
     if args.verbose {
-        println!("Scanning directory tree: {}", path.display());
+        println!(
+            "{}",
+            msg!(
+                ScanFound,
+                tree.dirs.len(),
+                tree.leaves.len(),
+                tree.file_count,
+                format!("{:.2?}", scan_time)
+            )
+        );
+        if !tree.skipped.is_empty() {
+            println!(
+                "{}",
+                msg!(ScanSkipping, tree.skipped.len(), tree.retained.len())
+            );
+        }
     }
+
+    timings.add(Stage::Scan, scan_time);
+    Ok(ScannedPath {
+        tree,
+        timings,
+        manifest: Vec::new(),
+        stream: None,
+        filters: Arc::new(scan_options.filters),
+        identity,
+    })
+}
+
+/// Scan `path` into `broker` while the workers delete what has been found so
+/// far (`--stream`); returns how long the scan took
+///
+/// On Ctrl+C (an error of kind `Interrupted`) or a failed scan the broker is
+/// aborted, so the workers stop too.
+fn stream_scan(
+    path: &Path,
+    options: &tree::ScanOptions,
+    broker: &Broker,
+    progress: &tree::ScanProgress,
+    args: &Args,
+) -> std::io::Result<Duration> {
+    let _as_user = impersonate::enter();
     let start = Instant::now();
+    SCANNING.store(true, Ordering::SeqCst);
+    let scanned = tree::stream_tree(path, options, progress, |mut listed| {
+        listed.kept |= args.keep_root && listed.dir == path;
+        broker.add_scanned_dir(listed);
+    });
+    SCANNING.store(false, Ordering::SeqCst);
+
+    match scanned {
+        Ok(()) => broker.finish_scan(),
+        Err(_) => broker.abort(),
+    }
+    scanned.map(|()| start.elapsed())
+}
+
+/// Snapshot the filesystem holding `path` if the target is over `threshold`
+///
+/// Failing to take the snapshot stops the delete unless `--force` is given.
+fn take_snapshot(
+    path: &Path,
+    tree: &tree::DirectoryTree,
+    threshold: u64,
+    args: &Args,
+) -> Result<Option<snapshot::Snapshot>, Error> {
+    let size = if args.byte_progress {
+        tree.total_bytes()
+    } else {
+        let _as_user = impersonate::enter();
+        tree::dir_size(path)
+    };
+    if size <= threshold {
+        return Ok(None);
+    }
 
-    let tree = tree::discover_tree(path).map_err(|e| Error::io_with_path(path.to_path_buf(), e))?;
+    match snapshot::create(path) {
+        Ok(snapshot) => {
+            if !args.json {
+                println!("{}", msg!(SnapshotTaken, path.display(), snapshot));
+            }
+            Ok(Some(snapshot))
+        }
+        Err(e) if args.force => {
+            eprintln!("\n{}", msg!(SnapshotFailedWithForce, e));
+            Ok(None)
+        }
+        Err(e) => {
+            eprintln!("\n{}", msg!(SnapshotFailed, units::format_bytes(size), e));
+            eprintln!("{}", msg!(UseForce));
+            Err(Error::io_with_path(path.to_path_buf(), e))
+        }
+    }
+}
+
+fn delete_scanned(
+    path: &Path,
+    scan: ScannedPath,
+    args: &Args,
+    run: RunProgress,
+    journal: Option<Arc<journal::Journal>>,
+) -> Result<DeletionStats, Error> {
+    let ScannedPath {
+        mut tree,
+        mut timings,
+        stream,
+        filters,
+        identity,
+        ..
+    } = scan;
+    let scan_time = timings.get(Stage::Scan);
+
+    let worker_count = thread_count(args);
 
-    let scan_time = start.elapsed();
     let dir_count = tree.dirs.len();
     let file_count = tree.file_count;
+    // Directories holding skipped entries stay behind
+    let kept_dirs = tree.retained.len();
+    let items_skipped = tree.skipped.len();
+    let bytes_skipped = tree.skipped_bytes();
 
-    if args.verbose {
-        println!(
-            "Found {} directories ({} initial leaves), {} files in {:.2?}",
-            dir_count,
-            tree.leaves.len(),
-            file_count,
-            scan_time
-        );
+    if dir_count == 0 && stream.is_none() {
+        if args.verbose {
+            println!("{}", msg!(NothingToDelete, path.display()));
+        }
+        return Ok(DeletionStats {
+            items_skipped,
+            bytes_skipped,
+            timings,
+            ..Default::default()
+        });
+    }
+
+    if let Some(limit) = args.max_path_length {
+        let shown = if args.dry_run { LONG_PATHS_SHOWN } else { 0 };
+        print_long_paths(&pathlen::find_long_paths(&tree, path, limit, shown));
     }
 
     if args.confirm && !args.dry_run {
-        println!("\nAbout to delete:");
-        println!("  {} directories", dir_count);
-        println!("  {} files", file_count);
-        println!("  Total: {} items", dir_count + file_count);
-        println!("\nAre you sure? [y/N] ");
+        println!("\n{}", msg!(AboutToDelete));
+        match safety::resolve_drive_mapping(path) {
+            Some(real) => println!("{}", msg!(MapsTo, path.display(), real.display())),
+            None => println!("  {}", path.display()),
+        }
+        println!("{}", msg!(CountDirs, dir_count - kept_dirs));
+        println!("{}", msg!(CountFiles, file_count));
+        println!("{}", msg!(CountTotal, dir_count - kept_dirs + file_count));
+        if items_skipped > 0 {
+            println!("{}", msg!(CountSkipping, items_skipped));
+        }
+        println!("\n{}", msg!(ConfirmPrompt));
 
         use std::io::{self, BufRead};
         let stdin = io::stdin();
@@ -261,49 +2714,273 @@ fn process_single_path(path: &Path, args: &Args) -> Result<DeletionStats, Error>
         stdin.lock().read_line(&mut response).ok();
 
         let response = response.trim().to_lowercase();
-        if response != "y" && response != "yes" {
-            println!("Aborted.");
+        let accepted = msg!(ConfirmAnswers)
+            .split(',')
+            .any(|answer| answer.trim().to_lowercase() == response);
+        if response.is_empty() || !accepted {
+            println!("{}", msg!(Aborted));
             return Ok(DeletionStats {
-                dirs_deleted: 0,
-                files_deleted: 0,
-                total_scan_time: scan_time,
-                total_delete_time: std::time::Duration::ZERO,
+                timings,
+                ..Default::default()
             });
         }
     }
 
     if args.dry_run {
+        if args.list {
+            let _as_user = impersonate::enter();
+            print_listing(&tree, args.list_sizes);
+        }
         if args.verbose {
             println!("\n{}", "=".repeat(60));
-            println!("DRY RUN RESULTS");
+            println!("{}", msg!(DryRunResults));
             println!("{}", "=".repeat(60));
-            println!("\nWould delete:");
-            println!("  {} directories", dir_count);
-            println!("  {} files", file_count);
-            println!("  {} total items", dir_count + file_count);
+            if args.trash {
+                println!("\n{}", msg!(WouldTrash));
+            } else if staging_area(args).is_some() {
+                println!("\n{}", msg!(WouldStage));
+            } else {
+                println!("\n{}", msg!(WouldDelete));
+            }
+            println!("{}", msg!(CountDirs, dir_count - kept_dirs));
+            println!("{}", msg!(CountFiles, file_count));
+            println!(
+                "{}",
+                msg!(CountTotalItems, dir_count - kept_dirs + file_count)
+            );
+            print_skipped(&tree.skipped, args.verbose);
 
-            println!("\nTo proceed with deletion:");
+            println!("\n{}", msg!(ToProceed));
             println!("  rmbrr {}", path.display());
         }
+        return Ok(DeletionStats {
+            dirs_deleted: dir_count - kept_dirs,
+            files_deleted: file_count,
+            items_skipped,
+            bytes_skipped,
+            timings,
+            ..Default::default()
+        });
+    }
+    let plan_start = Instant::now();
+
+    // A confirmed path replaced by, say, a symlink to somewhere else since
+    // the scan must not be what gets deleted
+    if let Some(identity) = identity {
+        let now = {
+            let _as_user = impersonate::enter();
+            winapi::file_identity(path)
+        };
+        match now {
+            Ok(now) if now == identity => {}
+            Ok(_) => {
+                return Err(Error::Refused {
+                    path: path.to_path_buf(),
+                    code: safety::SafetyCode::Replaced,
+                    reason: "was replaced by something else after it was scanned".to_string(),
+                })
+            }
+            Err(e) => return Err(Error::io_with_path(path.to_path_buf(), e)),
+        }
+    }
+
+    #[cfg(feature = "trash")]
+    if args.trash {
+        if args.keep_root {
+            return Err(Error::InvalidPath {
+                path: path.to_path_buf(),
+                reason: "--keep-root cannot be combined with --trash".to_string(),
+            });
+        }
+        // Trashing moves the whole tree, skipped entries and the directories
+        // kept for them (or for --min-depth) included
+        if items_skipped + kept_dirs > 0 {
+            return Err(Error::InvalidPath {
+                path: path.to_path_buf(),
+                reason: format!(
+                    "contains {} entries that must stay, which --trash cannot leave behind",
+                    items_skipped + kept_dirs
+                ),
+            });
+        }
+        let _as_user = impersonate::enter();
+        // Anything over the trash's limit would be deleted permanently instead
+        if let Ok(Some(capacity)) = trash::capacity(path) {
+            let size = tree::dir_size(path);
+            if size > capacity {
+                let (size, capacity) = (units::format_bytes(size), units::format_bytes(capacity));
+                if !args.force {
+                    eprintln!("\n{}", msg!(TrashTooSmall, size, capacity));
+                    eprintln!("{}", msg!(UseForce));
+                    return Err(Error::InvalidPath {
+                        path: path.to_path_buf(),
+                        reason: "too large for the trash - requires --force".to_string(),
+                    });
+                }
+                eprintln!("\n{}", msg!(TrashTooSmallWithForce, size, capacity));
+            }
+        }
+        let trash_start = Instant::now();
+        timings.add(Stage::Plan, trash_start - plan_start);
+        trash::move_to_trash(path).map_err(|e| Error::io_with_path(path.to_path_buf(), e))?;
+        if args.verbose {
+            println!("{}", msg!(MovedToTrash, path.display()));
+        }
+        timings.since(Stage::Delete, trash_start);
+        return Ok(DeletionStats {
+            dirs_deleted: dir_count,
+            files_deleted: file_count,
+            timings,
+            ..Default::default()
+        });
+    }
+
+    if let Some(area) = staging_area(args) {
+        // Staging moves the whole tree, skipped entries and the directories
+        // kept for them (or for --min-depth) included
+        if items_skipped + kept_dirs > 0 {
+            return Err(Error::InvalidPath {
+                path: path.to_path_buf(),
+                reason: format!(
+                    "contains {} entries that must stay, which --stage cannot leave behind",
+                    items_skipped + kept_dirs
+                ),
+            });
+        }
+        let stage_start = Instant::now();
+        timings.add(Stage::Plan, stage_start - plan_start);
+        let staged = {
+            let _as_user = impersonate::enter();
+            area.stage(path, args.stage_for)
+        }
+        .map_err(|e| Error::io_with_path(path.to_path_buf(), e))?;
+        timings.since(Stage::Delete, stage_start);
+        if args.verbose {
+            println!(
+                "{}",
+                msg!(
+                    MovedToStage,
+                    path.display(),
+                    staged.path.display(),
+                    units::format_age(args.stage_for)
+                )
+            );
+        }
         return Ok(DeletionStats {
             dirs_deleted: dir_count,
             files_deleted: file_count,
-            total_scan_time: scan_time,
-            total_delete_time: std::time::Duration::ZERO,
+            timings,
+            ..Default::default()
+        });
+    }
+
+    let snapshot = match args.snapshot_above {
+        Some(threshold) => take_snapshot(path, &tree, threshold, args)?,
+        None => None,
+    };
+
+    let saved_root = if args.keep_root {
+        let _as_user = impersonate::enter();
+        match preserve::SavedMetadata::capture(path) {
+            Ok(saved) => Some(saved),
+            Err(e) => {
+                eprintln!("{}", msg!(RootMetadataUnreadable, path.display(), e));
+                None
+            }
+        }
+    } else {
+        None
+    };
+
+    // Skipped entries and kept directories have to stay where they are, so
+    // such targets can't move
+    let renamed = if args.rename_first && items_skipped + kept_dirs > 0 {
+        eprintln!(
+            "{}",
+            msg!(RenameSkipped, path.display(), items_skipped + kept_dirs)
+        );
+        None
+    } else if args.rename_first && !leftovers::is_renamed(path) {
+        match rename_aside(path) {
+            Ok(aside) => {
+                if args.verbose {
+                    println!("{}", msg!(RenamedAside, path.display(), aside.display()));
+                }
+                tree.rebase(path, &aside);
+                Some(aside)
+            }
+            Err(e) => {
+                eprintln!("{}", msg!(RenameFailed, path.display(), e));
+                None
+            }
+        }
+    } else {
+        None
+    };
+    let path = renamed.as_deref().unwrap_or(path);
+
+    // A few empty directories: removing them serially beats starting workers
+    let serial_start = Instant::now();
+    let dirs_removed = if stream.is_none() && worker::is_empty_skeleton(&tree) {
+        let _as_user = impersonate::enter();
+        worker::remove_empty_dirs(&mut tree)
+    } else {
+        0
+    };
+    if tree.dirs.is_empty() && stream.is_none() {
+        let delete_time = serial_start.elapsed();
+        timings.add(Stage::Plan, serial_start - plan_start);
+        timings.add(Stage::Delete, delete_time);
+        if !args.json {
+            if args.verbose {
+                println!("\n{}", msg!(DeletionComplete));
+            }
+            if args.stats {
+                let error_tracker = worker::ErrorTracker::new();
+                print_stats(dir_count, 0, 0, &timings, &error_tracker, args);
+            } else if args.verbose {
+                print_times(scan_time, delete_time, scan_time + delete_time);
+            }
+        }
+        return Ok(DeletionStats {
+            dirs_deleted: dirs_removed,
+            timings,
+            items_scanned: dir_count,
+            ..Default::default()
         });
     }
 
-    let (broker, tx, rx) = Broker::new(tree);
+    let (broker, tx, rx) = match stream {
+        Some(_) => Broker::streaming(args.max_per_parent),
+        None => Broker::with_parent_limit(tree, args.max_per_parent),
+    };
     let broker = Arc::new(broker);
+    if let Some(journal) = journal {
+        broker.record_to(journal);
+    }
+    if !args.stall_after.is_zero() {
+        broker.track_workers();
+    }
 
     let error_tracker = Arc::new(worker::ErrorTracker::new());
     let worker_config = worker::WorkerConfig {
         verbose: args.verbose,
         ignore_errors: args.ignore_errors,
+        retry: worker::RetryPolicy {
+            attempts: args.retries,
+            delay: args.retry_delay,
+            rules: args.retry_on.clone(),
+        },
+        // Only worth a stat per file when the total is shown; sizes from a
+        // --byte-progress scan are used as they are
+        count_bytes: (args.verbose || args.stats || args.json) && !args.byte_progress,
+        filters,
+        order: args.delete_order,
+        unlock: args.unlock,
     };
 
     if args.verbose {
-        println!("Spawning {} worker threads...", worker_count);
+        println!("{}", msg!(SpawningWorkers, worker_count));
     }
     let handles = worker::spawn_workers(
         worker_count,
@@ -316,114 +2993,292 @@ fn process_single_path(path: &Path, args: &Args) -> Result<DeletionStats, Error>
     drop(tx);
 
     if args.verbose {
-        println!("Deleting directories...");
+        println!("{}", msg!(DeletingDirectories));
     }
     let delete_start = Instant::now();
+    timings.add(Stage::Plan, delete_start - plan_start);
 
-    let progress_handle = if args.verbose {
-        let total = broker.total_dirs();
-        let broker_clone = broker.clone();
-        Some(std::thread::spawn(move || loop {
-            std::thread::sleep(std::time::Duration::from_millis(250));
-            let completed = broker_clone.completed_count();
-            if completed >= total {
-                break;
-            }
-            let pct = (completed as f64 / total as f64 * 100.0) as u32;
-            print!("\rDeleting... {}% ({}/{} dirs)", pct, completed, total);
-            use std::io::Write;
-            std::io::stdout().flush().ok();
-        }))
-    } else {
-        None
+    // Percentages mean nothing while the total is still being found
+    let progress_handle = match stream {
+        Some(_) => None,
+        None => spawn_progress_line(&broker, run, args),
     };
 
-    for handle in handles {
-        handle.join().expect("Worker thread panicked");
+    let scan_progress = tree::ScanProgress::new();
+    let workers_done = AtomicBool::new(false);
+    let stalls = Arc::new(Mutex::new(Vec::new()));
+    let stall_hook = (!args.stall_after.is_zero()).then(|| {
+        let stalls = stalls.clone();
+        stall::StallHook::new(args.stall_after, move |stall: &stall::Stall| {
+            print_stall(stall);
+            stalls.lock().unwrap().push(stall.clone());
+        })
+    });
+    DELETING.store(true, Ordering::SeqCst);
+    let (joined, streamed) = std::thread::scope(|s| {
+        if args.when_idle && idle::IdleMonitor::is_supported() {
+            let (broker, workers_done) = (&broker, &workers_done);
+            s.spawn(move || watch_idle(broker, workers_done));
+        }
+        if let Some(hook) = &stall_hook {
+            let (broker, workers_done) = (&broker, &workers_done);
+            s.spawn(move || stall::watch(broker, hook, workers_done));
+        }
+        {
+            let (broker, workers_done) = (&broker, &workers_done);
+            s.spawn(move || watch_cancel(broker, workers_done));
+        }
+        if let Some(interval) = heartbeat_interval(args) {
+            let (broker, error_tracker, workers_done) = (&broker, &error_tracker, &workers_done);
+            let (scan_progress, streaming) = (&scan_progress, stream.is_some());
+            s.spawn(move || {
+                heartbeat(interval, workers_done, || {
+                    let failures = error_tracker.failure_count();
+                    if streaming {
+                        stream_heartbeat(broker, scan_progress, failures)
+                    } else {
+                        delete_heartbeat(broker, failures, &run)
+                    }
+                })
+            });
+        }
+        let streamed = stream
+            .as_ref()
+            .map(|options| stream_scan(path, options, &broker, &scan_progress, args));
+        let joined = worker::join_workers(handles);
+        workers_done.store(true, Ordering::Relaxed);
+        (joined, streamed)
+    });
+    DELETING.store(false, Ordering::SeqCst);
+    let delete_time = delete_start.elapsed();
+    timings.add(Stage::Delete, delete_time);
+    if let Err(e) = joined {
+        if let Some(handle) = progress_handle {
+            handle.join().ok();
+        }
+        return Err(e);
+    }
+
+    if let Some(saved) = &saved_root {
+        let restore = timings.time(Stage::Finish, || {
+            let _as_user = impersonate::enter();
+            saved.restore()
+        });
+        for (what, e) in restore {
+            eprintln!("{}", msg!(RootRestoreFailed, what, path.display(), e));
+        }
     }
 
     if let Some(handle) = progress_handle {
         handle.join().ok();
+    }
+    // Streamed: the totals are only known now
+    let (dir_count, file_count, scan_time) = match streamed {
+        Some(Ok(scan_time)) => {
+            if args.verbose {
+                println!(
+                    "{}",
+                    msg!(
+                        StreamFound,
+                        scan_progress.dirs(),
+                        scan_progress.files(),
+                        format!("{:.2?}", scan_time)
+                    )
+                );
+            }
+            timings.add(Stage::Scan, scan_time);
+            (scan_progress.dirs(), scan_progress.files(), scan_time)
+        }
+        Some(Err(e)) if e.kind() == std::io::ErrorKind::Interrupted => {
+            return Err(Error::StreamCancelled {
+                path: path.to_path_buf(),
+                dirs_deleted: broker.completed_count(),
+            })
+        }
+        Some(Err(e)) => return Err(Error::io_with_path(path.to_path_buf(), e)),
+        None => (dir_count, file_count, scan_time),
+    };
+    if DELETE_CANCEL.is_cancelled() {
+        let cancelled = Error::DeleteCancelled {
+            path: path.to_path_buf(),
+            dirs_deleted: dirs_removed + broker.completed_count(),
+            files_deleted: broker.progress().files_done,
+            dirs_left: broker.total_dirs().saturating_sub(broker.resolved_count()),
+        };
+        if !args.json {
+            print_cancelled(&cancelled, error_tracker.failure_count(), args);
+        }
+        return Err(cancelled);
+    }
+    if args.verbose {
         let total = broker.total_dirs();
-        println!("\rDeleting... 100% ({}/{} dirs) - Complete!", total, total);
+        let completed = broker.completed_count();
+        if run.multi {
+            outln!(
+                "{}",
+                msg!(
+                    DoneMulti,
+                    run.percent(&broker.progress()) as u32,
+                    completed,
+                    total
+                )
+            );
+        } else if completed + broker.kept_count() == total {
+            outln!("{}", msg!(DoneComplete, total, total));
+        } else {
+            outln!("{}", msg!(DoneWithErrors, completed, total));
+        }
     }
 
-    let delete_time = delete_start.elapsed();
-    let total_time = start.elapsed();
+    // A streamed scan overlaps the deletion rather than preceding it
+    let total_time = match stream {
+        Some(_) => delete_time,
+        None => scan_time + delete_time,
+    };
 
     let failures = error_tracker.get_failures();
     let failure_count = failures.len();
+    let blocked = broker.blocked_dirs();
+    let excluded = broker.excluded_entries();
+    let warnings = error_tracker.get_warnings();
 
     let stats = DeletionStats {
-        dirs_deleted: dir_count,
-        files_deleted: file_count,
-        total_scan_time: scan_time,
-        total_delete_time: delete_time,
+        dirs_deleted: dirs_removed + broker.completed_count(),
+        files_deleted: broker.progress().files_done,
+        bytes_freed: broker.progress().bytes_done,
+        items_skipped: excluded.len(),
+        bytes_skipped: excluded.iter().map(|e| e.bytes).sum(),
+        timings,
+        snapshots: snapshot.into_iter().collect(),
+        items_scanned: dir_count + file_count,
+        failures,
+        dirs_blocked: blocked.len(),
+        warnings,
+        stalls: std::mem::take(&mut *stalls.lock().unwrap()),
     };
 
-    if failure_count == 0 {
+    if args.json {
+        return Ok(stats);
+    }
+    let (failures, warnings) = (&stats.failures, &stats.warnings);
+    if failure_count == 0 && blocked.is_empty() {
         if args.verbose {
-            println!("\nDeletion complete!");
+            println!("\n{}", msg!(DeletionComplete));
+            print_bytes_freed(stats.bytes_freed);
+        }
+        print_skipped(&excluded, args.verbose);
+        if args.report_failures.shows_warnings() {
+            print_enumeration_warnings(warnings, args.verbose);
         }
         if args.stats {
-            println!("\nStatistics:");
-            println!("  Directories: {}", dir_count);
-            println!("  Files:       {}", file_count);
-            println!("  Total items: {}", dir_count + file_count);
-            println!("\nTiming:");
-            println!("  Scan time:   {:.2?}", scan_time);
-            println!("  Delete time: {:.2?}", delete_time);
-            println!("  Total time:  {:.2?}", total_time);
-            println!("\nPerformance:");
-            let items_per_sec = (dir_count + file_count) as f64 / total_time.as_secs_f64();
-            println!("  Throughput:  {:.0} items/sec", items_per_sec);
+            print_stats(
+                dir_count,
+                file_count,
+                stats.bytes_freed,
+                &stats.timings,
+                &error_tracker,
+                args,
+            );
         } else if args.verbose {
-            println!("  Scan time:   {:.2?}", scan_time);
-            println!("  Delete time: {:.2?}", delete_time);
-            println!("  Total time:  {:.2?}", total_time);
+            print_times(scan_time, delete_time, total_time);
         }
         Ok(stats)
     } else {
         if args.verbose {
-            println!("\nDeletion completed with errors!");
+            println!("\n{}", msg!(CompletedWithErrors));
+            print_bytes_freed(stats.bytes_freed);
+        }
+        print_skipped(&excluded, args.verbose);
+        if args.report_failures.shows_warnings() {
+            print_enumeration_warnings(warnings, args.verbose);
         }
         if args.verbose {
-            println!("  Scan time:   {:.2?}", scan_time);
-            println!("  Delete time: {:.2?}", delete_time);
-            println!("  Total time:  {:.2?}", total_time);
+            print_times(scan_time, delete_time, total_time);
         }
 
-        let total_completed = broker.completed_count();
-        let total_items = total_completed + failure_count;
+        let total_items = dir_count + file_count;
+        let mut failures: Vec<_> = failures
+            .iter()
+            .filter(|f| args.report_failures.shows(f))
+            .collect();
+        args.sort_failures.sort(&mut failures, path);
+        let hidden = failure_count - failures.len();
+        let failure_count = failures.len();
 
-        println!("\nError Summary:");
-        println!(
-            "  {} of {} items failed to delete",
-            failure_count, total_items
-        );
+        println!("\n{}", msg!(ErrorSummaryHeader));
+        if failure_count > 0 || blocked.is_empty() {
+            println!("{}", msg!(ItemsFailed, failure_count, total_items));
+        }
+        if hidden > 0 {
+            println!("{}", msg!(FailuresHidden, hidden));
+        }
+        if !blocked.is_empty() {
+            println!("{}", msg!(BlockedDirs, blocked.len()));
+            if args.verbose {
+                for dir in &blocked {
+                    println!("    {}", dir.display());
+                }
+            }
+        }
 
-        let display_count = std::cmp::min(10, failure_count);
-        println!("\nFirst {} failures:", display_count);
+        let display_count = std::cmp::min(args.show_failures, failure_count);
+        if display_count > 0 {
+            println!("\n{}", msg!(FirstFailures, display_count));
+        }
         for (i, failure) in failures.iter().take(display_count).enumerate() {
-            let item_type = if failure.is_dir { "dir" } else { "file" };
+            let item_type = if failure.is_dir {
+                msg!(KindDir)
+            } else {
+                msg!(KindFile)
+            };
             println!(
-                "  {}. [{}] {}: {}",
-                i + 1,
-                item_type,
-                failure.path.display(),
-                failure.error
+                "{}",
+                msg!(
+                    FailureLine,
+                    i + 1,
+                    item_type,
+                    failure.path.display(),
+                    failure.error,
+                    failure.detail()
+                )
             );
         }
 
-        if failure_count > 10 {
-            println!("\n  ... and {} more failures", failure_count - 10);
-            println!("\nRun with --verbose to see all errors as they occur");
+        if failure_count > display_count {
+            println!("\n{}", msg!(MoreFailures, failure_count - display_count));
+            println!("\n{}", msg!(RunVerbose));
+        }
+        for hint in hints::hints(failures.iter().copied()) {
+            println!("\n{}", messages::text(hint));
         }
 
-        Err(Error::PartialFailure {
-            total: total_items,
-            failed: failure_count,
-            errors: failures,
-        })
+        // The caller turns what was left behind into a partial failure
+        Ok(stats)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn verify_cli() {
+        Args::command().debug_assert();
+    }
+
+    #[test]
+    fn test_config_flags_exist() {
+        let command = Args::command();
+        let names: Vec<String> = command
+            .get_arguments()
+            .flat_map(|arg| {
+                let long = arg.get_long().map(|long| format!("--{}", long));
+                let short = arg.get_short().map(|short| format!("-{}", short));
+                long.into_iter().chain(short)
+            })
+            .collect();
+        for flag in config::LOCAL_FLAGS.iter().chain(config::PATH_FLAGS) {
+            assert!(names.iter().any(|name| name == flag), "{}", flag);
+        }
     }
 }
@@ -0,0 +1,492 @@
+//! Audit record of what a run found and what it removed (`--manifest-out`),
+//! and comparing a target against one taken earlier (`--diff`)
+
+use crate::error::{raw_path, FailedItem};
+use crate::tree::DirectoryTree;
+use std::collections::HashMap;
+use std::fs::{self, File};
+use std::io::{self, BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// An item as it was when the target was scanned
+#[derive(Debug, Clone)]
+pub struct Entry {
+    pub path: PathBuf,
+    pub is_dir: bool,
+    /// File size in bytes (directories have none)
+    pub size: Option<u64>,
+    pub modified: Option<SystemTime>,
+    /// Why the scan left this item in place, if it did
+    pub skipped: Option<&'static str>,
+}
+
+/// Stat every item in a freshly scanned tree
+///
+/// Skipped entries are listed themselves, but not what's inside them.
+pub fn record(tree: &DirectoryTree) -> Vec<Entry> {
+    let dirs = tree.dirs.iter().map(|dir| {
+        let skipped = tree
+            .retained
+            .contains(dir)
+            .then_some("holds skipped entries");
+        stat(dir, true, skipped)
+    });
+    let files = tree.files.iter().map(|file| stat(file, false, None));
+    let skipped = tree
+        .skipped
+        .iter()
+        .map(|entry| stat(&entry.path, entry.is_dir, Some(entry.reason.description())));
+    dirs.chain(files).chain(skipped).collect()
+}
+
+fn stat(path: &Path, is_dir: bool, skipped: Option<&'static str>) -> Entry {
+    let metadata = fs::symlink_metadata(path).ok();
+    Entry {
+        path: path.to_path_buf(),
+        is_dir,
+        size: metadata.as_ref().filter(|_| !is_dir).map(|m| m.len()),
+        modified: metadata.and_then(|m| m.modified().ok()),
+        skipped,
+    }
+}
+
+/// CSV file the entries of every target are appended to
+pub struct Manifest {
+    out: BufWriter<File>,
+}
+
+impl Manifest {
+    /// Create (or truncate) the manifest and write its header
+    pub fn create(path: &Path) -> io::Result<Self> {
+        let mut out = BufWriter::new(File::create(path)?);
+        writeln!(out, "path,path_raw,type,size,modified,outcome,detail")?;
+        Ok(Self { out })
+    }
+
+    /// Append a target's entries with what became of them
+    ///
+    /// Items no longer on disk count as removed (`removed_as` is "deleted",
+    /// "trashed" or "staged"); the rest are "skipped", "failed" (with the error from
+    /// `failures`) or "remaining".
+    pub fn write_target(
+        &mut self,
+        entries: &[Entry],
+        failures: &[FailedItem],
+        removed_as: &str,
+    ) -> io::Result<()> {
+        let failures: HashMap<&Path, &FailedItem> = failures
+            .iter()
+            .map(|failure| (failure.path.as_path(), failure))
+            .collect();
+
+        for entry in entries {
+            let gone = matches!(
+                fs::symlink_metadata(&entry.path),
+                Err(e) if e.kind() == io::ErrorKind::NotFound
+            );
+            let (outcome, detail) = match (entry.skipped, failures.get(entry.path.as_path())) {
+                _ if gone => (removed_as, String::new()),
+                (Some(reason), _) => ("skipped", reason.to_string()),
+                (None, Some(failure)) => ("failed", failure.error.to_string()),
+                (None, None) => ("remaining", String::new()),
+            };
+            // Exact spelling of non-Unicode names
+            let raw = match raw_path(&entry.path) {
+                Some(_) => encode_raw(&entry.path),
+                None => String::new(),
+            };
+
+            writeln!(
+                self.out,
+                "{},{},{},{},{},{},{}",
+                quote(&entry.path.to_string_lossy()),
+                raw,
+                if entry.is_dir { "dir" } else { "file" },
+                entry.size.map(|s| s.to_string()).unwrap_or_default(),
+                entry.modified.map(format_utc).unwrap_or_default(),
+                outcome,
+                quote(&detail)
+            )?;
+        }
+        self.out.flush()
+    }
+}
+
+/// Read back the entries of a manifest written earlier
+///
+/// Outcomes are ignored, and `skipped` is only set (to "skipped") for
+/// entries a dry run would have left in place.
+pub fn read(path: &Path) -> io::Result<Vec<Entry>> {
+    let contents = fs::read_to_string(path)?;
+    let invalid = |line: usize, what: &str| {
+        io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("line {}: {}", line, what),
+        )
+    };
+
+    let mut records = parse_csv(&contents).into_iter().enumerate();
+    match records.next() {
+        Some((_, header)) if header.first().map(String::as_str) == Some("path") => {}
+        _ => return Err(invalid(1, "not an rmbrr manifest")),
+    }
+    let mut entries = Vec::new();
+    for (i, fields) in records {
+        let [path, raw, kind, size, modified, outcome, _detail] = &fields[..] else {
+            return Err(invalid(i + 1, "expected 7 fields"));
+        };
+        let path = match decode_raw(raw) {
+            Some(raw) => raw,
+            None if raw.is_empty() => PathBuf::from(path),
+            None => return Err(invalid(i + 1, "bad path_raw")),
+        };
+        entries.push(Entry {
+            path,
+            is_dir: kind == "dir",
+            size: size.parse().ok(),
+            modified: parse_utc(modified),
+            skipped: (outcome == "skipped").then_some("skipped"),
+        });
+    }
+    Ok(entries)
+}
+
+/// What changed in a target since a manifest of it was taken
+#[derive(Debug, Default)]
+pub struct Diff {
+    /// Items that weren't there when the manifest was taken
+    pub new: Vec<PathBuf>,
+    /// Items in the manifest that are no longer there
+    pub gone: Vec<PathBuf>,
+    /// Items whose type, size or modification time differ, with which
+    pub changed: Vec<(PathBuf, &'static str)>,
+}
+
+impl Diff {
+    pub fn is_empty(&self) -> bool {
+        self.new.is_empty() && self.gone.is_empty() && self.changed.is_empty()
+    }
+}
+
+/// Compare a fresh scan of `root` with the entries a manifest recorded for it
+///
+/// Manifest entries outside `root` (other targets of the same run) are
+/// ignored. Paths must be spelled the same way as when the manifest was
+/// taken. Modification times are compared to the second.
+pub fn diff(planned: &[Entry], root: &Path, current: &[Entry]) -> Diff {
+    let mut planned: HashMap<&Path, &Entry> = planned
+        .iter()
+        .filter(|entry| entry.path.starts_with(root))
+        .map(|entry| (entry.path.as_path(), entry))
+        .collect();
+
+    let mut diff = Diff::default();
+    for entry in current {
+        let Some(before) = planned.remove(entry.path.as_path()) else {
+            diff.new.push(entry.path.clone());
+            continue;
+        };
+        let secs = |time: Option<SystemTime>| {
+            time.and_then(|t| t.duration_since(UNIX_EPOCH).ok())
+                .map(|d| d.as_secs())
+        };
+        let what = if before.is_dir != entry.is_dir {
+            "type"
+        } else if before.size != entry.size {
+            "size"
+        } else if secs(before.modified) != secs(entry.modified) {
+            "modified"
+        } else {
+            continue;
+        };
+        diff.changed.push((entry.path.clone(), what));
+    }
+    diff.gone = planned.into_keys().map(Path::to_path_buf).collect();
+
+    diff.new.sort();
+    diff.gone.sort();
+    diff.changed.sort();
+    diff
+}
+
+/// Split CSV text into records, undoing [`quote`]
+fn parse_csv(text: &str) -> Vec<Vec<String>> {
+    let mut records = Vec::new();
+    let mut fields = Vec::new();
+    let mut field = String::new();
+    let mut quoted = false;
+    let mut chars = text.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '"' if quoted => {
+                if chars.next_if_eq(&'"').is_some() {
+                    field.push('"');
+                } else {
+                    quoted = false;
+                }
+            }
+            '"' if field.is_empty() => quoted = true,
+            ',' if !quoted => fields.push(std::mem::take(&mut field)),
+            '\r' if !quoted && chars.peek() == Some(&'\n') => {}
+            '\n' if !quoted => {
+                fields.push(std::mem::take(&mut field));
+                records.push(std::mem::take(&mut fields));
+            }
+            c => field.push(c),
+        }
+    }
+    if !field.is_empty() || !fields.is_empty() {
+        fields.push(field);
+        records.push(fields);
+    }
+    records
+}
+
+/// Hex of the exact bytes (UTF-16 units on Windows) of `path`, as in a
+/// `path_raw` field
+#[cfg(unix)]
+pub(crate) fn encode_raw(path: &Path) -> String {
+    use std::os::unix::ffi::OsStrExt;
+    path.as_os_str()
+        .as_bytes()
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect()
+}
+
+/// Hex of the exact bytes (UTF-16 units on Windows) of `path`, as in a
+/// `path_raw` field
+#[cfg(windows)]
+pub(crate) fn encode_raw(path: &Path) -> String {
+    use std::os::windows::ffi::OsStrExt;
+    path.as_os_str()
+        .encode_wide()
+        .map(|u| format!("{:04x}", u))
+        .collect()
+}
+
+#[cfg(not(any(unix, windows)))]
+pub(crate) fn encode_raw(_path: &Path) -> String {
+    String::new()
+}
+
+/// The path spelled by a `path_raw` field, if it holds one
+#[cfg(unix)]
+pub(crate) fn decode_raw(hex: &str) -> Option<PathBuf> {
+    use std::os::unix::ffi::OsStringExt;
+    let bytes = (0..hex.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(hex.get(i..i + 2)?, 16).ok())
+        .collect::<Option<Vec<u8>>>()?;
+    (!bytes.is_empty()).then(|| std::ffi::OsString::from_vec(bytes).into())
+}
+
+/// The path spelled by a `path_raw` field, if it holds one
+#[cfg(windows)]
+pub(crate) fn decode_raw(hex: &str) -> Option<PathBuf> {
+    use std::os::windows::ffi::OsStringExt;
+    let units = (0..hex.len())
+        .step_by(4)
+        .map(|i| u16::from_str_radix(hex.get(i..i + 4)?, 16).ok())
+        .collect::<Option<Vec<u16>>>()?;
+    (!units.is_empty()).then(|| std::ffi::OsString::from_wide(&units).into())
+}
+
+#[cfg(not(any(unix, windows)))]
+pub(crate) fn decode_raw(_hex: &str) -> Option<PathBuf> {
+    None
+}
+
+/// A CSV field, quoted when it holds a delimiter, quote or line break
+fn quote(field: &str) -> String {
+    if field.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_string()
+    }
+}
+
+/// RFC 3339 timestamp in UTC, to the second
+fn format_utc(time: SystemTime) -> String {
+    let secs = match time.duration_since(UNIX_EPOCH) {
+        Ok(since) => since.as_secs() as i64,
+        Err(before) => -(before.duration().as_secs_f64().ceil() as i64),
+    };
+    let (days, rem) = (secs.div_euclid(86_400), secs.rem_euclid(86_400));
+
+    // Civil date from days since 1970-01-01 (Howard Hinnant's algorithm)
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z.rem_euclid(146_097);
+    let yoe = (doe - doe / 1460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + i64::from(month <= 2);
+
+    format!(
+        "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}Z",
+        year,
+        month,
+        day,
+        rem / 3600,
+        rem % 3600 / 60,
+        rem % 60
+    )
+}
+
+/// Inverse of [`format_utc`]
+fn parse_utc(text: &str) -> Option<SystemTime> {
+    let text = text.strip_suffix('Z')?;
+    let (date, time) = text.split_once('T')?;
+    let mut date = date.splitn(3, '-').map(str::parse::<i64>);
+    let (year, month, day) = (date.next()?.ok()?, date.next()?.ok()?, date.next()?.ok()?);
+    let mut time = time.splitn(3, ':').map(str::parse::<i64>);
+    let (hour, min, sec) = (time.next()?.ok()?, time.next()?.ok()?, time.next()?.ok()?);
+
+    // Days since 1970-01-01 from a civil date (Howard Hinnant's algorithm)
+    let y = if month <= 2 { year - 1 } else { year };
+    let era = y.div_euclid(400);
+    let yoe = y.rem_euclid(400);
+    let mp = if month > 2 { month - 3 } else { month + 9 };
+    let doy = (153 * mp + 2) / 5 + day - 1;
+    let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+    let days = era * 146_097 + doe - 719_468;
+
+    let secs = days * 86_400 + hour * 3600 + min * 60 + sec;
+    Some(if secs >= 0 {
+        UNIX_EPOCH + Duration::from_secs(secs as u64)
+    } else {
+        UNIX_EPOCH - Duration::from_secs(secs.unsigned_abs())
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_format_utc() {
+        assert_eq!(format_utc(UNIX_EPOCH), "1970-01-01T00:00:00Z");
+        let leap_day = UNIX_EPOCH + Duration::from_secs(951_782_400 + 3_723);
+        assert_eq!(format_utc(leap_day), "2000-02-29T01:02:03Z");
+        let before = UNIX_EPOCH - Duration::from_secs(1);
+        assert_eq!(format_utc(before), "1969-12-31T23:59:59Z");
+    }
+
+    #[test]
+    fn test_parse_utc_round_trip() {
+        for secs in [0, 951_782_400 + 3_723, 1_700_000_000] {
+            let time = UNIX_EPOCH + Duration::from_secs(secs);
+            assert_eq!(parse_utc(&format_utc(time)), Some(time));
+        }
+        let before = UNIX_EPOCH - Duration::from_secs(1);
+        assert_eq!(parse_utc(&format_utc(before)), Some(before));
+        assert_eq!(parse_utc("yesterday"), None);
+    }
+
+    #[test]
+    fn test_parse_csv() {
+        let records = parse_csv("a,\"b,c\",\"say \"\"hi\"\"\"\r\n\"x\ny\",\n");
+        assert_eq!(
+            records,
+            vec![vec!["a", "b,c", "say \"hi\""], vec!["x\ny", ""]]
+        );
+    }
+
+    #[test]
+    fn test_diff_against_read_manifest() {
+        let temp = std::env::temp_dir().join("rmbrr_manifest_diff_test");
+        let _ = fs::remove_dir_all(&temp);
+        let target = temp.join("target");
+        fs::create_dir_all(target.join("sub")).unwrap();
+        fs::write(target.join("sub/same.txt"), b"1").unwrap();
+        fs::write(target.join("grows.txt"), b"1").unwrap();
+        fs::write(target.join("goes.txt"), b"1").unwrap();
+
+        let tree = crate::tree::discover_tree(&target).unwrap();
+        let csv = temp.join("plan.csv");
+        let mut manifest = Manifest::create(&csv).unwrap();
+        manifest
+            .write_target(&record(&tree), &[], "deleted")
+            .unwrap();
+        drop(manifest);
+        let planned = read(&csv).unwrap();
+        assert_eq!(planned.len(), 5);
+
+        fs::write(target.join("grows.txt"), b"12").unwrap();
+        fs::remove_file(target.join("goes.txt")).unwrap();
+        fs::write(target.join("sub/new, \"quoted\".txt"), b"1").unwrap();
+
+        let current = record(&crate::tree::discover_tree(&target).unwrap());
+        let changes = diff(&planned, &target, &current);
+        assert_eq!(changes.new, vec![target.join("sub/new, \"quoted\".txt")]);
+        assert_eq!(changes.gone, vec![target.join("goes.txt")]);
+        assert!(changes
+            .changed
+            .contains(&(target.join("grows.txt"), "size")));
+        assert!(!changes
+            .changed
+            .iter()
+            .any(|(path, _)| path.ends_with("same.txt")));
+
+        // Entries of other targets in the same manifest don't count
+        let other = temp.join("other");
+        assert!(diff(&planned, &other, &[]).is_empty());
+
+        fs::remove_dir_all(&temp).ok();
+    }
+
+    #[test]
+    fn test_quote() {
+        assert_eq!(quote("plain"), "plain");
+        assert_eq!(quote("a,b"), "\"a,b\"");
+        assert_eq!(quote("say \"hi\""), "\"say \"\"hi\"\"\"");
+    }
+
+    #[test]
+    fn test_manifest_outcomes() {
+        let temp = std::env::temp_dir().join("rmbrr_manifest_test");
+        let _ = fs::remove_dir_all(&temp);
+        let target = temp.join("target");
+        fs::create_dir_all(target.join("sub")).unwrap();
+        fs::write(target.join("sub/gone.txt"), b"12345").unwrap();
+        fs::write(target.join("stuck.txt"), b"x").unwrap();
+
+        let tree = crate::tree::discover_tree(&target).unwrap();
+        let entries = record(&tree);
+        assert_eq!(entries.len(), 4);
+
+        fs::remove_file(target.join("sub/gone.txt")).unwrap();
+        let failure = FailedItem::new(
+            target.join("stuck.txt"),
+            io::Error::new(io::ErrorKind::PermissionDenied, "denied"),
+            false,
+        );
+
+        let csv = temp.join("manifest.csv");
+        let mut manifest = Manifest::create(&csv).unwrap();
+        manifest
+            .write_target(&entries, &[failure], "deleted")
+            .unwrap();
+        drop(manifest);
+
+        let contents = fs::read_to_string(&csv).unwrap();
+        let line = |name: &str| {
+            contents
+                .lines()
+                .find(|line| line.split(',').next().unwrap().ends_with(name))
+                .unwrap()
+                .to_string()
+        };
+        assert!(contents.starts_with("path,path_raw,type,size,modified,outcome,detail\n"));
+        assert!(line("gone.txt").contains(",file,5,"));
+        assert!(line("gone.txt").ends_with(",deleted,"));
+        assert!(line("stuck.txt").ends_with(",failed,denied"));
+        assert!(line("sub").ends_with(",remaining,"));
+
+        fs::remove_dir_all(&temp).ok();
+    }
+}
@@ -0,0 +1,546 @@
+//! User-facing CLI text, with English defaults and per-locale overrides
+//!
+//! Every message has a stable key (its variant name) and an English
+//! template. Templates use `{}` for the next argument or `{0}`, `{1}`, ...
+//! for a specific one, so translations can reorder them; `{{` and `}}`
+//! are literal braces.
+//!
+//! Overrides are plain text files of `Key = template` lines (`#` starts a
+//! comment, `\n` in a template is a line break). [`init`] loads the file
+//! named by `RMBRR_MESSAGES`, or else `<lang>.txt` for the current locale
+//! (`LC_ALL`, `LC_MESSAGES`, then `LANG`; `de_DE.UTF-8` tries `de_DE` then
+//! `de`) from a `locale` directory next to the executable or from
+//! `../share/rmbrr/locale`. Missing keys fall back to English.
+//!
+//! Classic Windows consoles on a legacy code page can't draw emoji or box
+//! drawing, so there [`init`] swaps templates for ASCII-safe ones (see
+//! [`ascii_safe`]); `RMBRR_ASCII=1` or `=0` forces this on or off. Text
+//! itself reaches the console through `WriteConsoleW` (std's console
+//! writer), so accented letters and Unicode paths show as they are.
+
+use std::collections::HashMap;
+use std::env;
+use std::fmt::Display;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
+
+macro_rules! catalog {
+    ($($name:ident => $text:literal,)*) => {
+        /// A user-facing message
+        #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+        pub enum Msg {
+            $($name,)*
+        }
+
+        impl Msg {
+            pub const ALL: &'static [Msg] = &[$(Msg::$name,)*];
+
+            /// Key used for this message in override files
+            pub fn key(self) -> &'static str {
+                match self {
+                    $(Msg::$name => stringify!($name),)*
+                }
+            }
+
+            /// Built-in English template
+            pub fn english(self) -> &'static str {
+                match self {
+                    $(Msg::$name => $text,)*
+                }
+            }
+        }
+    };
+}
+
+catalog! {
+    ErrorPrefix => "Error: {}",
+    FailedToProcess => "Failed to process {}: {}",
+    OperationStart => "Operation ID: {}",
+    ScanningTarget => "[{}/{}] Scanning: {}",
+    TargetMissing => "{} does not exist, nothing to delete",
+    ProcessingTarget => "[{}/{}] Processing: {}",
+    DuplicateTarget => "Skipping {}: same directory as {}",
+    RecentlyDeleted => "⚠️  WARNING: {} was already deleted {} ago by another rmbrr run ({}); is something running this twice? (--no-history turns this check off)",
+    HistoryUnwritable => "Could not update the history of recent deletions ({}): {}",
+    TargetsLargestFirst => "Targets (largest first):",
+    FreeGoalMet => "Leaving {}: {} already free (--free-at-least {})",
+    FreeGoalReached => "{} free, at least {} wanted: {} targets left in place",
+    DiffHeader => "Changes in {} since {}:",
+    DiffNone => "No changes in {} since {}",
+    DiffSummary => "{} new, {} gone, {} changed",
+    IdleWaiting => "Waiting for the machine to go idle (--when-idle)...",
+    IdlePaused => "Machine in use: paused until it's idle again",
+    IdleResumed => "Machine idle: resuming",
+    IdleUnsupported => "Warning: can't observe activity on this system; --when-idle starts right away",
+    UringUnsupported => "Warning: io_uring can't delete files on this system (Linux 5.11+ needed); using --backend sync",
+    TakeOwnershipUnavailable => "Warning: --take-ownership is off: {}",
+    UnlockPrompt => "{} has {} open. End it? [y/N] ",
+    UnlockEnded => "Ended {}, which had {} open",
+    UnlockFailed => "Couldn't end {}: {}",
+    UnlockCritical => "Not ending {}: the system or other programs need it",
+    AsUserPassword => "Password for {}: ",
+    AsUserFailed => "Error: can't log on as {}: {}",
+    OwnFileKept => "Keeping {} until the end of the run, as rmbrr writes it",
+    OwnFileDeleted => "Deleted {} last, once done writing it",
+    OwnFileLeft => "Could not delete {} at the end of the run: {}",
+
+    // Safety checks
+    DangerousDetected => "⚠️  WARNING: Dangerous operation detected!",
+    DangerousWithForce => "⚠️  WARNING: Deleting dangerous path with --force",
+    UseForce => "   To proceed anyway, use the --force flag",
+    ForceExample => "   Example: rmbrr --force {}",
+    CannotDelete => "   This path cannot be deleted for safety reasons.",
+    NoSystemDirs => "   Deletion of system directories is not allowed.",
+    SystemDirForbidden => "⛔ ERROR: Cannot delete system directory",
+    ProtectedInside => "⛔ ERROR: Target contains a protected directory: {}",
+    UseSkipProtected => "   To delete everything around it, use --skip-protected",
+    RootMismatch => "⛔ ERROR: Refusing to delete {}: its name doesn't match --root-must-match",
+    SystemDirNoForce => "   System directories cannot be deleted even with --force",
+
+    // Scanning
+    DryRunMode => "DRY RUN MODE - no files will be deleted",
+    ScanningTree => "Scanning directory tree: {}",
+    StreamingTree => "Scanning and deleting at the same time: {}",
+    ResumingTree => "Resuming {}: {} directories left, skipping those already deleted",
+    NothingToResume => "Nothing left to resume in {}",
+    ScanSpinner => "{} Scanning... {} dirs / {} files so far",
+    KeepEntryMissing => "Warning: keep-list entry not found: {}",
+    ScanFound => "Found {} directories ({} initial leaves), {} files in {}",
+    ScanSkipping => "Skipping {} items ({} directories kept to hold them)",
+    NothingToDelete => "Nothing to delete in {}",
+    HeartbeatScan => "scanning {}, {} items found",
+    StreamFound => "Scanned {} directories, {} files in {} while deleting",
+
+    // Confirmation and dry run
+    AboutToDelete => "About to delete:",
+    MapsTo => "  {} (maps to {})",
+    CountDirs => "  {} directories",
+    CountFiles => "  {} files",
+    CountTotal => "  Total: {} items",
+    CountTotalItems => "  {} total items",
+    CountSkipping => "  Skipping: {} items",
+    ConfirmPrompt => "Are you sure? [y/N] ",
+    ConfirmAnswers => "y,yes",
+    Aborted => "Aborted.",
+    DryRunResults => "DRY RUN RESULTS",
+    WouldDelete => "Would delete:",
+    WouldTrash => "Would move to trash:",
+    WouldStage => "Would move to the staging area:",
+    ToProceed => "To proceed with deletion:",
+    LongPathsNone => "None of the {} paths is over {} characters",
+    LongPathsFound => "{} of {} paths are over {} characters (longest: {})",
+    LongPathsExtended => "  rmbrr deletes them through extended-length (\\\\?\\) paths, but other tools may fail on them",
+    LongPathsNoLimit => "  No MAX_PATH limit applies here, but Windows tools would fail on them (e.g. on a shared drive)",
+    LongPathsWorst => "Longest {} paths:",
+    LongPathLine => "  {}  {}",
+    MovedToTrash => "Moved to trash: {}",
+    MovedToStage => "Staged: {} as {} (purged after {})",
+    NothingExpired => "Nothing staged in {} has expired",
+    RenamedAside => "Renamed {} to {}; deleting it from there",
+    RenameFailed => "Warning: could not rename {} aside ({}); deleting it in place",
+    RenameSkipped => "Warning: not renaming {}: {} entries inside must stay where they are; deleting it in place",
+    LeftoversFound => "Found {} directories left by interrupted rmbrr runs (renamed aside by --rename-first):",
+    LeftoverLine => "  {} (operation {})",
+    LeftoversHint => "Add --clean-leftovers to delete them too",
+    LeftoversCleaning => "Also deleting {} directories left by interrupted rmbrr runs",
+    RootMetadataUnreadable => "Warning: cannot read metadata of {} to preserve it: {}",
+    RootRestoreFailed => "Warning: could not restore the {} of {}: {}",
+    TrashTooSmall => "⚠️  WARNING: Target ({}) exceeds the trash's {} limit and would be deleted permanently",
+    TrashTooSmallWithForce => "⚠️  WARNING: Target ({}) exceeds the trash's {} limit; continuing with --force",
+
+    // Snapshots
+    SnapshotTaken => "Snapshot of {} taken before deleting: {}",
+    SnapshotFailed => "⚠️  WARNING: Target ({}) is over the --snapshot-above size but could not be snapshotted: {}",
+    SnapshotFailedWithForce => "⚠️  WARNING: Could not snapshot before deleting; continuing with --force: {}",
+
+    // Deletion progress
+    SpawningWorkers => "Spawning {} worker threads...",
+    DeletingDirectories => "Deleting directories...",
+    ProgressBar => "Deleting {} {}% ({}{} items, {}/s, ETA {})",
+    ProgressBarMulti => "Deleting {} {}% overall ({}{} items in this path, {}/s, ETA {})",
+    BytesProgress => "{} of {}, ",
+    DoneComplete => "Deleting... 100% ({}/{} dirs) - Complete!",
+    DoneMulti => "Deleting... {}% overall ({}/{} dirs deleted in this path)",
+    DoneWithErrors => "Deleting... finished with errors ({}/{} dirs deleted)",
+    HeartbeatDelete => "{}% done, {}{} items, {} failures, ETA {}",
+    HeartbeatStream => "{} items found so far, {} deleted, {} failures",
+    CancelStopping => "Stopping: finishing the directories in progress (Ctrl+C again to quit at once)",
+    CancelSummary => "Interrupted while deleting {}:",
+    CancelDeleted => "  Deleted:     {} directories, {} files",
+    CancelLeft => "  Not deleted: {} directories, with everything in them",
+    CancelFailed => "  Failed:      {} items",
+    CancelResume => "  Finish with: rmbrr --resume {}",
+    StallDetected => "Warning: nothing completed for {}; workers are still on:",
+    StallWorker => "  {}: {} (for {})",
+    EtaUnknown => "unknown",
+
+    // Results
+    DeletionComplete => "Deletion complete!",
+    CompletedWithErrors => "Deletion completed with errors!",
+    BytesFreed => "Freed {}",
+    StatisticsHeader => "Statistics:",
+    StatDirs => "  Directories: {}",
+    StatFiles => "  Files:       {}",
+    StatTotal => "  Total items: {}",
+    StatBytesFreed => "  Freed:       {}",
+    StatTypeChanged => "  Type changed: {} (file/dir swapped since scan, deleted anyway)",
+    StatCompressed => "  NTFS-compressed files: {}",
+    StatEncrypted => "  EFS-encrypted files: {}",
+    TimingHeader => "Timing:",
+    ScanTime => "  Scan time:   {}",
+    ScanTimeRate => "  Scan time:   {} ({} items/sec)",
+    DeleteTime => "  Delete time: {}",
+    TimeTotal => "  Total time:  {}",
+    StagesHeader => "Stages:",
+    StageTime => "  {}: {}",
+    PerformanceHeader => "Performance:",
+    Throughput => "  Throughput:  {} items/sec",
+    ByteThroughput => "               {}/sec",
+    SkippedHeader => "Skipped (left in place):",
+    SkippedReason => "  {}: {} items ({})",
+    ListWarningsHeader => "Warnings: {} directories could not be listed (their contents were left behind)",
+    AndMore => "  ... and {} more",
+    ErrorSummaryHeader => "Error Summary:",
+    ItemsFailed => "  {} of {} items failed to delete",
+    FailuresHidden => "  {} more not listed (already deleted by something else; --report-failures all lists them)",
+    BlockedDirs => "  {} directories not attempted (blocked by a failure inside them)",
+    FirstFailures => "First {} failures:",
+    FailureLine => "  {}. [{}] {}: {} ({})",
+    KindDir => "dir",
+    KindFile => "file",
+    MoreFailures => "  ... and {} more failures",
+    RunVerbose => "List them all with --show-failures all, or see errors as they occur with --verbose",
+    HintInUseWindows => "Files in use: close programs that have them open (editors, terminals, Explorer previews), or end them with --unlock prompt, or wait out scanners with more retries (--retries, --retry-on in_use=N:DELAY)",
+    HintInUse => "Files busy: stop the processes using them (`lsof +D DIR` or `fuser -vm DIR` lists them) and rerun, or retry longer with --retries",
+    HintAccessDeniedWindows => "Access denied: rerun from an elevated (Run as administrator) prompt, with --take-ownership for entries owned by other users or services",
+    HintSudo => "Permission denied under a system directory: rerun with sudo",
+    HintPermissionDenied => "Permission denied: deleting an entry needs write access to the directory holding it; check that directory's owner and mode (ls -ld), or rerun as its owner",
+    HintReadOnly => "Read-only filesystem or write-protected media: remount it read-write or remove the write protection, then rerun",
+    HintPathTooLong => "Names or paths too long for the filesystem: rename a directory above them to something shorter, then rerun",
+    HintNotEmpty => "Directories not empty: something kept creating entries in them; stop it and rerun, or retry with --retry-on not_empty=N",
+    HintEncrypted => "Encrypted (EFS) files can only be opened by their owner or a recovery agent: run as that user, import their EFS certificate and key, or use a recovery agent account",
+
+    // Multi-path summary
+    SummaryTitle => "SUMMARY",
+    SummaryOperation => "Operation ID: {}",
+    SummaryPaths => "Paths processed: {}",
+    SummaryDirsDeleted => "Directories deleted: {}",
+    SummaryFilesDeleted => "Files deleted: {}",
+    SummaryBytesFreed => "Space freed: {}",
+    SummarySkipped => "Skipped: {} items ({})",
+    SummaryFailedItems => "Failed items: {}",
+    SummaryFailedPaths => "Failed paths: {}",
+    SummarySnapshot => "Snapshot taken: {}",
+    TotalScanTime => "  Total scan time:   {}",
+    TotalDeleteTime => "  Total delete time: {}",
+    TotalTime => "  Total time:        {}",
+
+    // Estimate
+    EstimateExact => "Estimate for {} (counted in full in {}):",
+    EstimateSampled => "Estimate for {} (sampled {} of {} directories in {}):",
+    EstimateItems => "  Items:    {}",
+    EstimateSize => "  Size:     {}",
+    EstimateDuration => "  Duration: ~{}",
+    EstimateItemsRange => "  Items:    {}  (95% range {} - {})",
+    EstimateSizeRange => "  Size:     {}  (95% range {} - {})",
+    EstimateDurationRange => "  Duration: ~{}  ({} - {})",
+
+    // Tables (sweep-projects, analyze)
+    HeaderKind => "KIND",
+    HeaderIdle => "IDLE",
+    HeaderSize => "SIZE",
+    HeaderItems => "ITEMS",
+    HeaderDirs => "DIRS",
+    HeaderFiles => "FILES",
+    HeaderPath => "PATH",
+    SearchingProjects => "Searching for stale projects in: {}",
+    NoStaleProjects => "No stale build directories found.",
+    SweepTotal => "{} directories, {} reclaimable",
+    AnalyzeMore => "({} more)",
+    AnalyzeRootFiles => "(files directly in the path)",
+    AnalyzeTotal => "{} (total)",
+    AnalyzedIn => "Analyzed in {}",
+}
+
+static OVERRIDES: OnceLock<HashMap<Msg, String>> = OnceLock::new();
+
+/// Load locale overrides; call once at startup, before printing anything
+///
+/// Later calls (and calls after the first message was looked up) are no-ops.
+pub fn init() {
+    let mut overrides = override_file()
+        .and_then(|file| fs::read_to_string(file).ok())
+        .map(|text| parse_overrides(&text))
+        .unwrap_or_default();
+    if ascii_output() {
+        for &msg in Msg::ALL {
+            let template = overrides.get(&msg).map_or(msg.english(), String::as_str);
+            if !template.is_ascii() {
+                let safe = ascii_safe(template);
+                overrides.insert(msg, safe);
+            }
+        }
+    }
+    OVERRIDES.set(overrides).ok();
+}
+
+/// `RMBRR_ASCII` if set to `1` or `0`, else whether output goes to a
+/// legacy console
+fn ascii_output() -> bool {
+    match env::var("RMBRR_ASCII").as_deref() {
+        Ok("1") => true,
+        Ok("0") => false,
+        _ => legacy_console(),
+    }
+}
+
+/// A classic console window (not Windows Terminal) whose output code page
+/// isn't UTF-8
+#[cfg(windows)]
+fn legacy_console() -> bool {
+    use windows::Win32::System::Console::{
+        GetConsoleMode, GetConsoleOutputCP, GetStdHandle, CONSOLE_MODE, STD_ERROR_HANDLE,
+        STD_OUTPUT_HANDLE,
+    };
+
+    const CP_UTF8: u32 = 65001;
+    if env::var_os("WT_SESSION").is_some() {
+        return false;
+    }
+    let on_console = [STD_OUTPUT_HANDLE, STD_ERROR_HANDLE]
+        .into_iter()
+        .any(|which| unsafe {
+            let mut mode = CONSOLE_MODE(0);
+            GetStdHandle(which).is_ok_and(|handle| GetConsoleMode(handle, &mut mode).is_ok())
+        });
+    on_console && unsafe { GetConsoleOutputCP() } != CP_UTF8
+}
+
+#[cfg(not(windows))]
+fn legacy_console() -> bool {
+    false
+}
+
+/// `template` without what legacy consoles can't draw
+///
+/// Emoji and other pictographs are dropped (with the spacing after them
+/// when they lead the template), box drawing becomes `-`, `|` and `+`,
+/// and block elements `#`. Letters of any script are kept.
+fn ascii_safe(template: &str) -> String {
+    let mut out = String::with_capacity(template.len());
+    let mut leading = true;
+    let mut dropped = false;
+    for c in template.chars() {
+        let kept = match c {
+            '\u{2500}'..='\u{257F}' => Some(box_char(c)),
+            '\u{2580}'..='\u{259F}' => Some('#'),
+            '\u{2190}'..='\u{21FF}'
+            | '\u{2300}'..='\u{23FF}'
+            | '\u{25A0}'..='\u{27BF}'
+            | '\u{2B00}'..='\u{2BFF}'
+            | '\u{FE00}'..='\u{FE0F}'
+            | '\u{200D}'
+            | '\u{1F000}'.. => None,
+            _ => Some(c),
+        };
+        match kept {
+            None => dropped |= leading,
+            Some(c) if leading && dropped && c == ' ' => {}
+            Some(c) => {
+                leading = false;
+                out.push(c);
+            }
+        }
+    }
+    out
+}
+
+/// ASCII stand-in for a box-drawing character
+fn box_char(c: char) -> char {
+    match c {
+        '─' | '━' | '┄' | '┅' | '┈' | '┉' | '╌' | '╍' | '═' | '╴' | '╶' | '╸' | '╺' | '╼' | '╾' => {
+            '-'
+        }
+        '│' | '┃' | '┆' | '┇' | '┊' | '┋' | '╎' | '╏' | '║' | '╵' | '╷' | '╹' | '╻' | '╽' | '╿' => {
+            '|'
+        }
+        _ => '+',
+    }
+}
+
+/// Template for `msg`: the locale override if there is one, else English
+pub fn text(msg: Msg) -> &'static str {
+    OVERRIDES
+        .get_or_init(HashMap::new)
+        .get(&msg)
+        .map_or(msg.english(), String::as_str)
+}
+
+/// Fill in the template for `msg` with `args`
+pub fn format(msg: Msg, args: &[&dyn Display]) -> String {
+    render(text(msg), args)
+}
+
+/// Render `msg` with arguments, like `format!`
+#[macro_export]
+macro_rules! msg {
+    ($msg:ident) => {
+        $crate::messages::text($crate::messages::Msg::$msg)
+    };
+    ($msg:ident, $($arg:expr),+ $(,)?) => {
+        $crate::messages::format($crate::messages::Msg::$msg, &[$(&$arg),+])
+    };
+}
+
+/// Substitute `{}` / `{N}` placeholders in `template`
+///
+/// Placeholders without a matching argument are left as they are, so a
+/// broken translation shows up as visible braces rather than a crash.
+fn render(template: &str, args: &[&dyn Display]) -> String {
+    let mut out = String::with_capacity(template.len() + 16 * args.len());
+    let mut next = 0;
+    let mut rest = template;
+    while let Some(pos) = rest.find(['{', '}']) {
+        out.push_str(&rest[..pos]);
+        rest = &rest[pos..];
+        if rest.starts_with("{{") || rest.starts_with("}}") {
+            out.push_str(&rest[..1]);
+            rest = &rest[2..];
+            continue;
+        }
+        if rest.starts_with('{') {
+            if let Some(end) = rest.find('}') {
+                let inner = &rest[1..end];
+                let index = if inner.is_empty() {
+                    next += 1;
+                    Some(next - 1)
+                } else {
+                    inner.parse::<usize>().ok()
+                };
+                if let Some(arg) = index.and_then(|i| args.get(i)) {
+                    out.push_str(&arg.to_string());
+                    rest = &rest[end + 1..];
+                    continue;
+                }
+            }
+        }
+        out.push_str(&rest[..1]);
+        rest = &rest[1..];
+    }
+    out.push_str(rest);
+    out
+}
+
+/// Parse `Key = template` lines; unknown keys are ignored so one file can
+/// serve several versions
+fn parse_overrides(text: &str) -> HashMap<Msg, String> {
+    let by_key: HashMap<&str, Msg> = Msg::ALL.iter().map(|&m| (m.key(), m)).collect();
+    text.lines()
+        .filter(|line| !line.trim_start().starts_with('#'))
+        .filter_map(|line| line.split_once('='))
+        .filter_map(|(key, value)| {
+            let msg = *by_key.get(key.trim())?;
+            let value = value.strip_prefix(' ').unwrap_or(value);
+            Some((msg, value.replace("\\n", "\n")))
+        })
+        .collect()
+}
+
+/// Override file to load, if any
+fn override_file() -> Option<PathBuf> {
+    if let Some(file) = env::var_os("RMBRR_MESSAGES") {
+        return Some(PathBuf::from(file));
+    }
+
+    let exe = env::current_exe().ok()?;
+    let exe_dir = exe.parent()?;
+    let dirs = [
+        exe_dir.join("locale"),
+        exe_dir.join("../share/rmbrr/locale"),
+    ];
+    let locale = ["LC_ALL", "LC_MESSAGES", "LANG"]
+        .iter()
+        .filter_map(|var| env::var(var).ok())
+        .find(|value| !value.is_empty())?;
+    locale_candidates(&locale)
+        .into_iter()
+        .flat_map(|lang| {
+            dirs.iter()
+                .map(move |dir| dir.join(format!("{}.txt", lang)))
+        })
+        .find(|file| Path::new(file).is_file())
+}
+
+/// `de_DE.UTF-8@euro` -> `["de_DE", "de"]`; nothing for `C` / `POSIX`
+fn locale_candidates(locale: &str) -> Vec<String> {
+    let base = locale.split(['.', '@']).next().unwrap_or_default();
+    if base.is_empty() || base == "C" || base == "POSIX" {
+        return Vec::new();
+    }
+    let mut candidates = vec![base.to_string()];
+    if let Some((lang, _)) = base.split_once(['_', '-']) {
+        candidates.push(lang.to_string());
+    }
+    candidates
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_render() {
+        assert_eq!(render("{} of {}", &[&1, &"two"]), "1 of two");
+        assert_eq!(render("{1} before {0}", &[&"a", &"b"]), "b before a");
+        assert_eq!(render("{{literal}} {}", &[&5]), "{literal} 5");
+        // Missing arguments stay visible instead of panicking
+        assert_eq!(render("{} and {}", &[&1]), "1 and {}");
+        assert_eq!(render("{x}", &[&1]), "{x}");
+    }
+
+    #[test]
+    fn test_ascii_safe() {
+        assert_eq!(
+            ascii_safe("⚠️  WARNING: {} was deleted"),
+            "WARNING: {} was deleted"
+        );
+        assert_eq!(ascii_safe("⛔ ERROR: {}"), "ERROR: {}");
+        assert_eq!(ascii_safe("┌──┐\n│ok│\n└──┘"), "+--+\n|ok|\n+--+");
+        assert_eq!(ascii_safe("[████░░] done ✅"), "[######] done ");
+        // Letters stay, and so does indentation not left by a pictograph
+        assert_eq!(ascii_safe("  Würde löschen:"), "  Würde löschen:");
+        for &msg in Msg::ALL {
+            assert!(ascii_safe(msg.english()).is_ascii(), "{}", msg.key());
+        }
+    }
+
+    #[test]
+    fn test_parse_overrides() {
+        let overrides = parse_overrides(
+            "# German\nAborted = Abgebrochen.\nCountDirs =   {} Verzeichnisse\nNoSuchKey = x\nRunVerbose = a\\nb\n",
+        );
+        assert_eq!(overrides[&Msg::Aborted], "Abgebrochen.");
+        assert_eq!(overrides[&Msg::CountDirs], "  {} Verzeichnisse");
+        assert_eq!(overrides[&Msg::RunVerbose], "a\nb");
+        assert_eq!(overrides.len(), 3);
+    }
+
+    #[test]
+    fn test_locale_candidates() {
+        assert_eq!(locale_candidates("de_DE.UTF-8"), vec!["de_DE", "de"]);
+        assert_eq!(locale_candidates("fr"), vec!["fr"]);
+        assert!(locale_candidates("C.UTF-8").is_empty());
+        assert!(locale_candidates("POSIX").is_empty());
+    }
+
+    #[test]
+    fn test_english_defaults() {
+        assert_eq!(msg!(Aborted), "Aborted.");
+        assert_eq!(msg!(CountDirs, 3), "  3 directories");
+        // Keys are unique and match variant names
+        let keys: std::collections::HashSet<_> = Msg::ALL.iter().map(|m| m.key()).collect();
+        assert_eq!(keys.len(), Msg::ALL.len());
+        assert_eq!(Msg::ErrorPrefix.key(), "ErrorPrefix");
+    }
+}
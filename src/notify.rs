@@ -0,0 +1,42 @@
+//! Tell file managers about deleted targets, so open windows don't show
+//! stale folders
+//!
+//! On Windows, Explorer is sent `SHChangeNotify` events: the target's
+//! removal (or an update, if it's still there emptied or in part) and an
+//! update of its parent. Explorer doesn't watch every view itself, notably
+//! ones on network drives. File managers elsewhere watch the directories
+//! they show (inotify, FSEvents) and see the deletion happen, and there is
+//! no standard D-Bus call asking them to refresh, so nothing is sent.
+
+use std::path::Path;
+
+/// `path` has been deleted, or emptied when it still exists
+#[cfg(windows)]
+pub fn deleted(path: &Path) {
+    use std::os::windows::ffi::OsStrExt;
+    use windows::Win32::UI::Shell::{
+        SHChangeNotify, SHCNE_ID, SHCNE_RMDIR, SHCNE_UPDATEDIR, SHCNF_FLAGS, SHCNF_FLUSHNOWAIT,
+        SHCNF_PATHW,
+    };
+
+    // The shell wants plain absolute paths (no \\?\)
+    let Ok(path) = std::path::absolute(path) else {
+        return;
+    };
+    let send = |event: SHCNE_ID, path: &Path| {
+        let wide: Vec<u16> = path.as_os_str().encode_wide().chain([0]).collect();
+        let flags = SHCNF_FLAGS(SHCNF_PATHW.0 | SHCNF_FLUSHNOWAIT.0);
+        unsafe { SHChangeNotify(event, flags, Some(wide.as_ptr().cast()), None) };
+    };
+    if path.exists() {
+        send(SHCNE_UPDATEDIR, &path);
+    } else {
+        send(SHCNE_RMDIR, &path);
+    }
+    if let Some(parent) = path.parent() {
+        send(SHCNE_UPDATEDIR, parent);
+    }
+}
+
+#[cfg(not(windows))]
+pub fn deleted(_path: &Path) {}
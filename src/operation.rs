@@ -0,0 +1,90 @@
+//! Identity of one rmbrr run, for tying its output and artifacts together
+
+use std::collections::hash_map::RandomState;
+use std::fmt;
+use std::hash::{BuildHasher, Hasher};
+use std::sync::OnceLock;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// A random (version 4) UUID naming one run
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct OperationId([u8; 16]);
+
+impl OperationId {
+    /// A fresh random ID
+    ///
+    /// The bits come from std's per-process random hasher keys mixed with
+    /// the time and process ID, which is plenty to keep runs across a fleet
+    /// apart without pulling in a UUID crate.
+    pub fn generate() -> Self {
+        let nanos = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_nanos())
+            .unwrap_or(0);
+        let mut bytes = [0u8; 16];
+        for (i, half) in bytes.chunks_mut(8).enumerate() {
+            let mut hasher = RandomState::new().build_hasher();
+            hasher.write_u128(nanos);
+            hasher.write_u32(std::process::id());
+            hasher.write_usize(i);
+            half.copy_from_slice(&hasher.finish().to_le_bytes());
+        }
+        Self::from_bytes(bytes)
+    }
+
+    /// An ID from raw bytes, with the version 4 / RFC 4122 variant bits set
+    pub fn from_bytes(mut bytes: [u8; 16]) -> Self {
+        bytes[6] = (bytes[6] & 0x0f) | 0x40;
+        bytes[8] = (bytes[8] & 0x3f) | 0x80;
+        Self(bytes)
+    }
+}
+
+impl fmt::Display for OperationId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (i, byte) in self.0.iter().enumerate() {
+            if matches!(i, 4 | 6 | 8 | 10) {
+                f.write_str("-")?;
+            }
+            write!(f, "{:02x}", byte)?;
+        }
+        Ok(())
+    }
+}
+
+impl serde::Serialize for OperationId {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+/// ID of the current run, generated on first use
+pub fn current() -> OperationId {
+    static CURRENT: OnceLock<OperationId> = OnceLock::new();
+    *CURRENT.get_or_init(OperationId::generate)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_format() {
+        let id = OperationId::from_bytes([0xab; 16]);
+        assert_eq!(id.to_string(), "abababab-abab-4bab-abab-abababababab");
+        assert_eq!(
+            serde_json::to_string(&id).unwrap(),
+            "\"abababab-abab-4bab-abab-abababababab\""
+        );
+    }
+
+    #[test]
+    fn test_generate_unique() {
+        let a = OperationId::generate();
+        let b = OperationId::generate();
+        assert_ne!(a, b);
+        assert_eq!(a.to_string().len(), 36);
+        assert_eq!(a.to_string().as_bytes()[14], b'4');
+        assert_eq!(current(), current());
+    }
+}
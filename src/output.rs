@@ -0,0 +1,165 @@
+//! Terminal output while a status line is drawn
+//!
+//! The progress bar and the scan spinner are redrawn in place with `\r` on
+//! stdout. Anything else written meanwhile, like a worker's verbose
+//! warning, would land in the middle of them, so output that can happen
+//! during a run goes through here instead: one lock serializes the writes,
+//! and a line printed while a status line is up blanks it first and draws
+//! it again below.
+
+use std::fmt;
+use std::io::{self, Write};
+use std::sync::{Mutex, MutexGuard};
+
+static SCREEN: Mutex<Screen> = Mutex::new(Screen::new());
+
+/// Print a line to stdout, above the status line if one is drawn
+#[macro_export]
+macro_rules! outln {
+    ($($arg:tt)*) => {
+        $crate::output::print_line(false, format_args!($($arg)*))
+    };
+}
+
+/// Print a line to stderr, above the status line if one is drawn
+#[macro_export]
+macro_rules! errln {
+    ($($arg:tt)*) => {
+        $crate::output::print_line(true, format_args!($($arg)*))
+    };
+}
+
+/// The status line currently drawn on stdout
+#[derive(Debug, Default)]
+struct Screen {
+    line: String,
+    /// Characters in `line`, blanked out by whatever replaces it
+    width: usize,
+}
+
+impl Screen {
+    const fn new() -> Self {
+        Self {
+            line: String::new(),
+            width: 0,
+        }
+    }
+
+    fn draw(&mut self, out: &mut dyn Write, line: &str) {
+        let width = line.chars().count();
+        let padding = " ".repeat(self.width.saturating_sub(width));
+        write!(out, "\r{}{}", line, padding).ok();
+        out.flush().ok();
+        self.line = line.to_string();
+        self.width = width;
+    }
+
+    fn blank(&self, out: &mut dyn Write) {
+        if self.width > 0 {
+            write!(out, "\r{}\r", " ".repeat(self.width)).ok();
+            out.flush().ok();
+        }
+    }
+
+    fn clear(&mut self, out: &mut dyn Write) {
+        self.blank(out);
+        self.line.clear();
+        self.width = 0;
+    }
+
+    /// Write `text` to `to` with the status line out of the way meanwhile
+    fn above(&self, out: &mut dyn Write, to: Option<&mut dyn Write>, text: fmt::Arguments) {
+        self.blank(out);
+        match to {
+            Some(to) => {
+                writeln!(to, "{}", text).ok();
+                to.flush().ok();
+            }
+            None => {
+                writeln!(out, "{}", text).ok();
+                out.flush().ok();
+            }
+        }
+        self.redraw(out);
+    }
+
+    fn redraw(&self, out: &mut dyn Write) {
+        if self.width > 0 {
+            write!(out, "{}", self.line).ok();
+            out.flush().ok();
+        }
+    }
+}
+
+fn screen() -> MutexGuard<'static, Screen> {
+    // A panic while printing leaves nothing half-updated worth refusing
+    SCREEN.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+/// Draw `line` as the status line, replacing the one drawn before
+pub fn status(line: &str) {
+    screen().draw(&mut io::stdout().lock(), line);
+}
+
+/// Blank the status line and stop drawing it again after printed lines
+pub fn clear_status() {
+    screen().clear(&mut io::stdout().lock());
+}
+
+/// Print a line to stderr or stdout, keeping it off the status line;
+/// see [`outln!`] and [`errln!`]
+pub fn print_line(stderr: bool, text: fmt::Arguments) {
+    let screen = screen();
+    let mut out = io::stdout().lock();
+    if stderr {
+        screen.above(&mut out, Some(&mut io::stderr().lock()), text);
+    } else {
+        screen.above(&mut out, None, text);
+    }
+}
+
+/// Run `ask` (a prompt and reading the answer) with the status line
+/// blanked, and nothing else printed, until it returns
+pub fn prompt<T>(ask: impl FnOnce() -> T) -> T {
+    let screen = screen();
+    screen.blank(&mut io::stdout().lock());
+    let answer = ask();
+    screen.redraw(&mut io::stdout().lock());
+    answer
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_lines_above_the_status_line() {
+        let mut screen = Screen::new();
+        let mut out = Vec::new();
+        let mut err = Vec::new();
+
+        // Nothing to blank or redraw without a status line
+        screen.above(&mut out, Some(&mut err), format_args!("early"));
+        assert!(out.is_empty());
+        assert_eq!(err, b"early\n");
+
+        screen.draw(&mut out, "[####    ] 50%");
+        screen.draw(&mut out, "[#####   ] 6%");
+        assert_eq!(out, b"\r[####    ] 50%\r[#####   ] 6% ");
+
+        out.clear();
+        err.clear();
+        screen.above(&mut out, Some(&mut err), format_args!("Warning: {}", 1));
+        assert_eq!(err, b"Warning: 1\n");
+        assert_eq!(out, b"\r             \r[#####   ] 6%");
+
+        out.clear();
+        screen.above(&mut out, None, format_args!("done"));
+        assert_eq!(out, b"\r             \rdone\n[#####   ] 6%");
+
+        out.clear();
+        screen.clear(&mut out);
+        screen.above(&mut out, None, format_args!("summary"));
+        assert_eq!(out, b"\r             \rsummary\n");
+    }
+}
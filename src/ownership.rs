@@ -0,0 +1,291 @@
+//! Taking over entries access is denied to (`--take-ownership`, Windows)
+//!
+//! With SeTakeOwnershipPrivilege (held by administrators, enabled by
+//! [`enable`]) any entry can be made the current user's, and an owner can
+//! always replace an entry's DACL. [`take`] does both, leaving a DACL that
+//! grants the current user full access and nothing else, so entries left
+//! by other users or services can be deleted. The entry's owner and DACL
+//! are read first (with SeBackupPrivilege, whatever the DACL says), and
+//! an entry that still can't be deleted afterwards gets them back
+//! ([`Original::restore`], with SeRestorePrivilege for owners other than
+//! the current user); one whose security can't be read isn't taken over.
+
+use std::io;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+#[cfg(windows)]
+use std::path::Path;
+#[cfg(windows)]
+use std::sync::OnceLock;
+#[cfg(windows)]
+use windows::core::{PCWSTR, PWSTR};
+#[cfg(windows)]
+use windows::Win32::Foundation::{
+    CloseHandle, GetLastError, LocalFree, ERROR_NOT_ALL_ASSIGNED, HANDLE, HLOCAL, LUID, WIN32_ERROR,
+};
+#[cfg(windows)]
+use windows::Win32::Security::Authorization::{
+    GetSecurityInfo, SetEntriesInAclW, SetNamedSecurityInfoW, EXPLICIT_ACCESS_W,
+    NO_MULTIPLE_TRUSTEE, SET_ACCESS, SE_FILE_OBJECT, TRUSTEE_IS_SID, TRUSTEE_IS_USER, TRUSTEE_W,
+};
+#[cfg(windows)]
+use windows::Win32::Security::{
+    AdjustTokenPrivileges, GetSecurityDescriptorControl, GetTokenInformation,
+    LookupPrivilegeValueW, TokenUser, ACL, DACL_SECURITY_INFORMATION, LUID_AND_ATTRIBUTES,
+    NO_INHERITANCE, OWNER_SECURITY_INFORMATION, PROTECTED_DACL_SECURITY_INFORMATION,
+    PSECURITY_DESCRIPTOR, PSID, SE_BACKUP_NAME, SE_DACL_PROTECTED, SE_PRIVILEGE_ENABLED,
+    SE_RESTORE_NAME, SE_TAKE_OWNERSHIP_NAME, TOKEN_ADJUST_PRIVILEGES, TOKEN_PRIVILEGES,
+    TOKEN_QUERY, TOKEN_USER, UNPROTECTED_DACL_SECURITY_INFORMATION,
+};
+#[cfg(windows)]
+use windows::Win32::Storage::FileSystem::{
+    CreateFileW, FILE_ALL_ACCESS, FILE_FLAG_BACKUP_SEMANTICS, FILE_FLAG_OPEN_REPARSE_POINT,
+    FILE_SHARE_DELETE, FILE_SHARE_READ, FILE_SHARE_WRITE, OPEN_EXISTING, READ_CONTROL,
+};
+#[cfg(windows)]
+use windows::Win32::System::Threading::{GetCurrentProcess, OpenProcessToken};
+
+static ENABLED: AtomicBool = AtomicBool::new(false);
+
+/// The current user's `TOKEN_USER`, which the SID [`take`] assigns points into
+#[cfg(windows)]
+static USER: OnceLock<Vec<u64>> = OnceLock::new();
+
+/// Whether [`take`] is tried on entries access is denied to
+pub fn is_enabled() -> bool {
+    ENABLED.load(Ordering::Relaxed)
+}
+
+/// Enable SeTakeOwnershipPrivilege for the process and turn [`take`] on;
+/// fails without it (not elevated)
+///
+/// SeBackupPrivilege and SeRestorePrivilege, for reading and putting back
+/// what was there, are enabled too where the token has them.
+#[cfg(windows)]
+pub fn enable() -> io::Result<()> {
+    unsafe {
+        let mut token = HANDLE::default();
+        OpenProcessToken(
+            GetCurrentProcess(),
+            TOKEN_ADJUST_PRIVILEGES | TOKEN_QUERY,
+            &mut token,
+        )
+        .map_err(os_error)?;
+        let result = enable_privilege(token, SE_TAKE_OWNERSHIP_NAME).and_then(|()| {
+            for name in [SE_BACKUP_NAME, SE_RESTORE_NAME] {
+                enable_privilege(token, name).ok();
+            }
+            current_user(token)
+        });
+        CloseHandle(token).ok();
+        let user = result?;
+        USER.get_or_init(|| user);
+    }
+    ENABLED.store(true, Ordering::Relaxed);
+    Ok(())
+}
+
+#[cfg(not(windows))]
+pub fn enable() -> io::Result<()> {
+    Err(io::Error::new(
+        io::ErrorKind::Unsupported,
+        "only available on Windows",
+    ))
+}
+
+#[cfg(windows)]
+unsafe fn enable_privilege(token: HANDLE, name: PCWSTR) -> io::Result<()> {
+    let mut luid = LUID::default();
+    LookupPrivilegeValueW(PCWSTR::null(), name, &mut luid).map_err(os_error)?;
+    let privileges = TOKEN_PRIVILEGES {
+        PrivilegeCount: 1,
+        Privileges: [LUID_AND_ATTRIBUTES {
+            Luid: luid,
+            Attributes: SE_PRIVILEGE_ENABLED,
+        }],
+    };
+    AdjustTokenPrivileges(token, false, Some(&privileges), 0, None, None).map_err(os_error)?;
+    // Succeeds without enabling anything when the token lacks the privilege
+    if GetLastError() == ERROR_NOT_ALL_ASSIGNED {
+        return Err(io::Error::new(
+            io::ErrorKind::PermissionDenied,
+            format!(
+                "{} is missing (run from an elevated prompt)",
+                String::from_utf16_lossy(name.as_wide())
+            ),
+        ));
+    }
+    Ok(())
+}
+
+#[cfg(windows)]
+unsafe fn current_user(token: HANDLE) -> io::Result<Vec<u64>> {
+    let mut size = 0;
+    // Fails with the size needed
+    GetTokenInformation(token, TokenUser, None, 0, &mut size).ok();
+    let mut buffer = vec![0u64; (size as usize).div_ceil(8)];
+    GetTokenInformation(
+        token,
+        TokenUser,
+        Some(buffer.as_mut_ptr().cast()),
+        size,
+        &mut size,
+    )
+    .map_err(os_error)?;
+    Ok(buffer)
+}
+
+/// An entry's owner and DACL from before [`take`]
+#[cfg(windows)]
+pub struct Original {
+    wide_path: Vec<u16>,
+    /// What `owner` and `dacl` point into; freed on drop
+    descriptor: PSECURITY_DESCRIPTOR,
+    owner: PSID,
+    /// Null for a null DACL, which grants everyone everything
+    dacl: *mut ACL,
+    /// Inherited entries didn't apply
+    protected: bool,
+}
+
+#[cfg(windows)]
+impl Original {
+    /// Read the owner and DACL of `wide_path`
+    unsafe fn read(wide_path: &[u16]) -> io::Result<Self> {
+        // Backup semantics: READ_CONTROL with SeBackupPrivilege, whatever
+        // the DACL says
+        let handle = CreateFileW(
+            PCWSTR(wide_path.as_ptr()),
+            READ_CONTROL.0,
+            FILE_SHARE_READ | FILE_SHARE_WRITE | FILE_SHARE_DELETE,
+            None,
+            OPEN_EXISTING,
+            FILE_FLAG_BACKUP_SEMANTICS | FILE_FLAG_OPEN_REPARSE_POINT,
+            HANDLE::default(),
+        )
+        .map_err(os_error)?;
+        let mut original = Original {
+            wide_path: wide_path.to_vec(),
+            descriptor: PSECURITY_DESCRIPTOR::default(),
+            owner: PSID::default(),
+            dacl: std::ptr::null_mut(),
+            protected: false,
+        };
+        let result = win32(GetSecurityInfo(
+            handle,
+            SE_FILE_OBJECT,
+            OWNER_SECURITY_INFORMATION | DACL_SECURITY_INFORMATION,
+            Some(&mut original.owner),
+            None,
+            Some(&mut original.dacl),
+            None,
+            Some(&mut original.descriptor),
+        ));
+        CloseHandle(handle).ok();
+        result?;
+        let (mut control, mut revision) = (0, 0);
+        GetSecurityDescriptorControl(original.descriptor, &mut control, &mut revision)
+            .map_err(os_error)?;
+        original.protected = control & SE_DACL_PROTECTED.0 != 0;
+        Ok(original)
+    }
+
+    /// Put the owner and DACL back
+    pub fn restore(self) -> io::Result<()> {
+        let protection = if self.protected {
+            PROTECTED_DACL_SECURITY_INFORMATION
+        } else {
+            UNPROTECTED_DACL_SECURITY_INFORMATION
+        };
+        unsafe {
+            win32(SetNamedSecurityInfoW(
+                PCWSTR(self.wide_path.as_ptr()),
+                SE_FILE_OBJECT,
+                OWNER_SECURITY_INFORMATION | DACL_SECURITY_INFORMATION | protection,
+                self.owner,
+                PSID::default(),
+                (!self.dacl.is_null()).then_some(self.dacl.cast_const()),
+                None,
+            ))
+        }
+    }
+}
+
+#[cfg(windows)]
+impl Drop for Original {
+    fn drop(&mut self) {
+        unsafe { LocalFree(HLOCAL(self.descriptor.0)) };
+    }
+}
+
+/// Make the current user the owner of `path`, with full access to it;
+/// returns what it had before, for [`Original::restore`]
+#[cfg(windows)]
+pub fn take(path: &Path) -> io::Result<Original> {
+    let user = USER
+        .get()
+        .ok_or_else(|| io::Error::other("--take-ownership is not enabled"))?;
+    let wide_path = crate::winapi::path_to_wide(path);
+    let name = PCWSTR(wide_path.as_ptr());
+    unsafe {
+        let original = Original::read(&wide_path)?;
+        let sid = (*user.as_ptr().cast::<TOKEN_USER>()).User.Sid;
+        // Owner first: the owner may replace the DACL whatever it says
+        win32(SetNamedSecurityInfoW(
+            name,
+            SE_FILE_OBJECT,
+            OWNER_SECURITY_INFORMATION,
+            sid,
+            PSID::default(),
+            None,
+            None,
+        ))?;
+
+        let access = EXPLICIT_ACCESS_W {
+            grfAccessPermissions: FILE_ALL_ACCESS.0,
+            grfAccessMode: SET_ACCESS,
+            grfInheritance: NO_INHERITANCE,
+            Trustee: TRUSTEE_W {
+                pMultipleTrustee: std::ptr::null_mut(),
+                MultipleTrusteeOperation: NO_MULTIPLE_TRUSTEE,
+                TrusteeForm: TRUSTEE_IS_SID,
+                TrusteeType: TRUSTEE_IS_USER,
+                ptstrName: PWSTR(sid.0.cast()),
+            },
+        };
+        let mut dacl: *mut ACL = std::ptr::null_mut();
+        win32(SetEntriesInAclW(Some(&[access]), None, &mut dacl))?;
+        // Protected, so deny entries inherited from the parent don't apply
+        let result = win32(SetNamedSecurityInfoW(
+            name,
+            SE_FILE_OBJECT,
+            DACL_SECURITY_INFORMATION | PROTECTED_DACL_SECURITY_INFORMATION,
+            PSID::default(),
+            PSID::default(),
+            Some(dacl),
+            None,
+        ));
+        LocalFree(HLOCAL(dacl.cast()));
+        match result {
+            Ok(()) => Ok(original),
+            // Owned by the current user, but without access to show for it
+            Err(e) => {
+                original.restore().ok();
+                Err(e)
+            }
+        }
+    }
+}
+
+#[cfg(windows)]
+fn os_error(e: windows::core::Error) -> io::Error {
+    io::Error::from_raw_os_error(e.code().0 & 0xFFFF)
+}
+
+#[cfg(windows)]
+fn win32(code: WIN32_ERROR) -> io::Result<()> {
+    match code.0 {
+        0 => Ok(()),
+        code => Err(io::Error::from_raw_os_error(code as i32)),
+    }
+}
@@ -0,0 +1,106 @@
+//! Paths too long for tools bound by the classic Windows MAX_PATH
+//! (`--max-path-length`)
+
+use crate::tree::DirectoryTree;
+use std::path::{Path, PathBuf};
+
+/// Longest path, in characters, that MAX_PATH (260 including the
+/// terminating NUL) leaves room for
+pub const MAX_PATH_CHARS: usize = 259;
+
+/// Whether this build deletes overlong paths through extended-length
+/// (`\\?\`) paths; elsewhere there is no such limit to work around
+pub const EXTENDED_LENGTH: bool = cfg!(windows);
+
+/// Paths of a tree longer than a limit
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LongPaths {
+    pub limit: usize,
+    /// Directories and files checked
+    pub checked: usize,
+    /// How many of them are over the limit
+    pub count: usize,
+    /// The longest ones with their lengths, longest first
+    pub worst: Vec<(PathBuf, usize)>,
+}
+
+/// Length of `path` in UTF-16 units, which is what MAX_PATH counts
+pub fn path_chars(path: &Path) -> usize {
+    #[cfg(windows)]
+    {
+        use std::os::windows::ffi::OsStrExt;
+        path.as_os_str().encode_wide().count()
+    }
+    #[cfg(not(windows))]
+    {
+        path.to_string_lossy().encode_utf16().count()
+    }
+}
+
+/// Directories and files of `tree` (scanned from `root`) longer than
+/// `limit` characters once made absolute, keeping the `keep` longest
+pub fn find_long_paths(tree: &DirectoryTree, root: &Path, limit: usize, keep: usize) -> LongPaths {
+    // Every path starts with `root`, so making it absolute adds the same to each
+    let extra = std::path::absolute(root)
+        .map(|full| path_chars(&full).saturating_sub(path_chars(root)))
+        .unwrap_or(0);
+
+    let mut long: Vec<(&PathBuf, usize)> = tree
+        .dirs
+        .iter()
+        .chain(&tree.files)
+        .map(|path| (path, path_chars(path) + extra))
+        .filter(|(_, chars)| *chars > limit)
+        .collect();
+    let count = long.len();
+    long.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(b.0)));
+    long.truncate(keep);
+
+    LongPaths {
+        limit,
+        checked: tree.dirs.len() + tree.files.len(),
+        count,
+        worst: long
+            .into_iter()
+            .map(|(path, chars)| {
+                let path = std::path::absolute(path).unwrap_or_else(|_| path.clone());
+                (path, chars)
+            })
+            .collect(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_find_long_paths() {
+        let root = PathBuf::from("/data/t");
+        let deep = root.join("d".repeat(40));
+        let mut tree = DirectoryTree::new();
+        tree.dirs = vec![root.clone(), deep.clone()];
+        tree.files = vec![
+            root.join("short.txt"),
+            deep.join("f".repeat(30)),
+            deep.join("g".repeat(20)),
+        ];
+
+        let long = find_long_paths(&tree, &root, 60, 1);
+        assert_eq!(long.checked, 5);
+        assert_eq!(long.count, 2);
+        let (path, chars) = &long.worst[0];
+        assert!(path.ends_with("f".repeat(30)));
+        assert_eq!(*chars, path_chars(path));
+        assert_eq!(long.worst.len(), 1);
+
+        let none = find_long_paths(&tree, &root, MAX_PATH_CHARS, 10);
+        assert_eq!((none.count, none.worst.len()), (0, 0));
+    }
+
+    #[test]
+    fn test_path_chars_counts_utf16_units() {
+        assert_eq!(path_chars(Path::new("a/é")), 3);
+        assert_eq!(path_chars(Path::new("😀")), 2);
+    }
+}
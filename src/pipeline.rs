@@ -0,0 +1,358 @@
+//! The deletion half of the pipeline on its own, for callers that already
+//! know which directories to delete and in what order
+//!
+//! ```no_run
+//! use std::path::PathBuf;
+//!
+//! let (out, cache) = (PathBuf::from("out"), PathBuf::from("out/cache"));
+//! // `out` is removed only once `out/cache` is gone
+//! let report = rmbrr::Pipeline::from_graph([out.clone(), cache.clone()], [(cache, out)])?
+//!     .threads(4)
+//!     .run()?;
+//! println!("{} directories deleted", report.dirs_deleted);
+//! # Ok::<(), rmbrr::error::Error>(())
+//! ```
+
+use crate::broker::Broker;
+use crate::deleter::{self, Report};
+use crate::error::Error;
+use crate::filter::Filters;
+use crate::handles::Unlock;
+use crate::stall::{self, StallHook};
+use crate::tree::DirectoryTree;
+use crate::worker::{self, DeleteOrder, ErrorTracker, RetryPolicy, WorkerConfig};
+use std::collections::{HashMap, HashSet};
+use std::path::{Component, Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+/// Runs the workers [`crate::Deleter`] uses over a dependency graph of
+/// directories, deleting each one's files and then the directory itself
+/// once every directory that depends on it is gone
+///
+/// Subdirectories missing from the graph are found and deleted like ones
+/// created while a [`crate::Deleter`] runs.
+#[derive(Debug)]
+pub struct Pipeline {
+    tree: DirectoryTree,
+    threads: Option<usize>,
+    force: bool,
+    max_per_parent: Option<usize>,
+    retry: RetryPolicy,
+    order: DeleteOrder,
+    filters: Arc<Filters>,
+    stall: Option<StallHook>,
+    /// Built by [`Pipeline::from_graph`]: every directory is checked, not
+    /// just the roots of a tree scanned below them
+    check_all: bool,
+}
+
+impl Pipeline {
+    /// Delete the directories `nodes`, where each edge `(child, parent)`
+    /// holds `parent` back until `child` is deleted
+    ///
+    /// Directories have at most one parent each, so the graph is a forest;
+    /// edges naming unknown directories, a child that isn't inside its
+    /// parent, giving a directory two parents or forming a cycle are refused.
+    pub fn from_graph(
+        nodes: impl IntoIterator<Item = PathBuf>,
+        edges: impl IntoIterator<Item = (PathBuf, PathBuf)>,
+    ) -> Result<Self, Error> {
+        let mut tree = DirectoryTree::new();
+        let mut known = HashSet::new();
+        for node in nodes {
+            if known.insert(node.clone()) {
+                tree.dirs.push(node);
+            }
+        }
+
+        let mut parents: HashMap<PathBuf, PathBuf> = HashMap::new();
+        for (child, parent) in edges {
+            if let Some(unknown) = [&child, &parent].into_iter().find(|n| !known.contains(*n)) {
+                return Err(invalid(
+                    unknown.clone(),
+                    "not one of the graph's directories",
+                ));
+            }
+            if !is_inside(&child, &parent) {
+                return Err(invalid(child, "not inside its parent"));
+            }
+            match parents.get(&child) {
+                Some(existing) if *existing == parent => continue,
+                Some(_) => return Err(invalid(child, "has more than one parent")),
+                None => {}
+            }
+            parents.insert(child.clone(), parent.clone());
+            tree.children.entry(parent).or_default().push(child);
+        }
+
+        // Peel off leaves; whatever can't be reached that way is on a cycle
+        let mut pending: HashMap<&PathBuf, usize> = tree
+            .children
+            .iter()
+            .map(|(parent, children)| (parent, children.len()))
+            .collect();
+        let mut ready: Vec<&PathBuf> = tree
+            .dirs
+            .iter()
+            .filter(|dir| !pending.contains_key(dir))
+            .collect();
+        let leaves: Vec<PathBuf> = ready.iter().map(|dir| (*dir).clone()).collect();
+        let mut resolved = 0;
+        while let Some(dir) = ready.pop() {
+            resolved += 1;
+            if let Some(parent) = parents.get(dir) {
+                let count = pending.get_mut(parent).unwrap();
+                *count -= 1;
+                if *count == 0 {
+                    ready.push(parent);
+                }
+            }
+        }
+        if resolved < tree.dirs.len() {
+            let stuck = pending.into_iter().find(|(_, count)| *count > 0);
+            let dir = stuck.map(|(dir, _)| dir.clone()).unwrap_or_default();
+            return Err(invalid(dir, "part of a dependency cycle"));
+        }
+
+        tree.leaves = leaves;
+        Ok(Self {
+            check_all: true,
+            ..Self::from_tree(tree)
+        })
+    }
+
+    /// Delete a scanned tree
+    pub(crate) fn from_tree(tree: DirectoryTree) -> Self {
+        Self {
+            tree,
+            threads: None,
+            force: false,
+            max_per_parent: None,
+            retry: RetryPolicy::default(),
+            order: DeleteOrder::default(),
+            filters: Arc::default(),
+            stall: None,
+            check_all: false,
+        }
+    }
+
+    /// Number of threads deleting (default: logical CPU count)
+    pub fn threads(mut self, threads: usize) -> Self {
+        self.threads = Some(threads.max(1));
+        self
+    }
+
+    /// Allow deleting the working directory or one of its parents
+    pub fn force(mut self, force: bool) -> Self {
+        self.force = force;
+        self
+    }
+
+    /// At most `limit` children of any one directory in flight at once
+    pub fn max_per_parent(mut self, limit: usize) -> Self {
+        self.max_per_parent = Some(limit);
+        self
+    }
+
+    /// How deletes failing because something else has the file open are retried
+    pub fn retry(mut self, retry: RetryPolicy) -> Self {
+        self.retry = retry;
+        self
+    }
+
+    /// Order the files of each directory are deleted in
+    pub fn order(mut self, order: DeleteOrder) -> Self {
+        self.order = order;
+        self
+    }
+
+    /// Call `hook` whenever nothing has completed for `hook.after` while
+    /// workers are busy, with what each of them is on
+    pub fn on_stall(mut self, hook: StallHook) -> Self {
+        self.stall = Some(hook);
+        self
+    }
+
+    /// Filters the tree was scanned with, applied again to files that
+    /// change or appear while deleting
+    pub(crate) fn filters(mut self, filters: Filters) -> Self {
+        self.filters = Arc::new(filters);
+        self
+    }
+
+    /// Delete every directory, children before parents
+    ///
+    /// The same safety checks as for [`crate::Deleter`] apply to each
+    /// directory of a graph. Failures to delete individual entries don't
+    /// make this an error; they are listed in the report.
+    pub fn run(self) -> Result<Report, Error> {
+        let tree = self.tree;
+        let has_parent: HashSet<&PathBuf> = tree.children.values().flatten().collect();
+        let checked = tree
+            .dirs
+            .iter()
+            .filter(|dir| self.check_all || !has_parent.contains(dir));
+        for dir in checked {
+            deleter::check_target(dir, self.force)?;
+        }
+
+        let delete_start = Instant::now();
+        let threads = self.threads.unwrap_or_else(|| {
+            std::thread::available_parallelism()
+                .map(|n| n.get())
+                .unwrap_or(4)
+        });
+        let (broker, tx, rx) = Broker::with_parent_limit(tree, self.max_per_parent);
+        let broker = Arc::new(broker);
+        if self.stall.is_some() {
+            broker.track_workers();
+        }
+        let error_tracker = Arc::new(ErrorTracker::new());
+        let config = WorkerConfig {
+            verbose: false,
+            ignore_errors: true,
+            retry: self.retry,
+            count_bytes: true,
+            filters: self.filters,
+            order: self.order,
+            // Never ends other processes, let alone asks on stdin
+            unlock: Unlock::Skip,
+        };
+        let handles =
+            worker::spawn_workers(threads, rx, broker.clone(), config, error_tracker.clone());
+        drop(tx);
+        let workers_done = AtomicBool::new(false);
+        std::thread::scope(|s| {
+            if let Some(hook) = &self.stall {
+                let (broker, workers_done) = (&broker, &workers_done);
+                s.spawn(move || stall::watch(broker, hook, workers_done));
+            }
+            let joined = worker::join_workers(handles);
+            workers_done.store(true, Ordering::Relaxed);
+            joined
+        })?;
+
+        Ok(Report {
+            dirs_deleted: broker.completed_count(),
+            files_deleted: broker.progress().files_done,
+            bytes_freed: broker.progress().bytes_done,
+            dirs_kept: broker.kept_count(),
+            skipped: broker.excluded_entries(),
+            failures: error_tracker.get_failures(),
+            blocked: broker.blocked_dirs(),
+            warnings: error_tracker.get_warnings(),
+            scan_time: Duration::ZERO,
+            delete_time: delete_start.elapsed(),
+        })
+    }
+}
+
+/// Whether `child` is strictly below `parent`, resolving symlinks when both
+/// exist; `..` never counts as inside
+fn is_inside(child: &Path, parent: &Path) -> bool {
+    if let (Ok(child), Ok(parent)) = (child.canonicalize(), parent.canonicalize()) {
+        return child != parent && child.starts_with(&parent);
+    }
+    child != parent
+        && child.starts_with(parent)
+        && !child.components().any(|c| c == Component::ParentDir)
+}
+
+fn invalid(path: PathBuf, reason: &str) -> Error {
+    Error::InvalidPath {
+        path,
+        reason: reason.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    #[test]
+    fn test_from_graph_deletes_in_order() {
+        let temp = std::env::temp_dir().join("rmbrr_pipeline_test");
+        let _ = fs::remove_dir_all(&temp);
+        let (a, b, c) = (temp.join("a"), temp.join("a/b"), temp.join("c"));
+        fs::create_dir_all(&b).unwrap();
+        fs::create_dir_all(c.join("unlisted")).unwrap();
+        fs::write(b.join("file.txt"), "x").unwrap();
+        fs::write(c.join("unlisted/file.txt"), "xy").unwrap();
+
+        let report =
+            Pipeline::from_graph([a.clone(), b.clone(), c.clone()], [(b.clone(), a.clone())])
+                .unwrap()
+                .threads(2)
+                .run()
+                .unwrap();
+        assert!(report.is_complete());
+        assert_eq!(report.dirs_deleted, 4);
+        assert_eq!((report.files_deleted, report.bytes_freed), (2, 3));
+        assert!(!a.exists() && !c.exists());
+        assert!(temp.exists());
+
+        fs::remove_dir_all(&temp).ok();
+    }
+
+    #[test]
+    fn test_from_graph_refuses_bad_graphs() {
+        let (a, b) = (PathBuf::from("a"), PathBuf::from("b"));
+        let reason = |result: Result<Pipeline, Error>| match result {
+            Err(Error::InvalidPath { path, reason }) => (path, reason),
+            other => panic!("expected a refusal, got {:?}", other.map(|_| ())),
+        };
+
+        let (ab, abc) = (a.join("b"), a.join("b/c"));
+
+        let (path, _) = reason(Pipeline::from_graph([a.clone()], [(ab.clone(), a.clone())]));
+        assert_eq!(path, ab);
+        let (path, reason_text) = reason(Pipeline::from_graph(
+            [a.clone(), ab.clone(), abc.clone()],
+            [(abc.clone(), ab.clone()), (abc.clone(), a.clone())],
+        ));
+        assert_eq!(path, abc);
+        assert!(reason_text.contains("more than one parent"));
+        // Edges between unrelated directories, and so cycles, are refused
+        for edges in [vec![(a.clone(), b.clone())], vec![(a.clone(), ab.clone())]] {
+            let (_, reason_text) = reason(Pipeline::from_graph(
+                [a.clone(), b.clone(), ab.clone()],
+                edges,
+            ));
+            assert!(reason_text.contains("not inside its parent"));
+        }
+        let up = a.join("../c");
+        let (path, _) = reason(Pipeline::from_graph(
+            [a.clone(), up.clone()],
+            [(up.clone(), a.clone())],
+        ));
+        assert_eq!(path, up);
+
+        // The same edge twice is fine
+        assert!(
+            Pipeline::from_graph([a.clone(), ab.clone()], [(ab.clone(), a.clone()), (ab, a)])
+                .is_ok()
+        );
+    }
+
+    #[test]
+    fn test_run_refuses_protected_directories() {
+        let temp = std::env::temp_dir().join("rmbrr_pipeline_protected_test");
+        let _ = fs::remove_dir_all(&temp);
+        let (a, b) = (temp.join("a"), temp.join("a/b"));
+        fs::create_dir_all(&b).unwrap();
+        crate::safety::protect([b.clone()]);
+
+        let result = Pipeline::from_graph([b.clone()], []).unwrap().run();
+        assert!(matches!(result, Err(Error::Refused { path, .. }) if path == b));
+        let result = Pipeline::from_graph([a.clone(), b.clone()], [(b.clone(), a)])
+            .unwrap()
+            .run();
+        assert!(matches!(result, Err(Error::Refused { .. })));
+        assert!(b.exists());
+
+        fs::remove_dir_all(&temp).ok();
+    }
+}
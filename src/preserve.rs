@@ -0,0 +1,118 @@
+//! Keeping a directory's own metadata intact while its contents are deleted
+
+use std::fs::{self, File, FileTimes};
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// Metadata of a directory, captured so it can be put back after emptying it
+///
+/// Deleting children bumps a directory's modification (and access) time,
+/// which tools that key off the directory itself notice. Permissions,
+/// ownership, attributes and ACLs aren't touched by deleting children, but
+/// permissions and ownership are checked and re-applied in case something
+/// else changed them meanwhile.
+pub struct SavedMetadata {
+    path: PathBuf,
+    metadata: fs::Metadata,
+}
+
+impl SavedMetadata {
+    pub fn capture(path: &Path) -> io::Result<Self> {
+        Ok(Self {
+            path: path.to_path_buf(),
+            metadata: fs::metadata(path)?,
+        })
+    }
+
+    /// Put the captured metadata back, returning what couldn't be restored
+    pub fn restore(&self) -> Vec<(&'static str, io::Error)> {
+        let mut failures = Vec::new();
+        if let Err(e) = self.restore_times() {
+            failures.push(("timestamps", e));
+        }
+
+        let current = match fs::metadata(&self.path) {
+            Ok(current) => current,
+            Err(e) => {
+                failures.push(("permissions", e));
+                return failures;
+            }
+        };
+        if current.permissions() != self.metadata.permissions() {
+            if let Err(e) = fs::set_permissions(&self.path, self.metadata.permissions()) {
+                failures.push(("permissions", e));
+            }
+        }
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::MetadataExt;
+            let (uid, gid) = (self.metadata.uid(), self.metadata.gid());
+            if (current.uid(), current.gid()) != (uid, gid) {
+                if let Err(e) = std::os::unix::fs::chown(&self.path, Some(uid), Some(gid)) {
+                    failures.push(("ownership", e));
+                }
+            }
+        }
+        failures
+    }
+
+    fn restore_times(&self) -> io::Result<()> {
+        let times = FileTimes::new()
+            .set_accessed(self.metadata.accessed()?)
+            .set_modified(self.metadata.modified()?);
+        #[cfg(windows)]
+        let times = {
+            use std::os::windows::fs::FileTimesExt;
+            times.set_created(self.metadata.created()?)
+        };
+        open_for_times(&self.path)?.set_times(times)
+    }
+}
+
+/// Open a directory with enough access to change its timestamps
+#[cfg(windows)]
+fn open_for_times(path: &Path) -> io::Result<File> {
+    use std::os::windows::fs::OpenOptionsExt;
+
+    const FILE_WRITE_ATTRIBUTES: u32 = 0x100;
+    const FILE_FLAG_BACKUP_SEMANTICS: u32 = 0x0200_0000;
+    fs::OpenOptions::new()
+        .access_mode(FILE_WRITE_ATTRIBUTES)
+        .custom_flags(FILE_FLAG_BACKUP_SEMANTICS)
+        .open(path)
+}
+
+/// Open a directory with enough access to change its timestamps
+#[cfg(not(windows))]
+fn open_for_times(path: &Path) -> io::Result<File> {
+    File::open(path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::{Duration, SystemTime};
+
+    #[test]
+    fn test_restore_times_after_emptying() {
+        let temp = std::env::temp_dir().join("rmbrr_preserve_test");
+        let _ = fs::remove_dir_all(&temp);
+        fs::create_dir_all(&temp).unwrap();
+        fs::write(temp.join("child"), b"x").unwrap();
+
+        let old = SystemTime::now() - Duration::from_secs(7 * 24 * 60 * 60);
+        open_for_times(&temp)
+            .unwrap()
+            .set_times(FileTimes::new().set_accessed(old).set_modified(old))
+            .unwrap();
+
+        let saved = SavedMetadata::capture(&temp).unwrap();
+        fs::remove_file(temp.join("child")).unwrap();
+        assert_ne!(fs::metadata(&temp).unwrap().modified().unwrap(), old);
+
+        assert!(saved.restore().is_empty());
+        assert_eq!(fs::metadata(&temp).unwrap().modified().unwrap(), old);
+
+        fs::remove_dir_all(&temp).ok();
+    }
+}
@@ -0,0 +1,144 @@
+//! Progress bar for the deletion phase: bar, rate and ETA
+
+use std::time::{Duration, Instant};
+
+/// Width of the bar itself, in characters
+pub const BAR_WIDTH: usize = 24;
+
+/// How quickly the smoothed rate follows changes; bursts shorter than this
+/// barely move the rate or the ETA
+const SMOOTHING: Duration = Duration::from_secs(3);
+
+/// Smoothed deletion rate and the ETA that follows from it
+///
+/// Workers finish directories in bursts, so a rate taken from the last
+/// redraw alone jumps around; this keeps an exponential moving average
+/// weighted by the time between samples instead.
+#[derive(Debug, Clone)]
+pub struct RateTracker {
+    last: Option<Sample>,
+    /// Items per second
+    items_rate: f64,
+    /// Completed fraction (0-1) per second
+    fraction_rate: f64,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Sample {
+    at: Instant,
+    items: usize,
+    fraction: f64,
+}
+
+impl RateTracker {
+    pub fn new() -> Self {
+        Self {
+            last: None,
+            items_rate: 0.0,
+            fraction_rate: 0.0,
+        }
+    }
+
+    /// Record `items` deleted so far and the `fraction` (0-1) complete
+    pub fn update(&mut self, items: usize, fraction: f64) {
+        self.update_at(Instant::now(), items, fraction);
+    }
+
+    fn update_at(&mut self, at: Instant, items: usize, fraction: f64) {
+        let sample = Sample {
+            at,
+            items,
+            fraction,
+        };
+        let Some(last) = self.last else {
+            self.last = Some(sample);
+            return;
+        };
+        let dt = at.saturating_duration_since(last.at).as_secs_f64();
+        if dt <= 0.0 {
+            return;
+        }
+        let items_rate = items.saturating_sub(last.items) as f64 / dt;
+        let fraction_rate = (fraction - last.fraction).max(0.0) / dt;
+        // The first interval has nothing to average with
+        let weight = if self.items_rate == 0.0 && self.fraction_rate == 0.0 {
+            1.0
+        } else {
+            1.0 - (-dt / SMOOTHING.as_secs_f64()).exp()
+        };
+        self.items_rate += weight * (items_rate - self.items_rate);
+        self.fraction_rate += weight * (fraction_rate - self.fraction_rate);
+        self.last = Some(sample);
+    }
+
+    /// Items deleted per second
+    pub fn items_per_sec(&self) -> f64 {
+        self.items_rate
+    }
+
+    /// Time left at the current rate, once there is a rate to go by
+    pub fn eta(&self) -> Option<Duration> {
+        let last = self.last?;
+        if self.fraction_rate <= 0.0 || last.fraction >= 1.0 {
+            return None;
+        }
+        let secs = (1.0 - last.fraction) / self.fraction_rate;
+        (secs.is_finite() && secs < 1e7).then(|| Duration::from_secs_f64(secs))
+    }
+}
+
+impl Default for RateTracker {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// `[#########>          ]` for `fraction` (0-1) complete
+pub fn bar(fraction: f64, width: usize) -> String {
+    let fraction = fraction.clamp(0.0, 1.0);
+    let filled = (fraction * width as f64) as usize;
+    let mut bar = String::with_capacity(width + 2);
+    bar.push('[');
+    for i in 0..width {
+        bar.push(match i.cmp(&filled) {
+            std::cmp::Ordering::Less => '#',
+            std::cmp::Ordering::Equal => '>',
+            std::cmp::Ordering::Greater => ' ',
+        });
+    }
+    bar.push(']');
+    bar
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_bar() {
+        assert_eq!(bar(0.0, 4), "[>   ]");
+        assert_eq!(bar(0.5, 4), "[##> ]");
+        assert_eq!(bar(1.0, 4), "[####]");
+        assert_eq!(bar(7.0, 4), "[####]");
+    }
+
+    #[test]
+    fn test_rate_tracker_smooths_bursts() {
+        let start = Instant::now();
+        let mut rate = RateTracker::new();
+        assert_eq!(rate.eta(), None);
+
+        rate.update_at(start, 0, 0.0);
+        rate.update_at(start + Duration::from_secs(1), 100, 0.1);
+        assert!((rate.items_per_sec() - 100.0).abs() < 1e-9);
+        let eta = rate.eta().unwrap().as_secs_f64();
+        assert!((eta - 9.0).abs() < 1e-6);
+
+        // A stall moves the rate down only part of the way
+        rate.update_at(start + Duration::from_secs(2), 100, 0.1);
+        assert!(rate.items_per_sec() > 50.0 && rate.items_per_sec() < 100.0);
+
+        rate.update_at(start + Duration::from_secs(3), 1000, 1.0);
+        assert_eq!(rate.eta(), None);
+    }
+}
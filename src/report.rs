@@ -0,0 +1,303 @@
+//! Machine-readable report of a run (`--json`)
+
+use crate::error::{serialize_path, EnumerationWarning, Error, FailedItem, EXIT_SUCCESS};
+use crate::operation::{self, OperationId};
+use crate::safety::SafetyCode;
+use crate::stall::Stall;
+use crate::timing::Timings;
+use serde::ser::{Serialize, SerializeStruct, Serializer};
+use std::path::PathBuf;
+use std::time::Duration;
+
+/// Bumped on any change that could break a consumer (fields are only
+/// ever added within one version)
+pub const SCHEMA_VERSION: u32 = 1;
+
+/// Everything a run did, printed as one JSON document at the end
+#[derive(Debug, serde::Serialize)]
+pub struct RunReport {
+    pub schema_version: u32,
+    pub operation: OperationId,
+    pub dry_run: bool,
+    pub targets: Vec<TargetReport>,
+    pub totals: Totals,
+    /// The error the run ended with, if any
+    pub error: Option<String>,
+    /// Safety check behind `error`, also when it only failed because
+    /// targets were refused
+    pub error_code: Option<SafetyCode>,
+    pub exit_code: i32,
+}
+
+/// What became of one target
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Outcome {
+    /// Deleted, apart from entries skipped on purpose
+    Deleted,
+    Trashed,
+    /// Moved into a staging area (`--stage`)
+    Staged,
+    /// Dry run: would have been deleted
+    WouldDelete,
+    /// Left alone because `--free-at-least` was already met
+    Spared,
+    /// Didn't exist, and `--missing-ok` allowed that
+    Missing,
+    /// Deleted in part; see `failures`
+    Partial,
+    /// Not deleted at all; see `error`
+    Failed,
+}
+
+#[derive(Debug)]
+pub struct TargetReport {
+    pub path: PathBuf,
+    pub outcome: Outcome,
+    pub error: Option<String>,
+    /// Safety check that refused the target
+    pub error_code: Option<SafetyCode>,
+    pub dirs_deleted: usize,
+    pub files_deleted: usize,
+    /// Bytes in the files deleted (0 for dry runs and the trash)
+    pub bytes_freed: u64,
+    pub items_skipped: usize,
+    pub bytes_skipped: u64,
+    /// Directories not attempted because something inside them failed
+    pub dirs_blocked: usize,
+    pub scan_time: Duration,
+    pub delete_time: Duration,
+    /// Time per stage (see [`crate::timing::Stage`])
+    pub stages: Timings,
+    /// Snapshots taken before deleting (`--snapshot-above`)
+    pub snapshots: Vec<String>,
+    pub failures: Vec<FailedItem>,
+    pub warnings: Vec<EnumerationWarning>,
+    /// Times nothing completed for `--stall-after`
+    pub stalls: Vec<Stall>,
+}
+
+impl TargetReport {
+    /// A target that failed before anything was deleted
+    pub fn failed(path: PathBuf, error: &Error) -> Self {
+        Self {
+            error: Some(error.to_string()),
+            error_code: error.safety_code(),
+            ..Self::empty(path, Outcome::Failed)
+        }
+    }
+
+    /// A target that didn't exist (`--missing-ok`)
+    pub fn missing(path: PathBuf) -> Self {
+        Self::empty(path, Outcome::Missing)
+    }
+
+    fn empty(path: PathBuf, outcome: Outcome) -> Self {
+        Self {
+            path,
+            outcome,
+            error: None,
+            error_code: None,
+            dirs_deleted: 0,
+            files_deleted: 0,
+            bytes_freed: 0,
+            items_skipped: 0,
+            bytes_skipped: 0,
+            dirs_blocked: 0,
+            scan_time: Duration::ZERO,
+            delete_time: Duration::ZERO,
+            stages: Timings::default(),
+            snapshots: Vec::new(),
+            failures: Vec::new(),
+            warnings: Vec::new(),
+            stalls: Vec::new(),
+        }
+    }
+}
+
+/// Serialized with `path` (and `path_raw`) like failures, and times as
+/// fractional milliseconds (`scan_ms`, `delete_ms`, `stages_ms`)
+impl Serialize for TargetReport {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut state = serializer.serialize_struct("TargetReport", 19)?;
+        serialize_path(&mut state, &self.path)?;
+        state.serialize_field("outcome", &self.outcome)?;
+        state.serialize_field("error", &self.error)?;
+        state.serialize_field("error_code", &self.error_code)?;
+        state.serialize_field("dirs_deleted", &self.dirs_deleted)?;
+        state.serialize_field("files_deleted", &self.files_deleted)?;
+        state.serialize_field("bytes_freed", &self.bytes_freed)?;
+        state.serialize_field("items_skipped", &self.items_skipped)?;
+        state.serialize_field("bytes_skipped", &self.bytes_skipped)?;
+        state.serialize_field("dirs_blocked", &self.dirs_blocked)?;
+        state.serialize_field("scan_ms", &millis(self.scan_time))?;
+        state.serialize_field("delete_ms", &millis(self.delete_time))?;
+        state.serialize_field("stages_ms", &self.stages)?;
+        state.serialize_field("snapshots", &self.snapshots)?;
+        state.serialize_field("failures", &self.failures)?;
+        state.serialize_field("warnings", &self.warnings)?;
+        state.serialize_field("stalls", &self.stalls)?;
+        state.end()
+    }
+}
+
+/// Sums over all targets
+#[derive(Debug, Default, serde::Serialize)]
+pub struct Totals {
+    pub targets: usize,
+    pub targets_failed: usize,
+    pub dirs_deleted: usize,
+    pub files_deleted: usize,
+    pub bytes_freed: u64,
+    pub items_skipped: usize,
+    pub bytes_skipped: u64,
+    pub items_failed: usize,
+    pub scan_ms: f64,
+    pub delete_ms: f64,
+    /// Time per stage, including parsing arguments once for the run
+    pub stages_ms: Timings,
+}
+
+impl RunReport {
+    pub fn new(dry_run: bool) -> Self {
+        Self {
+            schema_version: SCHEMA_VERSION,
+            operation: operation::current(),
+            dry_run,
+            targets: Vec::new(),
+            totals: Totals::default(),
+            error: None,
+            error_code: None,
+            exit_code: EXIT_SUCCESS,
+        }
+    }
+
+    pub fn push(&mut self, target: TargetReport) {
+        let totals = &mut self.totals;
+        totals.targets += 1;
+        if target.outcome == Outcome::Failed {
+            totals.targets_failed += 1;
+        }
+        totals.dirs_deleted += target.dirs_deleted;
+        totals.files_deleted += target.files_deleted;
+        totals.bytes_freed += target.bytes_freed;
+        totals.items_skipped += target.items_skipped;
+        totals.bytes_skipped += target.bytes_skipped;
+        totals.items_failed += target.failures.len();
+        totals.scan_ms += millis(target.scan_time);
+        totals.delete_ms += millis(target.delete_time);
+        totals.stages_ms.merge(&target.stages);
+        self.targets.push(target);
+    }
+
+    /// Record how the run ended
+    pub fn finish(&mut self, result: &Result<(), Error>) {
+        if let Err(e) = result {
+            self.error = Some(e.to_string());
+            self.error_code = e.safety_code().or_else(|| self.refusal());
+            self.exit_code = e.exit_code();
+        }
+    }
+
+    /// The first target's code, if refusals are all that went wrong
+    fn refusal(&self) -> Option<SafetyCode> {
+        let mut codes = self
+            .targets
+            .iter()
+            .filter(|t| matches!(t.outcome, Outcome::Failed | Outcome::Partial))
+            .map(|t| t.error_code);
+        let first = codes.next()??;
+        codes.all(|code| code.is_some()).then_some(first)
+    }
+}
+
+fn millis(duration: Duration) -> f64 {
+    duration.as_secs_f64() * 1000.0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::timing::Stage;
+    use std::io;
+
+    #[test]
+    fn test_run_report_json() {
+        let mut report = RunReport::new(false);
+        let mut target = TargetReport::failed(
+            PathBuf::from("/data/build"),
+            &Error::InvalidPath {
+                path: PathBuf::from("/data/build"),
+                reason: "gone".to_string(),
+            },
+        );
+        target.outcome = Outcome::Partial;
+        target.files_deleted = 3;
+        target.bytes_freed = 4096;
+        target.scan_time = Duration::from_millis(2);
+        target.stages.add(Stage::Scan, Duration::from_millis(2));
+        target.failures.push(FailedItem::new(
+            PathBuf::from("/data/build/locked"),
+            io::Error::from(io::ErrorKind::PermissionDenied),
+            false,
+        ));
+        report.push(target);
+        report.finish(&Err(Error::PartialFailure {
+            total: 4,
+            failed: 1,
+            errors: Vec::new(),
+        }));
+
+        let json = serde_json::to_value(&report).unwrap();
+        assert_eq!(json["schema_version"], SCHEMA_VERSION);
+        assert_eq!(json["exit_code"], crate::error::EXIT_PARTIAL);
+        assert_eq!(json["totals"]["files_deleted"], 3);
+        assert_eq!(json["totals"]["bytes_freed"], 4096);
+        assert_eq!(json["totals"]["items_failed"], 1);
+        let target = &json["targets"][0];
+        assert_eq!(target["path"], "/data/build");
+        assert_eq!(target["outcome"], "partial");
+        assert_eq!(target["scan_ms"], 2.0);
+        assert_eq!(target["stages_ms"]["scan"], 2.0);
+        assert_eq!(json["totals"]["stages_ms"]["scan"], 2.0);
+        assert_eq!(target["failures"][0]["path"], "/data/build/locked");
+        assert!(target["error_code"].is_null());
+        assert!(json["error_code"].is_null());
+    }
+
+    #[test]
+    fn test_refusal_codes() {
+        let mut report = RunReport::new(false);
+        report.push(TargetReport::failed(
+            PathBuf::from("/usr"),
+            &Error::Refused {
+                path: PathBuf::from("/usr"),
+                code: SafetyCode::SystemDir,
+                reason: "dangerous path".to_string(),
+            },
+        ));
+        report.finish(&Err(Error::PartialFailure {
+            total: 1,
+            failed: 1,
+            errors: Vec::new(),
+        }));
+
+        let json = serde_json::to_value(&report).unwrap();
+        assert_eq!(json["targets"][0]["error_code"], "SAFETY_SYSTEM_DIR");
+        assert_eq!(json["error_code"], "SAFETY_SYSTEM_DIR");
+        assert!(json["error"].as_str().unwrap().contains("Partial"));
+    }
+
+    #[test]
+    fn test_missing_target() {
+        let mut report = RunReport::new(false);
+        report.push(TargetReport::missing(PathBuf::from("/data/dist")));
+        report.finish(&Ok(()));
+
+        let json = serde_json::to_value(&report).unwrap();
+        assert_eq!(json["targets"][0]["outcome"], "missing");
+        assert!(json["targets"][0]["error"].is_null());
+        assert_eq!(json["totals"]["targets_failed"], 0);
+        assert_eq!(json["exit_code"], EXIT_SUCCESS);
+    }
+}
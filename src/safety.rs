@@ -1,87 +1,311 @@
 //! Safety checks and validation for rmbrr
 
+use crate::filter::Glob;
 use std::env;
 use std::path::{Path, PathBuf};
+use std::sync::Mutex;
 
-/// Check if a path is a protected system directory
-pub fn is_system_directory(path: &Path) -> bool {
-    let canonical = path.canonicalize().ok();
-    let path_str = path.to_string_lossy();
-    let canonical_str = canonical.as_ref().map(|p| p.to_string_lossy());
+/// System directories that may never be deleted
+#[cfg(windows)]
+const PROTECTED_SYSTEM_DIRS: &[&str] = &[
+    "C:\\Windows",
+    "C:\\Windows\\System32",
+    "C:\\Program Files",
+    "C:\\Program Files (x86)",
+    "C:\\ProgramData",
+    "C:\\",
+    "C:\\Users",
+];
 
-    // Check common system directories
-    #[cfg(windows)]
-    {
-        let protected_windows = [
-            "C:\\Windows",
-            "C:\\Windows\\System32",
-            "C:\\Program Files",
-            "C:\\Program Files (x86)",
-            "C:\\ProgramData",
-            "C:\\",
-            "C:\\Users",
-        ];
-
-        for protected in &protected_windows {
-            if path_str.eq_ignore_ascii_case(protected) {
-                return true;
-            }
-            if let Some(ref canonical) = canonical_str {
-                if canonical.eq_ignore_ascii_case(protected) {
-                    return true;
+/// System directories that may never be deleted
+#[cfg(unix)]
+const PROTECTED_SYSTEM_DIRS: &[&str] = &[
+    "/", "/bin", "/boot", "/dev", "/etc", "/lib", "/lib64", "/proc", "/root", "/sbin", "/sys",
+    "/usr", "/var",
+];
+
+/// Paths and patterns added with [`protect`] (the deny list)
+static USER_PROTECTED: Mutex<Vec<PathPattern>> = Mutex::new(Vec::new());
+/// Paths and patterns added with [`allow`]
+static USER_ALLOWED: Mutex<Vec<PathPattern>> = Mutex::new(Vec::new());
+
+/// Protect `paths` like system directories for the rest of the process
+/// (`--protect`, or `protect` in a config file)
+///
+/// Components from the first one holding `*`, `?` or `[` on are a
+/// [`Glob`] over the directories below, so `D:\Shares\*` protects every
+/// directory directly in `D:\Shares`.
+pub fn protect(paths: impl IntoIterator<Item = PathBuf>) {
+    let patterns = paths.into_iter().map(PathPattern::new);
+    USER_PROTECTED.lock().unwrap().extend(patterns);
+}
+
+/// Waive the checks `--force` overrides, and the warning about a target
+/// deleted moments ago, for `paths` (`--allow`, or `allow` in the user's
+/// config file); patterns as for [`protect`]
+///
+/// Protected directories stay protected.
+pub fn allow(paths: impl IntoIterator<Item = PathBuf>) {
+    let patterns = paths.into_iter().map(PathPattern::new);
+    USER_ALLOWED.lock().unwrap().extend(patterns);
+}
+
+/// Check if a path is one added with [`protect`]
+pub fn is_user_protected(path: &Path) -> bool {
+    let protected = USER_PROTECTED.lock().unwrap();
+    !protected.is_empty() && protected.iter().any(|pattern| pattern.matches(path))
+}
+
+/// Check if a path is one added with [`allow`]
+pub fn is_allowed(path: &Path) -> bool {
+    let allowed = USER_ALLOWED.lock().unwrap();
+    !allowed.is_empty() && allowed.iter().any(|pattern| pattern.matches(path))
+}
+
+/// A path whose components from the first wildcard on form a pattern
+#[derive(Debug, Clone)]
+struct PathPattern {
+    /// The components before the first wildcard
+    base: PathBuf,
+    /// The rest, one pattern per component
+    rest: Vec<String>,
+    /// `rest` as a whole
+    glob: Option<Glob>,
+}
+
+impl PathPattern {
+    fn new(path: PathBuf) -> Self {
+        let is_wild = |part: &str| part.contains(['*', '?', '[']);
+        let parts: Vec<_> = path.components().collect();
+        let split = parts
+            .iter()
+            .position(|part| part.as_os_str().to_str().is_some_and(is_wild));
+        let Some(split) = split else {
+            return Self {
+                base: path,
+                rest: Vec::new(),
+                glob: None,
+            };
+        };
+        let rest: Vec<String> = parts[split..]
+            .iter()
+            .map(|part| part.as_os_str().to_string_lossy().into_owned())
+            .collect();
+        match Glob::anchored(&rest.join("/")) {
+            Ok(glob) => Self {
+                base: parts[..split].iter().collect(),
+                rest,
+                glob: Some(glob),
+            },
+            // Not a valid pattern after all: a name with a `[` in it
+            Err(_) => Self {
+                base: path,
+                rest: Vec::new(),
+                glob: None,
+            },
+        }
+    }
+
+    fn matches(&self, path: &Path) -> bool {
+        let (Ok(path), Ok(base)) = (path.canonicalize(), self.base.canonicalize()) else {
+            return false;
+        };
+        let Some(glob) = &self.glob else {
+            return path == base;
+        };
+        path.strip_prefix(&base).is_ok_and(|relative| {
+            let parts: Vec<_> = relative.iter().map(|part| part.to_string_lossy()).collect();
+            !parts.is_empty() && glob.matches(&parts, true)
+        })
+    }
+
+    /// Existing directories matched that are in `root` or hold it
+    fn expand(&self, root: &Path) -> Vec<PathBuf> {
+        let Ok(base) = self.base.canonicalize() else {
+            return Vec::new();
+        };
+        let relevant = |dir: &Path| dir.starts_with(root) || root.starts_with(dir);
+        let mut dirs = vec![base];
+        for part in &self.rest {
+            let mut next = Vec::new();
+            if part == "**" {
+                while let Some(dir) = dirs.pop() {
+                    dirs.extend(subdirs(&dir).filter(|sub| relevant(sub)));
+                    next.push(dir);
+                }
+            } else {
+                let Ok(glob) = Glob::anchored(part) else {
+                    return Vec::new();
+                };
+                for dir in &dirs {
+                    next.extend(subdirs(dir).filter(|sub| {
+                        let name = sub.file_name().unwrap_or_default().to_string_lossy();
+                        relevant(sub) && glob.matches(&[name], true)
+                    }));
                 }
             }
+            dirs = next;
         }
+        dirs
+    }
+}
+
+/// Subdirectories of `dir`, not following links
+fn subdirs(dir: &Path) -> impl Iterator<Item = PathBuf> {
+    std::fs::read_dir(dir)
+        .into_iter()
+        .flatten()
+        .flatten()
+        .filter(|entry| entry.file_type().is_ok_and(|t| t.is_dir()))
+        .map(|entry| entry.path())
+}
 
-        // Check if it's a drive root (C:\, D:\, etc.)
-        if path_str.len() <= 3 && path_str.ends_with(":\\") {
-            return true;
+/// Stable, machine-readable reason a path is refused (`SAFETY_*` in
+/// `--json` output and error messages)
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, serde::Serialize)]
+pub enum SafetyCode {
+    /// A system directory (`C:\Windows`, `/usr`, ...)
+    #[serde(rename = "SAFETY_SYSTEM_DIR")]
+    SystemDir,
+    /// A drive or filesystem root
+    #[serde(rename = "SAFETY_VOLUME_ROOT")]
+    VolumeRoot,
+    /// The user's home directory
+    #[serde(rename = "SAFETY_HOME")]
+    Home,
+    /// Added with `--protect` or `protect` in a config file
+    #[serde(rename = "SAFETY_DENYLIST")]
+    Denylist,
+    /// Holds a protected directory further down
+    #[serde(rename = "SAFETY_DEPTH")]
+    Depth,
+    /// The working directory or one of its parents (allowed with `--force`)
+    #[serde(rename = "SAFETY_CURRENT_DIR")]
+    CurrentDir,
+    /// Something else took the target's place after it was checked and
+    /// scanned (say, a symlink to another directory)
+    #[serde(rename = "SAFETY_REPLACED")]
+    Replaced,
+}
+
+impl SafetyCode {
+    pub const ALL: [SafetyCode; 7] = [
+        SafetyCode::SystemDir,
+        SafetyCode::VolumeRoot,
+        SafetyCode::Home,
+        SafetyCode::Denylist,
+        SafetyCode::Depth,
+        SafetyCode::CurrentDir,
+        SafetyCode::Replaced,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            SafetyCode::SystemDir => "SAFETY_SYSTEM_DIR",
+            SafetyCode::VolumeRoot => "SAFETY_VOLUME_ROOT",
+            SafetyCode::Home => "SAFETY_HOME",
+            SafetyCode::Denylist => "SAFETY_DENYLIST",
+            SafetyCode::Depth => "SAFETY_DEPTH",
+            SafetyCode::CurrentDir => "SAFETY_CURRENT_DIR",
+            SafetyCode::Replaced => "SAFETY_REPLACED",
         }
     }
+}
 
+impl std::fmt::Display for SafetyCode {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+/// Check if a path is a protected system directory
+pub fn is_system_directory(path: &Path) -> bool {
+    system_protection(path).is_some()
+}
+
+/// Which kind of protected system directory `path` is: a drive or
+/// filesystem root, the user's home or another system directory
+pub fn system_protection(path: &Path) -> Option<SafetyCode> {
+    let canonical = path.canonicalize().ok();
+    let path_str = path.to_string_lossy();
+    let canonical_str = canonical.as_ref().map(|p| p.to_string_lossy());
+
+    // Drive roots (C:\, D:\, etc.) and `/`
+    if canonical.as_ref().is_some_and(|p| p.parent().is_none()) {
+        return Some(SafetyCode::VolumeRoot);
+    }
+    #[cfg(windows)]
+    if path_str.len() <= 3 && path_str.ends_with(":\\") {
+        return Some(SafetyCode::VolumeRoot);
+    }
     #[cfg(unix)]
-    {
-        let protected_unix = [
-            "/", "/bin", "/boot", "/dev", "/etc", "/lib", "/lib64", "/proc", "/root", "/sbin",
-            "/sys", "/usr", "/var",
-        ];
-
-        for protected in &protected_unix {
-            if path_str == *protected {
-                return true;
-            }
-            if let Some(ref canonical) = canonical_str {
-                if canonical.as_ref() == *protected {
-                    return true;
-                }
+    if path_str == "/" {
+        return Some(SafetyCode::VolumeRoot);
+    }
+
+    // The user's home directory (USERPROFILE on Windows)
+    let homes = ["HOME", "USERPROFILE"].into_iter().filter_map(env::var_os);
+    if let Some(canonical) = &canonical {
+        for home in homes {
+            if PathBuf::from(home).canonicalize().ok().as_ref() == Some(canonical) {
+                return Some(SafetyCode::Home);
             }
         }
     }
 
-    // Check if it's the user's home directory
-    if let Ok(home) = env::var("HOME") {
-        let home_path = PathBuf::from(home);
-        if let (Ok(p1), Ok(p2)) = (path.canonicalize(), home_path.canonicalize()) {
-            if p1 == p2 {
-                return true;
+    // Check common system directories
+    for protected in PROTECTED_SYSTEM_DIRS {
+        let matches = |p: &str| {
+            if cfg!(windows) {
+                p.eq_ignore_ascii_case(protected)
+            } else {
+                p == *protected
             }
+        };
+        if matches(&path_str) || canonical_str.as_deref().is_some_and(matches) {
+            return Some(SafetyCode::SystemDir);
         }
     }
 
-    // Windows USERPROFILE
-    #[cfg(windows)]
-    {
-        if let Ok(userprofile) = env::var("USERPROFILE") {
-            let user_path = PathBuf::from(userprofile);
-            if let (Ok(p1), Ok(p2)) = (path.canonicalize(), user_path.canonicalize()) {
-                if p1 == p2 {
-                    return true;
-                }
-            }
+    None
+}
+
+/// Protected directories (system directories, home directories and ones
+/// added with [`protect`]) strictly inside `root`, spelled as paths under
+/// `root` as given
+///
+/// Checking the target alone misses these: deleting `/home` would take the
+/// current user's home with it. Symlinks are resolved on both sides.
+pub fn protected_paths_within(root: &Path) -> Vec<PathBuf> {
+    let Ok(canonical_root) = root.canonicalize() else {
+        return Vec::new();
+    };
+
+    let mut candidates: Vec<PathBuf> = PROTECTED_SYSTEM_DIRS.iter().map(PathBuf::from).collect();
+    candidates.extend(
+        ["HOME", "USERPROFILE"]
+            .iter()
+            .filter_map(env::var_os)
+            .map(PathBuf::from),
+    );
+    for pattern in USER_PROTECTED.lock().unwrap().iter() {
+        match pattern.glob {
+            None => candidates.push(pattern.base.clone()),
+            Some(_) => candidates.extend(pattern.expand(&canonical_root)),
         }
     }
 
-    false
+    let mut found: Vec<PathBuf> = candidates
+        .iter()
+        .filter_map(|p| p.canonicalize().ok())
+        .filter_map(|p| {
+            let relative = p.strip_prefix(&canonical_root).ok()?;
+            (!relative.as_os_str().is_empty()).then(|| root.join(relative))
+        })
+        .collect();
+    found.sort();
+    found.dedup();
+    found
 }
 
 /// Check if path is within current working directory
@@ -97,42 +321,327 @@ pub fn is_in_current_directory(path: &Path) -> bool {
 
 /// Get a human-readable description of why a path might be dangerous
 pub fn get_danger_reason(path: &Path) -> Option<String> {
-    if is_system_directory(path) {
-        return Some(format!(
-            "'{}' is a system directory - deleting it could break your system",
-            path.display()
+    danger(path).map(|(_, reason)| reason)
+}
+
+/// Why `path` might be dangerous, with its [`SafetyCode`]
+fn danger(path: &Path) -> Option<(SafetyCode, String)> {
+    let reason = |what: &str| format!("'{}' {}", path.display(), what);
+    if is_user_protected(path) {
+        return Some((
+            SafetyCode::Denylist,
+            reason("is protected (--protect or a config file)"),
         ));
     }
 
+    match system_protection(path) {
+        Some(code @ SafetyCode::VolumeRoot) => {
+            return Some((code, reason("is the root of a drive or filesystem")));
+        }
+        Some(code @ SafetyCode::Home) => {
+            return Some((code, reason("is your home directory")));
+        }
+        Some(code) => {
+            return Some((
+                code,
+                reason("is a system directory - deleting it could break your system"),
+            ));
+        }
+        None => {}
+    }
+
     if is_in_current_directory(path) {
-        return Some(format!(
-            "'{}' contains or is your current working directory",
-            path.display()
+        return Some((
+            SafetyCode::CurrentDir,
+            reason("contains or is your current working directory"),
         ));
     }
 
     None
 }
 
-/// Safety check result
-#[derive(Debug)]
+/// Resolve a path on a `subst` or mapped network drive to its real target
+///
+/// Returns `None` if the path isn't on a drive mapping (always on non-Windows).
+#[cfg(windows)]
+pub fn resolve_drive_mapping(path: &Path) -> Option<PathBuf> {
+    use std::path::{Component, Prefix};
+
+    let mut current = path.to_path_buf();
+    let mut resolved = None;
+
+    // subst drives can point at other subst drives; don't follow forever
+    for _ in 0..8 {
+        let mut components = current.components();
+        let drive = match components.next()? {
+            Component::Prefix(prefix) => match prefix.kind() {
+                Prefix::Disk(d) | Prefix::VerbatimDisk(d) => d as char,
+                _ => break,
+            },
+            _ => break,
+        };
+        let rest: PathBuf = components
+            .filter(|c| !matches!(c, Component::RootDir))
+            .collect();
+
+        let Some(target) = subst_target(drive).or_else(|| network_target(drive)) else {
+            break;
+        };
+        current = target.join(rest);
+        resolved = Some(current.clone());
+    }
+
+    resolved
+}
+
+#[cfg(not(windows))]
+pub fn resolve_drive_mapping(_path: &Path) -> Option<PathBuf> {
+    None
+}
+
+/// Target of a `subst` drive (QueryDosDevice returns `\??\C:\...` for those)
+#[cfg(windows)]
+fn subst_target(drive: char) -> Option<PathBuf> {
+    use windows::core::PCWSTR;
+    use windows::Win32::Storage::FileSystem::QueryDosDeviceW;
+
+    let name: Vec<u16> = format!("{}:", drive)
+        .encode_utf16()
+        .chain(std::iter::once(0))
+        .collect();
+    let mut buf = vec![0u16; 1024];
+    let len = unsafe { QueryDosDeviceW(PCWSTR(name.as_ptr()), Some(&mut buf)) } as usize;
+    if len == 0 {
+        return None;
+    }
+
+    let end = buf[..len].iter().position(|&c| c == 0).unwrap_or(len);
+    let target = String::from_utf16_lossy(&buf[..end]);
+    let target = target.strip_prefix(r"\??\")?;
+    match target.strip_prefix(r"UNC\") {
+        Some(share) => Some(PathBuf::from(format!(r"\\{}", share))),
+        None => Some(PathBuf::from(target)),
+    }
+}
+
+/// Remote share behind a mapped network drive
+#[cfg(windows)]
+fn network_target(drive: char) -> Option<PathBuf> {
+    use windows::core::{PCWSTR, PWSTR};
+    use windows::Win32::Foundation::NO_ERROR;
+    use windows::Win32::NetworkManagement::WNet::WNetGetConnectionW;
+
+    let name: Vec<u16> = format!("{}:", drive)
+        .encode_utf16()
+        .chain(std::iter::once(0))
+        .collect();
+    let mut buf = vec![0u16; 1024];
+    let mut len = buf.len() as u32;
+    let result =
+        unsafe { WNetGetConnectionW(PCWSTR(name.as_ptr()), PWSTR(buf.as_mut_ptr()), &mut len) };
+    if result != NO_ERROR {
+        return None;
+    }
+
+    let end = buf.iter().position(|&c| c == 0).unwrap_or(buf.len());
+    Some(PathBuf::from(String::from_utf16_lossy(&buf[..end])))
+}
+
+/// Expand Windows 8.3 short names (`PROGRA~1`) in an existing path
+///
+/// Returns `None` if the path has no short names or can't be resolved
+/// (always on non-Windows).
+#[cfg(windows)]
+pub fn expand_short_names(path: &Path) -> Option<PathBuf> {
+    use std::ffi::OsString;
+    use std::os::windows::ffi::{OsStrExt, OsStringExt};
+    use windows::core::PCWSTR;
+    use windows::Win32::Storage::FileSystem::GetLongPathNameW;
+
+    if !path.to_string_lossy().contains('~') {
+        return None;
+    }
+    let wide: Vec<u16> = path
+        .as_os_str()
+        .encode_wide()
+        .chain(std::iter::once(0))
+        .collect();
+    let needed = unsafe { GetLongPathNameW(PCWSTR(wide.as_ptr()), None) } as usize;
+    if needed == 0 {
+        return None;
+    }
+    let mut buf = vec![0u16; needed];
+    let len = unsafe { GetLongPathNameW(PCWSTR(wide.as_ptr()), Some(&mut buf)) } as usize;
+    if len == 0 || len >= buf.len() {
+        return None;
+    }
+
+    let long = PathBuf::from(OsString::from_wide(&buf[..len]));
+    (long != path).then_some(long)
+}
+
+#[cfg(not(windows))]
+pub fn expand_short_names(_path: &Path) -> Option<PathBuf> {
+    None
+}
+
+/// Key for the directory a target path refers to
+///
+/// The same directory named two ways (relative and absolute, through a
+/// symlink, or by its 8.3 short name) gets the same key.
+pub fn target_identity(path: &Path) -> PathBuf {
+    let long = expand_short_names(path).unwrap_or_else(|| path.to_path_buf());
+    long.canonicalize().unwrap_or(long)
+}
+
+/// Verdict of [`check_path_safety`]
+///
+/// Part of the public API so embedders can run the same pre-flight checks
+/// as the CLI and present their own prompt before deleting anything.
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize)]
+#[serde(tag = "verdict", rename_all = "snake_case")]
 pub enum SafetyCheck {
     Safe,
-    Dangerous { reason: String, can_override: bool },
+    /// Risky but allowed when the caller explicitly forces it
+    Dangerous {
+        code: SafetyCode,
+        reason: String,
+    },
+    /// Never allowed, not even when forced (system directories, drive roots,
+    /// home, directories added with [`protect`])
+    Forbidden {
+        code: SafetyCode,
+        reason: String,
+    },
+}
+
+impl SafetyCheck {
+    pub fn is_safe(&self) -> bool {
+        matches!(self, SafetyCheck::Safe)
+    }
+
+    /// Whether forcing the operation is enough to proceed
+    pub fn can_override(&self) -> bool {
+        !matches!(self, SafetyCheck::Forbidden { .. })
+    }
+
+    /// Whether deletion may go ahead, given whether the caller forced it
+    pub fn allows(&self, force: bool) -> bool {
+        match self {
+            SafetyCheck::Safe => true,
+            SafetyCheck::Dangerous { .. } => force,
+            SafetyCheck::Forbidden { .. } => false,
+        }
+    }
+
+    /// Why the path is risky, if it is
+    pub fn reason(&self) -> Option<&str> {
+        match self {
+            SafetyCheck::Safe => None,
+            SafetyCheck::Dangerous { reason, .. } | SafetyCheck::Forbidden { reason, .. } => {
+                Some(reason)
+            }
+        }
+    }
+
+    /// Which check refused the path, if one did
+    pub fn code(&self) -> Option<SafetyCode> {
+        match self {
+            SafetyCheck::Safe => None,
+            SafetyCheck::Dangerous { code, .. } | SafetyCheck::Forbidden { code, .. } => {
+                Some(*code)
+            }
+        }
+    }
+
+    fn new(code: SafetyCode, reason: String) -> Self {
+        if code == SafetyCode::CurrentDir {
+            SafetyCheck::Dangerous { code, reason }
+        } else {
+            SafetyCheck::Forbidden { code, reason }
+        }
+    }
 }
 
 /// Perform comprehensive safety checks on a path
+///
+/// Paths are compared both as given and canonicalized, with 8.3 short names
+/// expanded, and paths on `subst` or mapped drives are also checked through
+/// their real target, so neither symlinks, short names nor a mapping can be
+/// used to sneak past the protections. Paths added with [`allow`] pass
+/// the checks `--force` would override.
 pub fn check_path_safety(path: &Path) -> SafetyCheck {
-    if let Some(reason) = get_danger_reason(path) {
-        SafetyCheck::Dangerous {
+    allowing(path, check(path))
+}
+
+/// Like [`check_path_safety`], for emptying `path` but keeping it
+/// (`--keep-root`): the root of a volume other than the system one is
+/// only dangerous, not forbidden
+pub fn check_emptying_safety(path: &Path) -> SafetyCheck {
+    let check = match check(path) {
+        SafetyCheck::Forbidden {
+            code: SafetyCode::VolumeRoot,
             reason,
-            can_override: !is_system_directory(path), // System dirs cannot be overridden
-        }
+        } if !is_system_volume(path) => SafetyCheck::Dangerous {
+            code: SafetyCode::VolumeRoot,
+            reason,
+        },
+        check => check,
+    };
+    allowing(path, check)
+}
+
+fn allowing(path: &Path, check: SafetyCheck) -> SafetyCheck {
+    match check {
+        SafetyCheck::Dangerous { .. } if is_allowed(path) => SafetyCheck::Safe,
+        check => check,
+    }
+}
+
+/// Whether `path` is the root of the volume the system runs from: `/`, or
+/// the drive Windows is on (`%SystemDrive%`)
+pub fn is_system_volume(path: &Path) -> bool {
+    let system = if cfg!(windows) {
+        let mut drive = env::var_os("SystemDrive").unwrap_or_else(|| "C:".into());
+        drive.push("\\");
+        PathBuf::from(drive)
     } else {
-        SafetyCheck::Safe
+        PathBuf::from("/")
+    };
+    match (path.canonicalize(), system.canonicalize()) {
+        (Ok(path), Ok(system)) => path == system,
+        _ => path == system,
     }
 }
 
+fn check(path: &Path) -> SafetyCheck {
+    if let Some((code, reason)) = danger(path) {
+        return SafetyCheck::new(code, reason);
+    }
+
+    let long = expand_short_names(path);
+    if let Some(long) = &long {
+        if let Some((code, reason)) = danger(long) {
+            let reason = format!("{} (reached via short name '{}')", reason, path.display());
+            return SafetyCheck::new(code, reason);
+        }
+    }
+    let path = long.as_deref().unwrap_or(path);
+
+    if let Some(real) = resolve_drive_mapping(path) {
+        if let Some((code, reason)) = danger(&real) {
+            let reason = format!(
+                "{} (reached via drive mapping '{}')",
+                reason,
+                path.display()
+            );
+            return SafetyCheck::new(code, reason);
+        }
+    }
+
+    SafetyCheck::Safe
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -155,23 +664,181 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_safety_codes() {
+        #[cfg(unix)]
+        {
+            assert_eq!(
+                system_protection(Path::new("/")),
+                Some(SafetyCode::VolumeRoot)
+            );
+            assert_eq!(
+                system_protection(Path::new("/usr")),
+                Some(SafetyCode::SystemDir)
+            );
+            let check = check_path_safety(Path::new("/usr"));
+            assert_eq!(check.code(), Some(SafetyCode::SystemDir));
+            assert!(!check.can_override());
+        }
+        if let Some(home) = env::var_os("HOME") {
+            assert_eq!(system_protection(Path::new(&home)), Some(SafetyCode::Home));
+        }
+        let cwd = check_path_safety(&env::current_dir().unwrap());
+        assert_eq!(cwd.code(), Some(SafetyCode::CurrentDir));
+        assert!(cwd.can_override());
+        assert_eq!(check_path_safety(&std::env::temp_dir()).code(), None);
+
+        #[cfg(unix)]
+        {
+            assert!(is_system_volume(Path::new("/")));
+            assert!(!is_system_volume(&std::env::temp_dir()));
+            // Emptying the system volume is as forbidden as deleting it
+            let check = check_emptying_safety(Path::new("/"));
+            assert_eq!(check.code(), Some(SafetyCode::VolumeRoot));
+            assert!(!check.can_override());
+        }
+
+        for code in SafetyCode::ALL {
+            assert_eq!(serde_json::to_value(code).unwrap(), code.name());
+        }
+    }
+
+    #[test]
+    fn test_path_patterns() {
+        let temp = std::env::temp_dir().join("rmbrr_path_pattern_test");
+        let _ = std::fs::remove_dir_all(&temp);
+        let shares = temp.join("shares");
+        std::fs::create_dir_all(shares.join("a/deep")).unwrap();
+        std::fs::create_dir_all(shares.join("b")).unwrap();
+        std::fs::write(shares.join("file"), "x").unwrap();
+        let canonical = |p: PathBuf| p.canonicalize().unwrap();
+
+        let pattern = PathPattern::new(shares.join("*"));
+        assert!(pattern.matches(&shares.join("a")));
+        assert!(!pattern.matches(&shares));
+        assert!(!pattern.matches(&shares.join("a/deep")));
+        let mut found = pattern.expand(&canonical(temp.clone()));
+        found.sort();
+        assert_eq!(
+            found,
+            [canonical(shares.join("a")), canonical(shares.join("b"))]
+        );
+        // Only what's in the root (or holds it) is looked at
+        let found = pattern.expand(&canonical(shares.join("b")));
+        assert_eq!(found, [canonical(shares.join("b"))]);
+
+        let deep = PathPattern::new(shares.join("**/deep"));
+        assert!(deep.matches(&shares.join("a/deep")));
+        assert_eq!(
+            deep.expand(&canonical(temp.clone())),
+            [canonical(shares.join("a/deep"))]
+        );
+
+        let exact = PathPattern::new(shares.join("a"));
+        assert!(exact.matches(&shares.join("a/deep/..")));
+        assert!(!exact.matches(&shares.join("b")));
+
+        std::fs::remove_dir_all(&temp).ok();
+    }
+
     #[test]
     fn test_temp_directory_safe() {
         let temp = std::env::temp_dir();
         assert!(!is_system_directory(&temp));
     }
 
+    #[test]
+    fn test_plain_paths_have_no_drive_mapping() {
+        assert!(resolve_drive_mapping(&std::env::temp_dir()).is_none());
+        assert!(resolve_drive_mapping(Path::new("relative/dir")).is_none());
+    }
+
+    #[test]
+    fn test_protected_paths_within() {
+        #[cfg(unix)]
+        {
+            let inside = protected_paths_within(Path::new("/"));
+            assert!(inside.contains(&PathBuf::from("/usr")));
+            assert!(!inside.contains(&PathBuf::from("/")));
+        }
+        assert!(protected_paths_within(&std::env::temp_dir()).is_empty());
+        assert!(protected_paths_within(Path::new("missing/dir")).is_empty());
+    }
+
+    #[test]
+    fn test_target_identity() {
+        let temp = std::env::temp_dir().join("rmbrr_identity_test");
+        let _ = std::fs::remove_dir_all(&temp);
+        std::fs::create_dir_all(temp.join("sub")).unwrap();
+
+        assert_eq!(
+            target_identity(&temp.join("sub").join("..")),
+            target_identity(&temp)
+        );
+        assert_ne!(target_identity(&temp.join("sub")), target_identity(&temp));
+        // Paths that don't exist are compared as given
+        assert_eq!(
+            target_identity(Path::new("missing/dir")),
+            PathBuf::from("missing/dir")
+        );
+
+        std::fs::remove_dir_all(&temp).ok();
+    }
+
+    #[cfg(windows)]
+    #[test]
+    fn test_expand_short_names() {
+        // The temp dir is often reported in short form (C:\Users\RUNNER~1\...)
+        let temp = std::env::temp_dir();
+        if let Some(long) = expand_short_names(&temp) {
+            assert!(long.exists());
+            assert_eq!(target_identity(&long), target_identity(&temp));
+        }
+        assert!(expand_short_names(Path::new(r"C:\Windows")).is_none());
+    }
+
     #[test]
     fn test_safety_check() {
         let temp = std::env::temp_dir().join("test_safety");
+        let check = check_path_safety(&temp);
+        assert!(
+            check.is_safe(),
+            "temp directory should be safe: {:?}",
+            check
+        );
+        assert!(check.allows(false));
+    }
 
-        match check_path_safety(&temp) {
-            SafetyCheck::Safe => {
-                // Expected - temp dir is safe
-            }
-            SafetyCheck::Dangerous { .. } => {
-                panic!("Temp directory should be safe");
-            }
+    #[test]
+    fn test_safety_verdicts() {
+        #[cfg(unix)]
+        let system = Path::new("/usr");
+        #[cfg(windows)]
+        let system = Path::new("C:\\Windows");
+
+        let check = check_path_safety(system);
+        assert!(matches!(check, SafetyCheck::Forbidden { .. }));
+        assert!(!check.can_override());
+        assert!(!check.allows(true));
+        assert!(check.reason().unwrap().contains("system directory"));
+
+        // The working directory may be deleted, but only when forced
+        let cwd = env::current_dir().unwrap();
+        let check = check_path_safety(&cwd);
+        if let SafetyCheck::Dangerous { .. } = check {
+            assert!(check.can_override());
+            assert!(!check.allows(false));
+            assert!(check.allows(true));
         }
+
+        let json = serde_json::to_value(SafetyCheck::Dangerous {
+            code: SafetyCode::CurrentDir,
+            reason: "why".to_string(),
+        })
+        .unwrap();
+        assert_eq!(
+            json,
+            serde_json::json!({"verdict": "dangerous", "code": "SAFETY_CURRENT_DIR", "reason": "why"})
+        );
     }
 }
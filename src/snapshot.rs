@@ -0,0 +1,283 @@
+//! Filesystem snapshots taken before large deletes, so they can be undone
+
+use crate::operation;
+use std::fmt;
+use std::io;
+use std::path::{Path, PathBuf};
+#[cfg(any(target_os = "linux", windows))]
+use std::process::Command;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+/// Snapshot mechanism used for a target
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SnapshotKind {
+    Btrfs,
+    Zfs,
+    Vss,
+}
+
+impl fmt::Display for SnapshotKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            SnapshotKind::Btrfs => "btrfs",
+            SnapshotKind::Zfs => "ZFS",
+            SnapshotKind::Vss => "VSS",
+        })
+    }
+}
+
+/// A snapshot taken by [`create`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Snapshot {
+    pub kind: SnapshotKind,
+    /// What restores need: the snapshot path (btrfs), `dataset@name` (ZFS)
+    /// or the shadow copy ID (VSS)
+    pub id: String,
+}
+
+impl fmt::Display for Snapshot {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} snapshot {}", self.kind, self.id)
+    }
+}
+
+/// Snapshot the filesystem holding `path`, if it can do so cheaply
+///
+/// Supported: btrfs subvolumes and ZFS datasets on Linux (via the `btrfs` and
+/// `zfs` tools) and NTFS volumes on Windows (a VSS shadow copy). These
+/// usually need root/administrator rights. Anything else is `Unsupported`.
+pub fn create(path: &Path) -> io::Result<Snapshot> {
+    let path = path.canonicalize()?;
+    create_for(&path)
+}
+
+#[cfg(target_os = "linux")]
+fn create_for(path: &Path) -> io::Result<Snapshot> {
+    let mountinfo = std::fs::read_to_string("/proc/self/mountinfo")?;
+    let mount = find_mount(&mountinfo, path).ok_or_else(|| unsupported(path))?;
+    let name = snapshot_name();
+
+    match mount.fs_type.as_str() {
+        "zfs" => {
+            let id = format!("{}@{}", mount.source, name);
+            run(Command::new("zfs").arg("snapshot").arg(&id))?;
+            Ok(Snapshot {
+                kind: SnapshotKind::Zfs,
+                id,
+            })
+        }
+        "btrfs" => {
+            let subvolume = btrfs_subvolume(path, &mount.mount_point)?;
+            if subvolume == path {
+                // The snapshot would land inside the tree being deleted
+                return Err(io::Error::new(
+                    io::ErrorKind::Unsupported,
+                    format!(
+                        "{} is a btrfs subvolume root; snapshot it by hand",
+                        path.display()
+                    ),
+                ));
+            }
+            let dest = subvolume.join(format!(".{}", name));
+            run(Command::new("btrfs")
+                .args(["subvolume", "snapshot", "-r"])
+                .arg(&subvolume)
+                .arg(&dest))?;
+            Ok(Snapshot {
+                kind: SnapshotKind::Btrfs,
+                id: dest.display().to_string(),
+            })
+        }
+        _ => Err(unsupported(path)),
+    }
+}
+
+#[cfg(windows)]
+fn create_for(path: &Path) -> io::Result<Snapshot> {
+    use std::path::{Component, Prefix};
+
+    let volume = match path.components().next() {
+        Some(Component::Prefix(prefix)) => match prefix.kind() {
+            Prefix::Disk(letter) | Prefix::VerbatimDisk(letter) => {
+                format!("{}:\\", letter as char)
+            }
+            _ => return Err(unsupported(path)),
+        },
+        _ => return Err(unsupported(path)),
+    };
+    let script = format!(
+        "$r = Invoke-CimMethod -ClassName Win32_ShadowCopy -MethodName Create \
+         -Arguments @{{Volume='{}'; Context='ClientAccessible'}}; \
+         if ($r.ReturnValue -ne 0) {{ Write-Error \"shadow copy failed: $($r.ReturnValue)\"; exit 1 }}; \
+         $r.ShadowID",
+        volume
+    );
+    let id = run(Command::new("powershell")
+        .args(["-NoProfile", "-NonInteractive", "-Command"])
+        .arg(script))?;
+    Ok(Snapshot {
+        kind: SnapshotKind::Vss,
+        id,
+    })
+}
+
+#[cfg(not(any(target_os = "linux", windows)))]
+fn create_for(path: &Path) -> io::Result<Snapshot> {
+    Err(unsupported(path))
+}
+
+fn unsupported(path: &Path) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::Unsupported,
+        format!("no cheap snapshot available for {}", path.display()),
+    )
+}
+
+/// Name for a new snapshot: the run's operation ID plus a per-run counter
+#[cfg_attr(not(target_os = "linux"), allow(dead_code))]
+fn snapshot_name() -> String {
+    static TAKEN: AtomicUsize = AtomicUsize::new(0);
+    format!(
+        "rmbrr-{}-{}",
+        operation::current(),
+        TAKEN.fetch_add(1, Ordering::Relaxed)
+    )
+}
+
+/// Run a snapshot tool, returning its trimmed stdout
+#[cfg(any(target_os = "linux", windows))]
+fn run(command: &mut Command) -> io::Result<String> {
+    let output = command.output()?;
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        return Err(io::Error::other(format!(
+            "{:?} failed: {}",
+            command.get_program(),
+            stderr.trim()
+        )));
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).trim().to_string())
+}
+
+/// Mount table entry for the filesystem holding a path
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Mount {
+    mount_point: PathBuf,
+    fs_type: String,
+    source: String,
+}
+
+/// Innermost mount in `/proc/self/mountinfo` contents that contains `path`
+#[cfg_attr(not(target_os = "linux"), allow(dead_code))]
+fn find_mount(mountinfo: &str, path: &Path) -> Option<Mount> {
+    mountinfo
+        .lines()
+        .filter_map(|line| {
+            // id parent major:minor root mount-point options [optional...] - type source super
+            let (mount, fs) = line.split_once(" - ")?;
+            let mount_point = unescape(mount.split(' ').nth(4)?);
+            let mut fs = fs.split(' ');
+            Some(Mount {
+                mount_point: PathBuf::from(mount_point),
+                fs_type: fs.next()?.to_string(),
+                source: unescape(fs.next()?),
+            })
+        })
+        .filter(|m| path.starts_with(&m.mount_point))
+        // Later entries shadow earlier ones mounted at the same place
+        .max_by_key(|m| m.mount_point.components().count())
+}
+
+/// Undo the octal escapes (`\040` for a space) used in mountinfo fields
+#[cfg_attr(not(target_os = "linux"), allow(dead_code))]
+fn unescape(field: &str) -> String {
+    let bytes = field.as_bytes();
+    let mut out = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        let octal = bytes
+            .get(i + 1..i + 4)
+            .filter(|digits| digits.iter().all(|d| (b'0'..=b'7').contains(d)))
+            .and_then(|digits| u8::from_str_radix(std::str::from_utf8(digits).ok()?, 8).ok());
+        match octal {
+            Some(byte) if bytes[i] == b'\\' => {
+                out.push(byte);
+                i += 4;
+            }
+            _ => {
+                out.push(bytes[i]);
+                i += 1;
+            }
+        }
+    }
+    String::from_utf8_lossy(&out).into_owned()
+}
+
+/// Root of the btrfs subvolume holding `path`
+///
+/// Every subvolume root has inode 256, so this is the nearest ancestor
+/// (at or below the mount point) with that inode.
+#[cfg(target_os = "linux")]
+fn btrfs_subvolume(path: &Path, mount_point: &Path) -> io::Result<PathBuf> {
+    use std::os::unix::fs::MetadataExt;
+
+    const BTRFS_FIRST_FREE_OBJECTID: u64 = 256;
+
+    for dir in path.ancestors() {
+        if std::fs::metadata(dir)?.ino() == BTRFS_FIRST_FREE_OBJECTID {
+            return Ok(dir.to_path_buf());
+        }
+        if dir == mount_point {
+            break;
+        }
+    }
+    Ok(mount_point.to_path_buf())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MOUNTINFO: &str = "\
+22 1 8:2 / / rw,relatime shared:1 - ext4 /dev/sda2 rw
+35 22 0:31 / /data rw,relatime shared:15 - zfs tank/data rw,xattr
+36 35 0:32 / /data/my\\040files rw,relatime shared:16 - zfs tank/data/files rw
+40 22 0:40 /@home /home rw,relatime shared:20 - btrfs /dev/sdb1 rw,subvol=/@home
+";
+
+    #[test]
+    fn test_find_mount() {
+        let mount = find_mount(MOUNTINFO, Path::new("/data/my files/cache")).unwrap();
+        assert_eq!(mount.mount_point, PathBuf::from("/data/my files"));
+        assert_eq!(
+            (mount.fs_type.as_str(), mount.source.as_str()),
+            ("zfs", "tank/data/files")
+        );
+
+        let mount = find_mount(MOUNTINFO, Path::new("/data/other")).unwrap();
+        assert_eq!(mount.source, "tank/data");
+        let mount = find_mount(MOUNTINFO, Path::new("/home/user")).unwrap();
+        assert_eq!(mount.fs_type, "btrfs");
+        let mount = find_mount(MOUNTINFO, Path::new("/tmp")).unwrap();
+        assert_eq!(mount.fs_type, "ext4");
+        // Prefix matches are per component, not per character
+        let mount = find_mount(MOUNTINFO, Path::new("/database")).unwrap();
+        assert_eq!(mount.mount_point, PathBuf::from("/"));
+    }
+
+    #[test]
+    fn test_unescape() {
+        assert_eq!(unescape(r"/mnt/a\040b"), "/mnt/a b");
+        assert_eq!(unescape(r"/mnt/tab\011x\134y"), "/mnt/tab\tx\\y");
+        assert_eq!(unescape(r"/plain\0"), r"/plain\0");
+    }
+
+    #[test]
+    fn test_snapshot_display() {
+        let snapshot = Snapshot {
+            kind: SnapshotKind::Zfs,
+            id: "tank/data@rmbrr-1".to_string(),
+        };
+        assert_eq!(snapshot.to_string(), "ZFS snapshot tank/data@rmbrr-1");
+    }
+}
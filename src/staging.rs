@@ -0,0 +1,229 @@
+//! Staging area for soft deletes (`--stage`, `rmbrr purge-staged`)
+//!
+//! `--stage DIR` renames each target into `DIR` instead of deleting it,
+//! which is instant but needs `DIR` on the target's volume. `DIR/staged.tsv`
+//! lists what is there, one line per target:
+//! `<expiry, unix seconds>\t<name in DIR>\t<original path>`. Lines are
+//! written before the rename, so a failed rename leaves at most a line
+//! naming nothing; [`StagingArea::forget_missing`] drops those, and lines
+//! of targets restored by hand, after a purge.
+
+use crate::operation;
+use std::fs::{self, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Component, Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// Name of the list of staged targets in a staging area
+pub const INDEX_FILE: &str = "staged.tsv";
+
+/// A target moved into a staging area
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Staged {
+    /// When it may be purged
+    pub expires: SystemTime,
+    /// Where it is now
+    pub path: PathBuf,
+    /// Where it was
+    pub original: PathBuf,
+}
+
+/// A directory targets are staged in
+#[derive(Debug, Clone)]
+pub struct StagingArea {
+    dir: PathBuf,
+}
+
+impl StagingArea {
+    pub fn new(dir: impl Into<PathBuf>) -> Self {
+        Self { dir: dir.into() }
+    }
+
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    fn index(&self) -> PathBuf {
+        self.dir.join(INDEX_FILE)
+    }
+
+    /// Rename `target` into the area, to be purged once `keep_for` is over
+    pub fn stage(&self, target: &Path, keep_for: Duration) -> io::Result<Staged> {
+        fs::create_dir_all(&self.dir)?;
+        let original = std::path::absolute(target)?;
+        if self.dir.canonicalize()?.starts_with(target.canonicalize()?) {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "the staging area is inside the target",
+            ));
+        }
+        let staged = Staged {
+            expires: SystemTime::now() + keep_for,
+            path: self.dir.join(next_name(target)),
+            original,
+        };
+        let line = format_line(&staged).ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "path can't be recorded in the staging area's list",
+            )
+        })?;
+
+        let mut index = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(self.index())?;
+        index.write_all(format!("{}\n", line).as_bytes())?;
+        fs::rename(target, &staged.path).map_err(|e| match e.kind() {
+            io::ErrorKind::CrossesDevices => io::Error::new(
+                e.kind(),
+                "the staging area is on another volume (targets are only renamed into it)",
+            ),
+            _ => e,
+        })?;
+        Ok(staged)
+    }
+
+    /// Everything staged, oldest first (none if nothing ever was)
+    pub fn entries(&self) -> io::Result<Vec<Staged>> {
+        match fs::read_to_string(self.index()) {
+            Ok(text) => Ok(text
+                .lines()
+                .filter_map(|line| parse_line(&self.dir, line))
+                .collect()),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(Vec::new()),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Staged targets still in the area that expired by `now`
+    pub fn expired(&self, now: SystemTime) -> io::Result<Vec<Staged>> {
+        Ok(self
+            .entries()?
+            .into_iter()
+            .filter(|staged| staged.expires <= now && staged.path.exists())
+            .collect())
+    }
+
+    /// Drop the lines of targets no longer in the area
+    ///
+    /// A target staged by another run while this rewrites the list can
+    /// lose its line, and with it its expiry; it then stays until purged
+    /// by hand.
+    pub fn forget_missing(&self) -> io::Result<()> {
+        let entries = self.entries()?;
+        let kept: Vec<&Staged> = entries.iter().filter(|s| s.path.exists()).collect();
+        if kept.len() == entries.len() {
+            return Ok(());
+        }
+        let text: String = kept
+            .iter()
+            .filter_map(|staged| format_line(staged))
+            .map(|line| line + "\n")
+            .collect();
+        // Replace in one step so a concurrent reader never sees half a file
+        let index = self.index();
+        let temp = index.with_extension(format!("tmp{}", std::process::id()));
+        fs::write(&temp, text)?;
+        fs::rename(&temp, &index).inspect_err(|_| {
+            fs::remove_file(&temp).ok();
+        })
+    }
+}
+
+/// `<operation id>.<n>.<target name>`, unique for this run
+fn next_name(target: &Path) -> String {
+    static STAGED: AtomicUsize = AtomicUsize::new(0);
+    let name = target
+        .file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    format!(
+        "{}.{}.{}",
+        operation::current(),
+        STAGED.fetch_add(1, Ordering::Relaxed),
+        name
+    )
+}
+
+fn format_line(staged: &Staged) -> Option<String> {
+    let secs = staged.expires.duration_since(UNIX_EPOCH).ok()?.as_secs();
+    let name = staged.path.file_name()?.to_str()?;
+    let original = staged.original.to_str()?;
+    // A line per target, tab-separated
+    if name.contains(['\t', '\n']) || original.contains('\n') {
+        return None;
+    }
+    Some(format!("{}\t{}\t{}", secs, name, original))
+}
+
+fn parse_line(dir: &Path, line: &str) -> Option<Staged> {
+    let mut fields = line.splitn(3, '\t');
+    let secs: u64 = fields.next()?.parse().ok()?;
+    let name = fields.next()?;
+    // Only ever a name inside the area, whatever the file says
+    let mut parts = Path::new(name).components();
+    if !matches!(
+        (parts.next(), parts.next()),
+        (Some(Component::Normal(_)), None)
+    ) {
+        return None;
+    }
+    Some(Staged {
+        expires: UNIX_EPOCH + Duration::from_secs(secs),
+        path: dir.join(name),
+        original: PathBuf::from(fields.next()?),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_stage_and_expire() {
+        let temp = std::env::temp_dir().join("rmbrr_staging_test");
+        let _ = fs::remove_dir_all(&temp);
+        let (build, cache) = (temp.join("build"), temp.join("cache"));
+        fs::create_dir_all(build.join("sub")).unwrap();
+        fs::create_dir_all(&cache).unwrap();
+        fs::write(build.join("sub/file.txt"), "x").unwrap();
+        let area = StagingArea::new(temp.join("stage"));
+        assert!(area.entries().unwrap().is_empty());
+
+        let staged = area.stage(&build, Duration::ZERO).unwrap();
+        assert!(!build.exists());
+        assert!(staged.path.join("sub/file.txt").exists());
+        assert_eq!(staged.original, std::path::absolute(&build).unwrap());
+        area.stage(&cache, Duration::from_secs(3600)).unwrap();
+        // Not into itself
+        let err = area.stage(&temp, Duration::ZERO).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+
+        let now = SystemTime::now() + Duration::from_secs(1);
+        let expired = area.expired(now).unwrap();
+        assert_eq!(expired.len(), 1);
+        assert_eq!(expired[0].path, staged.path);
+
+        // A line pointing outside the area is ignored
+        let mut index = OpenOptions::new().append(true).open(area.index()).unwrap();
+        index.write_all(b"0\t../build\t/elsewhere\n").unwrap();
+        assert_eq!(area.entries().unwrap().len(), 2);
+
+        fs::remove_dir_all(&staged.path).unwrap();
+        area.forget_missing().unwrap();
+        let left = area.entries().unwrap();
+        assert_eq!(left.len(), 1);
+        assert!(left[0]
+            .path
+            .file_name()
+            .unwrap()
+            .to_str()
+            .unwrap()
+            .ends_with(".cache"));
+        assert!(area.expired(now).unwrap().is_empty());
+
+        fs::remove_dir_all(&temp).ok();
+    }
+}
@@ -0,0 +1,168 @@
+//! Alerts when deletion stops making progress (`--stall-after`)
+//!
+//! A worker stuck on one file (a network share that stopped answering, an
+//! antivirus scanner holding it) shows as no files or directories
+//! completing while work remains. The alert names what each busy worker is
+//! on and for how long.
+//!
+//! The command line prints it to stderr and adds it to the `--json`
+//! report's `stalls`; rmbrr keeps no log file and sends no events of its
+//! own, so those are the only sinks. Library users get every alert
+//! through the [`StallHook`] given to `Pipeline::on_stall` or
+//! `Deleter::on_stall`.
+
+use crate::broker::Broker;
+use serde::ser::{Serialize, SerializeStruct, Serializer};
+use std::fmt;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+/// How often the watcher checks for progress
+const POLL: Duration = Duration::from_millis(50);
+
+/// A worker in the middle of a directory
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Busy {
+    /// Thread name (`worker-<n>`)
+    pub worker: String,
+    /// Directory whose files it is deleting, or which it is removing
+    pub path: PathBuf,
+    /// Time since it took the directory on
+    pub busy_for: Duration,
+}
+
+/// Nothing completed for a while although workers are busy
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Stall {
+    /// Time since anything last completed
+    pub idle: Duration,
+    /// Busy workers, the longest busy first
+    pub workers: Vec<Busy>,
+}
+
+/// Serialized as `{ worker, path, path_raw?, busy_ms }`
+impl Serialize for Busy {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut state = serializer.serialize_struct("Busy", 4)?;
+        state.serialize_field("worker", &self.worker)?;
+        crate::error::serialize_path(&mut state, &self.path)?;
+        state.serialize_field("busy_ms", &(self.busy_for.as_secs_f64() * 1000.0))?;
+        state.end()
+    }
+}
+
+/// Serialized as `{ idle_ms, workers }`
+impl Serialize for Stall {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut state = serializer.serialize_struct("Stall", 2)?;
+        state.serialize_field("idle_ms", &(self.idle.as_secs_f64() * 1000.0))?;
+        state.serialize_field("workers", &self.workers)?;
+        state.end()
+    }
+}
+
+/// Called with every [`Stall`] once nothing has completed for `after`,
+/// and again each further `after` it lasts
+#[derive(Clone)]
+pub struct StallHook {
+    pub after: Duration,
+    hook: Arc<dyn Fn(&Stall) + Send + Sync>,
+}
+
+impl StallHook {
+    pub fn new(after: Duration, hook: impl Fn(&Stall) + Send + Sync + 'static) -> Self {
+        Self {
+            after,
+            hook: Arc::new(hook),
+        }
+    }
+}
+
+impl fmt::Debug for StallHook {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("StallHook")
+            .field("after", &self.after)
+            .finish_non_exhaustive()
+    }
+}
+
+/// Watch `broker` until `done` is set, calling `hook` on stalls
+///
+/// Paused runs (`--when-idle`) aren't stalled, and neither is a run with
+/// no worker busy, which is waiting on something other than the disk.
+pub fn watch(broker: &Broker, hook: &StallHook, done: &AtomicBool) {
+    let mut seen = broker.activity();
+    let mut quiet_since = Instant::now();
+    let mut next_alert = hook.after;
+    while !done.load(Ordering::Relaxed) {
+        std::thread::sleep(POLL);
+        let activity = broker.activity();
+        if activity != seen || broker.is_paused() {
+            seen = activity;
+            quiet_since = Instant::now();
+            next_alert = hook.after;
+            continue;
+        }
+        let idle = quiet_since.elapsed();
+        if idle < next_alert {
+            continue;
+        }
+        next_alert = idle + hook.after;
+        let workers = broker.busy_workers();
+        if !workers.is_empty() {
+            (hook.hook)(&Stall { idle, workers });
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tree::DirectoryTree;
+    use std::sync::Mutex;
+
+    #[test]
+    fn test_watch_reports_busy_workers() {
+        let mut tree = DirectoryTree::new();
+        tree.dirs.push(PathBuf::from("stuck"));
+        let (broker, _tx, _rx) = Broker::new(tree);
+        broker.track_workers();
+        let stalls = Arc::new(Mutex::new(Vec::new()));
+        let hook = {
+            let stalls = stalls.clone();
+            StallHook::new(Duration::from_millis(100), move |stall: &Stall| {
+                stalls.lock().unwrap().push(stall.clone())
+            })
+        };
+        let done = AtomicBool::new(false);
+
+        std::thread::scope(|s| {
+            s.spawn(|| watch(&broker, &hook, &done));
+            // Nobody busy: waiting, not stalled
+            std::thread::sleep(Duration::from_millis(250));
+            assert!(stalls.lock().unwrap().is_empty());
+
+            std::thread::Builder::new()
+                .name("worker-7".to_string())
+                .spawn_scoped(s, || {
+                    broker.begin_work(std::path::Path::new("stuck"));
+                    while !done.load(Ordering::Relaxed) {
+                        std::thread::sleep(POLL);
+                    }
+                })
+                .unwrap();
+            std::thread::sleep(Duration::from_millis(350));
+            done.store(true, Ordering::Relaxed);
+        });
+
+        let stalls = stalls.lock().unwrap();
+        assert!(!stalls.is_empty());
+        let busy = &stalls[0].workers;
+        assert_eq!(busy.len(), 1);
+        assert_eq!(busy[0].worker, "worker-7");
+        assert_eq!(busy[0].path, PathBuf::from("stuck"));
+        assert!(stalls[0].idle >= Duration::from_millis(100));
+    }
+}
@@ -0,0 +1,288 @@
+//! Workspace sweep: find stale build-artifact directories across many projects
+
+use crate::tree;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::time::{Duration, SystemTime};
+
+/// Directories that are never descended into while looking for projects
+const NEVER_DESCEND: &[&str] = &[".git", ".hg", ".svn", "node_modules"];
+
+/// Kind of project whose build artifacts can be swept
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ProjectKind {
+    /// Cargo project (`Cargo.toml` + `target/`)
+    Rust,
+    /// npm/yarn/pnpm project (`package.json` + `node_modules/`)
+    Node,
+}
+
+impl ProjectKind {
+    pub const ALL: [ProjectKind; 2] = [ProjectKind::Rust, ProjectKind::Node];
+
+    /// File that marks the root of a project of this kind
+    pub fn marker(self) -> &'static str {
+        match self {
+            ProjectKind::Rust => "Cargo.toml",
+            ProjectKind::Node => "package.json",
+        }
+    }
+
+    /// Build-artifact directory that lives next to the marker
+    pub fn artifact_dir(self) -> &'static str {
+        match self {
+            ProjectKind::Rust => "target",
+            ProjectKind::Node => "node_modules",
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            ProjectKind::Rust => "rust",
+            ProjectKind::Node => "node",
+        }
+    }
+}
+
+impl FromStr for ProjectKind {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        ProjectKind::ALL
+            .into_iter()
+            .find(|kind| kind.name().eq_ignore_ascii_case(s.trim()))
+            .ok_or_else(|| format!("unknown project kind '{}' (expected rust or node)", s))
+    }
+}
+
+/// A build-artifact directory whose project has not been touched recently
+#[derive(Debug, Clone)]
+pub struct Candidate {
+    /// Project root containing the marker file
+    pub project: PathBuf,
+    /// Artifact directory to delete
+    pub artifact: PathBuf,
+    pub kind: ProjectKind,
+    /// Most recent source or VCS modification time in the project
+    pub last_activity: SystemTime,
+    /// Estimated size of the artifact directory in bytes
+    pub size: u64,
+}
+
+impl Candidate {
+    /// Time elapsed since the project was last touched
+    pub fn age(&self) -> Duration {
+        SystemTime::now()
+            .duration_since(self.last_activity)
+            .unwrap_or(Duration::ZERO)
+    }
+}
+
+/// Find artifact directories under `root` whose project has been idle for at least `unused_for`
+///
+/// Candidates are returned sorted by size, largest first.
+pub fn find_candidates(
+    root: &Path,
+    kinds: &[ProjectKind],
+    unused_for: Duration,
+) -> io::Result<Vec<Candidate>> {
+    let cutoff = SystemTime::now()
+        .checked_sub(unused_for)
+        .unwrap_or(SystemTime::UNIX_EPOCH);
+
+    let mut candidates = Vec::new();
+    find_recursive(root, kinds, cutoff, &mut candidates)?;
+    candidates.sort_by_key(|c| std::cmp::Reverse(c.size));
+    Ok(candidates)
+}
+
+fn find_recursive(
+    dir: &Path,
+    kinds: &[ProjectKind],
+    cutoff: SystemTime,
+    candidates: &mut Vec<Candidate>,
+) -> io::Result<()> {
+    let mut artifacts = Vec::new();
+
+    for kind in kinds {
+        let artifact = dir.join(kind.artifact_dir());
+        if dir.join(kind.marker()).is_file() && is_real_dir(&artifact) {
+            artifacts.push((*kind, artifact));
+        }
+    }
+
+    if !artifacts.is_empty() {
+        let skip: Vec<&Path> = artifacts.iter().map(|(_, p)| p.as_path()).collect();
+        let last_activity = last_activity(dir, &skip);
+
+        if last_activity <= cutoff {
+            for (kind, artifact) in &artifacts {
+                candidates.push(Candidate {
+                    project: dir.to_path_buf(),
+                    artifact: artifact.clone(),
+                    kind: *kind,
+                    last_activity,
+                    size: tree::dir_size(artifact),
+                });
+            }
+        }
+    }
+
+    let entries = match fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(e) => {
+            eprintln!("Warning: Cannot read {}: {}", dir.display(), e);
+            return Ok(());
+        }
+    };
+
+    for entry in entries.flatten() {
+        let path = entry.path();
+        let is_dir = entry.file_type().map(|t| t.is_dir()).unwrap_or(false);
+        if !is_dir || is_never_descended(&path) || artifacts.iter().any(|(_, a)| *a == path) {
+            continue;
+        }
+        find_recursive(&path, kinds, cutoff, candidates)?;
+    }
+
+    Ok(())
+}
+
+/// Most recent modification time of sources and VCS metadata in a project
+///
+/// Artifact directories (and anything in `NEVER_DESCEND`) are ignored so
+/// that building a project does not count as activity.
+pub fn last_activity(project: &Path, skip: &[&Path]) -> SystemTime {
+    let mut latest = SystemTime::UNIX_EPOCH;
+
+    // Commits, checkouts and fetches touch these even when sources don't change
+    for vcs_file in [
+        ".git/index",
+        ".git/HEAD",
+        ".git/FETCH_HEAD",
+        ".git/logs/HEAD",
+    ] {
+        if let Some(mtime) = mtime(&project.join(vcs_file)) {
+            latest = latest.max(mtime);
+        }
+    }
+
+    let mut stack = vec![project.to_path_buf()];
+    while let Some(dir) = stack.pop() {
+        let Ok(entries) = fs::read_dir(&dir) else {
+            continue;
+        };
+        for entry in entries.flatten() {
+            let path = entry.path();
+            let Ok(file_type) = entry.file_type() else {
+                continue;
+            };
+            if file_type.is_dir() {
+                if !is_never_descended(&path) && !skip.contains(&path.as_path()) {
+                    stack.push(path);
+                }
+            } else if let Some(mtime) = entry.metadata().ok().and_then(|m| m.modified().ok()) {
+                latest = latest.max(mtime);
+            }
+        }
+    }
+
+    latest
+}
+
+fn is_real_dir(path: &Path) -> bool {
+    fs::symlink_metadata(path)
+        .map(|m| m.is_dir())
+        .unwrap_or(false)
+}
+
+fn is_never_descended(path: &Path) -> bool {
+    path.file_name()
+        .and_then(|n| n.to_str())
+        .is_some_and(|name| NEVER_DESCEND.contains(&name))
+}
+
+fn mtime(path: &Path) -> Option<SystemTime> {
+    fs::metadata(path).ok()?.modified().ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs::File;
+
+    fn set_mtime(path: &Path, age: Duration) {
+        let time = SystemTime::now() - age;
+        File::options()
+            .write(true)
+            .open(path)
+            .unwrap()
+            .set_modified(time)
+            .unwrap();
+    }
+
+    #[test]
+    fn test_project_kind_from_str() {
+        assert_eq!("rust".parse::<ProjectKind>().unwrap(), ProjectKind::Rust);
+        assert_eq!("Node".parse::<ProjectKind>().unwrap(), ProjectKind::Node);
+        assert!("python".parse::<ProjectKind>().is_err());
+    }
+
+    #[test]
+    fn test_find_stale_candidates() {
+        let temp = std::env::temp_dir().join("rmbrr_sweep_stale_test");
+        let _ = fs::remove_dir_all(&temp);
+
+        let old_days = Duration::from_secs(90 * 86400);
+
+        // Stale rust project
+        let stale = temp.join("stale");
+        fs::create_dir_all(stale.join("src")).unwrap();
+        fs::create_dir_all(stale.join("target/debug")).unwrap();
+        fs::write(stale.join("Cargo.toml"), "[package]").unwrap();
+        fs::write(stale.join("src/main.rs"), "fn main() {}").unwrap();
+        fs::write(stale.join("target/debug/app"), vec![0u8; 2048]).unwrap();
+        set_mtime(&stale.join("Cargo.toml"), old_days);
+        set_mtime(&stale.join("src/main.rs"), old_days);
+
+        // Active node project
+        let active = temp.join("active");
+        fs::create_dir_all(active.join("node_modules/dep")).unwrap();
+        fs::write(active.join("package.json"), "{}").unwrap();
+        fs::write(active.join("node_modules/dep/package.json"), "{}").unwrap();
+
+        let candidates =
+            find_candidates(&temp, &ProjectKind::ALL, Duration::from_secs(60 * 86400)).unwrap();
+
+        assert_eq!(candidates.len(), 1);
+        assert_eq!(candidates[0].artifact, stale.join("target"));
+        assert_eq!(candidates[0].kind, ProjectKind::Rust);
+        assert_eq!(candidates[0].size, 2048);
+
+        fs::remove_dir_all(&temp).ok();
+    }
+
+    #[test]
+    fn test_artifact_writes_are_not_activity() {
+        let temp = std::env::temp_dir().join("rmbrr_sweep_artifact_test");
+        let _ = fs::remove_dir_all(&temp);
+
+        fs::create_dir_all(temp.join("node_modules")).unwrap();
+        fs::write(temp.join("package.json"), "{}").unwrap();
+        fs::write(temp.join("node_modules/fresh.js"), "").unwrap();
+        set_mtime(&temp.join("package.json"), Duration::from_secs(10 * 86400));
+
+        let candidates =
+            find_candidates(&temp, &[ProjectKind::Node], Duration::from_secs(7 * 86400)).unwrap();
+        assert_eq!(candidates.len(), 1);
+
+        // Kinds not requested are ignored
+        let candidates =
+            find_candidates(&temp, &[ProjectKind::Rust], Duration::from_secs(7 * 86400)).unwrap();
+        assert!(candidates.is_empty());
+
+        fs::remove_dir_all(&temp).ok();
+    }
+}
@@ -0,0 +1,132 @@
+//! Wall-clock time spent in each stage of a run (`--stats`, `--json`)
+//!
+//! [`Timings`] adds up time per [`Stage`]; each target's stages are timed
+//! separately and summed over the run, like its other counts.
+
+use serde::ser::{Serialize, SerializeMap, Serializer};
+use std::time::{Duration, Instant};
+
+/// A named part of a run, in the order they happen
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Stage {
+    /// Parsing the command line and config files
+    Args,
+    /// Safety checks on a target, including for protected directories
+    /// inside it, and setting up its filters
+    Safety,
+    /// Listing the tree (with `--stream`, overlapping the deletion)
+    Scan,
+    /// Between the scan and the workers starting: snapshots, renaming the
+    /// target aside, building the work queue
+    Plan,
+    /// Deleting, retries included
+    Delete,
+    /// After deleting: restoring the root's metadata (`--keep-root`) and
+    /// telling the shell
+    Finish,
+}
+
+impl Stage {
+    pub const ALL: [Stage; 6] = [
+        Stage::Args,
+        Stage::Safety,
+        Stage::Scan,
+        Stage::Plan,
+        Stage::Delete,
+        Stage::Finish,
+    ];
+
+    /// Name in `--json` output and `--stats`
+    pub fn name(self) -> &'static str {
+        match self {
+            Stage::Args => "args",
+            Stage::Safety => "safety",
+            Stage::Scan => "scan",
+            Stage::Plan => "plan",
+            Stage::Delete => "delete",
+            Stage::Finish => "finish",
+        }
+    }
+}
+
+/// Time spent per [`Stage`]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Timings([Duration; Stage::ALL.len()]);
+
+impl Timings {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn add(&mut self, stage: Stage, time: Duration) {
+        self.0[stage as usize] += time;
+    }
+
+    /// Add the time since `start` to `stage`
+    pub fn since(&mut self, stage: Stage, start: Instant) {
+        self.add(stage, start.elapsed());
+    }
+
+    /// Run `f`, adding the time it takes to `stage`
+    pub fn time<T>(&mut self, stage: Stage, f: impl FnOnce() -> T) -> T {
+        let start = Instant::now();
+        let result = f();
+        self.since(stage, start);
+        result
+    }
+
+    pub fn get(&self, stage: Stage) -> Duration {
+        self.0[stage as usize]
+    }
+
+    pub fn merge(&mut self, other: &Timings) {
+        for stage in Stage::ALL {
+            self.add(stage, other.get(stage));
+        }
+    }
+
+    /// Stages that took any time, in order
+    pub fn iter(&self) -> impl Iterator<Item = (Stage, Duration)> + '_ {
+        Stage::ALL
+            .into_iter()
+            .map(|stage| (stage, self.get(stage)))
+            .filter(|(_, time)| !time.is_zero())
+    }
+}
+
+/// Serialized as `{ "<stage>": <fractional milliseconds>, ... }` with
+/// every stage, in order
+impl Serialize for Timings {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut map = serializer.serialize_map(Some(Stage::ALL.len()))?;
+        for stage in Stage::ALL {
+            map.serialize_entry(stage.name(), &(self.get(stage).as_secs_f64() * 1000.0))?;
+        }
+        map.end()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_timings() {
+        let mut timings = Timings::new();
+        assert_eq!(timings.iter().count(), 0);
+        timings.add(Stage::Scan, Duration::from_millis(2));
+        assert_eq!(timings.time(Stage::Delete, || 7), 7);
+
+        let mut total = Timings::new();
+        total.merge(&timings);
+        total.merge(&timings);
+        assert_eq!(total.get(Stage::Scan), Duration::from_millis(4));
+        let stages: Vec<Stage> = total.iter().map(|(stage, _)| stage).collect();
+        assert!(stages.starts_with(&[Stage::Scan]));
+
+        let json = serde_json::to_value(total).unwrap();
+        assert_eq!(json["scan"], 4.0);
+        assert_eq!(json["args"], 0.0);
+        assert_eq!(json.as_object().unwrap().len(), Stage::ALL.len());
+    }
+}
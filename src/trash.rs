@@ -0,0 +1,402 @@
+//! Move targets to the platform trash instead of deleting them permanently
+
+use std::io;
+use std::path::Path;
+
+/// Move `path` to the user's Trash via NSFileManager, so Finder can put it back
+#[cfg(target_os = "macos")]
+pub fn move_to_trash(path: &Path) -> io::Result<()> {
+    use objc2_foundation::{NSFileManager, NSString, NSURL};
+
+    let path_str = path.to_str().ok_or_else(|| {
+        io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "path is not valid UTF-8 and cannot be trashed",
+        )
+    })?;
+
+    let url = NSURL::fileURLWithPath(&NSString::from_str(path_str));
+    NSFileManager::defaultManager()
+        .trashItemAtURL_resultingItemURL_error(&url, None)
+        .map_err(|e| io::Error::other(e.localizedDescription().to_string()))
+}
+
+/// Move `path` to the Recycle Bin via `SHFileOperationW`, so Explorer can
+/// restore it
+///
+/// Silently deletes instead if `path` is over the bin's [`capacity`].
+#[cfg(windows)]
+pub fn move_to_trash(path: &Path) -> io::Result<()> {
+    use std::os::windows::ffi::OsStrExt;
+    use windows::core::PCWSTR;
+    use windows::Win32::UI::Shell::{
+        SHFileOperationW, FOF_ALLOWUNDO, FOF_NOCONFIRMATION, FOF_NOERRORUI, FOF_SILENT, FO_DELETE,
+        SHFILEOPSTRUCTW,
+    };
+
+    // The shell wants a plain absolute path (no \\?\), in a list ended by
+    // a second NUL
+    let path = std::path::absolute(path)?;
+    let from: Vec<u16> = path.as_os_str().encode_wide().chain([0, 0]).collect();
+    let mut operation = SHFILEOPSTRUCTW {
+        wFunc: FO_DELETE,
+        pFrom: PCWSTR(from.as_ptr()),
+        fFlags: (FOF_ALLOWUNDO | FOF_NOCONFIRMATION | FOF_NOERRORUI | FOF_SILENT).0 as u16,
+        ..Default::default()
+    };
+    let status = unsafe { SHFileOperationW(&mut operation) };
+    if status != 0 {
+        return Err(io::Error::other(format!(
+            "the shell could not move it to the Recycle Bin (error {:#x})",
+            status
+        )));
+    }
+    if operation.fAnyOperationsAborted.as_bool() {
+        return Err(io::Error::new(
+            io::ErrorKind::Interrupted,
+            "moving to the Recycle Bin was cancelled",
+        ));
+    }
+    Ok(())
+}
+
+/// Move `path` to the freedesktop.org trash (the home trash, or the one at
+/// the top of its filesystem), so file managers can restore it
+#[cfg(all(unix, not(target_os = "macos")))]
+pub fn move_to_trash(path: &Path) -> io::Result<()> {
+    let path = std::path::absolute(path)?;
+    freedesktop::Trash::for_path(&path)?.put(&path)
+}
+
+#[cfg(not(any(unix, windows)))]
+pub fn move_to_trash(_path: &Path) -> io::Result<()> {
+    Err(io::Error::new(
+        io::ErrorKind::Unsupported,
+        "--trash is not supported on this platform yet",
+    ))
+}
+
+/// The trash of the [freedesktop.org spec](https://specifications.freedesktop.org/trash-spec/latest/)
+///
+/// Items are renamed into `files/`, never copied, so only a trash on the
+/// item's own filesystem will do; `info/` holds a `.trashinfo` per item
+/// with its original path and when it was trashed.
+#[cfg(all(unix, not(target_os = "macos")))]
+mod freedesktop {
+    use std::env;
+    use std::ffi::{OsStr, OsString};
+    use std::fs::{self, DirBuilder, OpenOptions};
+    use std::io::{self, Write};
+    use std::os::unix::ffi::OsStrExt;
+    use std::os::unix::fs::{DirBuilderExt, MetadataExt, PermissionsExt};
+    use std::path::{Path, PathBuf};
+
+    pub struct Trash {
+        dir: PathBuf,
+        /// Top of the filesystem for a trash there, which original paths are
+        /// recorded relative to
+        top: Option<PathBuf>,
+    }
+
+    impl Trash {
+        /// A trash at `dir`, recording absolute paths (as the home trash does)
+        pub fn new(dir: PathBuf) -> Self {
+            Self { dir, top: None }
+        }
+
+        /// The trash for absolute `path`: the home trash if on the same
+        /// filesystem, else `.Trash/$uid` or `.Trash-$uid` at the top of it
+        pub fn for_path(path: &Path) -> io::Result<Self> {
+            let device = fs::symlink_metadata(path)?.dev();
+            if let Some(home) = home_trash() {
+                if create_private_dir(&home).is_ok() && fs::metadata(&home)?.dev() == device {
+                    return Ok(Self::new(home));
+                }
+            }
+
+            let top = filesystem_top(path, device);
+            let uid = unsafe { libc::getuid() };
+            // Set up by an administrator: usable only if sticky and not a symlink
+            let shared = top.join(".Trash");
+            if let Ok(meta) = fs::symlink_metadata(&shared) {
+                if meta.is_dir() && meta.permissions().mode() & 0o1000 != 0 {
+                    let dir = shared.join(uid.to_string());
+                    if create_private_dir(&dir).is_ok() {
+                        return Ok(Self {
+                            dir,
+                            top: Some(top),
+                        });
+                    }
+                }
+            }
+            let dir = top.join(format!(".Trash-{}", uid));
+            create_private_dir(&dir)?;
+            Ok(Self {
+                dir,
+                top: Some(top),
+            })
+        }
+
+        /// Move `path` (absolute, on this trash's filesystem) into the trash
+        pub fn put(&self, path: &Path) -> io::Result<()> {
+            let name = path.file_name().ok_or_else(|| {
+                io::Error::new(io::ErrorKind::InvalidInput, "nothing to name it by")
+            })?;
+            let recorded = match &self.top {
+                Some(top) => path.strip_prefix(top).unwrap_or(path),
+                None => path,
+            };
+            let info = format!(
+                "[Trash Info]\nPath={}\nDeletionDate={}\n",
+                escape(recorded.as_os_str()),
+                local_timestamp()
+            );
+
+            let (name, info_file) = self.claim_name(name, &info)?;
+            fs::rename(path, self.dir.join("files").join(name)).inspect_err(|_| {
+                fs::remove_file(&info_file).ok();
+            })
+        }
+
+        /// Write the info file under the first free name (`name`, `name.2`,
+        /// ...), which reserves that name for the item
+        fn claim_name(&self, name: &OsStr, info: &str) -> io::Result<(OsString, PathBuf)> {
+            for n in 1.. {
+                let mut candidate = name.to_os_string();
+                if n > 1 {
+                    candidate.push(format!(".{}", n));
+                }
+                let mut info_name = candidate.clone();
+                info_name.push(".trashinfo");
+                let info_file = self.dir.join("info").join(info_name);
+                let created = OpenOptions::new()
+                    .write(true)
+                    .create_new(true)
+                    .open(&info_file);
+                match created {
+                    Ok(_) if self.dir.join("files").join(&candidate).exists() => {
+                        fs::remove_file(&info_file).ok();
+                    }
+                    Ok(mut file) => {
+                        file.write_all(info.as_bytes())?;
+                        return Ok((candidate, info_file));
+                    }
+                    Err(e) if e.kind() == io::ErrorKind::AlreadyExists => {}
+                    Err(e) => return Err(e),
+                }
+            }
+            unreachable!()
+        }
+    }
+
+    /// `$XDG_DATA_HOME/Trash`, by default `~/.local/share/Trash`
+    fn home_trash() -> Option<PathBuf> {
+        match env::var_os("XDG_DATA_HOME") {
+            Some(data) if !data.is_empty() => Some(PathBuf::from(data).join("Trash")),
+            _ => Some(PathBuf::from(env::var_os("HOME")?).join(".local/share/Trash")),
+        }
+    }
+
+    /// `dir` with its `files` and `info`, readable only by this user
+    fn create_private_dir(dir: &Path) -> io::Result<()> {
+        let mut builder = DirBuilder::new();
+        builder.recursive(true).mode(0o700);
+        builder.create(dir.join("files"))?;
+        builder.create(dir.join("info"))?;
+        let meta = fs::symlink_metadata(dir)?;
+        if !meta.is_dir() || meta.uid() != unsafe { libc::getuid() } {
+            return Err(io::Error::new(
+                io::ErrorKind::PermissionDenied,
+                format!("{} is not a trash directory of this user", dir.display()),
+            ));
+        }
+        Ok(())
+    }
+
+    /// Highest directory above `path` still on filesystem `device`
+    fn filesystem_top(path: &Path, device: u64) -> PathBuf {
+        let mut top = path;
+        while let Some(parent) = top.parent() {
+            match fs::symlink_metadata(parent) {
+                Ok(meta) if meta.dev() == device => top = parent,
+                _ => break,
+            }
+        }
+        top.to_path_buf()
+    }
+
+    /// Percent-encoded as in a URL path, as the spec asks
+    pub fn escape(path: &OsStr) -> String {
+        let mut escaped = String::new();
+        for &byte in path.as_bytes() {
+            if byte.is_ascii_alphanumeric() || b"-_.~/".contains(&byte) {
+                escaped.push(byte as char);
+            } else {
+                escaped.push_str(&format!("%{:02X}", byte));
+            }
+        }
+        escaped
+    }
+
+    /// Local time, `YYYY-MM-DDThh:mm:ss`
+    fn local_timestamp() -> String {
+        let mut tm: libc::tm = unsafe { std::mem::zeroed() };
+        unsafe {
+            let now = libc::time(std::ptr::null_mut());
+            libc::localtime_r(&now, &mut tm);
+        }
+        format!(
+            "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}",
+            tm.tm_year + 1900,
+            tm.tm_mon + 1,
+            tm.tm_mday,
+            tm.tm_hour,
+            tm.tm_min,
+            tm.tm_sec
+        )
+    }
+}
+
+/// Largest total size the trash that would receive `path` accepts, in bytes
+///
+/// Windows permanently deletes anything bigger than the Recycle Bin's
+/// per-volume limit (0 when the bin is disabled), so `--trash` checks this
+/// first. `None` means no limit is configured, as with the macOS Trash.
+#[cfg(windows)]
+pub fn capacity(path: &Path) -> io::Result<Option<u64>> {
+    use std::os::windows::ffi::OsStrExt;
+    use windows::core::PCWSTR;
+    use windows::Win32::Storage::FileSystem::{
+        GetVolumeNameForVolumeMountPointW, GetVolumePathNameW,
+    };
+
+    let wide: Vec<u16> = path
+        .as_os_str()
+        .encode_wide()
+        .chain(std::iter::once(0))
+        .collect();
+    let mut mount_point = [0u16; 1024];
+    let mut volume = [0u16; 64];
+    unsafe {
+        GetVolumePathNameW(PCWSTR(wide.as_ptr()), &mut mount_point)?;
+        GetVolumeNameForVolumeMountPointW(PCWSTR(mount_point.as_ptr()), &mut volume)?;
+    }
+    let len = volume.iter().position(|&c| c == 0).unwrap_or(volume.len());
+    let volume = String::from_utf16_lossy(&volume[..len]);
+    let Some(guid) = volume_guid(&volume) else {
+        return Ok(None);
+    };
+
+    let key = format!(
+        r"Software\Microsoft\Windows\CurrentVersion\Explorer\BitBucket\Volume\{}",
+        guid
+    );
+    if read_dword(&key, "NukeOnDelete") == Some(1) {
+        return Ok(Some(0));
+    }
+    // MaxCapacity is stored in megabytes
+    Ok(read_dword(&key, "MaxCapacity").map(|mb| u64::from(mb) * 1024 * 1024))
+}
+
+#[cfg(not(windows))]
+pub fn capacity(_path: &Path) -> io::Result<Option<u64>> {
+    Ok(None)
+}
+
+/// `{GUID}` out of a volume name like `\\?\Volume{GUID}\`
+#[cfg(windows)]
+fn volume_guid(volume: &str) -> Option<&str> {
+    let guid = volume.strip_prefix(r"\\?\Volume")?.trim_end_matches('\\');
+    (guid.starts_with('{') && guid.ends_with('}')).then_some(guid)
+}
+
+/// A DWORD value under HKEY_CURRENT_USER, if present
+#[cfg(windows)]
+fn read_dword(key: &str, value: &str) -> Option<u32> {
+    use windows::core::HSTRING;
+    use windows::Win32::System::Registry::{RegGetValueW, HKEY_CURRENT_USER, RRF_RT_REG_DWORD};
+
+    let mut data = 0u32;
+    let mut size = std::mem::size_of::<u32>() as u32;
+    let status = unsafe {
+        RegGetValueW(
+            HKEY_CURRENT_USER,
+            &HSTRING::from(key),
+            &HSTRING::from(value),
+            RRF_RT_REG_DWORD,
+            None,
+            Some(&mut data as *mut u32 as *mut _),
+            Some(&mut size),
+        )
+    };
+    status.is_ok().then_some(data)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[cfg(target_os = "macos")]
+    #[test]
+    fn test_move_to_trash() {
+        let temp = std::env::temp_dir().join("rmbrr_trash_test");
+        let _ = std::fs::remove_dir_all(&temp);
+        std::fs::create_dir_all(temp.join("inner")).unwrap();
+
+        match move_to_trash(&temp) {
+            Ok(()) => assert!(!temp.exists()),
+            // Volumes without a Trash (some CI temp dirs) refuse the move
+            Err(_) => {
+                std::fs::remove_dir_all(&temp).ok();
+            }
+        }
+    }
+
+    #[cfg(all(unix, not(target_os = "macos")))]
+    #[test]
+    fn test_freedesktop_trash() {
+        use freedesktop::Trash;
+        use std::fs;
+
+        let temp = std::env::temp_dir().join("rmbrr_freedesktop_trash_test");
+        let _ = fs::remove_dir_all(&temp);
+        let trash = temp.join("Trash");
+        fs::create_dir_all(trash.join("files")).unwrap();
+        fs::create_dir_all(trash.join("info")).unwrap();
+        let trash = Trash::new(temp.join("Trash"));
+
+        for _ in 0..2 {
+            fs::create_dir_all(temp.join("my build/inner")).unwrap();
+            trash.put(&temp.join("my build")).unwrap();
+        }
+        assert!(!temp.join("my build").exists());
+        assert!(temp.join("Trash/files/my build/inner").is_dir());
+        assert!(temp.join("Trash/files/my build.2/inner").is_dir());
+
+        let info = fs::read_to_string(temp.join("Trash/info/my build.2.trashinfo")).unwrap();
+        let mut lines = info.lines();
+        assert_eq!(lines.next(), Some("[Trash Info]"));
+        let recorded = freedesktop::escape(temp.join("my build").as_os_str());
+        assert_eq!(lines.next(), Some(format!("Path={}", recorded).as_str()));
+        assert!(recorded.ends_with("/my%20build"));
+        assert!(lines.next().unwrap().starts_with("DeletionDate=20"));
+
+        fs::remove_dir_all(&temp).ok();
+    }
+
+    #[cfg(windows)]
+    #[test]
+    fn test_volume_guid() {
+        assert_eq!(
+            volume_guid(r"\\?\Volume{0b2e1f3a-1111-2222-3333-444455556666}\"),
+            Some("{0b2e1f3a-1111-2222-3333-444455556666}")
+        );
+        assert_eq!(volume_guid(r"C:\"), None);
+    }
+
+    #[cfg(not(windows))]
+    #[test]
+    fn test_capacity_unlimited() {
+        assert_eq!(capacity(&std::env::temp_dir()).unwrap(), None);
+    }
+}
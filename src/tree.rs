@@ -1,8 +1,15 @@
 // Directory tree discovery and dependency graph construction
 
+use crate::cancel::CancelToken;
+use crate::filter::Filters;
+use crossbeam_deque::{Steal, Stealer, Worker};
 use std::collections::{HashMap, HashSet};
+use std::fs;
 use std::io;
 use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::Mutex;
+use std::time::Duration;
 
 #[derive(Debug)]
 pub struct DirectoryTree {
@@ -16,6 +23,17 @@ pub struct DirectoryTree {
     pub leaves: Vec<PathBuf>,
     /// Total number of files in the tree
     pub file_count: usize,
+    /// Entries left in place instead of deleted
+    pub skipped: Vec<SkippedEntry>,
+    /// Directories kept because they (transitively) contain skipped entries
+    pub retained: HashSet<PathBuf>,
+    /// Bytes in the files directly inside each directory (only filled in
+    /// when [`ScanOptions::collect_sizes`] is set; empty directories omitted)
+    pub dir_bytes: HashMap<PathBuf, u64>,
+    /// Entry counts of directories with at least
+    /// [`LARGE_DIR_ENTRIES`](crate::winapi::LARGE_DIR_ENTRIES), which are
+    /// listed with a larger buffer when deleting
+    pub large_dirs: HashMap<PathBuf, usize>,
 }
 
 impl DirectoryTree {
@@ -26,8 +44,240 @@ impl DirectoryTree {
             children: HashMap::new(),
             leaves: Vec::new(),
             file_count: 0,
+            skipped: Vec::new(),
+            retained: HashSet::new(),
+            dir_bytes: HashMap::new(),
+            large_dirs: HashMap::new(),
         }
     }
+
+    /// Total bytes in skipped entries, where known
+    pub fn skipped_bytes(&self) -> u64 {
+        self.skipped.iter().map(|e| e.bytes).sum()
+    }
+
+    /// Every directory and file to delete, with whether it is a directory,
+    /// sorted so each directory's contents follow it
+    pub fn to_delete(&self) -> Vec<(&Path, bool)> {
+        let mut entries: Vec<(&Path, bool)> = self
+            .dirs
+            .iter()
+            .filter(|dir| !self.retained.contains(*dir))
+            .map(|dir| (dir.as_path(), true))
+            .chain(self.files.iter().map(|file| (file.as_path(), false)))
+            .collect();
+        entries.sort_unstable();
+        entries
+    }
+
+    /// Total bytes in files to delete, if sizes were collected
+    pub fn total_bytes(&self) -> u64 {
+        self.dir_bytes.values().sum()
+    }
+
+    /// Drop directories that were already removed, so the ones left over
+    /// can still be deleted from this tree
+    pub fn forget_dirs(&mut self, removed: &HashSet<PathBuf>) {
+        self.dirs.retain(|dir| !removed.contains(dir));
+        self.children.retain(|dir, children| {
+            children.retain(|child| !removed.contains(child));
+            !removed.contains(dir) && !children.is_empty()
+        });
+        self.leaves = self
+            .dirs
+            .iter()
+            .filter(|dir| !self.children.contains_key(*dir))
+            .cloned()
+            .collect();
+    }
+
+    /// Leave `dir` and everything in it in place, as if the scan had
+    /// skipped it for `reason`, keeping the directories above it
+    pub fn skip_dir(&mut self, dir: &Path, reason: SkipReason) {
+        let removed: HashSet<PathBuf> = self
+            .dirs
+            .iter()
+            .filter(|d| d.starts_with(dir))
+            .cloned()
+            .collect();
+        self.files.retain(|file| !file.starts_with(dir));
+        self.forget_dirs(&removed);
+        for ancestor in dir.ancestors().skip(1) {
+            if !self.dirs.iter().any(|d| d == ancestor) {
+                break;
+            }
+            self.retained.insert(ancestor.to_path_buf());
+        }
+        if !self.skipped.iter().any(|entry| entry.path == dir) {
+            self.skipped.push(SkippedEntry {
+                path: dir.to_path_buf(),
+                is_dir: true,
+                reason,
+                bytes: dir_size(dir),
+            });
+        }
+    }
+
+    /// Point every path under `from` at the same place under `to`, after
+    /// the root was renamed (skipped entries keep their old paths)
+    pub fn rebase(&mut self, from: &Path, to: &Path) {
+        let move_path = |path: &PathBuf| match path.strip_prefix(from) {
+            Ok(rest) if rest.as_os_str().is_empty() => to.to_path_buf(),
+            Ok(rest) => to.join(rest),
+            Err(_) => path.clone(),
+        };
+        let move_all = |paths: &[PathBuf]| paths.iter().map(move_path).collect::<Vec<_>>();
+
+        self.dirs = move_all(&self.dirs);
+        self.files = move_all(&self.files);
+        self.leaves = move_all(&self.leaves);
+        self.children = self
+            .children
+            .iter()
+            .map(|(dir, children)| (move_path(dir), move_all(children)))
+            .collect();
+        self.retained = self.retained.iter().map(move_path).collect();
+        self.dir_bytes = self
+            .dir_bytes
+            .iter()
+            .map(|(dir, bytes)| (move_path(dir), *bytes))
+            .collect();
+        self.large_dirs = self
+            .large_dirs
+            .iter()
+            .map(|(dir, entries)| (move_path(dir), *entries))
+            .collect();
+    }
+}
+
+/// Why an entry was left in place instead of deleted
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, serde::Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SkipReason {
+    /// Another filesystem is mounted here; its contents are never touched
+    MountPoint,
+    /// Listed in the keep list (`--keep-from`)
+    Kept,
+    /// Directory name matched `--exclude-dir-names`
+    ExcludedName,
+    /// A protected directory inside the target (`--skip-protected`)
+    Protected,
+    /// Matched an `--exclude` pattern
+    ExcludedGlob,
+    /// Not matched by any `--include` pattern
+    NotIncluded,
+    /// Modified more recently than `--older-than` allows
+    TooNew,
+    /// No larger than `--larger-than`
+    TooSmall,
+    /// No smaller than `--smaller-than`
+    TooLarge,
+    /// A file above `--min-depth`
+    TooShallow,
+    /// Below `--max-depth`; directories are kept whole
+    TooDeep,
+    /// A file rmbrr writes during the run (`--journal`, `--manifest-out`),
+    /// deleted at its end
+    OwnFile,
+}
+
+impl SkipReason {
+    pub const ALL: [SkipReason; 12] = [
+        SkipReason::MountPoint,
+        SkipReason::Kept,
+        SkipReason::ExcludedName,
+        SkipReason::Protected,
+        SkipReason::ExcludedGlob,
+        SkipReason::NotIncluded,
+        SkipReason::TooNew,
+        SkipReason::TooSmall,
+        SkipReason::TooLarge,
+        SkipReason::TooShallow,
+        SkipReason::TooDeep,
+        SkipReason::OwnFile,
+    ];
+
+    /// Name in `--json` output
+    pub fn name(self) -> &'static str {
+        match self {
+            SkipReason::MountPoint => "mount_point",
+            SkipReason::Kept => "kept",
+            SkipReason::ExcludedName => "excluded_name",
+            SkipReason::Protected => "protected",
+            SkipReason::ExcludedGlob => "excluded_glob",
+            SkipReason::NotIncluded => "not_included",
+            SkipReason::TooNew => "too_new",
+            SkipReason::TooSmall => "too_small",
+            SkipReason::TooLarge => "too_large",
+            SkipReason::TooShallow => "too_shallow",
+            SkipReason::TooDeep => "too_deep",
+            SkipReason::OwnFile => "own_file",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|reason| reason.name() == name)
+    }
+
+    pub fn description(self) -> &'static str {
+        match self {
+            SkipReason::MountPoint => "mount point",
+            SkipReason::Kept => "in keep list",
+            SkipReason::ExcludedName => "excluded by name",
+            SkipReason::Protected => "protected",
+            SkipReason::ExcludedGlob => "excluded by pattern",
+            SkipReason::NotIncluded => "not matched by --include",
+            SkipReason::TooNew => "newer than --older-than",
+            SkipReason::TooSmall => "not larger than --larger-than",
+            SkipReason::TooLarge => "not smaller than --smaller-than",
+            SkipReason::TooShallow => "above --min-depth",
+            SkipReason::TooDeep => "below --max-depth",
+            SkipReason::OwnFile => "rmbrr's journal or manifest, deleted last",
+        }
+    }
+}
+
+/// An entry the scan decided not to delete
+#[derive(Debug, Clone)]
+pub struct SkippedEntry {
+    pub path: PathBuf,
+    pub is_dir: bool,
+    pub reason: SkipReason,
+    /// Size of the entry, if known (not computed for directories)
+    pub bytes: u64,
+}
+
+/// Serialized as `{ path, path_raw?, is_dir, reason, bytes }` (see
+/// [`crate::error::serialize_path`])
+impl serde::Serialize for SkippedEntry {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        use serde::ser::SerializeStruct;
+
+        let mut state = serializer.serialize_struct("SkippedEntry", 5)?;
+        crate::error::serialize_path(&mut state, &self.path)?;
+        state.serialize_field("is_dir", &self.is_dir)?;
+        state.serialize_field("reason", &self.reason)?;
+        state.serialize_field("bytes", &self.bytes)?;
+        state.end()
+    }
+}
+
+/// Count and bytes of skipped entries per reason, in first-seen order
+pub fn summarize_skipped(skipped: &[SkippedEntry]) -> Vec<(SkipReason, usize, u64)> {
+    let mut summary: Vec<(SkipReason, usize, u64)> = Vec::new();
+    for entry in skipped {
+        match summary
+            .iter_mut()
+            .find(|(reason, ..)| *reason == entry.reason)
+        {
+            Some((_, count, bytes)) => {
+                *count += 1;
+                *bytes += entry.bytes;
+            }
+            None => summary.push((entry.reason, 1, entry.bytes)),
+        }
+    }
+    summary
 }
 
 impl Default for DirectoryTree {
@@ -36,80 +286,546 @@ impl Default for DirectoryTree {
     }
 }
 
+/// Live counters updated while a tree is being discovered
+///
+/// Shared with a display thread so long scans show progress instead of
+/// looking like a hang.
+#[derive(Debug, Default)]
+pub struct ScanProgress {
+    dirs: AtomicUsize,
+    files: AtomicUsize,
+}
+
+impl ScanProgress {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Directories scanned so far
+    pub fn dirs(&self) -> usize {
+        self.dirs.load(Ordering::Relaxed)
+    }
+
+    /// Files found so far
+    pub fn files(&self) -> usize {
+        self.files.load(Ordering::Relaxed)
+    }
+}
+
+/// Options controlling tree discovery
+#[derive(Debug, Clone, Default)]
+pub struct ScanOptions {
+    /// Abort the scan as soon as this is cancelled
+    pub cancel: Option<CancelToken>,
+    /// Entries to leave in place instead of scanning for deletion
+    pub filters: Filters,
+    /// Record file sizes per directory (one extra stat per file off Windows)
+    pub collect_sizes: bool,
+    /// Threads listing directories at once; 0 or 1 scans on the calling thread
+    pub threads: usize,
+    /// Descend into symlinks and junctions to directories, so what they lead
+    /// to is deleted too (see [`FollowedLinks::follow`]); otherwise links
+    /// are unlinked like files, never traversed
+    pub follow_links: bool,
+}
+
 pub fn discover_tree(root: &Path) -> io::Result<DirectoryTree> {
-    let mut tree = DirectoryTree::new();
-    let mut all_dirs = HashSet::new();
-    let mut has_children = HashSet::new();
-    let mut file_count = 0;
+    discover_tree_with_progress(root, &ScanProgress::new())
+}
+
+/// Discover a tree, publishing running totals to `progress` as it goes
+pub fn discover_tree_with_progress(
+    root: &Path,
+    progress: &ScanProgress,
+) -> io::Result<DirectoryTree> {
+    discover_tree_with_options(root, &ScanOptions::default(), progress)
+}
+
+/// Discover a tree using `options`, publishing running totals to `progress`
+///
+/// Lists directories on [`ScanOptions::threads`] threads. Returns an error
+/// of kind `Interrupted` if the scan was cancelled.
+pub fn discover_tree_with_options(
+    root: &Path,
+    options: &ScanOptions,
+    progress: &ScanProgress,
+) -> io::Result<DirectoryTree> {
+    let threads = options.threads.max(1);
+    let queues: Vec<Worker<PathBuf>> = (0..threads).map(|_| Worker::new_lifo()).collect();
+    queues[0].push(root.to_path_buf());
+    let walk = Walk {
+        stealers: queues.iter().map(Worker::stealer).collect(),
+        pending: AtomicUsize::new(1),
+        failure: Mutex::new(None),
+        failed: AtomicBool::new(false),
+        root_device: device_id(root),
+        links: options.follow_links.then(|| FollowedLinks::new(root)),
+        options,
+        progress,
+    };
 
-    scan_recursive(
-        root,
-        &mut all_dirs,
-        &mut tree.children,
-        &mut has_children,
-        &mut file_count,
-        &mut tree.files,
-    )?;
+    let found: Vec<Found> = if threads == 1 {
+        queues.into_iter().map(|queue| walk.run(0, queue)).collect()
+    } else {
+        let walk = &walk;
+        std::thread::scope(|s| {
+            let handles: Vec<_> = queues
+                .into_iter()
+                .enumerate()
+                .map(|(i, queue)| s.spawn(move || walk.run(i, queue)))
+                .collect();
+            handles.into_iter().map(|h| h.join().unwrap()).collect()
+        })
+    };
 
-    tree.dirs = all_dirs.iter().cloned().collect();
-    tree.dirs.sort();
+    match walk.failure.into_inner().unwrap() {
+        Some(e) => Err(e),
+        None => Ok(Found::merge(found)),
+    }
+}
 
-    for dir in &tree.dirs {
-        if !has_children.contains(dir) {
-            tree.leaves.push(dir.clone());
+/// Total size of all files below `dir` (symlinks are not followed)
+pub fn dir_size(dir: &Path) -> u64 {
+    let mut total = 0;
+    let mut stack = vec![dir.to_path_buf()];
+    while let Some(dir) = stack.pop() {
+        let Ok(entries) = fs::read_dir(&dir) else {
+            continue;
+        };
+        for entry in entries.flatten() {
+            let Ok(metadata) = entry.metadata() else {
+                continue;
+            };
+            if metadata.is_dir() {
+                stack.push(entry.path());
+            } else {
+                total += metadata.len();
+            }
         }
     }
+    total
+}
 
-    tree.file_count = file_count;
+/// Identifier of the filesystem `path` lives on (symlinks are not followed)
+#[cfg(unix)]
+fn device_id(path: &Path) -> Option<u64> {
+    use std::os::unix::fs::MetadataExt;
+    fs::symlink_metadata(path).ok().map(|m| m.dev())
+}
 
-    Ok(tree)
+#[cfg(not(unix))]
+fn device_id(_path: &Path) -> Option<u64> {
+    None
 }
 
-fn scan_recursive(
-    dir: &Path,
-    all_dirs: &mut HashSet<PathBuf>,
-    children_map: &mut HashMap<PathBuf, Vec<PathBuf>>,
-    has_children: &mut HashSet<PathBuf>,
-    file_count: &mut usize,
-    files: &mut Vec<PathBuf>,
-) -> io::Result<()> {
-    all_dirs.insert(dir.to_path_buf());
+/// A scan shared by its threads
+///
+/// Each thread lists directories from its own stack, depth first like a
+/// recursive scan, and steals from the others when it runs dry, so wide
+/// trees keep every thread busy.
+struct Walk<'a> {
+    stealers: Vec<Stealer<PathBuf>>,
+    /// Directories queued or being listed; the scan is over at 0
+    pending: AtomicUsize,
+    /// The first error, which stops every thread
+    failure: Mutex<Option<io::Error>>,
+    failed: AtomicBool,
+    /// Filesystem of the scan root; subdirectories on others are mount points
+    root_device: Option<u64>,
+    links: Option<FollowedLinks>,
+    options: &'a ScanOptions,
+    progress: &'a ScanProgress,
+}
+
+/// Pause of a scan thread with nothing to steal, after yielding a while
+const IDLE_WAIT: Duration = Duration::from_millis(1);
+
+impl Walk<'_> {
+    /// List directories until the scan is over; `index` is this thread's queue
+    fn run(&self, index: usize, queue: Worker<PathBuf>) -> Found {
+        let _as_user = crate::impersonate::enter();
+        let mut found = Found::default();
+        let mut idle = 0;
+        while self.pending.load(Ordering::SeqCst) > 0 && !self.failed.load(Ordering::SeqCst) {
+            let Some(dir) = self.next_dir(index, &queue) else {
+                // Others are still listing and may queue more
+                idle += 1;
+                if idle < 64 {
+                    std::thread::yield_now();
+                } else {
+                    std::thread::sleep(IDLE_WAIT);
+                }
+                continue;
+            };
+            idle = 0;
+            match found.scan_dir(&dir, self) {
+                Ok(children) => {
+                    // Count them before this one is done, so 0 really means over
+                    self.pending.fetch_add(children.len(), Ordering::SeqCst);
+                    for child in children.into_iter().rev() {
+                        queue.push(child);
+                    }
+                }
+                Err(e) => {
+                    self.failure.lock().unwrap().get_or_insert(e);
+                    self.failed.store(true, Ordering::SeqCst);
+                }
+            }
+            self.pending.fetch_sub(1, Ordering::SeqCst);
+        }
+        found
+    }
+
+    fn next_dir(&self, index: usize, queue: &Worker<PathBuf>) -> Option<PathBuf> {
+        queue.pop().or_else(|| loop {
+            let stolen: Steal<PathBuf> = self
+                .stealers
+                .iter()
+                .enumerate()
+                .filter(|(i, _)| *i != index)
+                .map(|(_, stealer)| stealer.steal_batch_and_pop(queue))
+                .collect();
+            if !stolen.is_retry() {
+                return stolen.success();
+            }
+        })
+    }
+}
+
+/// What one scan thread found, merged into the tree at the end
+#[derive(Default)]
+struct Found {
+    dirs: Vec<PathBuf>,
+    children: HashMap<PathBuf, Vec<PathBuf>>,
+    files: Vec<PathBuf>,
+    dir_bytes: HashMap<PathBuf, u64>,
+    large_dirs: HashMap<PathBuf, usize>,
+    skipped: Vec<SkippedEntry>,
+    /// Directories to keep once emptied (see [`Filters::retains_dir`])
+    kept_dirs: Vec<PathBuf>,
+}
+
+impl Found {
+    /// List `dir`; returns its subdirectories to scan
+    fn scan_dir(&mut self, dir: &Path, walk: &Walk) -> io::Result<Vec<PathBuf>> {
+        let options = walk.options;
+        if is_cancelled(options) {
+            return Err(cancelled());
+        }
+
+        self.dirs.push(dir.to_path_buf());
+        walk.progress.dirs.fetch_add(1, Ordering::Relaxed);
+        if options.filters.retains_dir(dir) {
+            self.kept_dirs.push(dir.to_path_buf());
+        }
 
-    let mut child_dirs = Vec::new();
+        let first_file = self.files.len();
+        let files = Some(&mut self.files);
+        let Some(listing) = list_dir(dir, options, walk.progress, walk.links.as_ref(), files)?
+        else {
+            return Ok(Vec::new());
+        };
 
-    if let Err(e) = crate::winapi::enumerate_files(dir, |path, is_dir| {
-        if is_dir {
-            child_dirs.push(path.to_path_buf());
+        let entries = listing.entries();
+        if entries >= crate::winapi::LARGE_DIR_ENTRIES {
+            self.large_dirs.insert(dir.to_path_buf(), entries);
+        }
+
+        if options.collect_sizes {
+            let bytes: u64 = self.files[first_file..]
+                .iter()
+                .filter_map(|file| fs::symlink_metadata(file).ok())
+                .map(|m| m.len())
+                .sum();
+            if bytes > 0 {
+                self.dir_bytes.insert(dir.to_path_buf(), bytes);
+            }
+        }
+
+        let (child_dirs, skipped) = listing.split_skipped(walk.root_device);
+        self.skipped.extend(skipped);
+        if !child_dirs.is_empty() {
+            self.children.insert(dir.to_path_buf(), child_dirs.clone());
+        }
+        Ok(child_dirs)
+    }
+
+    /// One tree out of what every thread found
+    fn merge(found: Vec<Found>) -> DirectoryTree {
+        let mut tree = DirectoryTree::new();
+        let mut kept_dirs = Vec::new();
+        for part in found {
+            tree.dirs.extend(part.dirs);
+            tree.children.extend(part.children);
+            tree.files.extend(part.files);
+            tree.dir_bytes.extend(part.dir_bytes);
+            tree.large_dirs.extend(part.large_dirs);
+            tree.skipped.extend(part.skipped);
+            kept_dirs.extend(part.kept_dirs);
+        }
+        tree.dirs.sort();
+        tree.skipped.sort_by(|a, b| a.path.cmp(&b.path));
+        tree.leaves = tree
+            .dirs
+            .iter()
+            .filter(|dir| !tree.children.contains_key(*dir))
+            .cloned()
+            .collect();
+        tree.file_count = tree.files.len();
+
+        // What holds a skipped entry stays, and so does every directory above it
+        let all_dirs: HashSet<&Path> = tree.dirs.iter().map(PathBuf::as_path).collect();
+        let holders = tree
+            .skipped
+            .iter()
+            .filter_map(|entry| entry.path.parent())
+            .chain(kept_dirs.iter().map(PathBuf::as_path));
+        for dir in holders {
+            for ancestor in dir.ancestors() {
+                if !all_dirs.contains(ancestor) || !tree.retained.insert(ancestor.to_path_buf()) {
+                    break;
+                }
+            }
+        }
+        tree
+    }
+}
+
+/// Entries of one directory, sorted the way the scan needs them
+struct Listing {
+    child_dirs: Vec<PathBuf>,
+    file_count: usize,
+    /// Entries a filter leaves in place
+    filtered: Vec<(PathBuf, bool, SkipReason)>,
+}
+
+impl Listing {
+    /// Entries listed, all kinds
+    fn entries(&self) -> usize {
+        self.child_dirs.len() + self.file_count + self.filtered.len()
+    }
+
+    /// Subdirectories to scan, and the entries to leave in place: filtered
+    /// ones and mount points (subdirectories not on `root_device`)
+    fn split_skipped(self, root_device: Option<u64>) -> (Vec<PathBuf>, Vec<SkippedEntry>) {
+        let mut skipped: Vec<SkippedEntry> = self
+            .filtered
+            .into_iter()
+            .map(|(path, is_dir, reason)| {
+                let bytes = if is_dir {
+                    dir_size(&path)
+                } else {
+                    fs::symlink_metadata(&path).map(|m| m.len()).unwrap_or(0)
+                };
+                SkippedEntry {
+                    path,
+                    is_dir,
+                    reason,
+                    bytes,
+                }
+            })
+            .collect();
+
+        let mut child_dirs = self.child_dirs;
+        child_dirs.retain(|child| {
+            if root_device.is_some() && device_id(child) != root_device {
+                skipped.push(SkippedEntry {
+                    path: child.clone(),
+                    is_dir: true,
+                    reason: SkipReason::MountPoint,
+                    bytes: 0,
+                });
+                false
+            } else {
+                true
+            }
+        });
+        (child_dirs, skipped)
+    }
+}
+
+/// Whether `path` itself is a symlink or junction, rather than what it
+/// leads to
+pub fn is_link(path: &Path) -> bool {
+    fs::symlink_metadata(path).is_ok_and(|m| m.file_type().is_symlink())
+}
+
+/// Links to directories a scan descends into, with what they lead to
+///
+/// Each directory is reached through one path only, so a link is not
+/// followed if it leads into the scan root, above it, or into (or above)
+/// a directory another link was followed to; that also rules out cycles.
+#[derive(Debug)]
+pub struct FollowedLinks {
+    root_device: Option<u64>,
+    /// Resolved paths of the scan root and of the directories followed to
+    reached: Mutex<Vec<PathBuf>>,
+}
+
+impl FollowedLinks {
+    pub fn new(root: &Path) -> Self {
+        Self {
+            root_device: device_id(root),
+            reached: Mutex::new(fs::canonicalize(root).into_iter().collect()),
+        }
+    }
+
+    /// Whether to descend into `link`: it leads to a directory on the scan
+    /// root's filesystem that isn't reached otherwise, isn't a filesystem
+    /// root and doesn't hold protected directories (see
+    /// [`crate::safety::check_path_safety`])
+    pub fn follow(&self, link: &Path) -> bool {
+        let Ok(target) = fs::canonicalize(link) else {
+            return false;
+        };
+        if !target.is_dir()
+            || target.parent().is_none()
+            || (self.root_device.is_some() && device_id(&target) != self.root_device)
+            || !crate::safety::check_path_safety(&target).allows(false)
+            || !crate::safety::protected_paths_within(&target).is_empty()
+        {
+            return false;
+        }
+        let mut reached = self.reached.lock().unwrap();
+        if reached
+            .iter()
+            .any(|dir| target.starts_with(dir) || dir.starts_with(&target))
+        {
+            return false;
+        }
+        reached.push(target);
+        true
+    }
+}
+
+/// List `dir`, appending its files to `files` if given (else just counting them)
+///
+/// Links count as files unless `links` says to follow them. `None` if it
+/// can't be read, after a warning: it is still deleted, or fails to be,
+/// like any other directory.
+fn list_dir(
+    dir: &Path,
+    options: &ScanOptions,
+    progress: &ScanProgress,
+    links: Option<&FollowedLinks>,
+    mut files: Option<&mut Vec<PathBuf>>,
+) -> io::Result<Option<Listing>> {
+    let mut listing = Listing {
+        child_dirs: Vec::new(),
+        file_count: 0,
+        filtered: Vec::new(),
+    };
+    let cancel = options.cancel.as_ref();
+    let filters = &options.filters;
+
+    if let Err(e) = crate::winapi::enumerate_entries(dir, |path, is_dir, attributes| {
+        // Huge directories can take a while on their own, so check per entry too
+        if cancel.is_some_and(CancelToken::is_cancelled) {
+            return Err(cancelled());
+        }
+        let is_dir = is_dir || (attributes.link && links.is_some_and(|links| links.follow(path)));
+        if let Some(reason) = filters.skip_reason(path, is_dir) {
+            listing.filtered.push((path.to_path_buf(), is_dir, reason));
+        } else if is_dir {
+            listing.child_dirs.push(path.to_path_buf());
         } else {
-            *file_count += 1;
-            files.push(path.to_path_buf());
+            if let Some(files) = files.as_deref_mut() {
+                files.push(path.to_path_buf());
+            }
+            listing.file_count += 1;
+            progress.files.fetch_add(1, Ordering::Relaxed);
         }
         Ok(())
     }) {
-        eprintln!("Warning: Cannot read {}: {}", dir.display(), e);
-        return Ok(());
+        if is_cancelled(options) {
+            return Err(cancelled());
+        }
+        crate::errln!("Warning: Cannot read {}: {}", dir.display(), e);
+        return Ok(None);
     }
+    Ok(Some(listing))
+}
 
-    if !child_dirs.is_empty() {
-        has_children.insert(dir.to_path_buf());
+/// A directory listed by [`stream_tree`]
+#[derive(Debug)]
+pub struct ListedDir {
+    pub dir: PathBuf,
+    /// Subdirectories to delete; each is reported after this directory
+    pub children: Vec<PathBuf>,
+    /// Files to delete directly inside
+    pub files: usize,
+    /// Entries directly inside to leave in place
+    pub skipped: Vec<SkippedEntry>,
+    /// The directory itself must stay once emptied (see [`Filters::retains_dir`])
+    pub kept: bool,
+    /// Entries listed, all kinds
+    pub entries: usize,
+}
 
-        for child in &child_dirs {
-            scan_recursive(
-                child,
-                all_dirs,
-                children_map,
-                has_children,
-                file_count,
-                files,
-            )?;
+/// Scan `root` depth first, handing each directory to `sink` as soon as it
+/// has been listed
+///
+/// Unlike [`discover_tree_with_options`] nothing is collected: every
+/// directory is reported before its subdirectories and file paths are only
+/// counted, so deletion can start right away and memory doesn't grow with
+/// the tree. Sizes are never collected. Returns an error of kind
+/// `Interrupted` if the scan was cancelled.
+pub fn stream_tree(
+    root: &Path,
+    options: &ScanOptions,
+    progress: &ScanProgress,
+    mut sink: impl FnMut(ListedDir),
+) -> io::Result<()> {
+    let root_device = device_id(root);
+    let links = options.follow_links.then(|| FollowedLinks::new(root));
+    let mut stack = vec![root.to_path_buf()];
+    while let Some(dir) = stack.pop() {
+        if is_cancelled(options) {
+            return Err(cancelled());
         }
+        progress.dirs.fetch_add(1, Ordering::Relaxed);
+        let kept = options.filters.retains_dir(&dir);
 
-        children_map.insert(dir.to_path_buf(), child_dirs);
+        let listed = match list_dir(&dir, options, progress, links.as_ref(), None)? {
+            Some(listing) => {
+                let (files, entries) = (listing.file_count, listing.entries());
+                let (children, skipped) = listing.split_skipped(root_device);
+                ListedDir {
+                    dir,
+                    children,
+                    files,
+                    skipped,
+                    kept,
+                    entries,
+                }
+            }
+            None => ListedDir {
+                dir,
+                children: Vec::new(),
+                files: 0,
+                skipped: Vec::new(),
+                kept,
+                entries: 0,
+            },
+        };
+        // Reversed, so subdirectories are listed in enumeration order
+        stack.extend(listed.children.iter().rev().cloned());
+        sink(listed);
     }
-
     Ok(())
 }
 
+fn is_cancelled(options: &ScanOptions) -> bool {
+    options
+        .cancel
+        .as_ref()
+        .is_some_and(CancelToken::is_cancelled)
+}
+
+fn cancelled() -> io::Error {
+    io::Error::new(io::ErrorKind::Interrupted, "scan cancelled")
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -157,6 +873,26 @@ mod tests {
         fs::remove_dir_all(&temp).ok();
     }
 
+    #[test]
+    fn test_rebase_after_rename() {
+        let temp = std::env::temp_dir().join("rmbrr_tree_rebase_test");
+        let moved = std::env::temp_dir().join("rmbrr_tree_rebase_test_moved");
+        let _ = fs::remove_dir_all(&temp);
+        let _ = fs::remove_dir_all(&moved);
+        create_test_tree(&temp).unwrap();
+        fs::write(temp.join("a/a1/f.txt"), "f").unwrap();
+
+        let mut tree = discover_tree(&temp).unwrap();
+        fs::rename(&temp, &moved).unwrap();
+        tree.rebase(&temp, &moved);
+
+        assert!(tree.dirs.contains(&moved));
+        assert!(tree.dirs.iter().chain(&tree.files).all(|p| p.exists()));
+        assert!(tree.children[&moved.join("a")].contains(&moved.join("a/a2")));
+
+        fs::remove_dir_all(&moved).ok();
+    }
+
     #[test]
     fn test_single_dir() {
         let temp = std::env::temp_dir().join("win_rmdir_single_test");
@@ -194,4 +930,352 @@ mod tests {
 
         fs::remove_dir_all(&temp).ok();
     }
+
+    #[test]
+    fn test_scan_progress_counts() {
+        let temp = std::env::temp_dir().join("rmbrr_scan_progress_test");
+        let _ = fs::remove_dir_all(&temp);
+
+        fs::create_dir_all(temp.join("a/b")).unwrap();
+        fs::write(temp.join("one.txt"), "1").unwrap();
+        fs::write(temp.join("a/b/two.txt"), "2").unwrap();
+
+        let progress = ScanProgress::new();
+        let tree = discover_tree_with_progress(&temp, &progress).unwrap();
+
+        assert_eq!(progress.dirs(), tree.dirs.len());
+        assert_eq!(progress.files(), tree.file_count);
+        assert_eq!(progress.files(), 2);
+
+        fs::remove_dir_all(&temp).ok();
+    }
+
+    #[test]
+    fn test_skip_retains_ancestors() {
+        let root = PathBuf::from("/scan/root");
+        let found = Found {
+            dirs: vec![root.clone(), root.join("a"), root.join("b")],
+            skipped: vec![SkippedEntry {
+                path: root.join("a/mnt"),
+                is_dir: true,
+                reason: SkipReason::MountPoint,
+                bytes: 0,
+            }],
+            ..Default::default()
+        };
+        let tree = Found::merge(vec![found]);
+
+        assert_eq!(tree.skipped.len(), 1);
+        assert!(tree.retained.contains(&root));
+        assert!(tree.retained.contains(&root.join("a")));
+        assert!(!tree.retained.contains(&root.join("b")));
+        assert!(!tree.retained.contains(Path::new("/scan")));
+
+        let summary = summarize_skipped(&tree.skipped);
+        assert_eq!(summary, vec![(SkipReason::MountPoint, 1, 0)]);
+    }
+
+    #[test]
+    fn test_skip_dir_after_scan() {
+        let temp = std::env::temp_dir().join("rmbrr_tree_skip_dir_test");
+        let _ = fs::remove_dir_all(&temp);
+        fs::create_dir_all(temp.join("a/p/q")).unwrap();
+        fs::create_dir_all(temp.join("b")).unwrap();
+        fs::write(temp.join("a/p/q/f.txt"), "abc").unwrap();
+
+        let mut tree = discover_tree(&temp).unwrap();
+        tree.skip_dir(&temp.join("a/p"), SkipReason::Protected);
+        assert_eq!(
+            tree.dirs,
+            vec![temp.clone(), temp.join("a"), temp.join("b")]
+        );
+        assert!(tree.files.is_empty());
+        assert_eq!(tree.leaves, vec![temp.join("a"), temp.join("b")]);
+        assert!(tree.retained.contains(&temp) && tree.retained.contains(&temp.join("a")));
+        assert!(!tree.retained.contains(&temp.join("b")));
+        assert_eq!(
+            summarize_skipped(&tree.skipped),
+            vec![(SkipReason::Protected, 1, 3)]
+        );
+
+        fs::remove_dir_all(&temp).ok();
+    }
+
+    #[test]
+    fn test_to_delete_lists_contents_after_their_directory() {
+        let root = PathBuf::from("/scan/root");
+        let found = Found {
+            dirs: vec![root.clone(), root.join("b"), root.join("a")],
+            files: vec![root.join("b/x.txt"), root.join("a/y.txt"), root.join("a-z")],
+            skipped: vec![SkippedEntry {
+                path: root.join("b/mnt"),
+                is_dir: true,
+                reason: SkipReason::MountPoint,
+                bytes: 0,
+            }],
+            ..Default::default()
+        };
+        let tree = Found::merge(vec![found]);
+
+        // The root and b stay for the mount point
+        let listed: Vec<(&Path, bool)> = tree.to_delete();
+        assert_eq!(
+            listed,
+            vec![
+                (root.join("a").as_path(), true),
+                (root.join("a/y.txt").as_path(), false),
+                (root.join("a-z").as_path(), false),
+                (root.join("b/x.txt").as_path(), false),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_parallel_scan_matches_serial() {
+        let temp = std::env::temp_dir().join("rmbrr_parallel_scan_test");
+        let _ = fs::remove_dir_all(&temp);
+        for i in 0..20 {
+            for j in 0..5 {
+                let dir = temp.join(format!("d{}/e{}", i, j));
+                fs::create_dir_all(&dir).unwrap();
+                fs::write(dir.join("f.txt"), "f").unwrap();
+            }
+        }
+        fs::write(temp.join("d3/keep.txt"), "kept").unwrap();
+
+        let mut options = ScanOptions::default();
+        options.filters.keep(temp.join("d3/keep.txt"));
+        let serial = discover_tree_with_options(&temp, &options, &ScanProgress::new()).unwrap();
+        options.threads = 4;
+        let progress = ScanProgress::new();
+        let parallel = discover_tree_with_options(&temp, &options, &progress).unwrap();
+
+        assert_eq!(parallel.dirs, serial.dirs);
+        assert_eq!(parallel.leaves, serial.leaves);
+        assert_eq!(parallel.children, serial.children);
+        assert_eq!(parallel.retained, serial.retained);
+        assert_eq!(parallel.file_count, 100);
+        assert_eq!((progress.dirs(), progress.files()), (121, 100));
+        let mut files = parallel.files.clone();
+        files.sort();
+        let mut serial_files = serial.files.clone();
+        serial_files.sort();
+        assert_eq!(files, serial_files);
+
+        fs::remove_dir_all(&temp).ok();
+    }
+
+    #[test]
+    fn test_cancelled_scan() {
+        let temp = std::env::temp_dir().join("rmbrr_cancelled_scan_test");
+        let _ = fs::remove_dir_all(&temp);
+        fs::create_dir_all(temp.join("a/b")).unwrap();
+
+        let token = CancelToken::new();
+        token.cancel();
+        let options = ScanOptions {
+            cancel: Some(token),
+            ..Default::default()
+        };
+
+        let err = discover_tree_with_options(&temp, &options, &ScanProgress::new()).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::Interrupted);
+
+        fs::remove_dir_all(&temp).ok();
+    }
+
+    #[test]
+    fn test_keep_list_scan() {
+        let temp = std::env::temp_dir().join("rmbrr_keep_scan_test");
+        let _ = fs::remove_dir_all(&temp);
+
+        fs::create_dir_all(temp.join("a/keep_dir/inner")).unwrap();
+        fs::create_dir_all(temp.join("b")).unwrap();
+        fs::create_dir_all(temp.join("c")).unwrap();
+        fs::write(temp.join("a/keep_dir/inner/x.bin"), vec![0u8; 40]).unwrap();
+        fs::write(temp.join("b/keep.txt"), vec![0u8; 10]).unwrap();
+        fs::write(temp.join("b/gone.txt"), "").unwrap();
+
+        let mut options = ScanOptions::default();
+        options.filters.keep(temp.join("a/keep_dir"));
+        options.filters.keep(temp.join("b/keep.txt"));
+
+        let tree = discover_tree_with_options(&temp, &options, &ScanProgress::new()).unwrap();
+
+        // Kept entries are neither scanned for deletion nor descended into
+        assert_eq!(tree.files, vec![temp.join("b/gone.txt")]);
+        assert!(!tree.dirs.contains(&temp.join("a/keep_dir")));
+        assert_eq!(tree.skipped.len(), 2);
+        assert_eq!(tree.skipped_bytes(), 50);
+
+        // Their ancestors stay, unrelated directories don't
+        let mut retained: Vec<_> = tree.retained.iter().cloned().collect();
+        retained.sort();
+        assert_eq!(retained, vec![temp.clone(), temp.join("a"), temp.join("b")]);
+
+        fs::remove_dir_all(&temp).ok();
+    }
+
+    #[test]
+    fn test_include_glob_scan() {
+        use crate::filter::Glob;
+
+        let temp = std::env::temp_dir().join("rmbrr_include_scan_test");
+        let _ = fs::remove_dir_all(&temp);
+
+        fs::create_dir_all(temp.join("maps/deep")).unwrap();
+        fs::create_dir_all(temp.join("empty")).unwrap();
+        fs::write(temp.join("maps/deep/a.map"), "").unwrap();
+        fs::write(temp.join("maps/b.js"), "").unwrap();
+
+        let mut options = ScanOptions::default();
+        options
+            .filters
+            .globs(temp.clone(), vec![Glob::parse("*.map").unwrap()], vec![]);
+
+        let tree = discover_tree_with_options(&temp, &options, &ScanProgress::new()).unwrap();
+
+        assert_eq!(tree.files, vec![temp.join("maps/deep/a.map")]);
+        assert_eq!(tree.skipped.len(), 1);
+        assert_eq!(tree.skipped[0].reason, SkipReason::NotIncluded);
+        // Directories aren't matched by the pattern, so all of them stay
+        let mut retained: Vec<_> = tree.retained.iter().cloned().collect();
+        retained.sort();
+        assert_eq!(
+            retained,
+            vec![
+                temp.clone(),
+                temp.join("empty"),
+                temp.join("maps"),
+                temp.join("maps/deep")
+            ]
+        );
+
+        fs::remove_dir_all(&temp).ok();
+    }
+
+    #[test]
+    fn test_stream_tree_parents_first() {
+        let temp = std::env::temp_dir().join("rmbrr_stream_tree_test");
+        let _ = fs::remove_dir_all(&temp);
+        create_test_tree(&temp).unwrap();
+        fs::write(temp.join("a/a1/f.txt"), "f").unwrap();
+        fs::write(temp.join("c/keep.txt"), "kept").unwrap();
+
+        let mut options = ScanOptions::default();
+        options.filters.keep(temp.join("c/keep.txt"));
+        let progress = ScanProgress::new();
+        let mut listed = Vec::new();
+        stream_tree(&temp, &options, &progress, |dir| listed.push(dir)).unwrap();
+
+        let order: Vec<_> = listed.iter().map(|l| l.dir.clone()).collect();
+        assert_eq!(order.len(), 7);
+        for dir in &listed {
+            let at = order.iter().position(|d| *d == dir.dir).unwrap();
+            for child in &dir.children {
+                assert!(order.iter().position(|d| d == child).unwrap() > at);
+            }
+        }
+        let a1 = listed.iter().find(|l| l.dir == temp.join("a/a1")).unwrap();
+        assert_eq!(a1.files, 1);
+        let c = listed.iter().find(|l| l.dir == temp.join("c")).unwrap();
+        assert_eq!(c.skipped.len(), 1);
+        assert_eq!(c.skipped[0].bytes, 4);
+        assert_eq!((progress.dirs(), progress.files()), (7, 1));
+
+        fs::remove_dir_all(&temp).ok();
+    }
+
+    #[test]
+    fn test_dir_size() {
+        let temp = std::env::temp_dir().join("rmbrr_dir_size_test");
+        let _ = fs::remove_dir_all(&temp);
+
+        fs::create_dir_all(temp.join("a/b")).unwrap();
+        fs::write(temp.join("one.bin"), vec![0u8; 100]).unwrap();
+        fs::write(temp.join("a/b/two.bin"), vec![0u8; 250]).unwrap();
+
+        assert_eq!(dir_size(&temp), 350);
+
+        fs::remove_dir_all(&temp).ok();
+    }
+
+    #[test]
+    fn test_collect_sizes() {
+        let temp = std::env::temp_dir().join("rmbrr_collect_sizes_test");
+        let _ = fs::remove_dir_all(&temp);
+
+        fs::create_dir_all(temp.join("a/empty")).unwrap();
+        fs::write(temp.join("one.bin"), vec![0u8; 100]).unwrap();
+        fs::write(temp.join("a/two.bin"), vec![0u8; 250]).unwrap();
+        fs::write(temp.join("a/three.bin"), vec![0u8; 5]).unwrap();
+
+        let tree = discover_tree(&temp).unwrap();
+        assert!(tree.dir_bytes.is_empty());
+
+        let options = ScanOptions {
+            collect_sizes: true,
+            ..Default::default()
+        };
+        let tree = discover_tree_with_options(&temp, &options, &ScanProgress::new()).unwrap();
+        assert_eq!(tree.dir_bytes.get(&temp), Some(&100));
+        assert_eq!(tree.dir_bytes.get(&temp.join("a")), Some(&255));
+        assert!(!tree.dir_bytes.contains_key(&temp.join("a/empty")));
+        assert_eq!(tree.total_bytes(), 355);
+
+        fs::remove_dir_all(&temp).ok();
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_links_followed_only_when_asked() {
+        use std::os::unix::fs::symlink;
+
+        let temp = std::env::temp_dir().join("rmbrr_links_test");
+        let _ = fs::remove_dir_all(&temp);
+        let (root, outside) = (temp.join("root"), temp.join("outside"));
+        fs::create_dir_all(root.join("a")).unwrap();
+        fs::create_dir_all(outside.join("sub")).unwrap();
+        fs::write(outside.join("sub/file.txt"), "x").unwrap();
+        symlink(&outside, root.join("out")).unwrap();
+        symlink(root.join("a"), root.join("in")).unwrap();
+        // Cycles back into the root and into the followed directory
+        symlink(&root, outside.join("back")).unwrap();
+        symlink(outside.join("sub"), outside.join("again")).unwrap();
+
+        let tree = discover_tree(&root).unwrap();
+        assert_eq!(tree.dirs, vec![root.clone(), root.join("a")]);
+        let mut files = tree.files.clone();
+        files.sort();
+        assert_eq!(files, vec![root.join("in"), root.join("out")]);
+
+        let options = ScanOptions {
+            follow_links: true,
+            threads: 2,
+            ..Default::default()
+        };
+        let tree = discover_tree_with_options(&root, &options, &ScanProgress::new()).unwrap();
+        assert_eq!(
+            tree.dirs,
+            vec![
+                root.clone(),
+                root.join("a"),
+                root.join("out"),
+                root.join("out/sub")
+            ]
+        );
+        let mut files = tree.files.clone();
+        files.sort();
+        assert_eq!(
+            files,
+            vec![
+                root.join("in"),
+                root.join("out/again"),
+                root.join("out/back"),
+                root.join("out/sub/file.txt")
+            ]
+        );
+
+        fs::remove_dir_all(&temp).ok();
+    }
 }
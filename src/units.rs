@@ -0,0 +1,215 @@
+//! Parsing and formatting helpers for human-friendly durations and sizes
+
+use std::time::Duration;
+
+/// Parse a duration like `250ms`, `90s`, `30m`, `12h`, `60d` or `2w`
+///
+/// A bare number is interpreted as seconds.
+pub fn parse_duration(input: &str) -> Result<Duration, String> {
+    let input = input.trim();
+    let split = input
+        .find(|c: char| !c.is_ascii_digit())
+        .unwrap_or(input.len());
+    let (digits, unit) = input.split_at(split);
+
+    let value: u64 = digits
+        .parse()
+        .map_err(|_| format!("invalid duration '{}': expected e.g. 60d, 12h", input))?;
+
+    let millis_per_unit = match unit.trim() {
+        "ms" => 1,
+        "" | "s" => 1000,
+        "m" => 60 * 1000,
+        "h" => 60 * 60 * 1000,
+        "d" => 24 * 60 * 60 * 1000,
+        "w" => 7 * 24 * 60 * 60 * 1000,
+        other => {
+            return Err(format!(
+                "invalid duration unit '{}' in '{}': use ms, s, m, h, d or w",
+                other, input
+            ))
+        }
+    };
+
+    value
+        .checked_mul(millis_per_unit)
+        .map(Duration::from_millis)
+        .ok_or_else(|| format!("duration '{}' is too large", input))
+}
+
+/// Parse a count, or `all` for no limit (`usize::MAX`)
+pub fn parse_limit(input: &str) -> Result<usize, String> {
+    let input = input.trim();
+    if input.eq_ignore_ascii_case("all") {
+        return Ok(usize::MAX);
+    }
+    input
+        .parse()
+        .map_err(|_| format!("invalid count '{}': expected a number or all", input))
+}
+
+/// Parse a size like `500M`, `10GB` or `2T` (binary units, as printed)
+///
+/// A bare number is interpreted as bytes.
+pub fn parse_bytes(input: &str) -> Result<u64, String> {
+    let input = input.trim();
+    let split = input
+        .find(|c: char| !c.is_ascii_digit())
+        .unwrap_or(input.len());
+    let (digits, unit) = input.split_at(split);
+
+    let value: u64 = digits
+        .parse()
+        .map_err(|_| format!("invalid size '{}': expected e.g. 500M, 10G", input))?;
+
+    let shift = match unit.trim().to_ascii_uppercase().as_str() {
+        "" | "B" => 0,
+        "K" | "KB" => 10,
+        "M" | "MB" => 20,
+        "G" | "GB" => 30,
+        "T" | "TB" => 40,
+        _ => {
+            return Err(format!(
+                "invalid size unit '{}' in '{}': use K, M, G or T",
+                unit.trim(),
+                input
+            ))
+        }
+    };
+
+    value
+        .checked_mul(1 << shift)
+        .ok_or_else(|| format!("size '{}' is too large", input))
+}
+
+/// Format a byte count using binary units (e.g. `1.5 GB`)
+pub fn format_bytes(bytes: u64) -> String {
+    const UNITS: [&str; 5] = ["B", "KB", "MB", "GB", "TB"];
+
+    if bytes < 1024 {
+        return format!("{} B", bytes);
+    }
+
+    let mut value = bytes as f64;
+    let mut unit = 0;
+    while value >= 1024.0 && unit < UNITS.len() - 1 {
+        value /= 1024.0;
+        unit += 1;
+    }
+    format!("{:.1} {}", value, UNITS[unit])
+}
+
+/// Format a duration as a compact age (e.g. `45s`, `3h`, `94d`)
+pub fn format_age(age: Duration) -> String {
+    let secs = age.as_secs();
+    if secs < 60 {
+        format!("{}s", secs)
+    } else if secs < 60 * 60 {
+        format!("{}m", secs / 60)
+    } else if secs < 24 * 60 * 60 {
+        format!("{}h", secs / (60 * 60))
+    } else {
+        format!("{}d", secs / (24 * 60 * 60))
+    }
+}
+
+/// Format a count compactly (e.g. `950`, `42.0K`, `1.1M`)
+pub fn format_count(count: usize) -> String {
+    let count = count as f64;
+    if count < 1e3 {
+        format!("{}", count)
+    } else if count < 1e6 {
+        format!("{:.1}K", count / 1e3)
+    } else if count < 1e9 {
+        format!("{:.1}M", count / 1e6)
+    } else {
+        format!("{:.1}B", count / 1e9)
+    }
+}
+
+/// Format a remaining-time estimate (e.g. `45s`, `2m10s`, `1h05m`)
+pub fn format_eta(eta: Duration) -> String {
+    let secs = eta.as_secs();
+    if secs < 60 {
+        format!("{}s", secs)
+    } else if secs < 60 * 60 {
+        format!("{}m{:02}s", secs / 60, secs % 60)
+    } else {
+        format!("{}h{:02}m", secs / 3600, (secs % 3600) / 60)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_duration() {
+        assert_eq!(parse_duration("45").unwrap(), Duration::from_secs(45));
+        assert_eq!(parse_duration("250ms").unwrap(), Duration::from_millis(250));
+        assert_eq!(parse_duration("30m").unwrap(), Duration::from_secs(1800));
+        assert_eq!(parse_duration("12h").unwrap(), Duration::from_secs(43200));
+        assert_eq!(
+            parse_duration("60d").unwrap(),
+            Duration::from_secs(60 * 86400)
+        );
+        assert_eq!(
+            parse_duration("2w").unwrap(),
+            Duration::from_secs(14 * 86400)
+        );
+
+        assert!(parse_duration("").is_err());
+        assert!(parse_duration("d").is_err());
+        assert!(parse_duration("10y").is_err());
+    }
+
+    #[test]
+    fn test_parse_bytes() {
+        assert_eq!(parse_bytes("512").unwrap(), 512);
+        assert_eq!(parse_bytes("500M").unwrap(), 500 << 20);
+        assert_eq!(parse_bytes("10gb").unwrap(), 10 << 30);
+        assert_eq!(parse_bytes("2T").unwrap(), 2 << 40);
+
+        assert!(parse_bytes("").is_err());
+        assert!(parse_bytes("G").is_err());
+        assert!(parse_bytes("10X").is_err());
+        assert!(parse_bytes("99999999999T").is_err());
+    }
+
+    #[test]
+    fn test_parse_limit() {
+        assert_eq!(parse_limit("25").unwrap(), 25);
+        assert_eq!(parse_limit("All").unwrap(), usize::MAX);
+        assert!(parse_limit("-1").is_err());
+        assert!(parse_limit("many").is_err());
+    }
+
+    #[test]
+    fn test_format_bytes() {
+        assert_eq!(format_bytes(0), "0 B");
+        assert_eq!(format_bytes(1023), "1023 B");
+        assert_eq!(format_bytes(1536), "1.5 KB");
+        assert_eq!(format_bytes(3 * 1024 * 1024 * 1024), "3.0 GB");
+    }
+
+    #[test]
+    fn test_format_count() {
+        assert_eq!(format_count(950), "950");
+        assert_eq!(format_count(42_000), "42.0K");
+        assert_eq!(format_count(1_100_000), "1.1M");
+    }
+
+    #[test]
+    fn test_format_eta() {
+        assert_eq!(format_eta(Duration::from_secs(45)), "45s");
+        assert_eq!(format_eta(Duration::from_secs(130)), "2m10s");
+        assert_eq!(format_eta(Duration::from_secs(3900)), "1h05m");
+    }
+
+    #[test]
+    fn test_format_age() {
+        assert_eq!(format_age(Duration::from_secs(5)), "5s");
+        assert_eq!(format_age(Duration::from_secs(7200)), "2h");
+        assert_eq!(format_age(Duration::from_secs(94 * 86400)), "94d");
+    }
+}
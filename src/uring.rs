@@ -0,0 +1,405 @@
+//! Batched `unlinkat` through io_uring on Linux (`--backend uring`)
+//!
+//! Just enough of the interface for one operation, straight on the raw
+//! syscalls: libc has the syscall numbers but none of the io_uring types.
+//! `IORING_OP_UNLINKAT` needs Linux 5.11; on older kernels, or where
+//! io_uring is disabled, [`unlink_batch`] returns `None` and callers use
+//! plain `unlinkat` instead, as they do for whatever a failing ring left
+//! unfinished.
+
+use std::cell::RefCell;
+use std::ffi::CStr;
+use std::io;
+use std::os::fd::{AsRawFd, FromRawFd, OwnedFd, RawFd};
+use std::sync::atomic::{AtomicBool, AtomicU32, Ordering};
+
+/// Submission queue size: one round trip deletes up to this many files
+const ENTRIES: u32 = 256;
+
+const IORING_OFF_SQ_RING: libc::off_t = 0;
+const IORING_OFF_CQ_RING: libc::off_t = 0x800_0000;
+const IORING_OFF_SQES: libc::off_t = 0x1000_0000;
+const IORING_ENTER_GETEVENTS: u32 = 1;
+const IORING_REGISTER_PROBE: u32 = 8;
+const IO_URING_OP_SUPPORTED: u16 = 1;
+const IORING_OP_UNLINKAT: u8 = 36;
+
+#[repr(C)]
+#[derive(Debug, Default)]
+struct SqringOffsets {
+    head: u32,
+    tail: u32,
+    ring_mask: u32,
+    ring_entries: u32,
+    flags: u32,
+    dropped: u32,
+    array: u32,
+    resv1: u32,
+    user_addr: u64,
+}
+
+#[repr(C)]
+#[derive(Debug, Default)]
+struct CqringOffsets {
+    head: u32,
+    tail: u32,
+    ring_mask: u32,
+    ring_entries: u32,
+    overflow: u32,
+    cqes: u32,
+    flags: u32,
+    resv1: u32,
+    user_addr: u64,
+}
+
+#[repr(C)]
+#[derive(Debug, Default)]
+struct Params {
+    sq_entries: u32,
+    cq_entries: u32,
+    flags: u32,
+    sq_thread_cpu: u32,
+    sq_thread_idle: u32,
+    features: u32,
+    wq_fd: u32,
+    resv: [u32; 3],
+    sq_off: SqringOffsets,
+    cq_off: CqringOffsets,
+}
+
+/// `struct io_uring_sqe`, with the unions flattened to the fields used here
+#[repr(C)]
+#[derive(Debug, Default)]
+struct Sqe {
+    opcode: u8,
+    flags: u8,
+    ioprio: u16,
+    fd: i32,
+    off: u64,
+    addr: u64,
+    len: u32,
+    /// `unlink_flags` for `UNLINKAT`
+    op_flags: u32,
+    user_data: u64,
+    buf_index: u16,
+    personality: u16,
+    splice_fd_in: i32,
+    addr3: u64,
+    pad: u64,
+}
+
+#[repr(C)]
+#[derive(Debug)]
+struct Cqe {
+    user_data: u64,
+    res: i32,
+    flags: u32,
+}
+
+/// A shared mapping of part of the ring
+struct Mapping {
+    ptr: *mut u8,
+    len: usize,
+}
+
+impl Mapping {
+    fn new(fd: RawFd, len: usize, offset: libc::off_t) -> io::Result<Self> {
+        let ptr = unsafe {
+            libc::mmap(
+                std::ptr::null_mut(),
+                len,
+                libc::PROT_READ | libc::PROT_WRITE,
+                libc::MAP_SHARED | libc::MAP_POPULATE,
+                fd,
+                offset,
+            )
+        };
+        if ptr == libc::MAP_FAILED {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(Self {
+            ptr: ptr.cast(),
+            len,
+        })
+    }
+
+    /// The `T` at byte `offset`
+    unsafe fn at<T>(&self, offset: u32) -> *mut T {
+        self.ptr.add(offset as usize).cast()
+    }
+}
+
+impl Drop for Mapping {
+    fn drop(&mut self) {
+        unsafe { libc::munmap(self.ptr.cast(), self.len) };
+    }
+}
+
+/// One io_uring instance, used by a single thread
+struct Ring {
+    fd: OwnedFd,
+    sq: Mapping,
+    cq: Mapping,
+    sqes: Mapping,
+    params: Params,
+}
+
+impl Ring {
+    fn new() -> io::Result<Self> {
+        let mut params = Params::default();
+        let fd = unsafe {
+            libc::syscall(
+                libc::SYS_io_uring_setup,
+                ENTRIES,
+                &mut params as *mut Params,
+            )
+        };
+        if fd < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        let fd = unsafe { OwnedFd::from_raw_fd(fd as RawFd) };
+        let raw = fd.as_raw_fd();
+
+        let sq_len = params.sq_off.array as usize + params.sq_entries as usize * 4;
+        let cq_len =
+            params.cq_off.cqes as usize + params.cq_entries as usize * std::mem::size_of::<Cqe>();
+        let sqes_len = params.sq_entries as usize * std::mem::size_of::<Sqe>();
+        let ring = Self {
+            sq: Mapping::new(raw, sq_len, IORING_OFF_SQ_RING)?,
+            cq: Mapping::new(raw, cq_len, IORING_OFF_CQ_RING)?,
+            sqes: Mapping::new(raw, sqes_len, IORING_OFF_SQES)?,
+            fd,
+            params,
+        };
+        if !ring.supports(IORING_OP_UNLINKAT)? {
+            return Err(io::Error::from(io::ErrorKind::Unsupported));
+        }
+        // Submission slot i always holds entry i
+        let array = unsafe { ring.sq.at::<u32>(ring.params.sq_off.array) };
+        for i in 0..ring.params.sq_entries {
+            unsafe { array.add(i as usize).write(i) };
+        }
+        Ok(ring)
+    }
+
+    /// Whether the kernel implements `opcode` (`IORING_REGISTER_PROBE`, 5.6+)
+    fn supports(&self, opcode: u8) -> io::Result<bool> {
+        // struct io_uring_probe: 16-byte header, then 8 bytes per opcode
+        let mut probe = [0u64; 2 + 256];
+        let res = unsafe {
+            libc::syscall(
+                libc::SYS_io_uring_register,
+                self.fd.as_raw_fd(),
+                IORING_REGISTER_PROBE,
+                probe.as_mut_ptr(),
+                256u32,
+            )
+        };
+        if res < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        let last_op = probe[0] as u8;
+        let op = probe[2 + opcode as usize];
+        // struct io_uring_probe_op { u8 op; u8 resv; u16 flags; u32 resv2 }
+        let flags = (op >> 16) as u16;
+        Ok(opcode <= last_op && flags & IO_URING_OP_SUPPORTED != 0)
+    }
+
+    fn atomic(&self, mapping: &Mapping, offset: u32) -> &AtomicU32 {
+        unsafe { &*mapping.at::<AtomicU32>(offset) }
+    }
+
+    fn enter(&self, to_submit: u32, min_complete: u32) -> io::Result<u32> {
+        loop {
+            let res = unsafe {
+                libc::syscall(
+                    libc::SYS_io_uring_enter,
+                    self.fd.as_raw_fd(),
+                    to_submit,
+                    min_complete,
+                    IORING_ENTER_GETEVENTS,
+                    std::ptr::null::<libc::sigset_t>(),
+                    0usize,
+                )
+            };
+            if res >= 0 {
+                return Ok(res as u32);
+            }
+            let e = io::Error::last_os_error();
+            if e.kind() != io::ErrorKind::Interrupted {
+                return Err(e);
+            }
+        }
+    }
+
+    /// `unlinkat(dirfd, name, 0)` for each name, results in the same order,
+    /// and the error that stopped the batch, if one did
+    fn unlink_all(&mut self, dirfd: RawFd, names: &[&CStr]) -> (Vec<Unlink>, io::Result<()>) {
+        let mut results: Vec<Unlink> = names.iter().map(|_| Unlink::NotTried).collect();
+        let (sq_off, cq_off) = (&self.params.sq_off, &self.params.cq_off);
+        let sq_mask = unsafe { *self.sq.at::<u32>(sq_off.ring_mask) };
+        let cq_mask = unsafe { *self.cq.at::<u32>(cq_off.ring_mask) };
+        let sq_tail = self.atomic(&self.sq, sq_off.tail);
+        let cq_head = self.atomic(&self.cq, cq_off.head);
+        let cq_tail = self.atomic(&self.cq, cq_off.tail);
+        let sqes = unsafe { self.sqes.at::<Sqe>(0) };
+        let cqes = unsafe { self.cq.at::<Cqe>(cq_off.cqes) };
+
+        let chunk_size = self.params.sq_entries as usize;
+        for (chunk_index, chunk) in names.chunks(chunk_size).enumerate() {
+            let base = chunk_index * chunk_size;
+            let tail = sq_tail.load(Ordering::Relaxed);
+            for (i, name) in chunk.iter().enumerate() {
+                let slot = tail.wrapping_add(i as u32) & sq_mask;
+                let sqe = Sqe {
+                    opcode: IORING_OP_UNLINKAT,
+                    fd: dirfd,
+                    addr: name.as_ptr() as u64,
+                    user_data: (base + i) as u64,
+                    ..Default::default()
+                };
+                unsafe { sqes.add(slot as usize).write(sqe) };
+            }
+            sq_tail.store(tail.wrapping_add(chunk.len() as u32), Ordering::Release);
+
+            // The kernel copies the names while submitting, before this returns
+            let mut pending = chunk.len();
+            let mut to_submit = chunk.len() as u32;
+            while pending > 0 {
+                let submitted = match self.enter(to_submit, 1) {
+                    Ok(submitted) => submitted,
+                    Err(e) => {
+                        let sent = chunk.len() - to_submit as usize;
+                        for result in &mut results[base..base + sent] {
+                            if matches!(result, Unlink::NotTried) {
+                                *result = Unlink::Unknown;
+                            }
+                        }
+                        return (results, Err(e));
+                    }
+                };
+                to_submit -= submitted.min(to_submit);
+                let head = cq_head.load(Ordering::Relaxed);
+                let tail = cq_tail.load(Ordering::Acquire);
+                let mut seen = head;
+                while seen != tail {
+                    let cqe = unsafe { &*cqes.add((seen & cq_mask) as usize) };
+                    results[cqe.user_data as usize] = Unlink::Done(match cqe.res {
+                        0.. => Ok(()),
+                        res => Err(io::Error::from_raw_os_error(-res)),
+                    });
+                    seen = seen.wrapping_add(1);
+                    pending -= 1;
+                }
+                cq_head.store(tail, Ordering::Release);
+            }
+        }
+        (results, Ok(()))
+    }
+}
+
+/// What became of one name passed to [`unlink_batch`]
+#[derive(Debug)]
+pub enum Unlink {
+    /// The kernel finished it
+    Done(io::Result<()>),
+    /// Submitted, but the ring failed before it was seen to finish: the
+    /// file may or may not be gone
+    Unknown,
+    /// Never submitted
+    NotTried,
+}
+
+/// io_uring turned out not to be usable here; don't keep trying
+static UNSUPPORTED: AtomicBool = AtomicBool::new(false);
+
+thread_local! {
+    static RING: RefCell<Option<Ring>> = const { RefCell::new(None) };
+}
+
+/// Whether this kernel can delete through io_uring
+pub fn is_supported() -> bool {
+    with_ring(|_| ()).is_some()
+}
+
+/// `unlinkat(dirfd, name, 0)` for every name in one pass through this
+/// thread's ring
+///
+/// `None` if io_uring isn't usable, in which case nothing was attempted.
+/// If the ring fails part way, the names it didn't finish are left to the
+/// caller and the thread gets a new ring next time.
+pub fn unlink_batch(dirfd: RawFd, names: &[&CStr]) -> Option<Vec<Unlink>> {
+    let (results, status) = with_ring(|ring| ring.unlink_all(dirfd, names))?;
+    if status.is_err() {
+        // Entries may still be queued in it, pointing at names about to be freed
+        RING.with(|cell| cell.borrow_mut().take());
+    }
+    Some(results)
+}
+
+fn with_ring<T>(f: impl FnOnce(&mut Ring) -> T) -> Option<T> {
+    if UNSUPPORTED.load(Ordering::Relaxed) {
+        return None;
+    }
+    RING.with(|cell| {
+        let mut ring = cell.borrow_mut();
+        if ring.is_none() {
+            match Ring::new() {
+                Ok(new) => *ring = Some(new),
+                Err(_) => {
+                    UNSUPPORTED.store(true, Ordering::Relaxed);
+                    return None;
+                }
+            }
+        }
+        ring.as_mut().map(f)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::ffi::CString;
+    use std::fs;
+
+    #[test]
+    fn test_layout_matches_kernel() {
+        assert_eq!(std::mem::size_of::<Sqe>(), 64);
+        assert_eq!(std::mem::size_of::<Cqe>(), 16);
+        assert_eq!(std::mem::size_of::<Params>(), 120);
+    }
+
+    #[test]
+    fn test_unlink_batch() {
+        if !is_supported() {
+            return;
+        }
+        let temp = std::env::temp_dir().join("rmbrr_uring_test");
+        let _ = fs::remove_dir_all(&temp);
+        fs::create_dir_all(&temp).unwrap();
+        // More than one submission queue's worth
+        let count = ENTRIES as usize + 10;
+        for i in 0..count {
+            fs::write(temp.join(format!("f{}", i)), "x").unwrap();
+        }
+
+        let dir = fs::File::open(&temp).unwrap();
+        let mut names: Vec<CString> = (0..count)
+            .map(|i| CString::new(format!("f{}", i)).unwrap())
+            .collect();
+        names.push(CString::new("missing").unwrap());
+        let refs: Vec<&CStr> = names.iter().map(|n| n.as_c_str()).collect();
+        let results = unlink_batch(dir.as_raw_fd(), &refs).unwrap();
+
+        assert!(results[..count]
+            .iter()
+            .all(|r| matches!(r, Unlink::Done(Ok(())))));
+        assert!(matches!(
+            &results[count],
+            Unlink::Done(Err(e)) if e.kind() == io::ErrorKind::NotFound
+        ));
+        assert_eq!(fs::read_dir(&temp).unwrap().count(), 0);
+
+        fs::remove_dir(&temp).unwrap();
+    }
+}
@@ -0,0 +1,153 @@
+//! Free space on the volume holding a path, and volume roots
+
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// Folders the system keeps at the root of a volume for itself, left in
+/// place when one is emptied (`--keep-root`); names are matched ignoring
+/// case, as Windows spells the Recycle Bin `$Recycle.Bin` or `$RECYCLE.BIN`
+pub const SYSTEM_FOLDERS: &[&str] = &["System Volume Information", "$RECYCLE.BIN", "lost+found"];
+
+/// The [`SYSTEM_FOLDERS`] in `root`, as they are actually spelled there
+pub fn system_folders(root: &Path) -> Vec<PathBuf> {
+    let Ok(entries) = std::fs::read_dir(root) else {
+        return Vec::new();
+    };
+    entries
+        .flatten()
+        .filter(|entry| {
+            let name = entry.file_name();
+            SYSTEM_FOLDERS.iter().any(|folder| {
+                name.to_str()
+                    .is_some_and(|name| name.eq_ignore_ascii_case(folder))
+            })
+        })
+        .map(|entry| entry.path())
+        .collect()
+}
+
+/// Whether `path` is the root of a volume: a drive root, `/`, or a
+/// directory another filesystem is mounted on
+pub fn is_root(path: &Path) -> bool {
+    let Ok(canonical) = path.canonicalize() else {
+        return false;
+    };
+    match canonical.parent() {
+        Some(parent) => is_mounted_on(&canonical, parent),
+        None => true,
+    }
+}
+
+#[cfg(unix)]
+fn is_mounted_on(dir: &Path, parent: &Path) -> bool {
+    use std::os::unix::fs::MetadataExt;
+
+    match (std::fs::metadata(dir), std::fs::metadata(parent)) {
+        (Ok(dir), Ok(parent)) => dir.dev() != parent.dev(),
+        _ => false,
+    }
+}
+
+#[cfg(not(unix))]
+fn is_mounted_on(_dir: &Path, _parent: &Path) -> bool {
+    false
+}
+
+/// Bytes available to this user on the volume holding `path`
+///
+/// `path` need not exist any more (a target that was just deleted); its
+/// nearest existing ancestor is asked instead.
+pub fn available(path: &Path) -> io::Result<u64> {
+    let existing = path
+        .ancestors()
+        .find(|p| !p.as_os_str().is_empty() && p.exists())
+        .unwrap_or(Path::new("."));
+    available_at(existing)
+}
+
+#[cfg(unix)]
+fn available_at(path: &Path) -> io::Result<u64> {
+    use std::ffi::CString;
+    use std::os::unix::ffi::OsStrExt;
+
+    let c_path = CString::new(path.as_os_str().as_bytes())
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?;
+    let mut stat: libc::statvfs = unsafe { std::mem::zeroed() };
+    if unsafe { libc::statvfs(c_path.as_ptr(), &mut stat) } != 0 {
+        return Err(io::Error::last_os_error());
+    }
+    // f_bavail excludes blocks reserved for root
+    Ok(stat.f_bavail as u64 * stat.f_frsize as u64)
+}
+
+#[cfg(windows)]
+fn available_at(path: &Path) -> io::Result<u64> {
+    use std::os::windows::ffi::OsStrExt;
+    use windows::core::PCWSTR;
+    use windows::Win32::Storage::FileSystem::GetDiskFreeSpaceExW;
+
+    let wide: Vec<u16> = path
+        .as_os_str()
+        .encode_wide()
+        .chain(std::iter::once(0))
+        .collect();
+    let mut available = 0u64;
+    unsafe {
+        GetDiskFreeSpaceExW(PCWSTR(wide.as_ptr()), Some(&mut available), None, None)?;
+    }
+    Ok(available)
+}
+
+#[cfg(not(any(unix, windows)))]
+fn available_at(_path: &Path) -> io::Result<u64> {
+    Err(io::Error::new(
+        io::ErrorKind::Unsupported,
+        "free space is not available on this platform",
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_available_for_missing_path() {
+        let temp = std::env::temp_dir();
+        let missing = temp.join("rmbrr_volume_test/not/there");
+        assert!(available(&temp).unwrap() > 0);
+        assert!(available(&missing).unwrap() > 0);
+    }
+
+    #[test]
+    fn test_is_root() {
+        #[cfg(unix)]
+        assert!(is_root(Path::new("/")));
+        #[cfg(windows)]
+        assert!(is_root(Path::new("C:\\")));
+        let temp = std::env::temp_dir().join("rmbrr_volume_root_test");
+        std::fs::create_dir_all(&temp).unwrap();
+        assert!(!is_root(&temp));
+        assert!(!is_root(&temp.join("missing")));
+        std::fs::remove_dir_all(&temp).ok();
+    }
+
+    #[test]
+    fn test_system_folders_ignore_case() {
+        let temp = std::env::temp_dir().join("rmbrr_volume_folders_test");
+        let _ = std::fs::remove_dir_all(&temp);
+        for name in ["$Recycle.Bin", "System Volume Information", "data"] {
+            std::fs::create_dir_all(temp.join(name)).unwrap();
+        }
+        let mut found = system_folders(&temp);
+        found.sort();
+        assert_eq!(
+            found,
+            [
+                temp.join("$Recycle.Bin"),
+                temp.join("System Volume Information")
+            ]
+        );
+        assert!(system_folders(&temp.join("missing")).is_empty());
+        std::fs::remove_dir_all(&temp).ok();
+    }
+}
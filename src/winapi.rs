@@ -2,35 +2,108 @@
 
 use std::io;
 use std::path::Path;
+use std::str::FromStr;
+use std::sync::atomic::{AtomicBool, AtomicU8, AtomicUsize, Ordering};
 
 #[cfg(windows)]
 use windows::core::PCWSTR;
 #[cfg(windows)]
+use windows::Wdk::Foundation::OBJECT_ATTRIBUTES;
+#[cfg(windows)]
 use windows::Wdk::Storage::FileSystem::{
-    FILE_DISPOSITION_DELETE, FILE_DISPOSITION_IGNORE_READONLY_ATTRIBUTE,
+    FileDispositionInformationEx, FileFullDirectoryInformation, NtOpenFile, NtQueryDirectoryFileEx,
+    NtSetInformationFile, FILE_DISPOSITION_DELETE, FILE_DISPOSITION_IGNORE_READONLY_ATTRIBUTE,
     FILE_DISPOSITION_INFORMATION_EX, FILE_DISPOSITION_INFORMATION_EX_FLAGS,
-    FILE_DISPOSITION_POSIX_SEMANTICS,
+    FILE_DISPOSITION_POSIX_SEMANTICS, FILE_FULL_DIR_INFORMATION, FILE_OPEN_REPARSE_POINT,
 };
 #[cfg(windows)]
-use windows::Win32::Foundation::{CloseHandle, HANDLE};
+use windows::Win32::Foundation::{
+    CloseHandle, RtlNtStatusToDosError, HANDLE, NTSTATUS, STATUS_NO_MORE_FILES, UNICODE_STRING,
+};
 #[cfg(windows)]
 use windows::Win32::Storage::FileSystem::{
     CreateFileW, FileDispositionInfoEx, FindClose, FindFirstFileExW, FindNextFileW,
-    SetFileInformationByHandle, DELETE, FILE_ATTRIBUTE_DIRECTORY, FILE_FLAG_BACKUP_SEMANTICS,
-    FILE_FLAG_OPEN_REPARSE_POINT, FILE_SHARE_DELETE, FILE_SHARE_READ, FILE_SHARE_WRITE,
-    FINDEX_INFO_LEVELS, FINDEX_SEARCH_OPS, FIND_FIRST_EX_FLAGS, OPEN_EXISTING, WIN32_FIND_DATAW,
+    GetFileAttributesW, GetFileInformationByHandle, SetFileAttributesW, SetFileInformationByHandle,
+    BY_HANDLE_FILE_INFORMATION, DELETE, FILE_ATTRIBUTE_DIRECTORY, FILE_ATTRIBUTE_HIDDEN,
+    FILE_ATTRIBUTE_NORMAL, FILE_ATTRIBUTE_READONLY, FILE_ATTRIBUTE_SYSTEM,
+    FILE_FLAGS_AND_ATTRIBUTES, FILE_FLAG_BACKUP_SEMANTICS, FILE_FLAG_OPEN_REPARSE_POINT,
+    FILE_LIST_DIRECTORY, FILE_SHARE_DELETE, FILE_SHARE_READ, FILE_SHARE_WRITE, FILE_TRAVERSE,
+    FINDEX_INFO_LEVELS, FINDEX_SEARCH_OPS, FIND_FIRST_EX_FLAGS, INVALID_FILE_ATTRIBUTES,
+    OPEN_EXISTING, WIN32_FIND_DATAW,
 };
+#[cfg(windows)]
+use windows::Win32::System::IO::IO_STATUS_BLOCK;
 
+/// NUL-terminated UTF-16 path, in extended-length `\\?\` form if absolute
+///
+/// Relative paths (and ones with `.` or `..` components, which `\\?\`
+/// would take literally) are passed as they are while they fit in
+/// MAX_PATH; longer ones are made absolute first, so deep trees don't
+/// depend on the LongPathsEnabled registry setting. Built from the raw
+/// UTF-16 units, so names that aren't valid Unicode (unpaired surrogates)
+/// reach the API unchanged.
 #[cfg(windows)]
-fn path_to_wide(path: &Path) -> Vec<u16> {
-    let path_str = path.to_string_lossy();
-    let prefixed = if path.is_absolute() && !path_str.starts_with(r"\\?\") {
-        format!(r"\\?\{}", path.display())
+pub(crate) fn path_to_wide(path: &Path) -> Vec<u16> {
+    use std::os::windows::ffi::OsStrExt;
+    use std::path::Component;
+
+    const MAX_PATH: usize = 260;
+
+    let wide: Vec<u16> = path.as_os_str().encode_wide().collect();
+    let needs_full = !path.is_absolute()
+        || path
+            .components()
+            .any(|c| matches!(c, Component::CurDir | Component::ParentDir));
+    let mut result = if !needs_full {
+        extended_length(&wide)
+    } else if wide.len() < MAX_PATH {
+        wide
     } else {
-        path_str.to_string()
+        match std::path::absolute(path) {
+            Ok(full) => extended_length(&full.as_os_str().encode_wide().collect::<Vec<_>>()),
+            Err(_) => wide,
+        }
+    };
+    result.push(0);
+    result
+}
+
+/// An absolute path in `\\?\` form: `C:\x` becomes `\\?\C:\x` and
+/// `\\server\share` becomes `\\?\UNC\server\share`, with `/` turned into `\`
+#[cfg(any(windows, test))]
+fn extended_length(wide: &[u16]) -> Vec<u16> {
+    const SLASH: u16 = b'/' as u16;
+    const BACKSLASH: u16 = b'\\' as u16;
+
+    let units = |s: &str| s.encode_utf16().collect::<Vec<u16>>();
+    if wide.starts_with(&units(r"\\?\")) || wide.starts_with(&units(r"\\.\")) {
+        return wide.to_vec();
+    }
+    let normalized: Vec<u16> = wide
+        .iter()
+        .map(|&u| if u == SLASH { BACKSLASH } else { u })
+        .collect();
+    let (prefix, rest) = match normalized.strip_prefix(&[BACKSLASH, BACKSLASH][..]) {
+        Some(unc) => (units(r"\\?\UNC\"), unc),
+        None => (units(r"\\?\"), &normalized[..]),
     };
+    let mut result = Vec::with_capacity(prefix.len() + rest.len() + 1);
+    result.extend_from_slice(&prefix);
+    result.extend_from_slice(rest);
+    result
+}
 
-    prefixed.encode_utf16().chain(std::iter::once(0)).collect()
+/// A directory entry name from UTF-16 units, without lossy conversion;
+/// `None` for `.` and `..`
+#[cfg(windows)]
+fn entry_name(name: &[u16]) -> Option<std::ffi::OsString> {
+    use std::os::windows::ffi::OsStringExt;
+
+    const DOT: u16 = b'.' as u16;
+    match name {
+        [DOT] | [DOT, DOT] => None,
+        _ => Some(std::ffi::OsString::from_wide(name)),
+    }
 }
 
 /// Delete file using POSIX semantics (immediate namespace removal)
@@ -38,7 +111,7 @@ fn path_to_wide(path: &Path) -> Vec<u16> {
 #[cfg(windows)]
 pub fn delete_file(path: &Path) -> io::Result<()> {
     let wide_path = path_to_wide(path);
-    unsafe { posix_delete_file(&wide_path) }
+    retry_denied(path, &wide_path, |wide| unsafe { posix_delete_file(wide) })
 }
 
 /// Delete directory using POSIX semantics (immediate namespace removal)
@@ -46,7 +119,85 @@ pub fn delete_file(path: &Path) -> io::Result<()> {
 #[cfg(windows)]
 pub fn remove_dir(path: &Path) -> io::Result<()> {
     let wide_path = path_to_wide(path);
-    unsafe { posix_delete_dir(&wide_path) }
+    retry_denied(path, &wide_path, |wide| unsafe { posix_delete_dir(wide) })
+}
+
+static CLEAR_ATTRIBUTES: AtomicBool = AtomicBool::new(true);
+
+/// Whether entries denied deletion have their read-only, hidden and system
+/// attributes cleared before one more try (Windows; on by default)
+pub fn set_clear_attributes(clear: bool) {
+    CLEAR_ATTRIBUTES.store(clear, Ordering::Relaxed);
+}
+
+/// Run `delete`, and while access is denied, run it again after each way
+/// around that is on: clearing the read-only, hidden and system
+/// attributes, then taking the entry over ([`crate::ownership`])
+///
+/// Files are deleted ignoring read-only already, but not everywhere:
+/// directories, and filesystems other than NTFS (FAT, network shares),
+/// refuse read-only entries.
+///
+/// An entry still there at the end gets its attributes, then its owner
+/// and DACL, back.
+#[cfg(windows)]
+fn retry_denied(
+    path: &Path,
+    wide_path: &[u16],
+    delete: impl Fn(&[u16]) -> io::Result<()>,
+) -> io::Result<()> {
+    let mut result = delete(wide_path);
+    let mut attributes = None;
+    if is_denied(&result) && CLEAR_ATTRIBUTES.load(Ordering::Relaxed) {
+        attributes = unsafe { clear_attributes(wide_path) };
+        if attributes.is_some() {
+            result = delete(wide_path);
+        }
+    }
+    let mut original = None;
+    if is_denied(&result) && crate::ownership::is_enabled() {
+        original = crate::ownership::take(path).ok();
+        if original.is_some() {
+            result = delete(wide_path);
+        }
+    }
+    if result.is_err() {
+        if let Some(attributes) = attributes {
+            unsafe { SetFileAttributesW(PCWSTR(wide_path.as_ptr()), attributes).ok() };
+        }
+        if let Some(original) = original {
+            original.restore().ok();
+        }
+    }
+    result
+}
+
+/// Failed with ERROR_ACCESS_DENIED, which [`retry_denied`] may get around
+#[cfg(windows)]
+fn is_denied(result: &io::Result<()>) -> bool {
+    matches!(result, Err(e) if e.raw_os_error() == Some(5))
+        && (CLEAR_ATTRIBUTES.load(Ordering::Relaxed) || crate::ownership::is_enabled())
+}
+
+/// Clear the read-only, hidden and system attributes, returning the ones
+/// it had; `None` if none was set or they couldn't be cleared
+#[cfg(windows)]
+unsafe fn clear_attributes(wide_path: &[u16]) -> Option<FILE_FLAGS_AND_ATTRIBUTES> {
+    const CLEARED: u32 =
+        FILE_ATTRIBUTE_READONLY.0 | FILE_ATTRIBUTE_HIDDEN.0 | FILE_ATTRIBUTE_SYSTEM.0;
+
+    let attributes = GetFileAttributesW(PCWSTR(wide_path.as_ptr()));
+    if attributes == INVALID_FILE_ATTRIBUTES || attributes & CLEARED == 0 {
+        return None;
+    }
+    // No attributes at all has to be spelled FILE_ATTRIBUTE_NORMAL
+    let remaining = match attributes & !CLEARED {
+        0 => FILE_ATTRIBUTE_NORMAL,
+        rest => FILE_FLAGS_AND_ATTRIBUTES(rest),
+    };
+    SetFileAttributesW(PCWSTR(wide_path.as_ptr()), remaining)
+        .ok()
+        .map(|()| FILE_FLAGS_AND_ATTRIBUTES(attributes))
 }
 
 #[cfg(windows)]
@@ -57,7 +208,8 @@ unsafe fn posix_delete_file(wide_path: &[u16]) -> io::Result<()> {
         FILE_SHARE_READ | FILE_SHARE_WRITE | FILE_SHARE_DELETE,
         None,
         OPEN_EXISTING,
-        FILE_FLAG_OPEN_REPARSE_POINT,
+        // Backup semantics so directory symlinks and junctions open too
+        FILE_FLAG_BACKUP_SEMANTICS | FILE_FLAG_OPEN_REPARSE_POINT,
         HANDLE::default(),
     )
     .map_err(|e| io::Error::from_raw_os_error(e.code().0 & 0xFFFF))?;
@@ -77,12 +229,14 @@ unsafe fn posix_delete_file(wide_path: &[u16]) -> io::Result<()> {
         std::mem::size_of::<FILE_DISPOSITION_INFORMATION_EX>() as u32,
     );
 
-    CloseHandle(handle).ok();
-
-    result.map_err(|e| {
-        let code = (e.code().0 & 0xFFFF) as u32;
-        io::Error::from_raw_os_error(code as i32)
-    })
+    match result {
+        Ok(()) => close_deleted(handle),
+        Err(e) => {
+            CloseHandle(handle).ok();
+            let code = (e.code().0 & 0xFFFF) as u32;
+            Err(io::Error::from_raw_os_error(code as i32))
+        }
+    }
 }
 
 #[cfg(windows)]
@@ -111,30 +265,618 @@ unsafe fn posix_delete_dir(wide_path: &[u16]) -> io::Result<()> {
         std::mem::size_of::<FILE_DISPOSITION_INFORMATION_EX>() as u32,
     );
 
-    CloseHandle(handle).ok();
+    match result {
+        Ok(()) => close_deleted(handle),
+        Err(e) => {
+            CloseHandle(handle).ok();
+            let code = (e.code().0 & 0xFFFF) as u32;
+            Err(io::Error::from_raw_os_error(code as i32))
+        }
+    }
+}
+
+/// Background threads closing the handles of deleted entries
+#[cfg(windows)]
+struct Closer {
+    tx: crossbeam_channel::Sender<usize>,
+    /// Handles handed over and not yet closed
+    pending: std::sync::Mutex<usize>,
+    drained: std::sync::Condvar,
+}
 
-    result.map_err(|e| {
-        let code = (e.code().0 & 0xFFFF) as u32;
-        io::Error::from_raw_os_error(code as i32)
-    })
+#[cfg(windows)]
+static CLOSER: std::sync::OnceLock<Closer> = std::sync::OnceLock::new();
+
+/// Close the handle of an entry whose POSIX delete disposition is set
+///
+/// With POSIX semantics the name is gone as soon as the disposition is
+/// set, so the parent can be removed before this handle is closed. Closing
+/// is where filter drivers (antivirus) do their work, so with close
+/// threads configured it's handed off rather than done inline.
+#[cfg(windows)]
+unsafe fn close_deleted(handle: HANDLE) -> io::Result<()> {
+    let Some(closer) = CLOSER.get() else {
+        CloseHandle(handle).ok();
+        return Ok(());
+    };
+    *closer.pending.lock().unwrap() += 1;
+    if let Err(e) = closer.tx.send(handle.0 as usize) {
+        CloseHandle(HANDLE(e.into_inner() as *mut _)).ok();
+        *closer.pending.lock().unwrap() -= 1;
+    }
+    Ok(())
+}
+
+/// Close handles of deleted entries on `threads` background threads
+/// instead of in the workers (Windows only; 0, the default, closes inline)
+///
+/// Can be set once per process; later calls have no effect.
+#[cfg(windows)]
+pub fn set_close_threads(threads: usize) {
+    if threads == 0 {
+        return;
+    }
+    CLOSER.get_or_init(|| {
+        let (tx, rx) = crossbeam_channel::unbounded::<usize>();
+        for i in 0..threads {
+            let rx = rx.clone();
+            std::thread::Builder::new()
+                .name(format!("closer-{}", i))
+                .spawn(move || {
+                    for handle in rx {
+                        unsafe { CloseHandle(HANDLE(handle as *mut _)).ok() };
+                        let closer = CLOSER.get().expect("closer threads run after init");
+                        let mut pending = closer.pending.lock().unwrap();
+                        *pending -= 1;
+                        if *pending == 0 {
+                            closer.drained.notify_all();
+                        }
+                    }
+                })
+                .expect("Failed to spawn closer thread");
+        }
+        Closer {
+            tx,
+            pending: std::sync::Mutex::new(0),
+            drained: std::sync::Condvar::new(),
+        }
+    });
+}
+
+/// Wait until every handle handed to the close threads is closed
+#[cfg(windows)]
+pub fn flush_closes() {
+    if let Some(closer) = CLOSER.get() {
+        let pending = closer.pending.lock().unwrap();
+        drop(
+            closer
+                .drained
+                .wait_while(pending, |pending| *pending > 0)
+                .unwrap(),
+        );
+    }
 }
 
+#[cfg(not(windows))]
+pub fn set_close_threads(_threads: usize) {}
+
+#[cfg(not(windows))]
+pub fn flush_closes() {}
+
 // Unix implementations - just use standard library
 #[cfg(not(windows))]
 pub fn delete_file(path: &Path) -> io::Result<()> {
     std::fs::remove_file(path)
 }
 
+/// Also removes a symlink to a directory, as scanned with
+/// [`crate::tree::ScanOptions::follow_links`] (Windows removes directory
+/// links in place of the directory already)
 #[cfg(not(windows))]
 pub fn remove_dir(path: &Path) -> io::Result<()> {
-    std::fs::remove_dir(path)
+    std::fs::remove_dir(path).or_else(|e| match std::fs::symlink_metadata(path) {
+        Ok(metadata) if metadata.file_type().is_symlink() => std::fs::remove_file(path),
+        _ => Err(e),
+    })
 }
 
-/// Enumerate files in a directory using direct Windows API
+/// What identifies a file or directory while it exists: its volume and its
+/// number there (`st_dev` and `st_ino`; the volume serial number and file
+/// index on Windows)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FileIdentity {
+    pub volume: u64,
+    pub file: u64,
+}
+
+/// Identity of `path` itself, not of what a link there leads to
 #[cfg(windows)]
+pub fn file_identity(path: &Path) -> io::Result<FileIdentity> {
+    let wide_path = path_to_wide(path);
+    unsafe {
+        let handle = CreateFileW(
+            PCWSTR(wide_path.as_ptr()),
+            0,
+            FILE_SHARE_READ | FILE_SHARE_WRITE | FILE_SHARE_DELETE,
+            None,
+            OPEN_EXISTING,
+            FILE_FLAG_BACKUP_SEMANTICS | FILE_FLAG_OPEN_REPARSE_POINT,
+            HANDLE::default(),
+        )
+        .map_err(|e| io::Error::from_raw_os_error(e.code().0 & 0xFFFF))?;
+        let mut info = BY_HANDLE_FILE_INFORMATION::default();
+        let result = GetFileInformationByHandle(handle, &mut info);
+        CloseHandle(handle).ok();
+        result.map_err(|e| io::Error::from_raw_os_error(e.code().0 & 0xFFFF))?;
+        Ok(FileIdentity {
+            volume: u64::from(info.dwVolumeSerialNumber),
+            file: u64::from(info.nFileIndexHigh) << 32 | u64::from(info.nFileIndexLow),
+        })
+    }
+}
+
+#[cfg(unix)]
+pub fn file_identity(path: &Path) -> io::Result<FileIdentity> {
+    use std::os::unix::fs::MetadataExt;
+
+    let metadata = std::fs::symlink_metadata(path)?;
+    Ok(FileIdentity {
+        volume: metadata.dev(),
+        file: metadata.ino(),
+    })
+}
+
+/// How files are deleted
+///
+/// Backends for another platform than the current one act like
+/// [`DeleteBackend::Sync`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum DeleteBackend {
+    /// One call per file on its full path (`CreateFileW` and
+    /// `SetFileInformationByHandle` on Windows, `unlink` elsewhere)
+    #[default]
+    Sync,
+    /// Windows: `NtOpenFile` by name relative to one handle on the
+    /// directory, then `NtSetInformationFile`
+    Nt,
+    /// Linux 5.11+: the files of a directory submitted to io_uring as one
+    /// batch of `unlinkat` calls
+    Uring,
+}
+
+impl FromStr for DeleteBackend {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            // `win32` was the name before there were other platforms' backends
+            "sync" | "win32" => Ok(DeleteBackend::Sync),
+            "nt" => Ok(DeleteBackend::Nt),
+            "uring" => Ok(DeleteBackend::Uring),
+            other => Err(format!(
+                "unknown deletion backend '{}' (expected sync, nt or uring)",
+                other
+            )),
+        }
+    }
+}
+
+static DELETE_BACKEND: AtomicU8 = AtomicU8::new(DeleteBackend::Sync as u8);
+
+/// Select the backend [`DirDeleter`] uses for the whole process
+pub fn set_delete_backend(backend: DeleteBackend) {
+    DELETE_BACKEND.store(backend as u8, Ordering::Relaxed);
+}
+
+pub fn delete_backend() -> DeleteBackend {
+    match DELETE_BACKEND.load(Ordering::Relaxed) {
+        1 => DeleteBackend::Nt,
+        2 => DeleteBackend::Uring,
+        _ => DeleteBackend::Sync,
+    }
+}
+
+/// Deletes the files directly inside one directory
+///
+/// With [`DeleteBackend::Nt`] the directory is opened once and each file
+/// is opened by name relative to that handle, so its path isn't resolved
+/// from the volume root every time. With [`DeleteBackend::Uring`] files
+/// are deleted in batches (see [`DirDeleter::batches`]). Either falls back
+/// to [`delete_file`] when the directory can't be opened, and for the rest
+/// of the process when the system doesn't support it (some network
+/// filesystems; kernels before 5.11 or with io_uring disabled).
+pub struct DirDeleter {
+    #[cfg(windows)]
+    handle: Option<HANDLE>,
+    #[cfg(target_os = "linux")]
+    dir: Option<std::fs::File>,
+}
+
+impl DirDeleter {
+    pub fn open(dir: &Path) -> Self {
+        #[cfg(windows)]
+        {
+            let handle = (delete_backend() == DeleteBackend::Nt)
+                .then(|| unsafe { open_dir_handle(dir) })
+                .and_then(Result::ok);
+            Self { handle }
+        }
+        #[cfg(target_os = "linux")]
+        {
+            let dir = (delete_backend() == DeleteBackend::Uring)
+                .then(|| std::fs::File::open(dir))
+                .and_then(Result::ok);
+            Self { dir }
+        }
+        #[cfg(not(any(windows, target_os = "linux")))]
+        {
+            let _ = dir;
+            Self {}
+        }
+    }
+
+    /// Files should be collected and passed to [`DirDeleter::delete_batch`]
+    /// rather than deleted one at a time
+    pub fn batches(&self) -> bool {
+        #[cfg(target_os = "linux")]
+        return self.dir.is_some();
+        #[cfg(not(target_os = "linux"))]
+        false
+    }
+
+    /// Delete `path`, a file directly inside this directory
+    pub fn delete_file(&self, path: &Path) -> io::Result<()> {
+        #[cfg(windows)]
+        if let (Some(handle), Some(name)) = (self.handle, path.file_name()) {
+            if delete_backend() == DeleteBackend::Nt {
+                match unsafe { nt_delete_file(handle, name) } {
+                    Err(e) if is_unsupported(&e) => set_delete_backend(DeleteBackend::Sync),
+                    // By full path, which gets around access denied if it can
+                    result if is_denied(&result) => {}
+                    result => return result,
+                }
+            }
+        }
+        delete_file(path)
+    }
+
+    /// Delete `paths`, files directly inside this directory, with one
+    /// result per path
+    pub fn delete_batch(&self, paths: &[&Path]) -> Vec<io::Result<()>> {
+        #[cfg(target_os = "linux")]
+        if let Some(results) = self.uring_batch(paths) {
+            return results;
+        }
+        paths.iter().map(|path| self.delete_file(path)).collect()
+    }
+
+    #[cfg(target_os = "linux")]
+    fn uring_batch(&self, paths: &[&Path]) -> Option<Vec<io::Result<()>>> {
+        use crate::uring::Unlink;
+        use std::ffi::{CStr, CString};
+        use std::os::fd::AsRawFd;
+        use std::os::unix::ffi::OsStrExt;
+
+        let dir = self.dir.as_ref()?;
+        let names: Vec<CString> = paths
+            .iter()
+            .map(|path| CString::new(path.file_name()?.as_bytes()).ok())
+            .collect::<Option<_>>()?;
+        let names: Vec<&CStr> = names.iter().map(CString::as_c_str).collect();
+        let Some(results) = crate::uring::unlink_batch(dir.as_raw_fd(), &names) else {
+            set_delete_backend(DeleteBackend::Sync);
+            return None;
+        };
+        let results = results
+            .into_iter()
+            .zip(paths)
+            .map(|(unlink, path)| match unlink {
+                Unlink::Done(result) => result,
+                Unlink::NotTried => self.delete_file(path),
+                // Gone already means the ring got to it before failing
+                Unlink::Unknown => match self.delete_file(path) {
+                    Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(()),
+                    result => result,
+                },
+            });
+        Some(results.collect())
+    }
+}
+
+#[cfg(windows)]
+impl Drop for DirDeleter {
+    fn drop(&mut self) {
+        if let Some(handle) = self.handle {
+            unsafe { CloseHandle(handle).ok() };
+        }
+    }
+}
+
+/// Handle on a directory to open its entries relative to
+#[cfg(windows)]
+unsafe fn open_dir_handle(dir: &Path) -> io::Result<HANDLE> {
+    let wide_path = path_to_wide(dir);
+    CreateFileW(
+        PCWSTR(wide_path.as_ptr()),
+        FILE_TRAVERSE.0,
+        FILE_SHARE_READ | FILE_SHARE_WRITE | FILE_SHARE_DELETE,
+        None,
+        OPEN_EXISTING,
+        FILE_FLAG_BACKUP_SEMANTICS | FILE_FLAG_OPEN_REPARSE_POINT,
+        HANDLE::default(),
+    )
+    .map_err(|e| io::Error::from_raw_os_error(e.code().0 & 0xFFFF))
+}
+
+/// POSIX delete of the file `name` inside the directory `root`
+#[cfg(windows)]
+unsafe fn nt_delete_file(root: HANDLE, name: &std::ffi::OsStr) -> io::Result<()> {
+    use std::os::windows::ffi::OsStrExt;
+
+    let mut wide: Vec<u16> = name.encode_wide().collect();
+    let length = u16::try_from(wide.len() * 2)
+        .map_err(|_| io::Error::from_raw_os_error(206 /* ERROR_FILENAME_EXCED_RANGE */))?;
+    let object_name = UNICODE_STRING {
+        Length: length,
+        MaximumLength: length,
+        Buffer: windows::core::PWSTR(wide.as_mut_ptr()),
+    };
+    // Case-sensitive: the name is exactly as enumerated, and directories
+    // can be case-sensitive (WSL)
+    let attributes = OBJECT_ATTRIBUTES {
+        Length: std::mem::size_of::<OBJECT_ATTRIBUTES>() as u32,
+        RootDirectory: root,
+        ObjectName: &object_name,
+        Attributes: 0,
+        SecurityDescriptor: std::ptr::null(),
+        SecurityQualityOfService: std::ptr::null(),
+    };
+    let mut handle = HANDLE::default();
+    let mut io_status = IO_STATUS_BLOCK::default();
+    nt_result(NtOpenFile(
+        &mut handle,
+        DELETE.0,
+        &attributes,
+        &mut io_status,
+        (FILE_SHARE_READ | FILE_SHARE_WRITE | FILE_SHARE_DELETE).0,
+        // Not FILE_NON_DIRECTORY_FILE: directory symlinks and junctions are
+        // deleted here too
+        FILE_OPEN_REPARSE_POINT.0,
+    ))?;
+
+    let info = FILE_DISPOSITION_INFORMATION_EX {
+        Flags: FILE_DISPOSITION_INFORMATION_EX_FLAGS(
+            FILE_DISPOSITION_DELETE.0
+                | FILE_DISPOSITION_POSIX_SEMANTICS.0
+                | FILE_DISPOSITION_IGNORE_READONLY_ATTRIBUTE.0,
+        ),
+    };
+    let status = NtSetInformationFile(
+        handle,
+        &mut io_status,
+        &info as *const _ as *const _,
+        std::mem::size_of::<FILE_DISPOSITION_INFORMATION_EX>() as u32,
+        FileDispositionInformationEx,
+    );
+    match nt_result(status) {
+        Ok(()) => close_deleted(handle),
+        Err(e) => {
+            CloseHandle(handle).ok();
+            Err(e)
+        }
+    }
+}
+
+#[cfg(windows)]
+fn nt_result(status: NTSTATUS) -> io::Result<()> {
+    if status.is_err() {
+        Err(io::Error::from_raw_os_error(
+            unsafe { RtlNtStatusToDosError(status) } as i32,
+        ))
+    } else {
+        Ok(())
+    }
+}
+
+/// The filesystem doesn't implement the NT calls or information class
+#[cfg(windows)]
+fn is_unsupported(error: &io::Error) -> bool {
+    // ERROR_INVALID_FUNCTION, ERROR_NOT_SUPPORTED, ERROR_INVALID_PARAMETER
+    matches!(error.raw_os_error(), Some(1 | 50 | 87))
+}
+
+/// How directories are listed on Windows (Linux uses `getdents64`, other
+/// platforms `read_dir`)
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum EnumBackend {
+    /// `FindFirstFileExW` / `FindNextFileW`
+    #[default]
+    FindFirstFile,
+    /// `NtQueryDirectoryFileEx` with a large `FILE_FULL_DIR_INFORMATION` buffer
+    NtQueryDirectory,
+}
+
+impl FromStr for EnumBackend {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "find-first-file" => Ok(EnumBackend::FindFirstFile),
+            "nt-query" => Ok(EnumBackend::NtQueryDirectory),
+            other => Err(format!(
+                "unknown enumeration backend '{}' (expected find-first-file or nt-query)",
+                other
+            )),
+        }
+    }
+}
+
+static ENUM_BACKEND: AtomicU8 = AtomicU8::new(EnumBackend::FindFirstFile as u8);
+
+/// Select the enumeration backend used by [`enumerate_files`] for the whole process
+pub fn set_enum_backend(backend: EnumBackend) {
+    ENUM_BACKEND.store(backend as u8, Ordering::Relaxed);
+}
+
+pub fn enum_backend() -> EnumBackend {
+    match ENUM_BACKEND.load(Ordering::Relaxed) {
+        1 => EnumBackend::NtQueryDirectory,
+        _ => EnumBackend::FindFirstFile,
+    }
+}
+
+/// Directories the scan found holding at least this many entries are
+/// listed with [`LARGE_ENUM_BUFFER`]
+pub const LARGE_DIR_ENTRIES: usize = 4096;
+
+/// Enumeration buffer for other directories
+pub const DEFAULT_ENUM_BUFFER: usize = 64 * 1024;
+
+/// Enumeration buffer for directories with [`LARGE_DIR_ENTRIES`] or more
+///
+/// Fewer calls matter most where each is a round trip (network shares);
+/// locally, beyond this the time to allocate and fault in the buffer for
+/// every directory cancels out the saving (`examples/enum_buffer.rs`).
+pub const LARGE_ENUM_BUFFER: usize = 256 * 1024;
+
+/// Bounds for `--enum-buffer`: below a few records per call, and above
+/// what any directory fills, a different size can't help
+const ENUM_BUFFER_RANGE: std::ops::RangeInclusive<usize> = 4 * 1024..=64 * 1024 * 1024;
+
+/// 0 picks a size per directory
+static ENUM_BUFFER: AtomicUsize = AtomicUsize::new(0);
+
+/// Use `size` bytes for every enumeration buffer instead of choosing one per
+/// directory (`None` restores the default)
+pub fn set_enum_buffer(size: Option<usize>) {
+    let size = size.map_or(0, |size| {
+        size.clamp(*ENUM_BUFFER_RANGE.start(), *ENUM_BUFFER_RANGE.end())
+    });
+    ENUM_BUFFER.store(size, Ordering::Relaxed);
+}
+
+/// Buffer size for listing a directory expected to hold `entries` entries
+/// (0 if unknown)
+///
+/// Sizes the `NtQueryDirectoryFileEx` and `getdents64` buffers; with
+/// `FindFirstFileExW`, which has no size to give, a large buffer asks for
+/// `FIND_FIRST_EX_LARGE_FETCH`. `read_dir` on other platforms ignores it.
+pub fn enum_buffer_size(entries: usize) -> usize {
+    match ENUM_BUFFER.load(Ordering::Relaxed) {
+        0 if entries >= LARGE_DIR_ENTRIES => LARGE_ENUM_BUFFER,
+        0 => DEFAULT_ENUM_BUFFER,
+        size => size,
+    }
+}
+
+/// NTFS attributes of an enumerated entry that rmbrr reports on, and what
+/// else the listing tells about it for free
+///
+/// Attributes and size are always unset off Windows.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct EntryAttributes {
+    /// NTFS-compressed
+    pub compressed: bool,
+    /// EFS-encrypted
+    pub encrypted: bool,
+    /// File size in bytes
+    pub size: Option<u64>,
+    /// A symlink or junction; listed as a non-directory, so it is unlinked
+    /// rather than traversed
+    pub link: bool,
+    /// Roughly where the entry was allocated on disk, for
+    /// [`crate::worker::DeleteOrder::Locality`]: its creation time on
+    /// Windows, its inode number elsewhere
+    pub locality: Option<u64>,
+}
+
+impl EntryAttributes {
+    /// From `FILE_ATTRIBUTE_*` flags
+    ///
+    /// Without its reparse tag, any reparse point counts as a link.
+    pub fn from_raw(attributes: u32) -> Self {
+        const FILE_ATTRIBUTE_COMPRESSED: u32 = 0x800;
+        const FILE_ATTRIBUTE_ENCRYPTED: u32 = 0x4000;
+        const FILE_ATTRIBUTE_REPARSE_POINT: u32 = 0x400;
+        Self {
+            compressed: attributes & FILE_ATTRIBUTE_COMPRESSED != 0,
+            encrypted: attributes & FILE_ATTRIBUTE_ENCRYPTED != 0,
+            size: None,
+            link: attributes & FILE_ATTRIBUTE_REPARSE_POINT != 0,
+            locality: None,
+        }
+    }
+
+    /// From `FILE_ATTRIBUTE_*` flags and the reparse tag of a reparse point:
+    /// only name surrogates (symlinks, junctions, volume mount points) are
+    /// links, not e.g. cloud placeholders
+    pub fn from_raw_tagged(attributes: u32, reparse_tag: u32) -> Self {
+        const NAME_SURROGATE: u32 = 0x2000_0000;
+        let raw = Self::from_raw(attributes);
+        Self {
+            link: raw.link && reparse_tag & NAME_SURROGATE != 0,
+            ..raw
+        }
+    }
+
+    /// Nothing known but whether it's a link (off Windows)
+    #[cfg(not(windows))]
+    fn linked(link: bool) -> Self {
+        Self {
+            link,
+            ..Self::default()
+        }
+    }
+
+    pub fn with_size(self, size: u64) -> Self {
+        Self {
+            size: Some(size),
+            ..self
+        }
+    }
+
+    pub fn with_locality(self, locality: u64) -> Self {
+        Self {
+            locality: Some(locality),
+            ..self
+        }
+    }
+}
+
+/// Enumerate files in a directory
 pub fn enumerate_files<F>(dir: &Path, mut callback: F) -> io::Result<()>
 where
     F: FnMut(&Path, bool) -> io::Result<()>,
+{
+    enumerate_entries(dir, |path, is_dir, _| callback(path, is_dir))
+}
+
+/// Enumerate files in a directory, with their attributes
+pub fn enumerate_entries<F>(dir: &Path, callback: F) -> io::Result<()>
+where
+    F: FnMut(&Path, bool, EntryAttributes) -> io::Result<()>,
+{
+    enumerate_entries_sized(dir, enum_buffer_size(0), callback)
+}
+
+/// [`enumerate_entries`] with a `buffer_size` from [`enum_buffer_size`]
+#[cfg(windows)]
+pub fn enumerate_entries_sized<F>(dir: &Path, buffer_size: usize, callback: F) -> io::Result<()>
+where
+    F: FnMut(&Path, bool, EntryAttributes) -> io::Result<()>,
+{
+    match enum_backend() {
+        EnumBackend::FindFirstFile => {
+            enumerate_find_first_file(dir, buffer_size >= LARGE_ENUM_BUFFER, callback)
+        }
+        EnumBackend::NtQueryDirectory => enumerate_nt_query(dir, buffer_size, callback),
+    }
+}
+
+#[cfg(windows)]
+fn enumerate_find_first_file<F>(dir: &Path, large_fetch: bool, mut callback: F) -> io::Result<()>
+where
+    F: FnMut(&Path, bool, EntryAttributes) -> io::Result<()>,
 {
     let search_path = dir.join("*");
     let wide_path = path_to_wide(&search_path);
@@ -147,7 +889,8 @@ where
             &mut find_data as *mut _ as *mut _,
             FINDEX_SEARCH_OPS(0),
             None,
-            FIND_FIRST_EX_FLAGS(0),
+            // FIND_FIRST_EX_LARGE_FETCH
+            FIND_FIRST_EX_FLAGS(if large_fetch { 2 } else { 0 }),
         ) {
             Ok(h) => h,
             Err(_) => return Err(io::Error::last_os_error()),
@@ -159,12 +902,25 @@ where
                 .iter()
                 .position(|&c| c == 0)
                 .unwrap_or(find_data.cFileName.len());
-            let filename = String::from_utf16_lossy(&find_data.cFileName[..name_len]);
-
-            if filename != "." && filename != ".." {
-                let is_dir = (find_data.dwFileAttributes & FILE_ATTRIBUTE_DIRECTORY.0) != 0;
+            if let Some(filename) = entry_name(&find_data.cFileName[..name_len]) {
                 let full_path = dir.join(&filename);
-                callback(&full_path, is_dir)?;
+                let size =
+                    (u64::from(find_data.nFileSizeHigh) << 32) | u64::from(find_data.nFileSizeLow);
+                // dwReserved0 holds the reparse tag of a reparse point
+                let created = (u64::from(find_data.ftCreationTime.dwHighDateTime) << 32)
+                    | u64::from(find_data.ftCreationTime.dwLowDateTime);
+                let attributes = EntryAttributes::from_raw_tagged(
+                    find_data.dwFileAttributes,
+                    find_data.dwReserved0,
+                )
+                .with_size(size)
+                .with_locality(created);
+                let is_dir = (find_data.dwFileAttributes & FILE_ATTRIBUTE_DIRECTORY.0) != 0
+                    && !attributes.link;
+                if let Err(e) = callback(&full_path, is_dir, attributes) {
+                    let _ = FindClose(handle);
+                    return Err(e);
+                }
             }
 
             if FindNextFileW(handle, &mut find_data).is_err() {
@@ -178,17 +934,182 @@ where
     Ok(())
 }
 
-/// Enumerate files in a directory using standard library (Unix)
-#[cfg(not(windows))]
-pub fn enumerate_files<F>(dir: &Path, mut callback: F) -> io::Result<()>
+#[cfg(windows)]
+fn enumerate_nt_query<F>(dir: &Path, buffer_size: usize, mut callback: F) -> io::Result<()>
 where
-    F: FnMut(&Path, bool) -> io::Result<()>,
+    F: FnMut(&Path, bool, EntryAttributes) -> io::Result<()>,
+{
+    let wide_path = path_to_wide(dir);
+
+    unsafe {
+        let handle = CreateFileW(
+            PCWSTR(wide_path.as_ptr()),
+            FILE_LIST_DIRECTORY.0,
+            FILE_SHARE_READ | FILE_SHARE_WRITE | FILE_SHARE_DELETE,
+            None,
+            OPEN_EXISTING,
+            FILE_FLAG_BACKUP_SEMANTICS | FILE_FLAG_OPEN_REPARSE_POINT,
+            HANDLE::default(),
+        )
+        .map_err(|e| io::Error::from_raw_os_error(e.code().0 & 0xFFFF))?;
+
+        // u64 elements keep the buffer 8-byte aligned, as the entries require
+        let mut buffer = vec![0u64; buffer_size / 8];
+        let result = loop {
+            let mut io_status = IO_STATUS_BLOCK::default();
+            let status = NtQueryDirectoryFileEx(
+                handle,
+                HANDLE::default(),
+                None,
+                None,
+                &mut io_status,
+                buffer.as_mut_ptr().cast(),
+                (buffer.len() * 8) as u32,
+                FileFullDirectoryInformation,
+                0,
+                None,
+            );
+            if status == STATUS_NO_MORE_FILES {
+                break Ok(());
+            }
+            if status.is_err() {
+                break Err(io::Error::from_raw_os_error(
+                    RtlNtStatusToDosError(status) as i32
+                ));
+            }
+            if let Err(e) = visit_directory_entries(dir, buffer.as_ptr().cast(), &mut callback) {
+                break Err(e);
+            }
+        };
+
+        CloseHandle(handle).ok();
+        result
+    }
+}
+
+/// Walk the `FILE_FULL_DIR_INFORMATION` records filled in by one query
+#[cfg(windows)]
+unsafe fn visit_directory_entries<F>(
+    dir: &Path,
+    mut entry: *const u8,
+    callback: &mut F,
+) -> io::Result<()>
+where
+    F: FnMut(&Path, bool, EntryAttributes) -> io::Result<()>,
+{
+    loop {
+        let info = &*(entry as *const FILE_FULL_DIR_INFORMATION);
+        let name =
+            std::slice::from_raw_parts(info.FileName.as_ptr(), info.FileNameLength as usize / 2);
+        if let Some(filename) = entry_name(name) {
+            // For a reparse point EaSize holds its reparse tag instead
+            let attributes = EntryAttributes::from_raw_tagged(info.FileAttributes, info.EaSize)
+                .with_size(info.EndOfFile as u64)
+                .with_locality(info.CreationTime as u64);
+            let is_dir =
+                (info.FileAttributes & FILE_ATTRIBUTE_DIRECTORY.0) != 0 && !attributes.link;
+            callback(&dir.join(&filename), is_dir, attributes)?;
+        }
+
+        if info.NextEntryOffset == 0 {
+            return Ok(());
+        }
+        entry = entry.add(info.NextEntryOffset as usize);
+    }
+}
+
+/// Enumerate with `getdents64` straight into a buffer of `buffer_size`
+/// bytes, so big directories take fewer calls than with `read_dir`
+#[cfg(target_os = "linux")]
+pub fn enumerate_entries_sized<F>(dir: &Path, buffer_size: usize, mut callback: F) -> io::Result<()>
+where
+    F: FnMut(&Path, bool, EntryAttributes) -> io::Result<()>,
+{
+    use std::ffi::OsStr;
+    use std::os::fd::AsRawFd;
+    use std::os::unix::ffi::OsStrExt;
+    use std::os::unix::fs::OpenOptionsExt;
+
+    let file = std::fs::OpenOptions::new()
+        .read(true)
+        .custom_flags(libc::O_DIRECTORY)
+        .open(dir)?;
+    // u64 elements keep the records 8-byte aligned
+    let mut buffer = vec![0u64; buffer_size / 8];
+    loop {
+        let len = unsafe {
+            libc::syscall(
+                libc::SYS_getdents64,
+                file.as_raw_fd(),
+                buffer.as_mut_ptr(),
+                buffer.len() * 8,
+            )
+        };
+        if len < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        if len == 0 {
+            return Ok(());
+        }
+        let records =
+            unsafe { std::slice::from_raw_parts(buffer.as_ptr().cast::<u8>(), len as usize) };
+
+        // struct linux_dirent64 { u64 d_ino; i64 d_off; u16 d_reclen; u8 d_type; char d_name[]; }
+        let mut offset = 0;
+        while offset < records.len() {
+            let mut inode = [0; 8];
+            inode.copy_from_slice(&records[offset..offset + 8]);
+            let record_len =
+                u16::from_ne_bytes([records[offset + 16], records[offset + 17]]) as usize;
+            let file_type = records[offset + 18];
+            let name = &records[offset + 19..offset + record_len];
+            let name = &name[..name.iter().position(|&b| b == 0).unwrap_or(name.len())];
+            offset += record_len;
+            if name == b"." || name == b".." {
+                continue;
+            }
+
+            let path = dir.join(OsStr::from_bytes(name));
+            let (is_dir, link) = match file_type {
+                libc::DT_DIR => (true, false),
+                libc::DT_LNK => (false, true),
+                // Some filesystems don't fill in the type
+                libc::DT_UNKNOWN => match std::fs::symlink_metadata(&path) {
+                    Ok(metadata) => {
+                        let file_type = metadata.file_type();
+                        (file_type.is_dir(), file_type.is_symlink())
+                    }
+                    // Deleted since it was listed: nothing left to do
+                    Err(e) if e.kind() == io::ErrorKind::NotFound => continue,
+                    Err(e) => return Err(e),
+                },
+                _ => (false, false),
+            };
+            let attributes = EntryAttributes::linked(link).with_locality(u64::from_ne_bytes(inode));
+            callback(&path, is_dir, attributes)?;
+        }
+    }
+}
+
+/// Enumerate files in a directory using standard library (other Unix;
+/// `read_dir` picks its own buffer size)
+#[cfg(not(any(windows, target_os = "linux")))]
+pub fn enumerate_entries_sized<F>(
+    dir: &Path,
+    _buffer_size: usize,
+    mut callback: F,
+) -> io::Result<()>
+where
+    F: FnMut(&Path, bool, EntryAttributes) -> io::Result<()>,
 {
     for entry in std::fs::read_dir(dir)? {
         let entry = entry?;
         let path = entry.path();
-        let is_dir = entry.file_type()?.is_dir();
-        callback(&path, is_dir)?;
+        let file_type = entry.file_type()?;
+        let attributes = EntryAttributes::linked(file_type.is_symlink());
+        #[cfg(unix)]
+        let attributes = attributes.with_locality(std::os::unix::fs::DirEntryExt::ino(&entry));
+        callback(&path, file_type.is_dir(), attributes)?;
     }
     Ok(())
 }
@@ -198,6 +1119,35 @@ mod tests {
     use super::*;
     use std::fs::File;
     use std::io::Write;
+    use std::sync::{Mutex, MutexGuard};
+
+    /// The process-wide backend and buffer settings, put back when a test
+    /// that changes them ends, even by panicking; such tests take turns.
+    /// They only pick among ways giving the same results, so tests that
+    /// merely run meanwhile are unaffected.
+    struct Settings {
+        backend: DeleteBackend,
+        enum_buffer: usize,
+        _turn: MutexGuard<'static, ()>,
+    }
+
+    impl Settings {
+        fn save() -> Self {
+            static TURN: Mutex<()> = Mutex::new(());
+            Self {
+                _turn: TURN.lock().unwrap_or_else(|e| e.into_inner()),
+                backend: delete_backend(),
+                enum_buffer: ENUM_BUFFER.load(Ordering::Relaxed),
+            }
+        }
+    }
+
+    impl Drop for Settings {
+        fn drop(&mut self) {
+            set_delete_backend(self.backend);
+            ENUM_BUFFER.store(self.enum_buffer, Ordering::Relaxed);
+        }
+    }
 
     #[test]
     fn test_delete_file() {
@@ -242,6 +1192,213 @@ mod tests {
         assert!(!test_dir.exists());
     }
 
+    #[test]
+    fn test_file_identity() {
+        let temp = std::env::temp_dir().join("rmbrr_file_identity_test");
+        let _ = std::fs::remove_dir_all(&temp);
+        let (target, other) = (temp.join("target"), temp.join("other"));
+        std::fs::create_dir_all(&target).unwrap();
+        std::fs::create_dir_all(&other).unwrap();
+
+        let identity = file_identity(&target).unwrap();
+        assert_eq!(file_identity(&target).unwrap(), identity);
+        assert_ne!(file_identity(&other).unwrap(), identity);
+
+        // Swapped for another directory under the same name
+        std::fs::rename(&target, temp.join("moved")).unwrap();
+        std::fs::rename(&other, &target).unwrap();
+        assert_ne!(file_identity(&target).unwrap(), identity);
+        assert_eq!(file_identity(&temp.join("moved")).unwrap(), identity);
+
+        std::fs::remove_dir_all(&temp).ok();
+    }
+
+    #[test]
+    fn test_extended_length() {
+        let convert = |path: &str| {
+            String::from_utf16(&extended_length(&path.encode_utf16().collect::<Vec<_>>())).unwrap()
+        };
+        assert_eq!(convert(r"C:\a\b"), r"\\?\C:\a\b");
+        assert_eq!(convert("C:/a/b"), r"\\?\C:\a\b");
+        assert_eq!(convert(r"\\server\share\x"), r"\\?\UNC\server\share\x");
+        assert_eq!(convert("//server/share/x"), r"\\?\UNC\server\share\x");
+        assert_eq!(convert(r"\\?\C:\a"), r"\\?\C:\a");
+        assert_eq!(convert(r"\\?\UNC\s\x"), r"\\?\UNC\s\x");
+        assert_eq!(convert(r"\\.\pipe\x"), r"\\.\pipe\x");
+    }
+
+    #[test]
+    fn test_entry_attributes_from_raw() {
+        // FILE_ATTRIBUTE_ARCHIVE | COMPRESSED
+        let attributes = EntryAttributes::from_raw(0x20 | 0x800);
+        assert!(attributes.compressed && !attributes.encrypted);
+        // FILE_ATTRIBUTE_ENCRYPTED
+        assert!(EntryAttributes::from_raw(0x4000).encrypted);
+        assert_eq!(attributes.size, None);
+        assert_eq!(attributes.with_size(42).size, Some(42));
+
+        // FILE_ATTRIBUTE_DIRECTORY | REPARSE_POINT
+        let reparse = 0x10 | 0x400;
+        assert!(EntryAttributes::from_raw_tagged(reparse, 0xA000_0003).link); // junction
+        assert!(EntryAttributes::from_raw_tagged(reparse, 0xA000_000C).link); // symlink
+        assert!(!EntryAttributes::from_raw_tagged(reparse, 0x9000_601A).link); // OneDrive
+        assert!(!EntryAttributes::from_raw_tagged(0x10, 0xA000_0003).link);
+    }
+
+    #[test]
+    fn test_delete_backend_from_str() {
+        assert_eq!("nt".parse::<DeleteBackend>().unwrap(), DeleteBackend::Nt);
+        assert_eq!(
+            "uring".parse::<DeleteBackend>().unwrap(),
+            DeleteBackend::Uring
+        );
+        assert_eq!(
+            "sync".parse::<DeleteBackend>().unwrap(),
+            DeleteBackend::Sync
+        );
+        assert!("posix".parse::<DeleteBackend>().is_err());
+    }
+
+    #[test]
+    fn test_dir_deleter() {
+        let temp = std::env::temp_dir().join("rmbrr_dir_deleter_test");
+        let _ = std::fs::remove_dir_all(&temp);
+        std::fs::create_dir_all(&temp).unwrap();
+        std::fs::write(temp.join("a.txt"), "a").unwrap();
+        let _settings = Settings::save();
+        #[cfg(windows)]
+        set_delete_backend(DeleteBackend::Nt);
+
+        {
+            let deleter = DirDeleter::open(&temp);
+            deleter.delete_file(&temp.join("a.txt")).unwrap();
+            assert!(!temp.join("a.txt").exists());
+            assert!(deleter.delete_file(&temp.join("a.txt")).is_err());
+        }
+        // The handle on the directory is closed, so it can go
+
+        #[cfg(target_os = "linux")]
+        set_delete_backend(DeleteBackend::Uring);
+        std::fs::write(temp.join("b.txt"), "b").unwrap();
+        std::fs::write(temp.join("c.txt"), "c").unwrap();
+        let results = DirDeleter::open(&temp).delete_batch(&[
+            &temp.join("b.txt"),
+            &temp.join("missing.txt"),
+            &temp.join("c.txt"),
+        ]);
+        assert!(results[0].is_ok() && results[2].is_ok());
+        assert_eq!(
+            results[1].as_ref().unwrap_err().kind(),
+            io::ErrorKind::NotFound
+        );
+
+        std::fs::remove_dir(&temp).unwrap();
+    }
+
+    #[test]
+    fn test_enum_backend_from_str() {
+        assert_eq!(
+            "nt-query".parse::<EnumBackend>().unwrap(),
+            EnumBackend::NtQueryDirectory
+        );
+        assert_eq!(
+            "find-first-file".parse::<EnumBackend>().unwrap(),
+            EnumBackend::FindFirstFile
+        );
+        assert!("readdir".parse::<EnumBackend>().is_err());
+    }
+
+    #[cfg(windows)]
+    #[test]
+    fn test_enumerate_backends_agree() {
+        let temp = std::env::temp_dir().join("rmbrr_enum_backends_test");
+        let _ = std::fs::remove_dir_all(&temp);
+        std::fs::create_dir_all(temp.join("sub")).unwrap();
+        for i in 0..2000 {
+            File::create(temp.join(format!("file_with_a_longish_name_{:05}.txt", i))).unwrap();
+        }
+
+        let mut find_first = Vec::new();
+        enumerate_find_first_file(&temp, true, |path, is_dir, attributes| {
+            find_first.push((path.to_path_buf(), is_dir, attributes));
+            Ok(())
+        })
+        .unwrap();
+        let mut nt_query = Vec::new();
+        // Smallest buffer, so it takes many queries
+        enumerate_nt_query(&temp, 4096, |path, is_dir, attributes| {
+            nt_query.push((path.to_path_buf(), is_dir, attributes));
+            Ok(())
+        })
+        .unwrap();
+        find_first.sort_by(|a, b| a.0.cmp(&b.0));
+        nt_query.sort_by(|a, b| a.0.cmp(&b.0));
+
+        assert_eq!(find_first.len(), 2001);
+        assert_eq!(find_first, nt_query);
+
+        std::fs::remove_dir_all(&temp).ok();
+    }
+
+    #[test]
+    fn test_enumerate_buffer_sizes_agree() {
+        let temp = std::env::temp_dir().join("rmbrr_enum_buffer_test");
+        let _ = std::fs::remove_dir_all(&temp);
+        std::fs::create_dir_all(temp.join("sub")).unwrap();
+        for i in 0..2000 {
+            File::create(temp.join(format!("file_with_a_longish_name_{:05}.txt", i))).unwrap();
+        }
+
+        let list = |buffer_size| {
+            let mut entries = Vec::new();
+            enumerate_entries_sized(&temp, buffer_size, |path, is_dir, _| {
+                entries.push((path.to_path_buf(), is_dir));
+                Ok(())
+            })
+            .unwrap();
+            entries.sort();
+            entries
+        };
+        let small = list(*ENUM_BUFFER_RANGE.start());
+        assert_eq!(small.len(), 2001);
+        assert!(small.contains(&(temp.join("sub"), true)));
+        assert_eq!(small, list(LARGE_ENUM_BUFFER));
+
+        std::fs::remove_dir_all(&temp).ok();
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_locality_is_inode() {
+        use std::os::unix::fs::MetadataExt;
+
+        let temp = std::env::temp_dir().join("rmbrr_locality_test");
+        let _ = std::fs::remove_dir_all(&temp);
+        std::fs::create_dir_all(&temp).unwrap();
+        File::create(temp.join("a")).unwrap();
+        File::create(temp.join("b")).unwrap();
+
+        enumerate_entries(&temp, |path, _, attributes| {
+            let inode = std::fs::symlink_metadata(path)?.ino();
+            assert_eq!(attributes.locality, Some(inode));
+            Ok(())
+        })
+        .unwrap();
+
+        std::fs::remove_dir_all(&temp).ok();
+    }
+
+    #[test]
+    fn test_enum_buffer_size() {
+        assert_eq!(enum_buffer_size(10), DEFAULT_ENUM_BUFFER);
+        assert_eq!(enum_buffer_size(LARGE_DIR_ENTRIES), LARGE_ENUM_BUFFER);
+        let _settings = Settings::save();
+        set_enum_buffer(Some(1));
+        assert_eq!(enum_buffer_size(LARGE_DIR_ENTRIES), 4 * 1024);
+        set_enum_buffer(None);
+        assert_eq!(enum_buffer_size(10), DEFAULT_ENUM_BUFFER);
+    }
+
     #[test]
     fn test_remove_nonexistent_dir() {
         let temp_dir = std::env::temp_dir();
@@ -1,12 +1,24 @@
 // Worker thread deletion logic
 
 use crate::broker::Broker;
-use crate::error::FailedItem;
-use crate::winapi::{delete_file, enumerate_files, remove_dir};
-use crossbeam_channel::Receiver;
+use crate::chaos;
+use crate::error::{EnumerationWarning, Error, ErrorCategory, FailedItem};
+use crate::filter::Filters;
+use crate::handles::{self, Unlock};
+use crate::tree;
+use crate::winapi::{
+    self, delete_file, enumerate_entries_sized, remove_dir, DirDeleter, EntryAttributes,
+};
+use crossbeam_channel::{Receiver, TryRecvError};
+use std::collections::HashSet;
+use std::fs;
+use std::io;
 use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::sync::atomic::{AtomicUsize, Ordering};
 use std::sync::{Arc, Mutex};
 use std::thread::{self, JoinHandle};
+use std::time::Duration;
 
 /// Configuration for worker error handling
 #[derive(Clone)]
@@ -15,6 +27,19 @@ pub struct WorkerConfig {
     pub verbose: bool,
     /// If true, continue on errors; if false, fail fast
     pub ignore_errors: bool,
+    /// How deletes failing with a transient error are retried
+    pub retry: RetryPolicy,
+    /// Stat files before deleting them where the listing has no sizes (off
+    /// Windows), so the bytes freed are known
+    pub count_bytes: bool,
+    /// Filters the scan applied; files are checked again by age and size,
+    /// which may have changed since, and directories that appear
+    /// mid-run are scanned with them
+    pub filters: Arc<Filters>,
+    /// Order the files of each directory are deleted in
+    pub order: DeleteOrder,
+    /// What to do about processes holding files open
+    pub unlock: Unlock,
 }
 
 impl Default for WorkerConfig {
@@ -22,20 +47,174 @@ impl Default for WorkerConfig {
         Self {
             verbose: false,
             ignore_errors: true, // Default: continue on errors
+            retry: RetryPolicy::default(),
+            count_bytes: false,
+            filters: Arc::default(),
+            order: DeleteOrder::default(),
+            unlock: Unlock::default(),
         }
     }
 }
 
+/// Order of the files deleted in one directory (`--delete-order`)
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum DeleteOrder {
+    /// As listed, each file deleted as soon as it is seen
+    #[default]
+    Listing,
+    /// Experimental: by [`EntryAttributes::locality`] once the whole
+    /// directory is listed, so space is freed in runs that were allocated
+    /// together, hoping for larger contiguous free extents and TRIM ranges
+    /// on SSDs (see `examples/delete_order.rs`)
+    Locality,
+}
+
+impl FromStr for DeleteOrder {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "listing" => Ok(DeleteOrder::Listing),
+            "locality" => Ok(DeleteOrder::Locality),
+            other => Err(format!(
+                "unknown delete order '{}' (expected listing or locality)",
+                other
+            )),
+        }
+    }
+}
+
+/// Retries for failures that usually clear up on their own (e.g. a file
+/// briefly held open by an indexer or antivirus scanner)
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RetryPolicy {
+    /// Retries after the first attempt
+    pub attempts: u32,
+    /// Wait before the first retry, doubled before each one after it
+    pub delay: Duration,
+    /// Kinds of failure retried differently; a rule overrides whether its
+    /// kind is retried at all
+    pub rules: Vec<RetryRule>,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            attempts: 2,
+            delay: Duration::from_millis(10),
+            rules: Vec::new(),
+        }
+    }
+}
+
+impl RetryPolicy {
+    /// Retries allowed after `error`, and the wait before the first of them
+    fn retries_for(&self, error: &io::Error) -> (u32, Duration) {
+        let category = ErrorCategory::of(error);
+        match self.rules.iter().find(|rule| rule.category == category) {
+            Some(rule) => (rule.attempts, rule.delay.unwrap_or(self.delay)),
+            None if is_retryable(error) => (self.attempts, self.delay),
+            None => (0, self.delay),
+        }
+    }
+}
+
+/// Retries for one kind of failure (`--retry-on`)
+///
+/// A `not_empty` directory is emptied again before each retry, for
+/// directories something keeps writing into.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RetryRule {
+    pub category: ErrorCategory,
+    /// Retries after the first attempt (0: never retried)
+    pub attempts: u32,
+    /// Wait before the first retry, if not the policy's
+    pub delay: Option<Duration>,
+}
+
+impl RetryRule {
+    /// `KIND=N` or `KIND=N:DELAY`, e.g. `in_use=5:50ms`, where KIND is a
+    /// failure category as in the JSON report
+    pub fn parse(input: &str) -> Result<Self, String> {
+        let (kind, retries) = input
+            .split_once('=')
+            .ok_or_else(|| format!("expected KIND=N[:DELAY], got '{}'", input))?;
+        let category = ErrorCategory::from_name(kind).ok_or_else(|| {
+            let kinds: Vec<_> = ErrorCategory::ALL.iter().map(|c| c.name()).collect();
+            format!("unknown kind '{}' (one of {})", kind, kinds.join(", "))
+        })?;
+        let (attempts, delay) = match retries.split_once(':') {
+            Some((attempts, delay)) => (attempts, Some(crate::units::parse_duration(delay)?)),
+            None => (retries, None),
+        };
+        let attempts = attempts
+            .trim()
+            .parse()
+            .map_err(|_| format!("invalid retry count '{}'", attempts))?;
+        Ok(Self {
+            category,
+            attempts,
+            delay,
+        })
+    }
+}
+
+/// Wait before retry number `retry` (counting from 1), starting at `delay`
+fn backoff(delay: Duration, retry: u32) -> Duration {
+    delay.saturating_mul(1 << retry.saturating_sub(1).min(16))
+}
+
 /// Shared error tracking state
 pub struct ErrorTracker {
     failures: Mutex<Vec<FailedItem>>,
+    /// Directories whose contents could not be listed
+    warnings: Mutex<Vec<EnumerationWarning>>,
+    /// Entries whose type (file vs directory) changed between scan and delete
+    type_changes: AtomicUsize,
+    /// Deleted files that were NTFS-compressed
+    compressed: AtomicUsize,
+    /// Deleted files that were EFS-encrypted
+    encrypted: AtomicUsize,
 }
 
 impl ErrorTracker {
     pub fn new() -> Self {
         Self {
             failures: Mutex::new(Vec::new()),
+            warnings: Mutex::new(Vec::new()),
+            type_changes: AtomicUsize::new(0),
+            compressed: AtomicUsize::new(0),
+            encrypted: AtomicUsize::new(0),
+        }
+    }
+
+    pub fn record_type_change(&self) {
+        self.type_changes.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Entries that were deleted with the other call because their type changed
+    pub fn type_change_count(&self) -> usize {
+        self.type_changes.load(Ordering::Relaxed)
+    }
+
+    /// Count a deleted file's compression and encryption
+    pub fn record_attributes(&self, attributes: EntryAttributes) {
+        if attributes.compressed {
+            self.compressed.fetch_add(1, Ordering::Relaxed);
         }
+        if attributes.encrypted {
+            self.encrypted.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    /// Deleted files that were NTFS-compressed
+    pub fn compressed_count(&self) -> usize {
+        self.compressed.load(Ordering::Relaxed)
+    }
+
+    /// Deleted files that were EFS-encrypted
+    pub fn encrypted_count(&self) -> usize {
+        self.encrypted.load(Ordering::Relaxed)
     }
 
     pub fn record_failure(&self, item: FailedItem) {
@@ -49,6 +228,14 @@ impl ErrorTracker {
     pub fn failure_count(&self) -> usize {
         self.failures.lock().unwrap().len()
     }
+
+    pub fn record_warning(&self, warning: EnumerationWarning) {
+        self.warnings.lock().unwrap().push(warning);
+    }
+
+    pub fn get_warnings(&self) -> Vec<EnumerationWarning> {
+        self.warnings.lock().unwrap().clone()
+    }
 }
 
 impl Default for ErrorTracker {
@@ -82,72 +269,516 @@ pub fn spawn_workers(
         .collect()
 }
 
+/// Largest tree of empty directories [`remove_empty_dirs`] is meant for;
+/// bigger ones still go faster on the worker pool
+pub const SERIAL_MAX_DIRS: usize = 128;
+
+/// The scan found nothing but a few directories (no files, nothing
+/// skipped or kept), which one thread removes before workers could start
+pub fn is_empty_skeleton(tree: &tree::DirectoryTree) -> bool {
+    tree.file_count == 0
+        && tree.skipped.is_empty()
+        && tree.retained.is_empty()
+        && tree.dirs.len() <= SERIAL_MAX_DIRS
+}
+
+/// Remove the directories of `tree` deepest first on this thread, dropping
+/// the removed ones from it
+///
+/// Any that can't be removed (something was created in them since the
+/// scan, say) stay in `tree`, with their parents, for the worker pool to
+/// deal with as usual. Returns the number removed.
+pub fn remove_empty_dirs(tree: &mut tree::DirectoryTree) -> usize {
+    let mut dirs: Vec<&PathBuf> = tree.dirs.iter().collect();
+    dirs.sort_by_key(|dir| std::cmp::Reverse(dir.components().count()));
+
+    let mut removed = HashSet::new();
+    for dir in dirs {
+        let children_left = tree
+            .children
+            .get(dir)
+            .is_some_and(|children| children.iter().any(|child| !removed.contains(child)));
+        if !children_left && chaos::inject().and_then(|()| remove_dir(dir)).is_ok() {
+            removed.insert(dir.clone());
+        }
+    }
+    tree.forget_dirs(&removed);
+    removed.len()
+}
+
+/// Wait for every worker, turning a panic into [`Error::WorkerPanic`]
+///
+/// All handles are joined even after a panic, so no thread outlives the
+/// call; the first panic (by worker index) is the one reported. Handles
+/// handed to close threads (see [`winapi::set_close_threads`]) are closed
+/// before this returns.
+pub fn join_workers(handles: Vec<JoinHandle<()>>) -> Result<(), Error> {
+    let mut first_panic = None;
+    for (worker, handle) in handles.into_iter().enumerate() {
+        if let Err(payload) = handle.join() {
+            first_panic.get_or_insert(Error::WorkerPanic {
+                worker,
+                payload: panic_message(payload.as_ref()),
+            });
+        }
+    }
+    // Deleted entries still open on close threads hold their space until closed
+    winapi::flush_closes();
+    first_panic.map_or(Ok(()), Err)
+}
+
+/// Text of a panic payload (`panic!` produces `&str` or `String`)
+fn panic_message(payload: &(dyn std::any::Any + Send)) -> String {
+    if let Some(message) = payload.downcast_ref::<&str>() {
+        message.to_string()
+    } else if let Some(message) = payload.downcast_ref::<String>() {
+        message.clone()
+    } else {
+        "non-string panic payload".to_string()
+    }
+}
+
+/// Aborts the broker if the worker holding it unwinds
+struct AbortOnPanic<'a>(&'a Broker);
+
+impl Drop for AbortOnPanic<'_> {
+    fn drop(&mut self) {
+        if thread::panicking() {
+            self.0.abort();
+        }
+    }
+}
+
 pub fn worker_thread(
     rx: Receiver<PathBuf>,
     broker: Arc<Broker>,
     config: WorkerConfig,
     error_tracker: Arc<ErrorTracker>,
 ) {
-    while let Ok(dir) = rx.recv() {
-        if let Err(e) = delete_files_in_dir(&dir, &config, &error_tracker) {
-            let msg = format!("{}", e);
-            if config.verbose {
-                eprintln!(
-                    "Warning: Failed to delete files in {}: {}",
-                    dir.display(),
-                    msg
-                );
+    let _abort_on_panic = AbortOnPanic(&broker);
+    let _as_user = crate::impersonate::enter();
+    let mut completed = CompletionBatch::new(&broker);
+
+    loop {
+        let dir = match rx.try_recv() {
+            Ok(dir) => dir,
+            // About to wait: hand in completions first, they may be what unblocks
+            // the next directory
+            Err(TryRecvError::Empty) => {
+                completed.flush();
+                broker.end_work();
+                match rx.recv() {
+                    Ok(dir) => dir,
+                    Err(_) => break,
+                }
             }
+            Err(TryRecvError::Disconnected) => break,
+        };
+        if broker.is_paused() {
+            completed.flush();
+            broker.end_work();
+            broker.wait_while_paused();
+        }
+        // Stopped: leave queued directories alone
+        if broker.is_aborted() {
+            break;
         }
+        broker.begin_work(&dir);
 
-        if let Err(e) = remove_dir(&dir) {
-            let msg = format!("{}", e);
-            error_tracker.record_failure(FailedItem {
-                path: dir.clone(),
-                error: msg.clone(),
-                is_dir: true,
-            });
+        let (new_dirs, files_failed) =
+            match delete_files_in_dir(&dir, &broker, &config, &error_tracker) {
+                Ok(pass) => {
+                    let scanned = broker.take_dir_bytes(&dir);
+                    let bytes = match pass.failed {
+                        _ if pass.unmeasured == 0 => pass.bytes,
+                        // Credit only the deleted share if some files failed
+                        0 => scanned,
+                        failed => scanned * pass.deleted as u64 / (pass.deleted + failed) as u64,
+                    };
+                    broker.record_files_deleted(pass.deleted, bytes);
+                    (pass.new_dirs, pass.failed)
+                }
+                // Scanned as a directory but replaced by a file (or symlink) since
+                Err(_) if !is_dir_now(&dir) && dir.symlink_metadata().is_ok() => {
+                    match with_retries(&config.retry, || {
+                        chaos::inject().and_then(|()| delete_file(&dir))
+                    }) {
+                        (Ok(()), _) => {
+                            if config.verbose {
+                                crate::errln!(
+                                    "Note: {} is no longer a directory, deleted as a file",
+                                    dir.display()
+                                );
+                            }
+                            error_tracker.record_type_change();
+                            completed.push(dir);
+                        }
+                        (Err(e), retries) => {
+                            let item = FailedItem::new(dir.clone(), e, false).with_retries(retries);
+                            report_failure(item, &config, &error_tracker);
+                            broker.mark_failed(dir);
+                        }
+                    }
+                    continue;
+                }
+                Err(e) => {
+                    if config.verbose {
+                        crate::errln!(
+                            "Warning: Failed to delete files in {}: {}",
+                            dir.display(),
+                            e
+                        );
+                    }
+                    // Gone already is not worth a warning; removal sorts it out
+                    if e.kind() != io::ErrorKind::NotFound {
+                        error_tracker.record_warning(EnumerationWarning::new(dir.clone(), e));
+                    }
+                    (Vec::new(), 0)
+                }
+            };
 
-            if config.verbose {
-                eprintln!("Warning: Failed to remove {}: {}", dir.display(), msg);
-            }
+        // Subdirectories created since the scan: schedule them and come back later
+        if !new_dirs.is_empty() && adopt_new_dirs(&dir, new_dirs, &broker, &config) {
+            broker.defer(&dir);
+            continue;
+        }
 
+        // A file inside could not be deleted, so removing the directory can only fail
+        if files_failed > 0 {
+            broker.mark_blocked(dir);
             continue;
         }
 
-        broker.mark_complete(dir);
+        // Still holds excluded entries, so it is kept rather than removed
+        if broker.is_retained(&dir) {
+            broker.mark_retained(dir);
+            continue;
+        }
+
+        let mut refill = false;
+        let mut late_dirs = Vec::new();
+        let (mut result, mut retries) = with_retries(&config.retry, || {
+            // Not empty last time (only retried if `--retry-on` asks for it):
+            // something wrote into it meanwhile, so empty it again
+            if refill && late_dirs.is_empty() {
+                if let Ok(pass) = delete_files_in_dir(&dir, &broker, &config, &error_tracker) {
+                    broker.record_files_deleted(pass.deleted, pass.bytes);
+                    late_dirs = pass.new_dirs;
+                }
+            }
+            let result = chaos::inject().and_then(|()| remove_dir(&dir));
+            refill = result
+                .as_ref()
+                .is_err_and(|e| ErrorCategory::of(e) == ErrorCategory::NotEmpty);
+            result
+        });
+        // A process's working directory, or a mount point (EBUSY)
+        let mut holders = Vec::new();
+        if matches!(&result, Err(e) if ErrorCategory::of(e) == ErrorCategory::InUse) {
+            holders = handles::holders(&dir);
+            if handles::unlock(&dir, &holders, config.unlock) {
+                retries += 1;
+                result = remove_dir(&dir);
+            }
+        }
+        match result {
+            Ok(()) => completed.push(dir),
+            // Subdirectories appeared meanwhile: schedule them and come back later
+            Err(_)
+                if !late_dirs.is_empty() && adopt_new_dirs(&dir, late_dirs, &broker, &config) =>
+            {
+                broker.defer(&dir);
+            }
+            Err(e) => {
+                let item = FailedItem::new(dir.clone(), e, true)
+                    .with_retries(retries)
+                    .with_holders(holders);
+                report_failure(item, &config, &error_tracker);
+                broker.mark_failed(dir);
+            }
+        }
+    }
+    broker.end_work();
+}
+
+/// Completions a worker collects before reporting them to the broker
+const COMPLETION_BATCH_SIZE: usize = 64;
+
+/// Deleted directories not yet reported to the broker
+///
+/// Reporting in batches cuts lock traffic on the broker; the batch is
+/// flushed when full, before the worker blocks waiting for work, and on drop.
+struct CompletionBatch<'a> {
+    broker: &'a Broker,
+    dirs: Vec<PathBuf>,
+}
+
+impl<'a> CompletionBatch<'a> {
+    fn new(broker: &'a Broker) -> Self {
+        Self {
+            broker,
+            dirs: Vec::with_capacity(COMPLETION_BATCH_SIZE),
+        }
+    }
+
+    fn push(&mut self, dir: PathBuf) {
+        self.dirs.push(dir);
+        if self.dirs.len() >= COMPLETION_BATCH_SIZE {
+            self.flush();
+        }
+    }
+
+    fn flush(&mut self) {
+        if !self.dirs.is_empty() {
+            let batch =
+                std::mem::replace(&mut self.dirs, Vec::with_capacity(COMPLETION_BATCH_SIZE));
+            self.broker.mark_complete_batch(batch);
+        }
+    }
+}
+
+impl Drop for CompletionBatch<'_> {
+    fn drop(&mut self) {
+        self.flush();
+    }
+}
+
+/// Run `op`, retrying with backoff while `policy` allows for how it failed
+///
+/// Returns the final result and the number of retries attempted.
+fn with_retries(
+    policy: &RetryPolicy,
+    mut op: impl FnMut() -> io::Result<()>,
+) -> (io::Result<()>, u32) {
+    let mut retries = 0;
+    loop {
+        match op() {
+            Err(e) => {
+                let (attempts, delay) = policy.retries_for(&e);
+                if retries >= attempts {
+                    return (Err(e), retries);
+                }
+                retries += 1;
+                thread::sleep(backoff(delay, retries));
+            }
+            result => return (result, retries),
+        }
+    }
+}
+
+/// Sharing violations, and on Windows access denied too: antivirus and
+/// indexers opening a file without FILE_SHARE_DELETE cause either
+fn is_retryable(error: &io::Error) -> bool {
+    // ERROR_ACCESS_DENIED
+    ErrorCategory::of(error).is_transient() || (cfg!(windows) && error.raw_os_error() == Some(5))
+}
+
+/// Record a failure, printing it with its category and retry count in verbose mode
+fn report_failure(item: FailedItem, config: &WorkerConfig, error_tracker: &ErrorTracker) {
+    if config.verbose {
+        crate::errln!("Warning: {} ({})", item, item.detail());
     }
+    error_tracker.record_failure(item);
 }
 
+/// Scan directories that appeared after the initial scan and add them to the broker
+///
+/// Returns true if at least one subtree was added below `dir`.
+fn adopt_new_dirs(
+    dir: &Path,
+    new_dirs: Vec<PathBuf>,
+    broker: &Broker,
+    config: &WorkerConfig,
+) -> bool {
+    let mut adopted = false;
+    for new_dir in new_dirs {
+        let options = tree::ScanOptions {
+            filters: Filters::clone(&config.filters),
+            ..Default::default()
+        };
+        match tree::discover_tree_with_options(&new_dir, &options, &tree::ScanProgress::new()) {
+            Ok(subtree) => {
+                if config.verbose {
+                    crate::errln!(
+                        "Note: {} appeared during deletion, scheduling {} more directories",
+                        new_dir.display(),
+                        subtree.dirs.len()
+                    );
+                }
+                broker.add_subtree(Some(dir), subtree);
+                adopted = true;
+            }
+            Err(e) => {
+                if config.verbose {
+                    crate::errln!("Warning: Cannot scan {}: {}", new_dir.display(), e);
+                }
+            }
+        }
+    }
+    adopted
+}
+
+/// True if `path` is currently a real directory (symlinks are not followed)
+fn is_dir_now(path: &Path) -> bool {
+    fs::symlink_metadata(path)
+        .map(|m| m.is_dir())
+        .unwrap_or(false)
+}
+
+/// Outcome of deleting the files directly inside one directory
+#[derive(Debug, Default)]
+struct FilesPass {
+    /// Files successfully deleted
+    deleted: usize,
+    /// Bytes in the deleted files whose size was known
+    bytes: u64,
+    /// Deleted files whose size wasn't known
+    unmeasured: usize,
+    /// Files that could not be deleted
+    failed: usize,
+    /// Subdirectories found, which can only be ones created after the scan
+    /// since children are always deleted before their parent
+    new_dirs: Vec<PathBuf>,
+}
+
+/// Delete the files directly inside `dir`, leaving entries the broker excludes
 fn delete_files_in_dir(
     dir: &Path,
+    broker: &Broker,
     config: &WorkerConfig,
     error_tracker: &Arc<ErrorTracker>,
-) -> std::io::Result<()> {
-    enumerate_files(dir, |path, is_dir| {
+) -> io::Result<FilesPass> {
+    let mut pass = FilesPass::default();
+    let deleter = DirDeleter::open(dir);
+    // With a batching backend, or to be sorted first, files are deleted once
+    // the listing is done
+    let collect = deleter.batches() || config.order == DeleteOrder::Locality;
+    let mut batch: Vec<(PathBuf, EntryAttributes)> = Vec::new();
+    let buffer_size = winapi::enum_buffer_size(broker.take_entry_count(dir));
+    enumerate_entries_sized(dir, buffer_size, |path, is_dir, attributes| {
+        // Excluded entries and the kept directories holding them stay put
+        // (or links followed to them)
+        if broker.is_excluded(path) || ((is_dir || attributes.link) && broker.is_retained(path)) {
+            return Ok(());
+        }
         if !is_dir {
-            if let Err(e) = delete_file(path) {
-                let msg = format!("{}", e);
-                error_tracker.record_failure(FailedItem {
+            if let Some(reason) = config.filters.recheck_file(path, attributes.size) {
+                let entry = tree::SkippedEntry {
                     path: path.to_path_buf(),
-                    error: msg.clone(),
-                    is_dir: false,
-                });
-
-                if config.verbose {
-                    eprintln!("Warning: Failed to delete {}: {}", path.display(), msg);
-                }
+                    is_dir,
+                    reason,
+                    bytes: attributes.size.unwrap_or(0),
+                };
+                broker.exclude_late(dir, entry);
+                return Ok(());
             }
         }
+        if is_dir {
+            pass.new_dirs.push(path.to_path_buf());
+            return Ok(());
+        }
+        let attributes = match attributes.size {
+            None if config.count_bytes => match fs::symlink_metadata(path) {
+                Ok(metadata) => attributes.with_size(metadata.len()),
+                Err(_) => attributes,
+            },
+            _ => attributes,
+        };
+        let first = chaos::inject();
+        if collect && first.is_ok() {
+            batch.push((path.to_path_buf(), attributes));
+        } else {
+            let first = first.and_then(|()| deleter.delete_file(path));
+            settle_file(
+                path,
+                attributes,
+                first,
+                &deleter,
+                config,
+                error_tracker,
+                &mut pass,
+            );
+            broker.note_file_settled();
+        }
         Ok(())
-    })
+    })?;
+
+    if config.order == DeleteOrder::Locality {
+        batch.sort_by_key(|(_, attributes)| attributes.locality);
+    }
+    if !batch.is_empty() {
+        let paths: Vec<&Path> = batch.iter().map(|(path, _)| path.as_path()).collect();
+        let results = deleter.delete_batch(&paths);
+        for ((path, attributes), first) in batch.iter().zip(results) {
+            settle_file(
+                path,
+                *attributes,
+                first,
+                &deleter,
+                config,
+                error_tracker,
+                &mut pass,
+            );
+            broker.note_file_settled();
+        }
+    }
+    Ok(pass)
+}
+
+/// Count a file whose first delete attempt returned `first`, retrying it
+/// (one at a time) and reporting it if that failed
+fn settle_file(
+    path: &Path,
+    attributes: EntryAttributes,
+    first: io::Result<()>,
+    deleter: &DirDeleter,
+    config: &WorkerConfig,
+    error_tracker: &ErrorTracker,
+    pass: &mut FilesPass,
+) {
+    let mut first = Some(first);
+    let (mut result, mut retries) = with_retries(&config.retry, || {
+        first
+            .take()
+            .unwrap_or_else(|| chaos::inject().and_then(|()| deleter.delete_file(path)))
+    });
+    let mut holders = Vec::new();
+    if matches!(&result, Err(e) if ErrorCategory::of(e) == ErrorCategory::InUse) {
+        holders = handles::holders(path);
+        // Once what held it open has ended, one more try
+        if handles::unlock(path, &holders, config.unlock) {
+            retries += 1;
+            result = deleter.delete_file(path);
+        }
+    }
+    if let Err(e) = result {
+        // Replaced by a directory after it was enumerated
+        if is_dir_now(path) {
+            error_tracker.record_type_change();
+            pass.new_dirs.push(path.to_path_buf());
+            return;
+        }
+
+        let item = FailedItem::new(path.to_path_buf(), e, false)
+            .with_retries(retries)
+            .with_encrypted(attributes.encrypted)
+            .with_holders(holders);
+        report_failure(item, config, error_tracker);
+        pass.failed += 1;
+    } else {
+        error_tracker.record_attributes(attributes);
+        pass.deleted += 1;
+        match attributes.size {
+            Some(size) => pass.bytes += size,
+            None => pass.unmeasured += 1,
+        }
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
     use crate::broker::Broker;
-    use crate::tree;
     use std::fs::{self, File};
     use std::sync::atomic::{AtomicUsize, Ordering};
     use std::time::Duration;
@@ -166,7 +797,8 @@ mod tests {
 
         let config = WorkerConfig::default();
         let error_tracker = Arc::new(ErrorTracker::new());
-        delete_files_in_dir(&temp, &config, &error_tracker).unwrap();
+        let (broker, _tx, _rx) = Broker::new(tree::DirectoryTree::new());
+        delete_files_in_dir(&temp, &broker, &config, &error_tracker).unwrap();
 
         // Files should be deleted, dir still exists
         assert_eq!(fs::read_dir(&temp).unwrap().count(), 0);
@@ -175,6 +807,142 @@ mod tests {
         fs::remove_dir(&temp).ok();
     }
 
+    #[test]
+    fn test_delete_files_counts_bytes() {
+        let temp = std::env::temp_dir().join("rmbrr_delete_files_bytes_test");
+        let _ = fs::remove_dir_all(&temp);
+        fs::create_dir(&temp).unwrap();
+        fs::write(temp.join("a.bin"), [0u8; 100]).unwrap();
+        fs::write(temp.join("b.bin"), [0u8; 23]).unwrap();
+
+        let config = WorkerConfig {
+            count_bytes: true,
+            ..WorkerConfig::default()
+        };
+        let error_tracker = Arc::new(ErrorTracker::new());
+        let (broker, _tx, _rx) = Broker::new(tree::DirectoryTree::new());
+        let pass = delete_files_in_dir(&temp, &broker, &config, &error_tracker).unwrap();
+        assert_eq!((pass.deleted, pass.bytes, pass.unmeasured), (2, 123, 0));
+
+        fs::remove_dir_all(&temp).ok();
+    }
+
+    /// Names of the entries deleted from a directory, in the order the
+    /// kernel saw them go
+    #[cfg(target_os = "linux")]
+    struct DeleteWatch {
+        fd: libc::c_int,
+    }
+
+    #[cfg(target_os = "linux")]
+    impl DeleteWatch {
+        fn new(dir: &Path) -> Self {
+            use std::os::unix::ffi::OsStrExt;
+            let dir = std::ffi::CString::new(dir.as_os_str().as_bytes()).unwrap();
+            let fd = unsafe { libc::inotify_init1(libc::IN_NONBLOCK | libc::IN_CLOEXEC) };
+            assert!(fd >= 0, "{}", io::Error::last_os_error());
+            let watch = Self { fd };
+            let added = unsafe { libc::inotify_add_watch(fd, dir.as_ptr(), libc::IN_DELETE) };
+            assert!(added >= 0, "{}", io::Error::last_os_error());
+            watch
+        }
+
+        fn deleted(&self) -> Vec<std::ffi::OsString> {
+            use std::os::unix::ffi::OsStrExt;
+            const HEADER: usize = std::mem::size_of::<libc::inotify_event>();
+            let mut names = Vec::new();
+            let mut buffer = vec![0u8; 64 * 1024];
+            loop {
+                let read = unsafe { libc::read(self.fd, buffer.as_mut_ptr().cast(), buffer.len()) };
+                let Ok(read) = usize::try_from(read) else {
+                    break;
+                };
+                let mut at = 0;
+                while at + HEADER <= read {
+                    let event: libc::inotify_event =
+                        unsafe { std::ptr::read_unaligned(buffer[at..].as_ptr().cast()) };
+                    let name = &buffer[at + HEADER..][..event.len as usize];
+                    let name = name.split(|&b| b == 0).next().unwrap_or_default();
+                    names.push(std::ffi::OsStr::from_bytes(name).to_owned());
+                    at += HEADER + event.len as usize;
+                }
+            }
+            names
+        }
+    }
+
+    #[cfg(target_os = "linux")]
+    impl Drop for DeleteWatch {
+        fn drop(&mut self) {
+            unsafe { libc::close(self.fd) };
+        }
+    }
+
+    #[test]
+    fn test_delete_files_in_locality_order() {
+        let temp = std::env::temp_dir().join("rmbrr_delete_files_locality_test");
+        let _ = fs::remove_dir_all(&temp);
+        fs::create_dir_all(temp.join("late")).unwrap();
+        for i in 0..50 {
+            fs::write(temp.join(format!("f{}.bin", (i * 7) % 50)), [0u8; 2]).unwrap();
+        }
+        let mut expected = Vec::new();
+        winapi::enumerate_entries(&temp, |path, is_dir, attributes| {
+            if !is_dir {
+                expected.push((attributes.locality, path.file_name().unwrap().to_owned()));
+            }
+            Ok(())
+        })
+        .unwrap();
+        expected.sort_by_key(|(locality, _)| *locality);
+        let expected: Vec<_> = expected.into_iter().map(|(_, name)| name).collect();
+        #[cfg(target_os = "linux")]
+        let watch = DeleteWatch::new(&temp);
+
+        let config = WorkerConfig {
+            count_bytes: true,
+            order: "locality".parse().unwrap(),
+            ..WorkerConfig::default()
+        };
+        let error_tracker = Arc::new(ErrorTracker::new());
+        let (broker, _tx, _rx) = Broker::new(tree::DirectoryTree::new());
+        let pass = delete_files_in_dir(&temp, &broker, &config, &error_tracker).unwrap();
+        assert_eq!((pass.deleted, pass.bytes, pass.failed), (50, 100, 0));
+        assert_eq!(pass.new_dirs, vec![temp.join("late")]);
+        #[cfg(target_os = "linux")]
+        assert_eq!(watch.deleted(), expected);
+        #[cfg(not(target_os = "linux"))]
+        let _ = expected;
+        assert!("nearest".parse::<DeleteOrder>().is_err());
+
+        fs::remove_dir_all(&temp).ok();
+    }
+
+    #[test]
+    fn test_remove_empty_dirs() {
+        let temp = std::env::temp_dir().join("rmbrr_remove_empty_dirs_test");
+        let _ = fs::remove_dir_all(&temp);
+        fs::create_dir_all(temp.join("a/a1/a2")).unwrap();
+        fs::create_dir_all(temp.join("b")).unwrap();
+
+        let mut tree = tree::discover_tree(&temp).unwrap();
+        assert!(is_empty_skeleton(&tree));
+        assert_eq!(remove_empty_dirs(&mut tree), 5);
+        assert!(tree.dirs.is_empty() && tree.leaves.is_empty());
+        assert!(!temp.exists());
+
+        // A file created after the scan keeps its directory and those above
+        fs::create_dir_all(temp.join("a/a1")).unwrap();
+        fs::create_dir_all(temp.join("b")).unwrap();
+        let mut tree = tree::discover_tree(&temp).unwrap();
+        File::create(temp.join("a/late.txt")).unwrap();
+        assert_eq!(remove_empty_dirs(&mut tree), 2);
+        assert_eq!(tree.dirs, vec![temp.clone(), temp.join("a")]);
+        assert_eq!(tree.leaves, vec![temp.join("a")]);
+
+        fs::remove_dir_all(&temp).ok();
+    }
+
     #[test]
     fn test_spawn_workers_concurrent_consumption() {
         // Create a simple tree with multiple leaves to test parallel consumption
@@ -221,7 +989,7 @@ mod tests {
                         let error_tracker = Arc::new(ErrorTracker::new());
                         while let Ok(dir) = rx.recv_timeout(Duration::from_millis(100)) {
                             work_count.fetch_add(1, Ordering::SeqCst);
-                            let _ = delete_files_in_dir(&dir, &config, &error_tracker);
+                            let _ = delete_files_in_dir(&dir, &broker, &config, &error_tracker);
                             let _ = remove_dir(&dir);
                             broker.mark_complete(dir);
                         }
@@ -249,4 +1017,358 @@ mod tests {
         // Clean up
         let _ = fs::remove_dir_all(&temp_root);
     }
+
+    #[test]
+    fn test_directories_created_after_scan_are_deleted() {
+        let temp = std::env::temp_dir().join("rmbrr_late_dirs_test");
+        let _ = fs::remove_dir_all(&temp);
+        fs::create_dir(&temp).unwrap();
+
+        let tree = tree::discover_tree(&temp).unwrap();
+        assert_eq!(tree.dirs.len(), 1);
+
+        // Appears between scan and deletion
+        fs::create_dir_all(temp.join("late/deeper")).unwrap();
+        File::create(temp.join("late/deeper/file.txt")).unwrap();
+
+        let (broker, tx, rx) = Broker::new(tree);
+        let broker = Arc::new(broker);
+        drop(tx);
+
+        let error_tracker = Arc::new(ErrorTracker::new());
+        let handles = spawn_workers(
+            2,
+            rx,
+            broker.clone(),
+            WorkerConfig::default(),
+            error_tracker.clone(),
+        );
+        for handle in handles {
+            handle.join().unwrap();
+        }
+
+        assert!(!temp.exists());
+        assert_eq!(error_tracker.failure_count(), 0);
+        assert_eq!(broker.total_dirs(), 3);
+        assert_eq!(broker.completed_count(), 3);
+    }
+
+    #[test]
+    fn test_aborted_broker_leaves_queued_dirs() {
+        let temp = std::env::temp_dir().join("rmbrr_aborted_queue_test");
+        let _ = fs::remove_dir_all(&temp);
+        fs::create_dir_all(temp.join("a")).unwrap();
+        fs::create_dir_all(temp.join("b")).unwrap();
+        File::create(temp.join("a/file.txt")).unwrap();
+
+        let tree = tree::discover_tree(&temp).unwrap();
+        let (broker, tx, rx) = Broker::new(tree);
+        let broker = Arc::new(broker);
+        drop(tx);
+        // Ctrl+C before any worker got going
+        broker.abort();
+
+        let error_tracker = Arc::new(ErrorTracker::new());
+        let handles = spawn_workers(
+            2,
+            rx,
+            broker.clone(),
+            WorkerConfig::default(),
+            error_tracker.clone(),
+        );
+        join_workers(handles).unwrap();
+
+        assert!(temp.join("a/file.txt").exists() && temp.join("b").exists());
+        assert_eq!(broker.completed_count(), 0);
+        assert_eq!(error_tracker.failure_count(), 0);
+
+        fs::remove_dir_all(&temp).ok();
+    }
+
+    #[test]
+    fn test_directory_replaced_by_file() {
+        let temp = std::env::temp_dir().join("rmbrr_type_change_test");
+        let _ = fs::remove_dir_all(&temp);
+        fs::create_dir_all(temp.join("was_dir")).unwrap();
+
+        let tree = tree::discover_tree(&temp).unwrap();
+        assert_eq!(tree.dirs.len(), 2);
+
+        fs::remove_dir(temp.join("was_dir")).unwrap();
+        File::create(temp.join("was_dir")).unwrap();
+
+        let (broker, tx, rx) = Broker::new(tree);
+        let broker = Arc::new(broker);
+        drop(tx);
+
+        let error_tracker = Arc::new(ErrorTracker::new());
+        let handles = spawn_workers(
+            1,
+            rx,
+            broker.clone(),
+            WorkerConfig::default(),
+            error_tracker.clone(),
+        );
+        for handle in handles {
+            handle.join().unwrap();
+        }
+
+        assert!(!temp.exists());
+        assert_eq!(error_tracker.failure_count(), 0);
+        assert_eq!(error_tracker.type_change_count(), 1);
+    }
+
+    #[test]
+    fn test_excluded_entries_are_kept() {
+        let temp = std::env::temp_dir().join("rmbrr_excluded_test");
+        let _ = fs::remove_dir_all(&temp);
+        let keep = temp.join("keep");
+        fs::create_dir_all(&keep).unwrap();
+        fs::create_dir_all(temp.join("gone")).unwrap();
+        File::create(keep.join("file.txt")).unwrap();
+        File::create(temp.join("gone/file.txt")).unwrap();
+
+        // Exclude "keep" the way the scan excludes a mount point
+        let mut tree = tree::discover_tree(&temp).unwrap();
+        tree.dirs.retain(|d| *d != keep);
+        tree.leaves.retain(|d| *d != keep);
+        tree.children.get_mut(&temp).unwrap().retain(|d| *d != keep);
+        tree.skipped.push(tree::SkippedEntry {
+            path: keep.clone(),
+            is_dir: true,
+            reason: tree::SkipReason::MountPoint,
+            bytes: 0,
+        });
+        tree.retained.insert(temp.clone());
+
+        let (broker, tx, rx) = Broker::new(tree);
+        let broker = Arc::new(broker);
+        drop(tx);
+
+        let error_tracker = Arc::new(ErrorTracker::new());
+        let handles = spawn_workers(
+            1,
+            rx,
+            broker.clone(),
+            WorkerConfig::default(),
+            error_tracker.clone(),
+        );
+        for handle in handles {
+            handle.join().unwrap();
+        }
+
+        assert!(keep.join("file.txt").exists());
+        assert!(!temp.join("gone").exists());
+        assert_eq!(error_tracker.failure_count(), 0);
+        assert_eq!(broker.completed_count(), 1);
+        assert_eq!(broker.kept_count(), 1);
+        assert!(broker.is_finished());
+
+        fs::remove_dir_all(&temp).ok();
+    }
+
+    #[test]
+    fn test_keep_list_keeps_ancestors() {
+        let temp = std::env::temp_dir().join("rmbrr_keep_ancestors_test");
+        let _ = fs::remove_dir_all(&temp);
+        fs::create_dir_all(temp.join("mid/deeper")).unwrap();
+        fs::create_dir_all(temp.join("other")).unwrap();
+        File::create(temp.join("mid/deeper/keep.txt")).unwrap();
+        File::create(temp.join("mid/deeper/drop.txt")).unwrap();
+        File::create(temp.join("other/drop.txt")).unwrap();
+
+        let mut options = tree::ScanOptions::default();
+        options.filters.keep(temp.join("mid/deeper/keep.txt"));
+        let tree =
+            tree::discover_tree_with_options(&temp, &options, &tree::ScanProgress::new()).unwrap();
+
+        let (broker, tx, rx) = Broker::new(tree);
+        let broker = Arc::new(broker);
+        drop(tx);
+
+        let error_tracker = Arc::new(ErrorTracker::new());
+        let handles = spawn_workers(
+            2,
+            rx,
+            broker.clone(),
+            WorkerConfig::default(),
+            error_tracker.clone(),
+        );
+        for handle in handles {
+            handle.join().unwrap();
+        }
+
+        assert!(temp.join("mid/deeper/keep.txt").exists());
+        assert!(!temp.join("mid/deeper/drop.txt").exists());
+        assert!(!temp.join("other").exists());
+        assert_eq!(error_tracker.failure_count(), 0);
+        assert_eq!(broker.kept_count(), 3);
+        assert_eq!(broker.completed_count(), 1);
+
+        fs::remove_dir_all(&temp).ok();
+    }
+
+    #[test]
+    fn test_older_than_keeps_new_entries() {
+        let temp = std::env::temp_dir().join("rmbrr_older_than_test");
+        let _ = fs::remove_dir_all(&temp);
+        fs::create_dir_all(temp.join("old")).unwrap();
+        fs::create_dir_all(temp.join("mixed")).unwrap();
+        File::create(temp.join("old/a.txt")).unwrap();
+        File::create(temp.join("mixed/old.txt")).unwrap();
+        // Everything is older than a cutoff an hour ahead, except what is
+        // dated after it
+        let now = std::time::SystemTime::now();
+        let cutoff = now + Duration::from_secs(3600);
+        let touch_new = |path: &Path| {
+            let file = File::create(path).unwrap();
+            file.set_modified(now + Duration::from_secs(86400)).unwrap();
+        };
+        touch_new(&temp.join("mixed/new.txt"));
+
+        let mut options = tree::ScanOptions::default();
+        options.filters.keep_newer_than(cutoff);
+        let tree =
+            tree::discover_tree_with_options(&temp, &options, &tree::ScanProgress::new()).unwrap();
+        assert_eq!(tree.skipped.len(), 1);
+        // Written between scan and deletion
+        touch_new(&temp.join("late.txt"));
+        fs::create_dir(temp.join("late_dir")).unwrap();
+        touch_new(&temp.join("late_dir/new.txt"));
+        File::create(temp.join("late_dir/old.txt")).unwrap();
+
+        let (broker, tx, rx) = Broker::new(tree);
+        let broker = Arc::new(broker);
+        drop(tx);
+
+        let config = WorkerConfig {
+            filters: Arc::new(options.filters.clone()),
+            ..WorkerConfig::default()
+        };
+        let error_tracker = Arc::new(ErrorTracker::new());
+        let handles = spawn_workers(2, rx, broker.clone(), config, error_tracker.clone());
+        for handle in handles {
+            handle.join().unwrap();
+        }
+
+        assert!(!temp.join("old").exists());
+        assert!(!temp.join("mixed/old.txt").exists());
+        assert!(temp.join("mixed/new.txt").exists());
+        assert!(temp.join("late.txt").exists());
+        // Directories appearing mid-run are scanned with the same filters
+        assert!(temp.join("late_dir/new.txt").exists());
+        assert!(!temp.join("late_dir/old.txt").exists());
+        assert_eq!(error_tracker.failure_count(), 0);
+        assert_eq!(broker.excluded_entries().len(), 3);
+        assert!(broker.is_finished());
+
+        fs::remove_dir_all(&temp).ok();
+    }
+
+    #[test]
+    fn test_with_retries() {
+        let policy = RetryPolicy::default();
+        let mut calls = 0;
+        let (result, retries) = with_retries(&policy, || {
+            calls += 1;
+            if calls < 3 {
+                Err(io::Error::from(io::ErrorKind::ResourceBusy))
+            } else {
+                Ok(())
+            }
+        });
+        assert!(result.is_ok());
+        assert_eq!(retries, 2);
+
+        // Hard errors are not retried
+        let mut calls = 0;
+        let (result, retries) = with_retries(&policy, || {
+            calls += 1;
+            Err(io::Error::from(io::ErrorKind::PermissionDenied))
+        });
+        assert!(result.is_err());
+        assert_eq!((calls, retries), (1, 0));
+
+        // Gives up after the configured number of retries
+        let policy = RetryPolicy {
+            attempts: 4,
+            delay: Duration::ZERO,
+            ..Default::default()
+        };
+        let mut calls = 0;
+        let (result, retries) = with_retries(&policy, || {
+            calls += 1;
+            Err(io::Error::from(io::ErrorKind::ResourceBusy))
+        });
+        assert!(result.is_err());
+        assert_eq!((calls, retries), (5, 4));
+    }
+
+    #[test]
+    fn test_retry_backoff_doubles() {
+        let delay = Duration::from_millis(50);
+        let waits: Vec<u128> = (1..=3).map(|r| backoff(delay, r).as_millis()).collect();
+        assert_eq!(waits, vec![50, 100, 200]);
+    }
+
+    #[test]
+    fn test_retry_rules() {
+        let rule = RetryRule::parse("in-use=5:1ms").unwrap();
+        assert_eq!(rule.category, ErrorCategory::InUse);
+        assert_eq!(rule.delay, Some(Duration::from_millis(1)));
+        assert!(RetryRule::parse("in_use").is_err());
+        assert!(RetryRule::parse("busy=2").is_err());
+        assert!(RetryRule::parse("in_use=x").is_err());
+
+        let policy = RetryPolicy {
+            delay: Duration::ZERO,
+            rules: vec![
+                rule,
+                RetryRule::parse("permission_denied=0").unwrap(),
+                RetryRule::parse("not_found=1").unwrap(),
+            ],
+            ..Default::default()
+        };
+        let count = |kind: io::ErrorKind| {
+            let mut calls = 0;
+            let (_, retries) = with_retries(&policy, || {
+                calls += 1;
+                Err(io::Error::from(kind))
+            });
+            (calls, retries)
+        };
+        assert_eq!(count(io::ErrorKind::ResourceBusy), (6, 5));
+        assert_eq!(count(io::ErrorKind::PermissionDenied), (1, 0));
+        // Not retried by default, but a rule turns it on
+        assert_eq!(count(io::ErrorKind::NotFound), (2, 1));
+        assert_eq!(count(io::ErrorKind::Other), (1, 0));
+    }
+
+    #[test]
+    fn test_join_workers_reports_panic() {
+        let (broker, _tx, _rx) = Broker::new(tree::DirectoryTree::new());
+        let broker = Arc::new(broker);
+
+        let handles: Vec<JoinHandle<()>> = (0..3)
+            .map(|i| {
+                let broker = broker.clone();
+                thread::spawn(move || {
+                    let _abort_on_panic = AbortOnPanic(&broker);
+                    if i == 1 {
+                        panic!("boom in worker {}", i);
+                    }
+                })
+            })
+            .collect();
+
+        match join_workers(handles) {
+            Err(Error::WorkerPanic { worker, payload }) => {
+                assert_eq!(worker, 1);
+                assert_eq!(payload, "boom in worker 1");
+            }
+            other => panic!("expected WorkerPanic, got {:?}", other),
+        }
+        // The panicking worker stopped the rest from waiting on it
+        assert!(broker.is_aborted());
+    }
 }
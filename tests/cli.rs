@@ -0,0 +1,160 @@
+// Exit codes of the rmbrr binary
+
+use std::fs::{self, File};
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+/// rmbrr without the user's config files or history, in `temp`
+fn rmbrr(temp: &Path) -> Command {
+    let mut command = Command::new(env!("CARGO_BIN_EXE_rmbrr"));
+    command
+        .arg("--no-config")
+        .env("RMBRR_HISTORY", temp.join("history.tsv"))
+        .current_dir(temp);
+    command
+}
+
+fn exit_code(command: &mut Command) -> i32 {
+    let output = command.output().unwrap();
+    output.status.code().expect("exited normally")
+}
+
+/// A fresh directory holding `target/sub/file.txt`
+fn setup(name: &str) -> (PathBuf, PathBuf) {
+    let temp = std::env::temp_dir().join(name);
+    let _ = fs::remove_dir_all(&temp);
+    let target = temp.join("target");
+    fs::create_dir_all(target.join("sub")).unwrap();
+    File::create(target.join("sub").join("file.txt")).unwrap();
+    (temp, target)
+}
+
+#[test]
+fn test_exit_success() {
+    let (temp, target) = setup("rmbrr_cli_success");
+    assert_eq!(exit_code(rmbrr(&temp).arg(&target)), 0);
+    assert!(!target.exists());
+    fs::remove_dir_all(&temp).ok();
+}
+
+#[test]
+fn test_exit_invalid_path() {
+    let (temp, _) = setup("rmbrr_cli_missing");
+    assert_eq!(exit_code(rmbrr(&temp).arg(temp.join("missing"))), 4);
+    fs::remove_dir_all(&temp).ok();
+}
+
+#[test]
+fn test_exit_refused() {
+    let (temp, target) = setup("rmbrr_cli_refused");
+    assert_eq!(
+        exit_code(rmbrr(&temp).arg("--protect").arg(&target).arg(&target)),
+        3
+    );
+    assert!(target.join("sub").join("file.txt").exists());
+    fs::remove_dir_all(&temp).ok();
+}
+
+// --chaos, failing deletes on purpose, is only in debug builds
+#[cfg(debug_assertions)]
+#[test]
+fn test_exit_partial_failure() {
+    let (temp, target) = setup("rmbrr_cli_partial");
+    assert_eq!(
+        exit_code(rmbrr(&temp).args(["--chaos", "100"]).arg(&target)),
+        2
+    );
+    assert!(target.exists());
+    fs::remove_dir_all(&temp).ok();
+}
+
+// What the report counts as deleted is what's gone, not what was found
+#[cfg(all(debug_assertions, feature = "json"))]
+#[test]
+fn test_json_counts_files_deleted() {
+    let temp = std::env::temp_dir().join("rmbrr_cli_json_partial");
+    let _ = fs::remove_dir_all(&temp);
+    let targets = [temp.join("one"), temp.join("two")];
+    for target in &targets {
+        fs::create_dir_all(target).unwrap();
+        for i in 0..20 {
+            fs::write(target.join(format!("{}.txt", i)), "x").unwrap();
+        }
+    }
+    let output = rmbrr(&temp)
+        .args(["--chaos", "50", "--retries", "0", "--json"])
+        .args(&targets)
+        .output()
+        .unwrap();
+    assert_eq!(output.status.code(), Some(2));
+
+    let report: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    let left = |target: &Path| fs::read_dir(target).map_or(0, |entries| entries.count());
+    for (target, json) in targets.iter().zip(report["targets"].as_array().unwrap()) {
+        assert_eq!(
+            json["files_deleted"].as_u64().unwrap() as usize,
+            20 - left(target)
+        );
+        assert_eq!(json["files_deleted"], json["bytes_freed"]);
+    }
+    let deleted = 40 - targets.iter().map(|target| left(target)).sum::<usize>();
+    assert_eq!(
+        report["totals"]["files_deleted"].as_u64().unwrap() as usize,
+        deleted
+    );
+    fs::remove_dir_all(&temp).ok();
+}
+
+#[test]
+fn test_exit_strict_warnings() {
+    let (temp, target) = setup("rmbrr_cli_strict");
+    // A history file under a regular file can't be written: a warning
+    File::create(temp.join("blocker")).unwrap();
+    let history = temp.join("blocker").join("history.tsv");
+    assert_eq!(
+        exit_code(rmbrr(&temp).env("RMBRR_HISTORY", &history).arg(&target)),
+        0
+    );
+
+    fs::create_dir_all(&target).unwrap();
+    assert_eq!(
+        exit_code(
+            rmbrr(&temp)
+                .env("RMBRR_HISTORY", &history)
+                .arg("--strict")
+                .arg(&target)
+        ),
+        2
+    );
+    assert!(!target.exists());
+    fs::remove_dir_all(&temp).ok();
+}
+
+#[test]
+fn test_exit_usage_error() {
+    let (temp, target) = setup("rmbrr_cli_usage");
+    assert_eq!(exit_code(rmbrr(&temp).arg("--bogus").arg(&target)), 64);
+    assert!(target.exists());
+    fs::remove_dir_all(&temp).ok();
+}
+
+#[test]
+fn test_exit_missing_ok() {
+    let (temp, target) = setup("rmbrr_cli_missing_ok");
+    let missing = temp.join("missing");
+    assert_eq!(
+        exit_code(rmbrr(&temp).arg("--missing-ok").arg(&missing).arg(&target)),
+        0
+    );
+    assert!(!target.exists());
+    assert_eq!(
+        exit_code(
+            rmbrr(&temp)
+                .args(["--missing-ok", "--estimate"])
+                .arg(&missing)
+        ),
+        0
+    );
+    assert_eq!(exit_code(rmbrr(&temp).arg("--estimate").arg(&missing)), 4);
+    fs::remove_dir_all(&temp).ok();
+}
@@ -53,6 +53,63 @@ fn test_unicode_filenames() {
     assert!(!temp.exists());
 }
 
+#[cfg(unix)]
+#[test]
+fn test_invalid_utf8_filenames() {
+    use std::ffi::OsStr;
+    use std::os::unix::ffi::OsStrExt;
+
+    let temp = std::env::temp_dir().join("rmbrr_test_invalid_utf8");
+    let _ = fs::remove_dir_all(&temp);
+    fs::create_dir(&temp).unwrap();
+
+    // Lone continuation byte and an overlong encoding: neither is UTF-8
+    let bad_dir = temp.join(OsStr::from_bytes(b"dir_\x80"));
+    if fs::create_dir(&bad_dir).is_err() {
+        // Some filesystems (e.g. macOS APFS) reject non-UTF-8 names outright
+        fs::remove_dir_all(&temp).ok();
+        return;
+    }
+    File::create(bad_dir.join(OsStr::from_bytes(b"file_\xc0\xaf.txt"))).unwrap();
+    File::create(temp.join(OsStr::from_bytes(b"\xff\xfe"))).unwrap();
+
+    let tree = tree::discover_tree(&temp).unwrap();
+    assert!(tree.dirs.contains(&bad_dir));
+    assert_eq!(tree.file_count, 2);
+
+    delete_directory(&temp).unwrap();
+    assert!(!temp.exists());
+}
+
+#[cfg(windows)]
+#[test]
+fn test_unpaired_surrogate_filenames() {
+    use std::ffi::OsString;
+    use std::os::windows::ffi::OsStringExt;
+
+    let temp = std::env::temp_dir().join("rmbrr_test_unpaired_surrogate");
+    let _ = fs::remove_dir_all(&temp);
+    fs::create_dir(&temp).unwrap();
+
+    // 0xD800 is a high surrogate with no low surrogate after it
+    let name = |prefix: &str| {
+        let mut units: Vec<u16> = prefix.encode_utf16().collect();
+        units.push(0xD800);
+        OsString::from_wide(&units)
+    };
+    let bad_dir = temp.join(name("dir_"));
+    fs::create_dir(&bad_dir).unwrap();
+    File::create(bad_dir.join(name("file_"))).unwrap();
+    File::create(temp.join(name(""))).unwrap();
+
+    let tree = tree::discover_tree(&temp).unwrap();
+    assert!(tree.dirs.contains(&bad_dir));
+    assert_eq!(tree.file_count, 2);
+
+    delete_directory(&temp).unwrap();
+    assert!(!temp.exists());
+}
+
 #[test]
 fn test_deep_nested_paths() {
     let temp = std::env::temp_dir().join("rmbrr_test_deep");
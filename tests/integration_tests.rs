@@ -1,6 +1,6 @@
 // Integration tests for rmbrr
 
-use rmbrr::{broker::Broker, tree, worker};
+use rmbrr::{broker::Broker, error::Error, safety, tree, worker, Deleter};
 use std::fs::{self, File};
 use std::path::Path;
 use std::sync::Arc;
@@ -233,3 +233,37 @@ fn test_mixed_structure() {
     println!("Deleted mixed structure in {:?}", elapsed);
     assert!(!temp.exists(), "Directory should be deleted");
 }
+
+#[test]
+fn test_deleter() {
+    let temp = std::env::temp_dir().join("rmbrr_test_deleter");
+    let _ = fs::remove_dir_all(&temp);
+    create_test_tree(&temp, 2, 3, 4);
+    let (dirs, files) = (count_dirs(&temp), count_files(&temp));
+
+    let report = Deleter::new(&temp).threads(4).run().unwrap();
+    assert!(report.is_complete());
+    assert_eq!(report.dirs_deleted, dirs);
+    assert_eq!(report.files_deleted, files);
+    assert!(!temp.exists());
+}
+
+#[test]
+fn test_deleter_refuses_protected_directories() {
+    let temp = std::env::temp_dir().join("rmbrr_test_deleter_protected");
+    let _ = fs::remove_dir_all(&temp);
+    let kept = temp.join("keep");
+    fs::create_dir_all(&kept).unwrap();
+    safety::protect([kept.clone()]);
+
+    // The protected directory itself, and a target holding it
+    for target in [&kept, &temp] {
+        match Deleter::new(target).run() {
+            Err(Error::Refused { path, .. }) => assert_eq!(&path, target),
+            other => panic!("expected a refusal, got {:?}", other.map(|_| ())),
+        }
+    }
+    assert!(kept.exists());
+
+    fs::remove_dir_all(&temp).ok();
+}